@@ -1,64 +1,177 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Instant;
 
 use agent_client_protocol as acp;
 use tokio::sync::{mpsc, oneshot, Mutex};
 
 use crate::config::AutoApprovePolicy;
-use crate::protocol::messages::{OutputEntry, OutputType};
+use crate::protocol::messages::{
+    Cost, OutputEntry, OutputType, PermissionInfo, PlanEntry, PlanPriority, PlanStatus, TokenUsage,
+};
 use crate::session::agent::{AgentStatus, OutputRingBuffer};
+use crate::session::usage::SharedUsage;
 
 // ==================== 权限请求队列 ====================
 
+/// 等待审批的 PermissionRequest 输出格式（auto-approve 的请求不带后缀）
+const PERMISSION_REQUESTED: &str = "Permission requested: ";
+const AWAITING_APPROVAL: &str = " (Waiting for approval)";
+const AUTO_APPROVED: &str = "Permission auto-approved: ";
+const APPROVED: &str = "Permission approved: ";
+const DENIED: &str = "Permission denied: ";
+
+/// 等待审批的权限请求输出 → 工具信息
+pub fn awaiting_approval(content: &str) -> Option<&str> {
+    content.strip_prefix(PERMISSION_REQUESTED)?.strip_suffix(AWAITING_APPROVAL)
+}
+
+/// allow / deny 的结果，写入输出缓冲
+pub fn permission_decided(tool_info: &str, approve: bool) -> String {
+    format!("{}{}", if approve { APPROVED } else { DENIED }, tool_info)
+}
+
+/// 权限输出 → (工具信息, 结果)；结果 None = 等待审批
+pub fn permission_outcome(content: &str) -> Option<(&str, Option<&'static str>)> {
+    if let Some(tool) = awaiting_approval(content) {
+        return Some((tool, None));
+    }
+    [(AUTO_APPROVED, "auto-approved"), (APPROVED, "approved"), (DENIED, "denied")]
+        .into_iter()
+        .find_map(|(prefix, outcome)| content.strip_prefix(prefix).map(|tool| (tool, Some(outcome))))
+}
+
+/// 权限请求 id，进程内递增（跨 session 唯一）
+static NEXT_PERMISSION_ID: AtomicU64 = AtomicU64::new(1);
+
 pub struct PendingPermission {
+    pub id: u64,
     pub tool_info: String,
+    /// ACP 工具类别（snake_case）
+    pub kind: Option<String>,
+    pub requested_at: Instant,
     pub response_tx: oneshot::Sender<PermissionDecision>,
 }
 
+impl PendingPermission {
+    pub fn new(tool_info: String, kind: Option<String>, response_tx: oneshot::Sender<PermissionDecision>) -> Self {
+        Self {
+            id: NEXT_PERMISSION_ID.fetch_add(1, Ordering::Relaxed),
+            tool_info,
+            kind,
+            requested_at: Instant::now(),
+            response_tx,
+        }
+    }
+
+    pub fn info(&self, session: Option<String>) -> PermissionInfo {
+        PermissionInfo {
+            id: self.id,
+            session,
+            tool: self.tool_info.clone(),
+            kind: self.kind.clone(),
+            age_secs: self.requested_at.elapsed().as_secs(),
+        }
+    }
+}
+
 pub enum PermissionDecision {
     Approve,
     Deny,
 }
 
-// ==================== ACP Client 实现 ====================
-// 每个 Agent 一个 TeamClient，处理回调（通知、权限等）
+// ==================== 命名 session ====================
 
-pub struct TeamClient {
+/// 一个 ACP session 的状态：输出缓冲、状态、权限队列各自独立
+#[derive(Clone)]
+pub struct SessionState {
+    /// None = 默认 session
+    pub name: Option<String>,
     pub status: Arc<std::sync::Mutex<AgentStatus>>,
     pub output_buffer: Arc<Mutex<OutputRingBuffer>>,
     pub pending_permissions: Arc<Mutex<VecDeque<PendingPermission>>>,
+    /// 最新上报的 plan
+    pub plan: SharedPlan,
+    /// token / 花费账本
+    pub usage: SharedUsage,
+}
+
+/// 最新 plan（每次 Plan 通知整体替换）
+pub type SharedPlan = Arc<std::sync::Mutex<Vec<PlanEntry>>>;
+
+impl SessionState {
+    pub fn new(name: Option<String>, buffer: OutputRingBuffer) -> Self {
+        Self {
+            name,
+            status: Arc::new(std::sync::Mutex::new(AgentStatus::Idle)),
+            output_buffer: Arc::new(Mutex::new(buffer)),
+            pending_permissions: Arc::new(Mutex::new(VecDeque::new())),
+            plan: SharedPlan::default(),
+            usage: SharedUsage::default(),
+        }
+    }
+
+    pub fn set_status(&self, s: AgentStatus) {
+        *self.status.lock().unwrap() = s;
+    }
+
+    pub fn get_status(&self) -> AgentStatus {
+        self.status.lock().unwrap().clone()
+    }
+}
+
+/// ACP session id → 命名 session；不在表中的回调归默认 session
+pub type SessionRoutes = Arc<std::sync::Mutex<HashMap<acp::SessionId, SessionState>>>;
+
+/// TeamClient 输出：(命名 session, 条目)
+pub type OutputSender = mpsc::UnboundedSender<(Option<String>, OutputEntry)>;
+
+// ==================== ACP Client 实现 ====================
+// 每个 Agent 一个 TeamClient，处理回调（通知、权限等），按 session id 分发
+
+pub struct TeamClient {
+    pub default: SessionState,
+    pub routes: SessionRoutes,
     pub auto_approve: AutoApprovePolicy,
-    pub output_tx: Option<mpsc::UnboundedSender<OutputEntry>>,
+    pub output_tx: Option<OutputSender>,
 }
 
 impl TeamClient {
     pub fn new(
-        status: Arc<std::sync::Mutex<AgentStatus>>,
-        buffer: Arc<Mutex<OutputRingBuffer>>,
-        pending: Arc<Mutex<VecDeque<PendingPermission>>>,
+        default: SessionState,
+        routes: SessionRoutes,
         auto_approve: AutoApprovePolicy,
-        output_tx: Option<mpsc::UnboundedSender<OutputEntry>>,
+        output_tx: Option<OutputSender>,
     ) -> Self {
         Self {
-            status,
-            output_buffer: buffer,
-            pending_permissions: pending,
+            default,
+            routes,
             auto_approve,
             output_tx,
         }
     }
 
+    fn state(&self, session_id: &acp::SessionId) -> SessionState {
+        self.routes
+            .lock()
+            .unwrap()
+            .get(session_id)
+            .cloned()
+            .unwrap_or_else(|| self.default.clone())
+    }
+
     /// push 到 buffer + 通知 stdout
-    async fn write_output(&self, update_type: OutputType, content: String) {
+    async fn write_output(&self, state: &SessionState, update_type: OutputType, content: String) {
         let entry = OutputEntry {
             timestamp: chrono::Utc::now().to_rfc3339(),
             update_type,
             content,
         };
         if let Some(tx) = &self.output_tx {
-            tx.send(entry.clone()).ok();
+            tx.send((state.name.clone(), entry.clone())).ok();
         }
-        self.output_buffer.lock().await.push(entry);
+        state.output_buffer.lock().await.push(entry);
     }
 }
 
@@ -69,12 +182,14 @@ impl acp::Client for TeamClient {
         args: acp::RequestPermissionRequest,
     ) -> acp::Result<acp::RequestPermissionResponse> {
         let tool_info = fmt_tool_info(&args.tool_call.fields);
+        let state = self.state(&args.session_id);
 
         // auto-approve 策略
         if matches!(self.auto_approve, AutoApprovePolicy::Always) {
             self.write_output(
+                &state,
                 OutputType::PermissionRequest,
-                format!("Permission auto-approved: {}", tool_info),
+                format!("{}{}", AUTO_APPROVED, tool_info),
             )
             .await;
             return Ok(permission_response(&args.options, true));
@@ -82,27 +197,26 @@ impl acp::Client for TeamClient {
 
         // 写入 output 让用户看到
         self.write_output(
+            &state,
             OutputType::PermissionRequest,
-            format!("Permission requested: {} (Waiting for approval)", tool_info),
+            format!("{}{}{}", PERMISSION_REQUESTED, tool_info, AWAITING_APPROVAL),
         )
         .await;
 
         // 创建 channel，放入 pending queue
         let (tx, rx) = oneshot::channel();
         {
-            let mut queue = self.pending_permissions.lock().await;
-            queue.push_back(PendingPermission {
-                tool_info,
-                response_tx: tx,
-            });
+            let mut queue = state.pending_permissions.lock().await;
+            let kind = args.tool_call.fields.kind.as_ref().and_then(tool_kind);
+            queue.push_back(PendingPermission::new(tool_info, kind, tx));
         }
 
         // 状态 → WaitingPermission
-        *self.status.lock().unwrap() = AgentStatus::WaitingPermission;
+        state.set_status(AgentStatus::WaitingPermission);
 
         // 等待用户回复
         let approved = matches!(rx.await, Ok(PermissionDecision::Approve));
-        *self.status.lock().unwrap() = AgentStatus::Running;
+        state.set_status(AgentStatus::Running);
         Ok(permission_response(&args.options, approved))
     }
 
@@ -110,6 +224,7 @@ impl acp::Client for TeamClient {
         &self,
         args: acp::SessionNotification,
     ) -> acp::Result<()> {
+        let state = self.state(&args.session_id);
         let (output_type, text) = match &args.update {
             // A3: 合并 chunk 处理
             acp::SessionUpdate::AgentMessageChunk(c) => {
@@ -125,6 +240,7 @@ impl acp::Client for TeamClient {
                 (OutputType::ToolCallUpdate, fmt_tool_call_update(&tcu.fields))
             }
             acp::SessionUpdate::Plan(plan) => {
+                *state.plan.lock().unwrap() = plan_entries(plan);
                 (OutputType::PlanUpdate, fmt_plan(plan))
             }
             acp::SessionUpdate::CurrentModeUpdate(m) => {
@@ -138,12 +254,23 @@ impl acp::Client for TeamClient {
                     .collect();
                 (OutputType::ConfigUpdate, items.join(", "))
             }
+            // session 累计花费；context 窗口用量不记录
+            acp::SessionUpdate::UsageUpdate(u) => {
+                if let Some(c) = &u.cost {
+                    let cost = Cost { amount: c.amount, currency: c.currency.clone() };
+                    state.usage.lock().unwrap().record_cost(cost);
+                }
+                return Ok(());
+            }
             // AvailableCommandsUpdate 等信息性通知，静默忽略
             _ => return Ok(()),
         };
 
+        if matches!(output_type, OutputType::AgentMessage | OutputType::AgentThought) {
+            state.usage.lock().unwrap().record_output(text.chars().count());
+        }
         if !text.is_empty() {
-            self.write_output(output_type, text).await;
+            self.write_output(&state, output_type, text).await;
         }
         Ok(())
     }
@@ -180,6 +307,11 @@ fn fmt_tool_info(fields: &acp::ToolCallUpdateFields) -> String {
     "Unknown tool".to_string()
 }
 
+/// ToolKind 的协议名（read / edit / execute ...）
+fn tool_kind(kind: &acp::ToolKind) -> Option<String> {
+    serde_json::to_value(kind).ok()?.as_str().map(str::to_string)
+}
+
 fn fmt_tool_call_update(fields: &acp::ToolCallUpdateFields) -> String {
     let mut parts = Vec::new();
     if let Some(title) = &fields.title {
@@ -200,6 +332,36 @@ fn fmt_plan(plan: &acp::Plan) -> String {
     format!("Plan:\n{}", lines.join("\n"))
 }
 
+/// ACP 上报的本 turn 用量
+pub fn token_usage(u: &acp::Usage) -> TokenUsage {
+    TokenUsage {
+        input: u.input_tokens,
+        output: u.output_tokens,
+        total: u.total_tokens,
+        estimated: false,
+    }
+}
+
+/// ACP plan → 结构化条目（未知的优先级 / 状态按 medium / pending 处理）
+fn plan_entries(plan: &acp::Plan) -> Vec<PlanEntry> {
+    plan.entries
+        .iter()
+        .map(|e| PlanEntry {
+            content: e.content.clone(),
+            priority: match e.priority {
+                acp::PlanEntryPriority::High => PlanPriority::High,
+                acp::PlanEntryPriority::Low => PlanPriority::Low,
+                _ => PlanPriority::Medium,
+            },
+            status: match e.status {
+                acp::PlanEntryStatus::InProgress => PlanStatus::InProgress,
+                acp::PlanEntryStatus::Completed => PlanStatus::Completed,
+                _ => PlanStatus::Pending,
+            },
+        })
+        .collect()
+}
+
 fn extract_text(content: &acp::ContentBlock) -> String {
     match content {
         acp::ContentBlock::Text(t) => t.text.clone(),
@@ -272,6 +434,15 @@ mod tests {
         assert_eq!(fmt_tool_info(&fields), "Unknown tool");
     }
 
+    #[test]
+    fn permission_outcomes_round_trip() {
+        let requested = format!("{}rm -rf build{}", PERMISSION_REQUESTED, AWAITING_APPROVAL);
+        assert_eq!(permission_outcome(&requested), Some(("rm -rf build", None)));
+        assert_eq!(permission_outcome(&permission_decided("rm -rf build", false)), Some(("rm -rf build", Some("denied"))));
+        assert_eq!(permission_outcome("Permission auto-approved: ls"), Some(("ls", Some("auto-approved"))));
+        assert_eq!(permission_outcome("ls"), None);
+    }
+
     #[test]
     fn extract_text_from_text_block() {
         let block = acp::ContentBlock::from("hello world");
@@ -286,38 +457,135 @@ mod tests {
         assert_eq!(extract_text(&block), "");
     }
 
+    fn test_client(buf: OutputRingBuffer, output_tx: Option<OutputSender>) -> TeamClient {
+        TeamClient::new(
+            SessionState::new(None, buf),
+            SessionRoutes::default(),
+            AutoApprovePolicy::Never,
+            output_tx,
+        )
+    }
+
     #[tokio::test]
     async fn write_output_pushes_to_buffer() {
-        let buf = Arc::new(Mutex::new(OutputRingBuffer::new(10)));
         let (tx, mut rx) = mpsc::unbounded_channel();
-        let client = TeamClient::new(
-            Arc::new(std::sync::Mutex::new(AgentStatus::Idle)),
-            Arc::clone(&buf),
-            Arc::new(Mutex::new(std::collections::VecDeque::new())),
-            AutoApprovePolicy::Never,
-            Some(tx),
-        );
-        client.write_output(OutputType::AgentMessage, "hello".into()).await;
-        let entries = buf.lock().await.last_msgs(0);
+        let client = test_client(OutputRingBuffer::new(10), Some(tx));
+        client.write_output(&client.default, OutputType::AgentMessage, "hello".into()).await;
+        let entries = client.default.output_buffer.lock().await.last_msgs(0);
         assert_eq!(entries.len(), 1);
         assert_eq!(entries[0].content, "hello");
         // also sent to channel
-        let received = rx.recv().await.unwrap();
+        let (session, received) = rx.recv().await.unwrap();
+        assert!(session.is_none());
         assert_eq!(received.content, "hello");
     }
 
+    /// buffer 合并 chunk，订阅者仍收到原始 chunk
+    #[tokio::test]
+    async fn write_output_merges_but_streams_raw() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let client = test_client(OutputRingBuffer::new(10), Some(tx));
+        client.write_output(&client.default, OutputType::AgentMessage, "foo".into()).await;
+        client.write_output(&client.default, OutputType::AgentMessage, "bar".into()).await;
+        let entries = client.default.output_buffer.lock().await.last_msgs(0);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].content, "foobar");
+        assert_eq!(rx.recv().await.unwrap().1.content, "foo");
+        assert_eq!(rx.recv().await.unwrap().1.content, "bar");
+    }
+
     #[tokio::test]
     async fn write_output_no_sender() {
-        let buf = Arc::new(Mutex::new(OutputRingBuffer::new(10)));
-        let client = TeamClient::new(
-            Arc::new(std::sync::Mutex::new(AgentStatus::Idle)),
-            Arc::clone(&buf),
-            Arc::new(Mutex::new(std::collections::VecDeque::new())),
-            AutoApprovePolicy::Never,
-            None,
-        );
-        client.write_output(OutputType::Error, "oops".into()).await;
-        let entries = buf.lock().await.last_msgs(0);
+        let client = test_client(OutputRingBuffer::new(10), None);
+        client.write_output(&client.default, OutputType::Error, "oops".into()).await;
+        let entries = client.default.output_buffer.lock().await.last_msgs(0);
         assert_eq!(entries.len(), 1);
     }
+
+    /// 通知按 session id 进入对应 session 的 buffer，未登记的归默认 session
+    #[tokio::test]
+    async fn notifications_route_by_session_id() {
+        use acp::Client as _;
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let client = test_client(OutputRingBuffer::new(10), Some(tx));
+        let review = SessionState::new(Some("review".into()), OutputRingBuffer::new(10));
+        client.routes.lock().unwrap().insert(acp::SessionId::new("s-2"), review.clone());
+
+        let chunk = |sid: &'static str, text: &str| {
+            acp::SessionNotification::new(
+                acp::SessionId::new(sid),
+                acp::SessionUpdate::AgentMessageChunk(acp::ContentChunk::new(text.to_string().into())),
+            )
+        };
+        client.session_notification(chunk("s-2", "for review")).await.unwrap();
+        client.session_notification(chunk("s-1", "for default")).await.unwrap();
+
+        assert_eq!(review.output_buffer.lock().await.last_msgs(0)[0].content, "for review");
+        assert_eq!(client.default.output_buffer.lock().await.last_msgs(0)[0].content, "for default");
+        assert_eq!(rx.recv().await.unwrap().0.as_deref(), Some("review"));
+        assert_eq!(rx.recv().await.unwrap().0, None);
+    }
+
+    /// Plan 通知整体替换 session 的结构化 plan，同时保留文本日志
+    #[tokio::test]
+    async fn plan_notification_updates_state() {
+        use acp::Client as _;
+
+        let client = test_client(OutputRingBuffer::new(10), None);
+        let plan = |entries| {
+            acp::SessionNotification::new(
+                acp::SessionId::new("s-1"),
+                acp::SessionUpdate::Plan(acp::Plan::new(entries)),
+            )
+        };
+        let entry = |c: &str, p, s| acp::PlanEntry::new(c, p, s);
+        client
+            .session_notification(plan(vec![
+                entry("read code", acp::PlanEntryPriority::High, acp::PlanEntryStatus::Completed),
+                entry("write fix", acp::PlanEntryPriority::Low, acp::PlanEntryStatus::InProgress),
+            ]))
+            .await
+            .unwrap();
+        {
+            let p = client.default.plan.lock().unwrap();
+            assert_eq!(p.len(), 2);
+            assert_eq!(p[0].status, PlanStatus::Completed);
+            assert_eq!(p[1].priority, PlanPriority::Low);
+        }
+        client
+            .session_notification(plan(vec![entry(
+                "ship", acp::PlanEntryPriority::Medium, acp::PlanEntryStatus::Pending,
+            )]))
+            .await
+            .unwrap();
+        assert_eq!(client.default.plan.lock().unwrap()[0].content, "ship");
+        let log = client.default.output_buffer.lock().await.last_msgs(0);
+        assert!(log.iter().all(|e| matches!(e.update_type, OutputType::PlanUpdate)));
+    }
+
+    /// 回复文本计入本 turn 的估算，usage_update 记录累计花费
+    #[tokio::test]
+    async fn usage_notifications_update_ledger() {
+        use acp::Client as _;
+
+        let client = test_client(OutputRingBuffer::new(10), None);
+        let notify = |update| acp::SessionNotification::new(acp::SessionId::new("s-1"), update);
+        client.default.usage.lock().unwrap().begin_turn(0);
+        client
+            .session_notification(notify(acp::SessionUpdate::AgentMessageChunk(
+                acp::ContentChunk::new("12345678".to_string().into()),
+            )))
+            .await
+            .unwrap();
+        client
+            .session_notification(notify(acp::SessionUpdate::UsageUpdate(
+                acp::UsageUpdate::new(100, 1000).cost(acp::Cost::new(0.5, "USD")),
+            )))
+            .await
+            .unwrap();
+        let turn = client.default.usage.lock().unwrap().end_turn(None);
+        assert_eq!(turn.tokens.output, 2);
+        assert_eq!(turn.cost.unwrap().amount, 0.5);
+    }
 }
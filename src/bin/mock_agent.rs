@@ -1,11 +1,21 @@
 // ==================== Mock ACP Echo Agent ====================
 // 用于集成测试的简单 ACP agent
-// 接收 prompt → echo 回消息 → 返回 PromptResponse
+// 接收 prompt → echo 回消息 → 返回 PromptResponse；支持 session/load（fork 测试）
+// prompt 以 "permission:" 开头时先向 client 请求权限（标题为其余文本），得到答复后结束 turn
 
-use agent_client_protocol as acp;
+use std::cell::OnceCell;
+use std::rc::Rc;
+
+use agent_client_protocol::{self as acp, Client as _};
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
-struct MockAgent;
+/// 每个 new_session 分配递增 id（mock-session-1, -2 …）
+#[derive(Default)]
+struct MockAgent {
+    sessions: std::cell::Cell<u64>,
+    /// 连接建立后回填，用于发起 request_permission
+    conn: Rc<OnceCell<acp::AgentSideConnection>>,
+}
 
 #[async_trait::async_trait(?Send)]
 impl acp::Agent for MockAgent {
@@ -13,7 +23,8 @@ impl acp::Agent for MockAgent {
         &self,
         _args: acp::InitializeRequest,
     ) -> acp::Result<acp::InitializeResponse> {
-        Ok(acp::InitializeResponse::new(acp::ProtocolVersion::V1))
+        Ok(acp::InitializeResponse::new(acp::ProtocolVersion::V1)
+            .agent_capabilities(acp::AgentCapabilities::new().load_session(true)))
     }
 
     async fn authenticate(
@@ -27,15 +38,47 @@ impl acp::Agent for MockAgent {
         &self,
         _args: acp::NewSessionRequest,
     ) -> acp::Result<acp::NewSessionResponse> {
-        Ok(acp::NewSessionResponse::new(acp::SessionId::new(
-            "mock-session-1",
-        )))
+        let n = self.sessions.get() + 1;
+        self.sessions.set(n);
+        Ok(acp::NewSessionResponse::new(acp::SessionId::new(format!(
+            "mock-session-{}",
+            n
+        ))))
+    }
+
+    /// 只认自己发出的 id 形式，其它 id 报错（测试回放 fallback）
+    async fn load_session(
+        &self,
+        args: acp::LoadSessionRequest,
+    ) -> acp::Result<acp::LoadSessionResponse> {
+        if args.session_id.to_string().starts_with("mock-session-") {
+            Ok(acp::LoadSessionResponse::new())
+        } else {
+            Err(acp::Error::invalid_params())
+        }
     }
 
     async fn prompt(
         &self,
-        _args: acp::PromptRequest,
+        args: acp::PromptRequest,
     ) -> acp::Result<acp::PromptResponse> {
+        let tool = args.prompt.iter().find_map(|b| match b {
+            acp::ContentBlock::Text(t) => t.text.strip_prefix("permission:"),
+            _ => None,
+        });
+        if let (Some(tool), Some(conn)) = (tool, self.conn.get()) {
+            let fields = acp::ToolCallUpdateFields::new().title(tool.trim().to_string());
+            let options = vec![
+                acp::PermissionOption::new("allow", "Allow", acp::PermissionOptionKind::AllowOnce),
+                acp::PermissionOption::new("reject", "Reject", acp::PermissionOptionKind::RejectOnce),
+            ];
+            let req = acp::RequestPermissionRequest::new(
+                args.session_id.clone(),
+                acp::ToolCallUpdate::new("mock-tool", fields),
+                options,
+            );
+            conn.request_permission(req).await?;
+        }
         // Mock agent：直接返回 EndTurn（ACP 内容通过 notification 发送，非 response）
         Ok(acp::PromptResponse::new(acp::StopReason::EndTurn))
     }
@@ -56,14 +99,17 @@ fn main() {
         let stdin = tokio::io::stdin().compat();
         let stdout = tokio::io::stdout().compat_write();
 
-        let (_conn, io_task) = acp::AgentSideConnection::new(
-            MockAgent,
+        let agent = MockAgent::default();
+        let conn_slot = agent.conn.clone();
+        let (conn, io_task) = acp::AgentSideConnection::new(
+            agent,
             stdout,
             stdin,
             |fut| {
                 tokio::task::spawn_local(fut);
             },
         );
+        let _ = conn_slot.set(conn);
 
         tokio::task::spawn_local(async move {
             if let Err(e) = io_task.await {
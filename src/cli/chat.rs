@@ -0,0 +1,397 @@
+// ============================================================
+// chat - 交互式对话
+// ============================================================
+// `chat <name>` 在一个多路复用连接上先 Subscribe 再发请求：回复实时输出，
+// 等待审批的权限请求就地询问，`/cancel` `/mode` `/set` `/attach` 直接转为对应请求。
+// rustyline 的 readline 会阻塞，放在独立线程，由主循环按需要一行；turn 进行中不读输入，
+// Ctrl+C 取消当前 turn。连接断开（agent 被重建、daemon 重启）后在一段时间内自动重连。
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use rustyline::error::ReadlineError;
+use tokio::sync::mpsc;
+
+use crate::acp_client::team_client::awaiting_approval;
+use crate::config::TeamConfig;
+use crate::protocol::messages::{FileAttachment, OutputType, SessionEvent, SessionRequest, SessionResponse};
+
+use super::{client, display};
+
+/// 断线后重连的最长等待
+const RECONNECT_SECS: u64 = 30;
+/// 重连尝试间隔
+const RECONNECT_INTERVAL_MS: u64 = 500;
+
+const HELP: &str = "\
+/cancel             Cancel the running turn (Ctrl+C while a reply streams)
+/mode <mode>        Switch agent mode
+/set <key> <value>  Change runtime config
+/attach <file>      Attach a file to the next prompt
+/quit               Leave the chat (Ctrl+D); the agent keeps running
+//text              Send a prompt that starts with '/'";
+
+// ==================== 输入解析 ====================
+
+#[derive(Debug, PartialEq)]
+pub enum Input {
+    Prompt(String),
+    Cancel,
+    Mode(String),
+    Set(String, String),
+    Attach(PathBuf),
+    Help,
+    Quit,
+}
+
+/// 解析一行输入；空行返回 None，`/` 开头为元命令（`//` 转义为普通 prompt）
+pub fn parse_input(line: &str) -> Result<Option<Input>> {
+    let line = line.trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let Some(rest) = line.strip_prefix('/') else {
+        return Ok(Some(Input::Prompt(line.to_string())));
+    };
+    if rest.starts_with('/') {
+        return Ok(Some(Input::Prompt(rest.to_string())));
+    }
+    let (cmd, arg) = match rest.split_once(char::is_whitespace) {
+        Some((c, a)) => (c, a.trim()),
+        None => (rest, ""),
+    };
+    let input = match cmd {
+        "cancel" => Input::Cancel,
+        "mode" if !arg.is_empty() => Input::Mode(arg.to_string()),
+        "mode" => bail!("Usage: /mode <mode>"),
+        "set" => match arg.split_once(char::is_whitespace) {
+            Some((k, v)) => Input::Set(k.to_string(), v.trim().to_string()),
+            None => bail!("Usage: /set <key> <value>"),
+        },
+        "attach" if !arg.is_empty() => Input::Attach(PathBuf::from(arg)),
+        "attach" => bail!("Usage: /attach <file>"),
+        "help" | "?" => Input::Help,
+        "quit" | "exit" => Input::Quit,
+        _ => bail!("Unknown command '/{}' (try /help)", cmd),
+    };
+    Ok(Some(input))
+}
+
+// ==================== 行编辑线程 ====================
+
+enum Line {
+    Text(String),
+    Interrupted,
+    Eof,
+}
+
+/// rustyline 在独立线程中运行：主循环发出提示符，线程读一行送回
+struct LineReader {
+    ask: std::sync::mpsc::Sender<String>,
+    lines: mpsc::UnboundedReceiver<Line>,
+}
+
+impl LineReader {
+    fn spawn() -> Result<Self> {
+        let mut rl = rustyline::DefaultEditor::new().context("Cannot initialize line editor")?;
+        let history = history_path();
+        let _ = rl.load_history(&history);
+        let (ask, prompts) = std::sync::mpsc::channel::<String>();
+        let (line_tx, lines) = mpsc::unbounded_channel();
+        std::thread::spawn(move || {
+            for prompt in prompts {
+                let line = match rl.readline(&prompt) {
+                    Ok(text) => {
+                        if !text.trim().is_empty() && rl.add_history_entry(text.as_str()).unwrap_or(false) {
+                            if let Some(dir) = history.parent() {
+                                let _ = std::fs::create_dir_all(dir);
+                            }
+                            let _ = rl.save_history(&history);
+                        }
+                        Line::Text(text)
+                    }
+                    Err(ReadlineError::Interrupted) => Line::Interrupted,
+                    Err(_) => Line::Eof,
+                };
+                if line_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self { ask, lines })
+    }
+
+    async fn read(&mut self, prompt: &str) -> Line {
+        if self.ask.send(prompt.to_string()).is_err() {
+            return Line::Eof;
+        }
+        self.lines.recv().await.unwrap_or(Line::Eof)
+    }
+}
+
+fn history_path() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("agent-team")
+        .join("chat_history")
+}
+
+// ==================== 连接 ====================
+
+/// 订阅中的多路复用连接；断开后按同一 agent 名重连
+struct ChatConn {
+    mux: client::MuxClient,
+    events: mpsc::UnboundedReceiver<(String, SessionEvent)>,
+}
+
+impl ChatConn {
+    async fn open(config: &TeamConfig, name: &str) -> Result<Self> {
+        let mut mux = client::SessionClient::connect(config, name).await?.into_mux()?;
+        mux.subscribe().await?;
+        let events = mux.take_notifications().context("Event stream already taken")?;
+        Ok(Self { mux, events })
+    }
+
+    /// 在 RECONNECT_SECS 内反复尝试，agent 重新出现即恢复
+    async fn reopen(config: &TeamConfig, name: &str) -> Result<Self> {
+        println!("[reconnecting] Lost connection to {}", name);
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(RECONNECT_SECS);
+        loop {
+            match Self::open(config, name).await {
+                Ok(conn) => {
+                    println!("[reconnected] {}", name);
+                    return Ok(conn);
+                }
+                Err(e) if tokio::time::Instant::now() >= deadline => {
+                    return Err(e.context(format!("Agent '{}' did not come back", name)));
+                }
+                Err(_) => tokio::time::sleep(Duration::from_millis(RECONNECT_INTERVAL_MS)).await,
+            }
+        }
+    }
+}
+
+struct Chat<'a> {
+    config: &'a TeamConfig,
+    name: &'a str,
+    session: Option<&'a str>,
+    conn: ChatConn,
+    reader: LineReader,
+    attachments: Vec<FileAttachment>,
+    follow: display::FollowState,
+}
+
+enum TurnEnd {
+    Done,
+    /// 输入已关闭，离开 chat（turn 与待审批权限留给 agent）
+    Quit,
+}
+
+impl Chat<'_> {
+    /// 发送请求；连接已断开时重连后重试一次
+    async fn request(&mut self, req: SessionRequest) -> Result<SessionResponse> {
+        match self.conn.mux.request(req.clone()).await {
+            Ok(resp) => Ok(resp),
+            Err(_) => {
+                self.conn = ChatConn::reopen(self.config, self.name).await?;
+                self.conn.mux.request(req).await
+            }
+        }
+    }
+
+    fn prompt_string(&self) -> String {
+        let who = match self.session {
+            Some(s) => format!("{}/{}", self.name, s),
+            None => self.name.to_string(),
+        };
+        match self.attachments.len() {
+            0 => format!("{}> ", who),
+            n => format!("{} [+{} file(s)]> ", who, n),
+        }
+    }
+
+    async fn run(&mut self) -> Result<()> {
+        loop {
+            let prompt = self.prompt_string();
+            let line = match self.reader.read(&prompt).await {
+                Line::Text(text) => text,
+                Line::Interrupted => continue,
+                Line::Eof => return Ok(()),
+            };
+            let input = match parse_input(&line) {
+                Ok(Some(input)) => input,
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    continue;
+                }
+            };
+            match input {
+                Input::Prompt(text) => {
+                    if let TurnEnd::Quit = self.prompt(text).await? {
+                        return Ok(());
+                    }
+                }
+                Input::Cancel => {
+                    let resp = self.request(SessionRequest::Cancel.in_session(self.session)).await?;
+                    display::print_session_response(&resp);
+                }
+                Input::Mode(mode) => {
+                    let resp = self.request(SessionRequest::SetMode { mode }).await?;
+                    display::print_session_response(&resp);
+                }
+                Input::Set(key, value) => {
+                    let resp = self.request(SessionRequest::SetConfig { key, value }).await?;
+                    display::print_session_response(&resp);
+                }
+                Input::Attach(path) => match tokio::fs::read_to_string(&path).await {
+                    Ok(content) => {
+                        println!("Attached {} ({} bytes) to the next prompt", path.display(), content.len());
+                        self.attachments.push(FileAttachment { path, content });
+                    }
+                    Err(e) => eprintln!("Cannot read {}: {}", path.display(), e),
+                },
+                Input::Help => println!("{}", HELP),
+                Input::Quit => return Ok(()),
+            }
+        }
+    }
+
+    async fn prompt(&mut self, text: String) -> Result<TurnEnd> {
+        // 丢弃上一 turn 之后积压的事件（idle 之后的通知等）
+        while self.conn.events.try_recv().is_ok() {}
+        let files = std::mem::take(&mut self.attachments);
+        let req = SessionRequest::Prompt { text, files, chain: vec![] }.in_session(self.session);
+        match self.request(req).await? {
+            SessionResponse::Ok { .. } => self.stream_turn().await,
+            resp => {
+                display::print_session_response(&resp);
+                Ok(TurnEnd::Done)
+            }
+        }
+    }
+
+    /// 实时输出直到 turn 结束；权限请求就地询问，Ctrl+C 发送 Cancel
+    async fn stream_turn(&mut self) -> Result<TurnEnd> {
+        loop {
+            let event = tokio::select! {
+                ev = self.conn.events.recv() => ev,
+                _ = tokio::signal::ctrl_c() => {
+                    let resp = self.request(SessionRequest::Cancel.in_session(self.session)).await?;
+                    print!("{}", self.follow.end_line());
+                    display::print_session_response(&resp);
+                    continue;
+                }
+            };
+            let Some((_, event)) = event else {
+                print!("{}", self.follow.end_line());
+                self.conn = ChatConn::reopen(self.config, self.name).await?;
+                return Ok(TurnEnd::Done);
+            };
+            let (from, ends) = match &event {
+                SessionEvent::Output { session, .. } => (session.as_deref(), false),
+                SessionEvent::Info { session, tag, .. } => (
+                    session.as_deref(),
+                    matches!(tag.as_str(), "idle" | "error" | "restarted" | "exited" | "stopped"),
+                ),
+            };
+            if from != self.session {
+                continue;
+            }
+            if shown(&event) {
+                print!("{}", display::render_event(&mut self.follow, &event));
+                std::io::stdout().flush().ok();
+            }
+            if let SessionEvent::Output { entry, .. } = &event {
+                if let Some(tool) = awaiting_approval(&entry.content) {
+                    if let TurnEnd::Quit = self.ask_permission(tool).await? {
+                        return Ok(TurnEnd::Quit);
+                    }
+                }
+            }
+            if ends {
+                print!("{}", self.follow.end_line());
+                return Ok(TurnEnd::Done);
+            }
+        }
+    }
+
+    async fn ask_permission(&mut self, tool: &str) -> Result<TurnEnd> {
+        let answer = self.reader.read(&format!("Allow {}? [y/N] ", tool)).await;
+        let req = match answer {
+            Line::Text(a) if matches!(a.trim().to_ascii_lowercase().as_str(), "y" | "yes") => {
+                SessionRequest::ApprovePermission
+            }
+            Line::Text(_) => SessionRequest::DenyPermission,
+            Line::Interrupted => SessionRequest::Cancel,
+            Line::Eof => {
+                println!("\nLeft pending; answer later with `agent-team allow/deny {}`", self.name);
+                return Ok(TurnEnd::Quit);
+            }
+        };
+        let resp = self.request(req.in_session(self.session)).await?;
+        display::print_session_response(&resp);
+        Ok(TurnEnd::Done)
+    }
+}
+
+/// chat 中不显示的事件：自己的 prompt 回显、请求日志、状态切换与通知
+fn shown(event: &SessionEvent) -> bool {
+    match event {
+        SessionEvent::Output { entry, .. } => !matches!(entry.update_type, OutputType::UserPrompt),
+        SessionEvent::Info { tag, .. } => !matches!(tag.as_str(), "request" | "running" | "idle" | "notify"),
+    }
+}
+
+/// `chat <name>` 入口
+pub async fn run(config: &TeamConfig, name: &str, session: Option<&str>) -> Result<()> {
+    let conn = ChatConn::open(config, name).await?;
+    let resp = conn.mux.request(SessionRequest::GetStatus.in_session(session)).await?;
+    match &resp {
+        SessionResponse::Status { summary } => println!(
+            "Chatting with {} ({}, {}). /help for commands, Ctrl+D to leave.",
+            name, summary.agent_type, summary.status,
+        ),
+        SessionResponse::Error { message } => bail!("{}", message),
+        _ => bail!("Unexpected response from {}", name),
+    }
+    let mut chat = Chat {
+        config,
+        name,
+        session,
+        conn,
+        reader: LineReader::spawn()?,
+        attachments: vec![],
+        follow: display::FollowState::default(),
+    };
+    chat.run().await
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_prompts_and_commands() {
+        let parse = |s| parse_input(s).unwrap();
+        assert_eq!(parse("  "), None);
+        assert_eq!(parse(" fix the bug "), Some(Input::Prompt("fix the bug".into())));
+        assert_eq!(parse("//etc/hosts"), Some(Input::Prompt("/etc/hosts".into())));
+        assert_eq!(parse("/cancel"), Some(Input::Cancel));
+        assert_eq!(parse("/mode architect"), Some(Input::Mode("architect".into())));
+        assert_eq!(parse("/set model  gpt 5"), Some(Input::Set("model".into(), "gpt 5".into())));
+        assert_eq!(parse("/attach src/a.rs"), Some(Input::Attach("src/a.rs".into())));
+        assert_eq!(parse("/exit"), Some(Input::Quit));
+    }
+
+    #[test]
+    fn parse_rejects_bad_commands() {
+        for line in ["/mode", "/set model", "/attach", "/fly"] {
+            assert!(parse_input(line).is_err(), "{}", line);
+        }
+    }
+}
@@ -1,79 +1,341 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::path::Path;
+use std::rc::Rc;
+
 use anyhow::{Context, Result};
+use tokio::sync::{mpsc, oneshot};
 
 use crate::config::TeamConfig;
-use crate::protocol::messages::{SessionRequest, SessionResponse};
-use crate::protocol::transport::{JsonLineReader, JsonLineWriter};
+use crate::protocol::messages::{
+    DaemonRequest, Envelope, SessionEvent, SessionRequest, SessionResponse, PROTOCOL_VERSION,
+    VERSION,
+};
+use crate::protocol::remote::{self, BoxStream, RemoteReader, RemoteWriter};
+use crate::protocol::transport::{Framing, JsonLineReader, JsonLineWriter};
 
-// ==================== 平台类型别名 ====================
+// ==================== SessionClient ====================
 
-#[cfg(unix)]
-type ReadHalf = tokio::net::unix::OwnedReadHalf;
-#[cfg(unix)]
-type WriteHalf = tokio::net::unix::OwnedWriteHalf;
+/// 复用连接的 session 客户端（本地 socket 或远程 TCP / TLS）
+pub struct SessionClient {
+    reader: RemoteReader,
+    writer: RemoteWriter,
+    /// 经 daemon / 远程端点转发时的目标 agent 名（请求包装为 DaemonRequest::Session）
+    via_daemon: Option<String>,
+    name: String,
+    peer: PeerInfo,
+}
 
-#[cfg(not(unix))]
-type ReadHalf = tokio::net::tcp::OwnedReadHalf;
-#[cfg(not(unix))]
-type WriteHalf = tokio::net::tcp::OwnedWriteHalf;
+/// Hello 握手得到的对端信息
+#[derive(Debug, Clone, Default)]
+pub struct PeerInfo {
+    /// 0 = 不认识 Hello 的旧版本 session
+    pub protocol: u32,
+    pub version: Option<String>,
+    /// None = 未知（旧版本），不做能力检查
+    pub capabilities: Option<Vec<String>>,
+}
 
-// ==================== SessionClient ====================
+impl PeerInfo {
+    /// 对端是否支持某个请求类型 / 连接能力；未知时按支持处理
+    pub fn supports(&self, capability: &str) -> bool {
+        self.capabilities
+            .as_ref()
+            .is_none_or(|caps| caps.iter().any(|c| c == capability))
+    }
 
-/// 复用连接的 session 客户端
-pub struct SessionClient {
-    reader: JsonLineReader<ReadHalf>,
-    writer: JsonLineWriter<WriteHalf>,
+    fn check(&self, agent: &str, capability: &str) -> Result<()> {
+        if self.supports(capability) {
+            return Ok(());
+        }
+        anyhow::bail!(
+            "Agent '{}' runs agent-team {} (protocol {}), which does not support {}; \
+             re-create it (rm + add) to upgrade",
+            agent,
+            self.version.as_deref().unwrap_or("?"),
+            self.protocol,
+            capability,
+        )
+    }
 }
 
 impl SessionClient {
-    /// 连接到指定 agent 的 session
+    /// 连接到指定 agent 的 session 并握手；agent 由 daemon 托管时连接 daemon 控制 socket，
+    /// 配置了远程端点（--host）时连接远程
     pub async fn connect(config: &TeamConfig, name: &str) -> Result<Self> {
+        let mut client = Self::open(config, name).await?;
+        let hello = SessionRequest::Hello {
+            protocol: PROTOCOL_VERSION,
+            version: VERSION.to_string(),
+        };
+        match client.send(hello).await {
+            Ok(SessionResponse::Hello { protocol, version, capabilities, max_frame }) => {
+                client.peer = PeerInfo {
+                    protocol,
+                    version: Some(version),
+                    capabilities: Some(capabilities),
+                };
+                client.writer.set_max_frame((max_frame > 0).then_some(max_frame));
+                if config.transport.length_prefix && client.peer.supports("length_prefix") {
+                    client.writer.set_framing(Framing::LengthPrefixed);
+                }
+                Ok(client)
+            }
+            // 旧版本 session 无法解析 Hello 会直接断开：重连，按旧协议通信
+            _ => Self::open(config, name).await,
+        }
+    }
+
+    async fn open(config: &TeamConfig, name: &str) -> Result<Self> {
+        if config.remote.host.is_some() {
+            let (mut reader, writer) = remote::connect(&config.remote).await?;
+            reader.set_max_frame(config.transport.max_frame);
+            return Ok(Self {
+                reader,
+                writer,
+                via_daemon: Some(name.to_string()),
+                name: name.to_string(),
+                peer: PeerInfo::default(),
+            });
+        }
+
         let sock_path = config.session_socket(name);
+        let marker = config.daemon_marker(name);
+        let via_daemon = !sock_path.exists() && marker.exists();
+        let (path, stale) = if via_daemon {
+            (config.daemon_socket(), marker)
+        } else {
+            (sock_path.clone(), sock_path)
+        };
 
-        #[cfg(unix)]
-        let stream = match tokio::net::UnixStream::connect(&sock_path).await {
+        let stream = match connect_stream(&path).await {
             Ok(s) => s,
             Err(e) => {
-                let _ = std::fs::remove_file(&sock_path);
+                let _ = std::fs::remove_file(&stale);
                 return Err(e).with_context(|| {
                     format!("Cannot connect to agent '{}'. Is it running?", name)
                 });
             }
         };
 
-        #[cfg(not(unix))]
-        let stream = {
-            let port_str = std::fs::read_to_string(&sock_path)
-                .with_context(|| format!("Cannot read port file for '{}'", name))?;
-            let port: u16 = port_str.trim().parse()
-                .with_context(|| format!("Invalid port in {}", sock_path.display()))?;
-            match tokio::net::TcpStream::connect(("127.0.0.1", port)).await {
-                Ok(s) => s,
-                Err(e) => {
-                    let _ = std::fs::remove_file(&sock_path);
-                    return Err(e).with_context(|| {
-                        format!("Cannot connect to agent '{}'. Is it running?", name)
-                    });
-                }
-            }
-        };
+        let mut client = Self::from_stream(stream, via_daemon.then(|| name.to_string()));
+        client.name = name.to_string();
+        client.reader.set_max_frame(config.transport.max_frame);
+        Ok(client)
+    }
 
-        let (read, write) = stream.into_split();
-        Ok(Self {
+    fn from_stream(stream: BoxStream, via_daemon: Option<String>) -> Self {
+        let (read, write) = tokio::io::split(stream);
+        Self {
             reader: JsonLineReader::new(read),
             writer: JsonLineWriter::new(write),
-        })
+            name: via_daemon.clone().unwrap_or_default(),
+            via_daemon,
+            peer: PeerInfo::default(),
+        }
+    }
+
+    /// 对端版本信息（握手结果）
+    pub fn peer(&self) -> &PeerInfo {
+        &self.peer
     }
 
-    /// 发送请求并读取响应
+    /// 发送请求并读取响应；对端不支持的请求在本地直接报错
     pub async fn send(&mut self, req: SessionRequest) -> Result<SessionResponse> {
-        self.writer.write(&req).await?;
-        self.reader
-            .read()
-            .await?
-            .context("Session closed connection unexpectedly")
+        let label = req.label().to_string();
+        self.peer.check(&self.name, &label)?;
+
+        let result = self.exchange(req).await;
+        // 旧版本 session 遇到不认识的请求会断开连接
+        if self.peer.protocol == 0 && label != "Hello" && !matches!(result, Ok(Some(_))) {
+            anyhow::bail!(
+                "Agent '{}' closed the connection; it may run an older agent-team without {} \
+                 support — re-create it (rm + add) to upgrade",
+                self.name,
+                label,
+            );
+        }
+        result?.context("Session closed connection unexpectedly")
+    }
+
+    async fn exchange(&mut self, req: SessionRequest) -> Result<Option<SessionResponse>> {
+        match &self.via_daemon {
+            Some(name) => {
+                self.writer
+                    .write(&DaemonRequest::Session {
+                        name: name.clone(),
+                        request: req,
+                    })
+                    .await?
+            }
+            None => self.writer.write(&req).await?,
+        }
+        self.reader.read().await
+    }
+
+    /// 切换为订阅模式；之后只能用 next_event 读取事件
+    pub async fn subscribe(&mut self) -> Result<()> {
+        match self.send(SessionRequest::Subscribe).await? {
+            SessionResponse::Ok { .. } => Ok(()),
+            SessionResponse::Error { message } => anyhow::bail!(message),
+            _ => anyhow::bail!("Unexpected response to Subscribe"),
+        }
+    }
+
+    /// 读取下一个订阅事件；None = session 已关闭
+    pub async fn next_event(&mut self) -> Result<Option<(String, SessionEvent)>> {
+        match self.reader.read::<SessionResponse>().await? {
+            Some(SessionResponse::Event { agent_name, event }) => Ok(Some((agent_name, event))),
+            Some(SessionResponse::Error { message }) => anyhow::bail!(message),
+            Some(_) => anyhow::bail!("Unexpected message on event stream"),
+            None => Ok(None),
+        }
+    }
+
+    /// 切换为多路复用连接（需要对端支持 multiplex，须在 LocalSet 中调用）
+    pub fn into_mux(self) -> Result<MuxClient> {
+        if self.peer.capabilities.is_none() {
+            anyhow::bail!(
+                "Agent '{}' runs an older agent-team without multiplexing; re-create it (rm + add) to upgrade",
+                self.name,
+            );
+        }
+        self.peer.check(&self.name, "multiplex")?;
+
+        let pending: Pending = Rc::default();
+        let (notify_tx, notify_rx) = mpsc::unbounded_channel();
+        let reader_task = tokio::task::spawn_local(route_responses(
+            self.reader,
+            Rc::clone(&pending),
+            notify_tx,
+        ));
+        Ok(MuxClient {
+            writer: tokio::sync::Mutex::new(self.writer),
+            via_daemon: self.via_daemon,
+            name: self.name,
+            peer: self.peer,
+            next_id: Cell::new(1),
+            pending,
+            notifications: Some(notify_rx),
+            reader_task,
+        })
+    }
+}
+
+// ==================== 多路复用 ====================
+
+type Pending = Rc<RefCell<HashMap<u64, oneshot::Sender<SessionResponse>>>>;
+
+/// 多路复用连接：请求带 id 并发发出、乱序完成；订阅事件作为推送通知经 take_notifications 读取
+pub struct MuxClient {
+    writer: tokio::sync::Mutex<RemoteWriter>,
+    via_daemon: Option<String>,
+    name: String,
+    peer: PeerInfo,
+    next_id: Cell<u64>,
+    pending: Pending,
+    notifications: Option<mpsc::UnboundedReceiver<(String, SessionEvent)>>,
+    reader_task: tokio::task::JoinHandle<()>,
+}
+
+impl MuxClient {
+    /// 发送请求并等待带同一 id 的响应；可在多个 task 中并发调用
+    pub async fn request(&self, req: SessionRequest) -> Result<SessionResponse> {
+        self.peer.check(&self.name, req.label())?;
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let (tx, rx) = oneshot::channel();
+        self.pending.borrow_mut().insert(id, tx);
+
+        let sent = {
+            let mut writer = self.writer.lock().await;
+            match &self.via_daemon {
+                Some(name) => {
+                    let body = DaemonRequest::Session {
+                        name: name.clone(),
+                        request: req,
+                    };
+                    writer.write(&Envelope { id, body }).await
+                }
+                None => writer.write(&Envelope { id, body: req }).await,
+            }
+        };
+        if let Err(e) = sent {
+            self.pending.borrow_mut().remove(&id);
+            return Err(e);
+        }
+        rx.await.context("Session closed connection unexpectedly")
+    }
+
+    /// 订阅本 agent 的事件（之后经 take_notifications 读取）
+    pub async fn subscribe(&self) -> Result<()> {
+        match self.request(SessionRequest::Subscribe).await? {
+            SessionResponse::Ok { .. } => Ok(()),
+            SessionResponse::Error { message } => anyhow::bail!(message),
+            _ => anyhow::bail!("Unexpected response to Subscribe"),
+        }
+    }
+
+    /// 推送通知 (agent 名, 事件)；只能取一次，连接关闭后返回 None
+    pub fn take_notifications(&mut self) -> Option<mpsc::UnboundedReceiver<(String, SessionEvent)>> {
+        self.notifications.take()
+    }
+}
+
+impl Drop for MuxClient {
+    fn drop(&mut self) {
+        self.reader_task.abort();
     }
 }
 
+/// 读端：带 id 的响应交给等待者，不带 id 的事件作为通知转发；断开时丢弃所有等待者。
+/// 服务端对坏帧的 Error 取不到 id，无法对应到请求，只能让全部等待中的请求失败，避免永远挂起
+async fn route_responses(
+    mut reader: RemoteReader,
+    pending: Pending,
+    notify_tx: mpsc::UnboundedSender<(String, SessionEvent)>,
+) {
+    while let Ok(Some(value)) = reader.read::<serde_json::Value>().await {
+        if value.get("id").is_some() {
+            if let Ok(Envelope { id, body }) = serde_json::from_value::<Envelope<SessionResponse>>(value) {
+                if let Some(tx) = pending.borrow_mut().remove(&id) {
+                    let _ = tx.send(body);
+                }
+            }
+        } else {
+            match serde_json::from_value(value) {
+                Ok(SessionResponse::Event { agent_name, event }) => {
+                    let _ = notify_tx.send((agent_name, event));
+                }
+                Ok(SessionResponse::Error { message }) => {
+                    for (_, tx) in pending.borrow_mut().drain() {
+                        let _ = tx.send(SessionResponse::Error { message: message.clone() });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    pending.borrow_mut().clear();
+}
+
+/// 连接本地 socket（Windows 为端口文件指向的本地 TCP 端口）
+async fn connect_stream(path: &Path) -> Result<BoxStream> {
+    #[cfg(unix)]
+    let stream = tokio::net::UnixStream::connect(path).await?;
+
+    #[cfg(not(unix))]
+    let stream = {
+        let port_str = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read port file {}", path.display()))?;
+        let port: u16 = port_str.trim().parse()
+            .with_context(|| format!("Invalid port in {}", path.display()))?;
+        tokio::net::TcpStream::connect(("127.0.0.1", port)).await?
+    };
+
+    Ok(Box::new(stream))
+}
+
 // ==================== 便捷函数 ====================
 
 /// 单次 connect + send + drop
@@ -86,6 +348,45 @@ pub async fn send(
     client.send(req).await
 }
 
+/// daemon 是否在运行（控制 socket 可连接）；远程模式下由远程端点处理
+pub async fn daemon_running(config: &TeamConfig) -> bool {
+    if config.remote.host.is_some() {
+        return true;
+    }
+    let path = config.daemon_socket();
+    path.exists() && connect_stream(&path).await.is_ok()
+}
+
+/// 向 daemon（或远程端点）发送一次控制请求
+pub async fn daemon_send(config: &TeamConfig, req: DaemonRequest) -> Result<SessionResponse> {
+    let (mut reader, mut writer) = if config.remote.host.is_some() {
+        remote::connect(&config.remote).await?
+    } else {
+        let stream = connect_stream(&config.daemon_socket())
+            .await
+            .context("Cannot connect to daemon. Is it running?")?;
+        let (read, write) = tokio::io::split(stream);
+        (JsonLineReader::new(read), JsonLineWriter::new(write))
+    };
+    writer.write(&req).await?;
+    reader
+        .read()
+        .await?
+        .context("Daemon closed connection unexpectedly")
+}
+
+/// 所有可访问的 agent 名：远程模式向远程端点 List，否则扫描本地 socket 目录
+pub async fn session_names(config: &TeamConfig) -> Result<Vec<String>> {
+    if config.remote.host.is_none() {
+        return Ok(config.scan_sessions());
+    }
+    match daemon_send(config, DaemonRequest::List).await? {
+        SessionResponse::Agents { agents } => Ok(agents.into_iter().map(|a| a.name).collect()),
+        SessionResponse::Error { message } => anyhow::bail!(message),
+        _ => anyhow::bail!("Unexpected response to List"),
+    }
+}
+
 // ==================== 测试 ====================
 
 #[cfg(test)]
@@ -122,6 +423,16 @@ mod tests {
             pending_permissions: 0,
             agent_info_name: None,
             agent_info_version: None,
+            ..Default::default()
+        }
+    }
+
+    fn hello_response(kinds: &[&str]) -> SessionResponse {
+        SessionResponse::Hello {
+            protocol: PROTOCOL_VERSION,
+            version: VERSION.into(),
+            capabilities: kinds.iter().map(|k| k.to_string()).collect(),
+            max_frame: 0,
         }
     }
 
@@ -132,17 +443,13 @@ mod tests {
         let listener = UnixListener::bind(&sock).unwrap();
 
         let resp = SessionResponse::Status {
-            summary: test_summary("a-1"),
+            summary: Box::new(test_summary("a-1")),
         };
         let server = tokio::spawn(mock_server(listener, vec![resp]));
 
         // 用底层构造（不走 config 路径）
         let stream = tokio::net::UnixStream::connect(&sock).await.unwrap();
-        let (read, write) = stream.into_split();
-        let mut client = SessionClient {
-            reader: JsonLineReader::new(read),
-            writer: JsonLineWriter::new(write),
-        };
+        let mut client = SessionClient::from_stream(Box::new(stream), None);
 
         let result = client.send(SessionRequest::GetStatus).await.unwrap();
         match result {
@@ -163,23 +470,20 @@ mod tests {
 
         let responses = vec![
             SessionResponse::Ok { message: "ok".into() },
-            SessionResponse::Status { summary: test_summary("b-1") },
+            SessionResponse::Status { summary: Box::new(test_summary("b-1")) },
             SessionResponse::Ok { message: "done".into() },
         ];
         let server = tokio::spawn(mock_server(listener, responses));
 
         let stream = tokio::net::UnixStream::connect(&sock).await.unwrap();
-        let (read, write) = stream.into_split();
-        let mut client = SessionClient {
-            reader: JsonLineReader::new(read),
-            writer: JsonLineWriter::new(write),
-        };
+        let mut client = SessionClient::from_stream(Box::new(stream), None);
 
         // 同一连接发 3 次
         let r1 = client
             .send(SessionRequest::Prompt {
                 text: "hi".into(),
                 files: vec![],
+                chain: vec![],
             })
             .await
             .unwrap();
@@ -193,4 +497,156 @@ mod tests {
 
         server.await.unwrap();
     }
+
+    #[tokio::test]
+    async fn client_routes_through_daemon_marker() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        std::fs::write(config.daemon_marker("coder"), "1").unwrap();
+        let listener = UnixListener::bind(config.daemon_socket()).unwrap();
+        assert!(daemon_running(&config).await);
+
+        let server = tokio::spawn(async move {
+            // daemon_running 的探测连接
+            drop(listener.accept().await.unwrap());
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, write) = stream.into_split();
+            let mut reader = JsonLineReader::new(read);
+            let mut writer = JsonLineWriter::new(write);
+            let hello: DaemonRequest = reader.read().await.unwrap().unwrap();
+            assert!(matches!(
+                hello,
+                DaemonRequest::Session { request: SessionRequest::Hello { .. }, .. }
+            ));
+            writer.write(&hello_response(SessionRequest::KINDS)).await.unwrap();
+            let req: DaemonRequest = reader.read().await.unwrap().unwrap();
+            let name = match req {
+                DaemonRequest::Session { name, request: SessionRequest::GetStatus } => name,
+                other => panic!("unexpected request: {:?}", other),
+            };
+            writer
+                .write(&SessionResponse::Status { summary: Box::new(test_summary(&name)) })
+                .await
+                .unwrap();
+        });
+
+        let resp = send(&config, "coder", SessionRequest::GetStatus).await.unwrap();
+        assert!(matches!(resp, SessionResponse::Status { summary } if summary.name == "coder"));
+        server.await.unwrap();
+
+        // daemon 已退出：标记视为过期并清理
+        std::fs::remove_file(config.daemon_socket()).unwrap();
+        assert!(!daemon_running(&config).await);
+        assert!(send(&config, "coder", SessionRequest::GetStatus).await.is_err());
+        assert!(!config.daemon_marker("coder").exists());
+    }
+
+    #[tokio::test]
+    async fn handshake_rejects_unsupported_requests_locally() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let listener = UnixListener::bind(config.session_socket("a-1")).unwrap();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (read, write) = stream.into_split();
+            let mut reader = JsonLineReader::new(read);
+            let mut writer = JsonLineWriter::new(write);
+            let _hello: SessionRequest = reader.read().await.unwrap().unwrap();
+            // 假装是不支持 Undo 的 session
+            writer.write(&hello_response(&["Hello", "GetStatus"])).await.unwrap();
+            let req: SessionRequest = reader.read().await.unwrap().unwrap();
+            assert!(matches!(req, SessionRequest::GetStatus));
+            writer
+                .write(&SessionResponse::Status { summary: Box::new(test_summary("a-1")) })
+                .await
+                .unwrap();
+        });
+
+        let mut client = SessionClient::connect(&config, "a-1").await.unwrap();
+        assert_eq!(client.peer().protocol, PROTOCOL_VERSION);
+        let err = client.send(SessionRequest::Undo { turn: None }).await.unwrap_err();
+        assert!(err.to_string().contains("does not support Undo"), "{}", err);
+        // 被拒绝的请求没有发出，连接仍可用
+        let resp = client.send(SessionRequest::GetStatus).await.unwrap();
+        assert!(matches!(resp, SessionResponse::Status { .. }));
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn handshake_falls_back_for_old_sessions() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let listener = UnixListener::bind(config.session_socket("old")).unwrap();
+        let server = tokio::spawn(async move {
+            // 旧版本：不认识 Hello，读失败后断开
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = JsonLineReader::new(stream);
+            assert!(reader.read::<serde_json::Value>().await.unwrap().is_some());
+            drop(reader);
+            mock_server(listener, vec![SessionResponse::Ok { message: "ok".into() }]).await;
+        });
+
+        let mut client = SessionClient::connect(&config, "old").await.unwrap();
+        assert_eq!(client.peer().protocol, 0);
+        assert!(client.peer().capabilities.is_none());
+        let resp = client.send(SessionRequest::Cancel).await.unwrap();
+        assert!(matches!(resp, SessionResponse::Ok { .. }));
+        // 旧 session 对后续请求断开时给出升级提示
+        let err = client.send(SessionRequest::GetStatus).await.unwrap_err();
+        assert!(err.to_string().contains("older agent-team"), "{}", err);
+        server.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn mux_request_fails_on_bad_frame_error() {
+        use crate::session::conn::{serve, Followup};
+        use futures::FutureExt;
+
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let dir = tempfile::tempdir().unwrap();
+                let sock = dir.path().join("mux.sock");
+                let listener = UnixListener::bind(&sock).unwrap();
+                // 真实连接层，单帧上限 256 字节
+                tokio::task::spawn_local(async move {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let (read, write) = stream.into_split();
+                    let mut reader = JsonLineReader::new(read);
+                    reader.set_max_frame(256);
+                    let ok = |_: SessionRequest| {
+                        async { (SessionResponse::Ok { message: "ok".into() }, Followup::None) }.boxed_local()
+                    };
+                    let _ = serve(reader, JsonLineWriter::new(write), ok).await;
+                });
+
+                let stream = tokio::net::UnixStream::connect(&sock).await.unwrap();
+                let mut client = SessionClient::from_stream(Box::new(stream), None);
+                client.peer.capabilities =
+                    Some(["multiplex", "Prompt", "GetStatus"].iter().map(|s| s.to_string()).collect());
+                let mux = client.into_mux().unwrap();
+
+                // 超出上限的帧：服务端回复不带 id 的 Error，请求应失败而不是挂起
+                let big = SessionRequest::Prompt { text: "x".repeat(1024), files: vec![], chain: vec![] };
+                let resp = tokio::time::timeout(std::time::Duration::from_secs(5), mux.request(big))
+                    .await
+                    .expect("request hung")
+                    .unwrap();
+                assert!(matches!(resp, SessionResponse::Error { ref message } if message.contains("exceeds")));
+
+                // 连接继续可用
+                let resp = mux.request(SessionRequest::GetStatus).await.unwrap();
+                assert!(matches!(resp, SessionResponse::Ok { .. }));
+            })
+            .await;
+    }
 }
@@ -1,13 +1,68 @@
-use clap::{Parser, Subcommand};
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
+use crate::config::AutoApprovePolicy;
+
 #[derive(Parser)]
 #[command(name = "agent-team", about = "Multi-agent orchestrator via ACP")]
 pub struct Cli {
+    /// Drive agents on a remote session / daemon: host:port or tls://host:port
+    #[arg(long, global = true, env = "AGENT_TEAM_HOST")]
+    pub host: Option<String>,
+
     #[command(subcommand)]
     pub command: Command,
 }
 
+/// `add` / `daemon` 的远程监听参数
+#[derive(Args, Clone, Debug, Default)]
+pub struct ListenArgs {
+    /// Also accept token-authenticated TCP connections on ADDR (e.g. 0.0.0.0:7070).
+    /// Non-loopback addresses need --tls-cert/--tls-key or --allow-plaintext
+    #[arg(long, value_name = "ADDR")]
+    pub listen: Option<String>,
+
+    /// TLS certificate chain (PEM) for --listen
+    #[arg(long, requires = "listen", requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// TLS private key (PEM) for --listen
+    #[arg(long, requires = "listen", requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Allow plain TCP on a non-loopback --listen address (trusted networks only)
+    #[arg(long, requires = "listen", conflicts_with = "tls_cert")]
+    pub allow_plaintext: bool,
+}
+
+impl ListenArgs {
+    /// 写入 RemoteConfig（前台 session / daemon）
+    pub fn apply(&self, remote: &mut crate::config::RemoteConfig) {
+        remote.listen.clone_from(&self.listen);
+        remote.tls_cert.clone_from(&self.tls_cert);
+        remote.tls_key.clone_from(&self.tls_key);
+        remote.allow_plaintext = self.allow_plaintext;
+    }
+
+    /// 还原为命令行参数（后台 re-exec）
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if let Some(ref l) = self.listen {
+            args.push(format!("--listen={}", l));
+        }
+        if let Some(ref c) = self.tls_cert {
+            args.push(format!("--tls-cert={}", c.display()));
+        }
+        if let Some(ref k) = self.tls_key {
+            args.push(format!("--tls-key={}", k.display()));
+        }
+        if self.allow_plaintext {
+            args.push("--allow-plaintext".into());
+        }
+        args
+    }
+}
+
 #[derive(Subcommand)]
 pub enum Command {
     /// Start a new agent session
@@ -32,6 +87,63 @@ pub enum Command {
         /// Run in background (detach from terminal)
         #[arg(long, short = 'b')]
         background: bool,
+
+        /// Permission policy: always, never, read_only (default: never)
+        #[arg(long)]
+        auto_approve: Option<AutoApprovePolicy>,
+
+        /// Don't expose the agent-team MCP server (agent-to-agent delegation)
+        #[arg(long)]
+        no_mcp: bool,
+
+        /// Run in a dedicated git worktree on BRANCH (default: agent-team/<name>)
+        #[arg(long, num_args = 0..=1, default_missing_value = "", value_name = "BRANCH")]
+        worktree: Option<String>,
+
+        #[command(flatten)]
+        listen: ListenArgs,
+    },
+
+    /// Run a daemon hosting all agents started afterwards (`add -b`, `up`)
+    Daemon {
+        /// Run in background (detach from terminal)
+        #[arg(long, short = 'b')]
+        background: bool,
+
+        /// Stop the running daemon and every agent it hosts
+        #[arg(long, conflicts_with = "background")]
+        stop: bool,
+
+        #[command(flatten)]
+        listen: ListenArgs,
+    },
+
+    /// Serve a REST + Server-Sent Events gateway over HTTP (token-authenticated)
+    Serve {
+        /// Address to listen on, e.g. 127.0.0.1:8080
+        #[arg(long, value_name = "ADDR")]
+        http: String,
+    },
+
+    /// Print the token remote clients authenticate with (created on first use)
+    Token {
+        /// Replace the token; running listeners keep the old one until restarted
+        #[arg(long)]
+        rotate: bool,
+    },
+
+    /// Start every agent in a team manifest (skips running ones)
+    Up {
+        /// Manifest path
+        #[arg(default_value = "team.toml")]
+        manifest: PathBuf,
+    },
+
+    /// Shut down every agent in a team manifest
+    Down {
+        /// Manifest path
+        #[arg(default_value = "team.toml")]
+        manifest: PathBuf,
     },
 
     /// Shut down an agent
@@ -40,17 +152,57 @@ pub enum Command {
         name: Option<String>,
 
         /// Shut down all agents
-        #[arg(long)]
+        #[arg(long, conflicts_with = "session")]
         all: bool,
+
+        /// Close only this named ACP session; the agent keeps running
+        #[arg(long)]
+        session: Option<String>,
+    },
+
+    /// Open another named ACP session inside a running agent process
+    New {
+        /// Agent name
+        name: String,
+
+        /// Session name, used with `--session` on ask / log / cancel / allow / deny / rm
+        #[arg(long)]
+        session: String,
+    },
+
+    /// Start a new agent of the same type and cwd that continues another agent's conversation
+    Fork {
+        /// Source agent
+        name: String,
+
+        /// Name for the new agent (default: next free <type>-N)
+        #[arg(long = "as", value_name = "NEW_NAME")]
+        new_name: Option<String>,
+    },
+
+    /// Shut down an agent and merge its worktree branch into the main checkout
+    Merge {
+        /// Agent name
+        name: String,
+    },
+
+    /// Shut down an agent and delete its worktree and branch without merging
+    Discard {
+        /// Agent name
+        name: String,
     },
 
     /// List running agents
     Ls,
 
-    /// Send a prompt to an agent (reads stdin if text omitted)
+    /// Full-screen live dashboard of all agents (select, view logs, prompt, approve, cancel, restart)
+    Top,
+
+    /// Send a prompt to one or more agents (reads stdin if text omitted)
     Ask {
-        /// Agent name
-        name: String,
+        /// Agent name, comma-separated names or glob (e.g. 'gemini-*').
+        /// With --all, this is the prompt text
+        name: Option<String>,
 
         /// Prompt text (omit to read from stdin)
         text: Option<String>,
@@ -58,6 +210,79 @@ pub enum Command {
         /// Attach file content
         #[arg(long, short = 'f')]
         file: Vec<PathBuf>,
+
+        /// Send to all running agents
+        #[arg(long, conflicts_with = "session")]
+        all: bool,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
+    },
+
+    /// Open an interactive chat with an agent: live replies, inline permission prompts, /commands
+    Chat {
+        /// Agent name
+        name: String,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
+    },
+
+    /// Hand a task over to another agent with a structured summary of the source session
+    Handoff {
+        /// Source agent
+        from: String,
+
+        /// Target agent
+        to: String,
+
+        /// What the target should do next (default: continue the work)
+        task: Option<String>,
+
+        /// Prompt template file ({{from}}, {{type}}, {{conversation}}, {{plan}}, {{files}}, {{task}})
+        #[arg(long)]
+        template: Option<PathBuf>,
+
+        /// Approximate token budget for the handoff prompt (~4 characters per token)
+        #[arg(long, default_value = "8000")]
+        budget: usize,
+
+        /// Print the handoff prompt instead of sending it
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Chain agents: each step's final message feeds the next step's prompt
+    Pipe {
+        /// Pipeline input (omit to read from stdin; ignored with --replay)
+        input: Option<String>,
+
+        /// Step as `agent` or `agent:template`
+        /// ({{input}} = previous output, {{prompt}} = pipeline input)
+        #[arg(long, short = 's')]
+        step: Vec<String>,
+
+        /// Load steps from a JSON pipeline file
+        #[arg(long)]
+        file: Option<PathBuf>,
+
+        /// Default per-step timeout in seconds
+        #[arg(long)]
+        timeout: Option<u64>,
+
+        /// Keep going after a failed step
+        #[arg(long)]
+        continue_on_error: bool,
+
+        /// Where to write the run record (default: <socket dir>/pipes/)
+        #[arg(long)]
+        record: Option<PathBuf>,
+
+        /// Re-run a recorded pipeline with its original input
+        #[arg(long, conflicts_with_all = ["step", "file"])]
+        replay: Option<PathBuf>,
     },
 
     /// View agent output history
@@ -72,24 +297,141 @@ pub enum Command {
         /// Show only agent messages (exclude user prompts)
         #[arg(long, short = 'a')]
         agent_only: bool,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Keep streaming live output after the history; rings the bell on notifications
+        #[arg(long, short = 'f')]
+        follow: bool,
+    },
+
+    /// Export an agent's transcript (prompts, messages, tools, plans, permissions, diffs)
+    Export {
+        /// Agent name
+        name: String,
+
+        /// Output format
+        #[arg(long, short = 'f', value_parser = ["md", "html", "json"], default_value = "md")]
+        format: String,
+
+        /// Only export these turns: 3, 2..5, 4.. or ..2 (1-based, inclusive)
+        #[arg(long)]
+        turns: Option<super::export::TurnRange>,
+
+        /// Write to a file instead of stdout
+        #[arg(long, short = 'o')]
+        output: Option<PathBuf>,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
+    },
+
+    /// Show an agent's current plan (entries, priority, status)
+    Plan {
+        /// Agent name
+        name: String,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
+    },
+
+    /// Show an agent's token usage, or cap its tokens / cost across all its sessions
+    Budget {
+        /// Agent name
+        name: String,
+
+        /// Token cap: new prompts are refused and a running turn is cancelled once reached
+        #[arg(long)]
+        tokens: Option<u64>,
+
+        /// Cost cap, in the currency the agent reports
+        #[arg(long)]
+        cost: Option<f64>,
+
+        /// Remove existing caps first
+        #[arg(long)]
+        clear: bool,
+    },
+
+    /// Turn an agent's notifications (pending permissions, finished turns) on or off
+    Notify {
+        /// Agent name
+        name: String,
+
+        /// on | off (omit to show the current setting)
+        #[arg(value_parser = ["on", "off"])]
+        state: Option<String>,
+    },
+
+    /// Show files an agent changed, per turn
+    Changes {
+        /// Agent name
+        name: String,
+
+        /// Turn number (default: list all turns)
+        turn: Option<u64>,
+
+        /// Include unified diffs
+        #[arg(long, short = 'd')]
+        diff: bool,
+    },
+
+    /// Revert the files an agent changed in a turn (default: latest turn with changes)
+    Undo {
+        /// Agent name
+        name: String,
+
+        /// Turn number
+        turn: Option<u64>,
     },
 
     /// Cancel current task
     Cancel {
         /// Agent name
         name: String,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
+    },
+
+    /// List pending permissions across all agents and approve / deny them one by one
+    Inbox {
+        /// Approve every pending permission whose tool or kind matches this glob, without prompting
+        #[arg(long, value_name = "PATTERN")]
+        approve_matching: Option<String>,
+
+        /// Deny every pending permission whose tool or kind matches this glob, without prompting
+        #[arg(long, value_name = "PATTERN", conflicts_with = "approve_matching")]
+        deny_matching: Option<String>,
+
+        /// Only list, do not prompt
+        #[arg(long)]
+        list: bool,
     },
 
     /// Allow pending permission
     Allow {
         /// Agent name
         name: String,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
     },
 
     /// Deny pending permission
     Deny {
         /// Agent name
         name: String,
+
+        /// Target a named ACP session (see `new`) instead of the default one
+        #[arg(long)]
+        session: Option<String>,
     },
 
     /// Show agent details
@@ -127,6 +469,22 @@ pub enum Command {
 
     /// Update agent-team to latest version
     Update,
+
+    /// Serve agent-team tools over MCP (stdio); started by sessions for their agent
+    #[command(hide = true)]
+    Mcp {
+        /// Agent that owns this server
+        #[arg(long)]
+        agent: String,
+
+        /// Named session of the agent that owns this server
+        #[arg(long)]
+        session: Option<String>,
+
+        /// Maximum delegation chain depth
+        #[arg(long, default_value = "3")]
+        max_depth: usize,
+    },
 }
 
 // ==================== 测试 ====================
@@ -140,7 +498,7 @@ mod tests {
     fn rm_with_name() {
         let cli = Cli::parse_from(["agent-team", "rm", "foo"]);
         match cli.command {
-            Command::Rm { name, all } => {
+            Command::Rm { name, all, .. } => {
                 assert_eq!(name.as_deref(), Some("foo"));
                 assert!(!all);
             }
@@ -152,7 +510,7 @@ mod tests {
     fn rm_all_without_name() {
         let cli = Cli::parse_from(["agent-team", "rm", "--all"]);
         match cli.command {
-            Command::Rm { name, all } => {
+            Command::Rm { name, all, .. } => {
                 assert!(name.is_none());
                 assert!(all);
             }
@@ -164,7 +522,7 @@ mod tests {
     fn rm_all_with_name_ignored() {
         let cli = Cli::parse_from(["agent-team", "rm", "--all", "foo"]);
         match cli.command {
-            Command::Rm { name, all } => {
+            Command::Rm { name, all, .. } => {
                 assert_eq!(name.as_deref(), Some("foo"));
                 assert!(all);
             }
@@ -178,11 +536,241 @@ mod tests {
         // 业务层校验在 run_async 中处理
         let cli = Cli::parse_from(["agent-team", "rm"]);
         match cli.command {
-            Command::Rm { name, all } => {
+            Command::Rm { name, all, .. } => {
                 assert!(name.is_none());
                 assert!(!all);
             }
             _ => panic!("expected Rm"),
         }
     }
+
+    #[test]
+    fn ask_single_name() {
+        let cli = Cli::parse_from(["agent-team", "ask", "gemini-1", "hi"]);
+        match cli.command {
+            Command::Ask { name, text, all, .. } => {
+                assert_eq!(name.as_deref(), Some("gemini-1"));
+                assert_eq!(text.as_deref(), Some("hi"));
+                assert!(!all);
+            }
+            _ => panic!("expected Ask"),
+        }
+    }
+
+    #[test]
+    fn ask_all_text_in_name_slot() {
+        // --all 时唯一的位置参数是 prompt，由 run_async 处理
+        let cli = Cli::parse_from(["agent-team", "ask", "--all", "hi", "-f", "a.rs"]);
+        match cli.command {
+            Command::Ask { name, text, all, file, .. } => {
+                assert_eq!(name.as_deref(), Some("hi"));
+                assert!(text.is_none());
+                assert!(all);
+                assert_eq!(file, vec![PathBuf::from("a.rs")]);
+            }
+            _ => panic!("expected Ask"),
+        }
+    }
+
+    #[test]
+    fn named_session_flags() {
+        let cli = Cli::parse_from(["agent-team", "new", "coder", "--session", "review"]);
+        assert!(matches!(cli.command, Command::New { name, session } if name == "coder" && session == "review"));
+        assert!(Cli::try_parse_from(["agent-team", "new", "coder"]).is_err());
+
+        let cli = Cli::parse_from(["agent-team", "ask", "coder", "hi", "--session", "review"]);
+        assert!(matches!(cli.command, Command::Ask { session: Some(s), .. } if s == "review"));
+        let cli = Cli::parse_from(["agent-team", "log", "coder", "--session", "review", "-n", "0"]);
+        assert!(matches!(cli.command, Command::Log { session: Some(_), last: 0, .. }));
+        let cli = Cli::parse_from(["agent-team", "rm", "coder", "--session", "review"]);
+        assert!(matches!(cli.command, Command::Rm { session: Some(_), all: false, .. }));
+        // --all 与 --session 互斥
+        assert!(Cli::try_parse_from(["agent-team", "rm", "--all", "--session", "x"]).is_err());
+        assert!(Cli::try_parse_from(["agent-team", "ask", "--all", "hi", "--session", "x"]).is_err());
+    }
+
+    #[test]
+    fn fork_optional_new_name() {
+        let cli = Cli::parse_from(["agent-team", "fork", "coder"]);
+        assert!(matches!(cli.command, Command::Fork { name, new_name: None } if name == "coder"));
+        let cli = Cli::parse_from(["agent-team", "fork", "coder", "--as", "coder-alt"]);
+        assert!(matches!(cli.command, Command::Fork { new_name: Some(n), .. } if n == "coder-alt"));
+        assert!(Cli::try_parse_from(["agent-team", "fork"]).is_err());
+    }
+
+    #[test]
+    fn handoff_args() {
+        let cli = Cli::parse_from(["agent-team", "handoff", "gemini-1", "claude-1"]);
+        match cli.command {
+            Command::Handoff { from, to, task, template, budget, dry_run } => {
+                assert_eq!((from.as_str(), to.as_str()), ("gemini-1", "claude-1"));
+                assert!(task.is_none() && template.is_none() && !dry_run);
+                assert_eq!(budget, 8000);
+            }
+            _ => panic!("expected Handoff"),
+        }
+        let cli = Cli::parse_from([
+            "agent-team", "handoff", "a", "b", "finish the tests", "--budget", "2000", "--dry-run",
+        ]);
+        assert!(matches!(
+            cli.command,
+            Command::Handoff { task: Some(t), budget: 2000, dry_run: true, .. } if t == "finish the tests"
+        ));
+        assert!(Cli::try_parse_from(["agent-team", "handoff", "a"]).is_err());
+    }
+
+    #[test]
+    fn pipe_steps() {
+        let cli = Cli::parse_from([
+            "agent-team", "pipe", "write fizzbuzz",
+            "-s", "coder", "-s", "reviewer:Review: {{input}}", "--timeout", "60",
+        ]);
+        match cli.command {
+            Command::Pipe { input, step, timeout, continue_on_error, .. } => {
+                assert_eq!(input.as_deref(), Some("write fizzbuzz"));
+                assert_eq!(step, vec!["coder", "reviewer:Review: {{input}}"]);
+                assert_eq!(timeout, Some(60));
+                assert!(!continue_on_error);
+            }
+            _ => panic!("expected Pipe"),
+        }
+        // --replay 自带步骤，不能再给 -s / --file
+        assert!(Cli::try_parse_from(["agent-team", "pipe", "--replay", "r.json", "-s", "coder"]).is_err());
+        assert!(Cli::try_parse_from(["agent-team", "pipe", "--replay", "r.json", "--file", "p.json"]).is_err());
+    }
+
+    #[test]
+    fn add_auto_approve() {
+        let cli = Cli::parse_from(["agent-team", "add", "gemini", "--auto-approve", "always"]);
+        match cli.command {
+            Command::Add { auto_approve, .. } => {
+                assert!(matches!(auto_approve, Some(AutoApprovePolicy::Always)));
+            }
+            _ => panic!("expected Add"),
+        }
+        assert!(Cli::try_parse_from(["agent-team", "add", "gemini", "--auto-approve", "x"]).is_err());
+    }
+
+    #[test]
+    fn add_worktree_optional_branch() {
+        let cli = Cli::parse_from(["agent-team", "add", "gemini", "--worktree"]);
+        match cli.command {
+            Command::Add { worktree, .. } => assert_eq!(worktree.as_deref(), Some("")),
+            _ => panic!("expected Add"),
+        }
+        let cli = Cli::parse_from(["agent-team", "add", "gemini", "--worktree", "fix-auth", "-b"]);
+        match cli.command {
+            Command::Add { worktree, background, .. } => {
+                assert_eq!(worktree.as_deref(), Some("fix-auth"));
+                assert!(background);
+            }
+            _ => panic!("expected Add"),
+        }
+        let cli = Cli::parse_from(["agent-team", "add", "gemini"]);
+        assert!(matches!(cli.command, Command::Add { worktree: None, .. }));
+    }
+
+    #[test]
+    fn changes_turn_and_diff() {
+        let cli = Cli::parse_from(["agent-team", "changes", "coder"]);
+        assert!(matches!(cli.command, Command::Changes { turn: None, diff: false, .. }));
+        let cli = Cli::parse_from(["agent-team", "changes", "coder", "3", "--diff"]);
+        match cli.command {
+            Command::Changes { name, turn, diff } => {
+                assert_eq!(name, "coder");
+                assert_eq!(turn, Some(3));
+                assert!(diff);
+            }
+            _ => panic!("expected Changes"),
+        }
+        assert!(Cli::try_parse_from(["agent-team", "changes", "coder", "x"]).is_err());
+    }
+
+    #[test]
+    fn plan_with_session() {
+        let cli = Cli::parse_from(["agent-team", "plan", "coder"]);
+        assert!(matches!(cli.command, Command::Plan { name, session: None } if name == "coder"));
+        let cli = Cli::parse_from(["agent-team", "plan", "coder", "--session", "review"]);
+        assert!(matches!(cli.command, Command::Plan { session: Some(s), .. } if s == "review"));
+    }
+
+    #[test]
+    fn budget_flags() {
+        let cli = Cli::parse_from(["agent-team", "budget", "coder"]);
+        assert!(matches!(cli.command, Command::Budget { tokens: None, cost: None, clear: false, .. }));
+        let cli = Cli::parse_from(["agent-team", "budget", "coder", "--tokens", "50000", "--cost", "2.5"]);
+        match cli.command {
+            Command::Budget { name, tokens, cost, clear } => {
+                assert_eq!(name, "coder");
+                assert_eq!(tokens, Some(50000));
+                assert_eq!(cost, Some(2.5));
+                assert!(!clear);
+            }
+            _ => panic!("expected Budget"),
+        }
+        assert!(Cli::try_parse_from(["agent-team", "budget", "coder", "--tokens", "lots"]).is_err());
+    }
+
+    #[test]
+    fn undo_optional_turn() {
+        let cli = Cli::parse_from(["agent-team", "undo", "coder"]);
+        assert!(matches!(cli.command, Command::Undo { turn: None, .. }));
+        let cli = Cli::parse_from(["agent-team", "undo", "coder", "2"]);
+        assert!(matches!(cli.command, Command::Undo { turn: Some(2), .. }));
+    }
+
+    #[test]
+    fn merge_and_discard() {
+        let cli = Cli::parse_from(["agent-team", "merge", "coder"]);
+        assert!(matches!(cli.command, Command::Merge { name } if name == "coder"));
+        let cli = Cli::parse_from(["agent-team", "discard", "coder"]);
+        assert!(matches!(cli.command, Command::Discard { name } if name == "coder"));
+        assert!(Cli::try_parse_from(["agent-team", "merge"]).is_err());
+    }
+
+    #[test]
+    fn daemon_flags() {
+        let cli = Cli::parse_from(["agent-team", "daemon", "-b"]);
+        assert!(matches!(cli.command, Command::Daemon { background: true, stop: false, .. }));
+        let cli = Cli::parse_from(["agent-team", "daemon", "--stop"]);
+        assert!(matches!(cli.command, Command::Daemon { background: false, stop: true, .. }));
+        assert!(Cli::try_parse_from(["agent-team", "daemon", "-b", "--stop"]).is_err());
+    }
+
+    #[test]
+    fn listen_and_host_flags() {
+        let cli = Cli::parse_from([
+            "agent-team", "add", "gemini", "--listen", "0.0.0.0:7070",
+            "--tls-cert", "c.pem", "--tls-key", "k.pem",
+        ]);
+        match cli.command {
+            Command::Add { listen, .. } => {
+                assert_eq!(listen.listen.as_deref(), Some("0.0.0.0:7070"));
+                assert_eq!(listen.tls_key, Some(PathBuf::from("k.pem")));
+            }
+            _ => panic!("expected Add"),
+        }
+        // 证书与私钥必须成对，且需要 --listen
+        assert!(Cli::try_parse_from(["agent-team", "daemon", "--listen", ":1", "--tls-cert", "c"]).is_err());
+        assert!(Cli::try_parse_from(["agent-team", "daemon", "--tls-cert", "c", "--tls-key", "k"]).is_err());
+
+        let cli = Cli::parse_from(["agent-team", "ls", "--host", "tls://build:7070"]);
+        assert_eq!(cli.host.as_deref(), Some("tls://build:7070"));
+        assert!(matches!(
+            Cli::parse_from(["agent-team", "token", "--rotate"]).command,
+            Command::Token { rotate: true },
+        ));
+        let cli = Cli::parse_from(["agent-team", "serve", "--http", "127.0.0.1:8080"]);
+        assert!(matches!(cli.command, Command::Serve { http } if http == "127.0.0.1:8080"));
+        assert!(Cli::try_parse_from(["agent-team", "serve"]).is_err());
+    }
+
+    #[test]
+    fn up_default_manifest() {
+        let cli = Cli::parse_from(["agent-team", "up"]);
+        match cli.command {
+            Command::Up { manifest } => assert_eq!(manifest, PathBuf::from("team.toml")),
+            _ => panic!("expected Up"),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use crate::protocol::messages::{
-    AgentSummary, OutputEntry, OutputType, SessionResponse,
+    fmt_tokens, AgentSummary, OutputEntry, OutputType, PermissionInfo, PlanEntry, PlanProgress,
+    SessionEvent, SessionResponse, TurnChanges, UsageSummary, VERSION,
 };
 
 // ==================== 终端输出格式化 ====================
@@ -15,6 +16,11 @@ pub fn print_session_response(resp: &SessionResponse) {
             eprintln!("Error: {}", message);
         }
 
+        SessionResponse::Hello { protocol, version, capabilities, .. } => {
+            println!("agent-team {} (protocol {})", version, protocol);
+            println!("Capabilities: {}", capabilities.join(", "));
+        }
+
         SessionResponse::Status { summary } => {
             println!("Name: {}", summary.name);
             println!("Type: {}", summary.agent_type);
@@ -23,15 +29,227 @@ pub fn print_session_response(resp: &SessionResponse) {
                 println!("Agent: {} v{}", info_name, ver);
             }
             println!("Cwd: {}", summary.cwd);
+            println!("Session: {}", session_version(summary));
+            if let Some(ref wt) = summary.worktree {
+                println!("Worktree: {} ({})", wt.branch, wt.repo);
+            }
             println!("Status: {}", summary.status);
             println!("Uptime: {}", summary.uptime);
             println!("Prompts: {}", summary.prompt_count);
             println!("Pending: {}", summary.pending_permissions);
+            if let Some(plan) = summary.plan {
+                println!("Plan: {}", plan);
+            }
+            for line in usage_lines(summary.usage.as_ref()) {
+                println!("{}", line);
+            }
+            if summary.notify_muted {
+                println!("Notifications: off");
+            }
+            if let Some(ref from) = summary.forked_from {
+                println!("Forked from: {}", from);
+            }
+            if !summary.forks.is_empty() {
+                println!("Forks: {}", summary.forks.join(", "));
+            }
+            for ns in &summary.sessions {
+                println!(
+                    "Session '{}': {}, {} prompt(s), {} pending",
+                    ns.name, ns.status, ns.prompt_count, ns.pending_permissions
+                );
+            }
         }
 
         SessionResponse::Output { agent_name, entries } => {
             print_entries(agent_name, entries);
         }
+
+        SessionResponse::Changes { turns, .. } => {
+            print!("{}", render_changes(turns));
+        }
+
+        SessionResponse::Plan { agent_name, entries } => {
+            print!("{}", render_plan(agent_name, entries));
+        }
+
+        SessionResponse::Agents { agents } => {
+            print_agent_list(agents);
+        }
+
+        SessionResponse::Permissions { agent_name, pending } => {
+            let rows: Vec<(&str, &PermissionInfo)> = pending.iter().map(|p| (agent_name.as_str(), p)).collect();
+            print!("{}", render_permissions(&rows));
+        }
+
+        SessionResponse::Event { agent_name, event } => match event {
+            SessionEvent::Output { entry, session } => {
+                print_entries(&scoped_name(agent_name, session), std::slice::from_ref(entry))
+            }
+            SessionEvent::Info { tag, message, session } => {
+                println!("[{}] [{}] {}", scoped_name(agent_name, session), tag, message)
+            }
+        },
+    }
+}
+
+/// 命名 session 的事件显示为 agent/session
+fn scoped_name(agent_name: &str, session: &Option<String>) -> String {
+    match session {
+        Some(s) => format!("{}/{}", agent_name, s),
+        None => agent_name.to_string(),
+    }
+}
+
+// ==================== Plan ====================
+
+/// 逐条列出 plan：状态标记、内容、优先级，末尾汇总进度
+pub fn render_plan(agent_name: &str, entries: &[PlanEntry]) -> String {
+    let Some(progress) = PlanProgress::of(entries) else {
+        return format!("No plan reported by {}\n", agent_name);
+    };
+    let mut out = format!("[{}] Plan ({})\n", agent_name, progress);
+    for e in entries {
+        out.push_str(&format!("  {} {} ({})\n", e.status.marker(), e.content, e.priority.label()));
+    }
+    out
+}
+
+/// ls 的 PLAN 列："3/7"，未上报为 "-"
+pub fn plan_cell(plan: Option<PlanProgress>) -> String {
+    plan.map(|p| format!("{}/{}", p.done, p.total)).unwrap_or_else(|| "-".into())
+}
+
+// ==================== 用量 ====================
+
+/// `budget <name>`（不带参数）：用量与预算
+pub fn print_usage(summary: &AgentSummary) {
+    let lines = usage_lines(summary.usage.as_ref());
+    if lines.is_empty() {
+        println!("No usage recorded by {} and no budget set", summary.name);
+    }
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+/// info 中的 Tokens / Cost / Budget 行
+fn usage_lines(usage: Option<&UsageSummary>) -> Vec<String> {
+    let Some(u) = usage else {
+        return vec![];
+    };
+    let mut lines = vec![format!("Tokens: {} over {} turn(s)", u.tokens, u.turns)];
+    if let Some(c) = &u.cost {
+        lines.push(format!("Cost: {}", c));
+    }
+    if !u.budget.is_empty() {
+        lines.push(format!("Budget: {}", u.budget));
+    }
+    lines
+}
+
+/// ls 的 TOKENS 列："~12.3k"（估算）或 "12.3k/50.0k"（有 token 上限），无记录为 "-"
+pub fn tokens_cell(usage: Option<&UsageSummary>) -> String {
+    let Some(u) = usage.filter(|u| u.turns > 0 || u.budget.max_tokens.is_some()) else {
+        return "-".into();
+    };
+    let used = format!("{}{}", if u.tokens.estimated { "~" } else { "" }, fmt_tokens(u.tokens.total));
+    match u.budget.max_tokens {
+        Some(max) => format!("{}/{}", used, fmt_tokens(max)),
+        None => used,
+    }
+}
+
+// ==================== 改动记录 ====================
+
+/// 多个 turn 且不含 diff 时输出表格，否则逐 turn 列出文件（及 diff）
+pub fn render_changes(turns: &[TurnChanges]) -> String {
+    if turns.is_empty() {
+        return "No turns recorded\n".into();
+    }
+    let with_diff = turns.iter().any(|t| t.files.iter().any(|f| f.diff.is_some()));
+    if turns.len() > 1 && !with_diff {
+        let rows: Vec<Vec<String>> = turns
+            .iter()
+            .map(|t| {
+                vec![
+                    t.turn.to_string(),
+                    local_time(&t.started_at),
+                    t.files.len().to_string(),
+                    undone_label(t),
+                ]
+            })
+            .collect();
+        return render_table(&["TURN", "TIME", "FILES", "PROMPT"], &rows);
+    }
+
+    let mut out = String::new();
+
+    for (i, t) in turns.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        out.push_str(&format!("Turn {} ({}) {}\n", t.turn, local_time(&t.started_at), undone_label(t)));
+        if t.files.is_empty() {
+            out.push_str("  (no changes)\n");
+        }
+        for f in &t.files {
+            out.push_str(&format!("  {} {}\n", f.kind.letter(), f.path));
+        }
+        for diff in t.files.iter().filter_map(|f| f.diff.as_deref()) {
+            out.push('\n');
+            out.push_str(diff);
+            if !diff.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+    }
+    out
+}
+
+fn undone_label(t: &TurnChanges) -> String {
+    if t.undone {
+        format!("{} (undone)", t.prompt)
+    } else {
+        t.prompt.clone()
+    }
+}
+
+fn local_time(rfc3339: &str) -> String {
+    chrono::DateTime::parse_from_rfc3339(rfc3339)
+        .map(|t| t.with_timezone(&chrono::Local).format("%H:%M:%S").to_string())
+        .unwrap_or_else(|_| rfc3339.to_string())
+}
+
+// ==================== 待审批权限 ====================
+
+/// inbox 表格：序号、agent（命名 session 为 agent/session）、类别、等待时长、工具
+pub fn render_permissions(pending: &[(&str, &PermissionInfo)]) -> String {
+    if pending.is_empty() {
+        return "No pending permissions\n".into();
+    }
+    let rows: Vec<Vec<String>> = pending
+        .iter()
+        .enumerate()
+        .map(|(i, (agent, p))| {
+            vec![
+                (i + 1).to_string(),
+                scoped_name(agent, &p.session),
+                p.kind.clone().unwrap_or_else(|| "-".into()),
+                fmt_age(p.age_secs),
+                p.tool.clone(),
+            ]
+        })
+        .collect();
+    render_table(&["#", "AGENT", "KIND", "AGE", "TOOL"], &rows)
+}
+
+/// "45s" / "3m" / "2h5m"
+pub fn fmt_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{}s", secs),
+        60..3600 => format!("{}m", secs / 60),
+        _ if secs % 3600 < 60 => format!("{}h", secs / 3600),
+        _ => format!("{}h{}m", secs / 3600, secs % 3600 / 60),
     }
 }
 
@@ -43,45 +261,52 @@ pub fn print_agent_list(agents: &[AgentSummary]) {
         return;
     }
 
-    let headers = ["NAME", "TYPE", "STATUS", "UPTIME", "PROMPTS", "PENDING", "CWD"];
-    let rows: Vec<[String; 7]> = agents
+    let headers = ["NAME", "TYPE", "STATUS", "UPTIME", "PROMPTS", "PENDING", "PLAN", "TOKENS", "CWD"];
+    // 命名 session 紧跟所属 agent，显示为 agent/session
+    let rows: Vec<Vec<String>> = agents
         .iter()
-        .map(|a| {
-            [
+        .flat_map(|a| {
+            let main = vec![
                 a.name.clone(),
                 a.agent_type.clone(),
                 a.status.clone(),
                 a.uptime.clone(),
                 a.prompt_count.to_string(),
                 a.pending_permissions.to_string(),
+                plan_cell(a.plan),
+                tokens_cell(a.usage.as_ref()),
                 a.cwd.clone(),
-            ]
+            ];
+            let named = a.sessions.iter().map(|s| {
+                vec![
+                    format!("{}/{}", a.name, s.name),
+                    String::new(),
+                    s.status.clone(),
+                    String::new(),
+                    s.prompt_count.to_string(),
+                    s.pending_permissions.to_string(),
+                    plan_cell(s.plan),
+                    tokens_cell(s.usage.as_ref()),
+                    String::new(),
+                ]
+            });
+            std::iter::once(main).chain(named)
         })
         .collect();
+    print_table(&headers, &rows);
 
-    let mut widths = headers.map(|h| h.len());
-    for row in &rows {
-        for (i, cell) in row.iter().enumerate() {
-            widths[i] = widths[i].max(cell.len());
-        }
-    }
-
-    for (i, h) in headers.iter().enumerate() {
-        if i > 0 {
-            print!("  ");
-        }
-        print!("{:<w$}", h, w = widths[i]);
-    }
-    println!();
-
-    for row in &rows {
-        for (i, cell) in row.iter().enumerate() {
-            if i > 0 {
-                print!("  ");
-            }
-            print!("{:<w$}", cell, w = widths[i]);
-        }
+    // session 由旧版本二进制启动（如 update 之前）：部分命令不可用
+    let outdated: Vec<_> = agents.iter().filter(|a| a.is_outdated()).collect();
+    if !outdated.is_empty() {
         println!();
+        for a in &outdated {
+            println!(
+                "Note: {} runs {} (this CLI: agent-team {}) — rm + add to upgrade",
+                a.name,
+                session_version(a),
+                VERSION,
+            );
+        }
     }
 
     // 有 pending 权限时提示操作方式
@@ -100,11 +325,87 @@ pub fn print_agent_list(agents: &[AgentSummary]) {
     }
 }
 
+/// session 进程的 agent-team 版本描述；旧版本不上报
+fn session_version(summary: &AgentSummary) -> String {
+    match &summary.version {
+        Some(v) => format!("agent-team {} (protocol {})", v, summary.protocol),
+        None => "an older agent-team (protocol 0)".into(),
+    }
+}
+
+/// 左对齐纯文本表格，列间两个空格
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    print!("{}", render_table(headers, rows));
+}
+
+fn render_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (i, cell) in row.iter().enumerate().take(widths.len()) {
+            widths[i] = widths[i].max(cell.len());
+        }
+    }
+
+    let mut out = String::new();
+    let last = widths.len().saturating_sub(1);
+    let mut push_row = |cells: &mut dyn Iterator<Item = &str>| {
+        for (i, cell) in cells.enumerate().take(widths.len()) {
+            if i > 0 {
+                out.push_str("  ");
+            }
+            // 最后一列不补空格
+            if i == last {
+                out.push_str(cell);
+            } else {
+                out.push_str(&format!("{:<w$}", cell, w = widths[i]));
+            }
+        }
+        out.push('\n');
+    };
+    push_row(&mut headers.iter().copied());
+    for row in rows {
+        push_row(&mut row.iter().map(String::as_str));
+    }
+    out
+}
+
 // ==================== 输出格式化 ====================
 
 /// 对话流显示：<msg> 包裹每条消息，空行分隔段落
 fn print_entries(agent_name: &str, entries: &[OutputEntry]) {
-    let mut state = MsgState::default();
+    print!("{}", render_entries(agent_name, entries));
+}
+
+/// 对话流渲染为文本（print_entries 的字符串版本）
+pub fn render_entries(agent_name: &str, entries: &[OutputEntry]) -> String {
+    render_entries_with(MsgState::default(), agent_name, entries)
+}
+
+/// 广播回复：agent 的 <msg> 标签附带最终状态
+pub fn print_agent_reply(agent_name: &str, status: &str, entries: &[OutputEntry]) {
+    let has_visible = entries
+        .iter()
+        .any(|e| !matches!(e.update_type, OutputType::PromptResponse));
+    if !has_visible {
+        println!("<msg role=\"agent\" name=\"{}\" status=\"{}\">", agent_name, status);
+        println!("</msg>");
+        return;
+    }
+    let state = MsgState {
+        status: Some(status.to_string()),
+        ..Default::default()
+    };
+    print!("{}", render_entries_with(state, agent_name, entries));
+}
+
+/// 广播失败：同样以 <msg> 块呈现，保持多 agent 输出结构一致
+pub fn print_agent_error(agent_name: &str, status: &str, message: &str) {
+    println!("<msg role=\"agent\" name=\"{}\" status=\"{}\">", agent_name, status);
+    println!("Error: {}", message);
+    println!("</msg>");
+}
+
+fn render_entries_with(mut state: MsgState, agent_name: &str, entries: &[OutputEntry]) -> String {
     let mut i = 0;
 
     while i < entries.len() {
@@ -119,16 +420,16 @@ fn print_entries(agent_name: &str, entries: &[OutputEntry]) {
 
         match entry.update_type {
             OutputType::UserPrompt => {
-                println!("{}", entry.content.trim());
+                state.line(entry.content.trim());
                 state.has_content = true;
                 i += 1;
             }
             OutputType::AgentMessage | OutputType::AgentThought => {
-                i += print_text_run(entries, i, &mut state);
+                i += render_text_run(entries, i, &mut state);
             }
             _ => {
-                if state.prev_was_text { println!(); }
-                println!("[{}] {}", entry.update_type.label(), entry.content);
+                if state.prev_was_text { state.line(""); }
+                state.line(&format!("[{}] {}", entry.update_type.label(), entry.content));
                 state.prev_was_text = false;
                 state.has_content = true;
                 state.after_interaction = matches!(entry.update_type, OutputType::PermissionRequest);
@@ -138,8 +439,9 @@ fn print_entries(agent_name: &str, entries: &[OutputEntry]) {
     }
 
     if !state.role.is_empty() {
-        println!("</msg>");
+        state.line("</msg>");
     }
+    state.out
 }
 
 #[derive(Default)]
@@ -148,21 +450,32 @@ struct MsgState {
     has_content: bool,
     prev_was_text: bool,
     after_interaction: bool,
+    /// 附加到 agent 标签的状态（广播模式）
+    status: Option<String>,
+    out: String,
 }
 
 impl MsgState {
+    fn line(&mut self, text: &str) {
+        self.out.push_str(text);
+        self.out.push('\n');
+    }
+
     fn switch_role_if_needed(&mut self, new_role: &str, agent_name: &str) {
         if new_role == self.role && !self.after_interaction {
             return;
         }
         if !self.role.is_empty() {
-            println!("</msg>\n");
+            self.line("</msg>\n");
         }
-        if new_role == "user" {
-            println!("<msg role=\"user\">");
+        let tag = if new_role == "user" {
+            "<msg role=\"user\">".to_string()
+        } else if let Some(status) = &self.status {
+            format!("<msg role=\"agent\" name=\"{}\" status=\"{}\">", agent_name, status)
         } else {
-            println!("<msg role=\"agent\" name=\"{}\">", agent_name);
-        }
+            format!("<msg role=\"agent\" name=\"{}\">", agent_name)
+        };
+        self.line(&tag);
         self.role = new_role.to_string();
         self.has_content = false;
         self.prev_was_text = false;
@@ -171,7 +484,7 @@ impl MsgState {
 }
 
 /// 合并连续同类型 chunk，返回消费的条目数
-fn print_text_run(entries: &[OutputEntry], start: usize, state: &mut MsgState) -> usize {
+fn render_text_run(entries: &[OutputEntry], start: usize, state: &mut MsgState) -> usize {
     let disc = std::mem::discriminant(&entries[start].update_type);
     let mut text = String::new();
     let mut count = 0;
@@ -182,14 +495,56 @@ fn print_text_run(entries: &[OutputEntry], start: usize, state: &mut MsgState) -
     }
     let text = text.trim();
     if !text.is_empty() {
-        if state.has_content { println!(); }
-        println!("{}", text);
+        if state.has_content { state.line(""); }
+        state.line(text);
         state.has_content = true;
         state.prev_was_text = true;
     }
     count
 }
 
+// ==================== log -f ====================
+
+/// 实时事件流的渲染状态：消息 chunk 原样续写，其它条目独占一行
+#[derive(Default)]
+pub struct FollowState {
+    in_message: bool,
+    needs_newline: bool,
+}
+
+impl FollowState {
+    /// 消息 chunk 未以换行结束时补一个换行（之后要输出提示符等）
+    pub fn end_line(&mut self) -> &'static str {
+        self.in_message = false;
+        if std::mem::take(&mut self.needs_newline) { "\n" } else { "" }
+    }
+}
+
+/// 与 session 日志相同的格式；`notify` 事件前加终端响铃
+pub fn render_event(state: &mut FollowState, event: &SessionEvent) -> String {
+    let line = match event {
+        SessionEvent::Output { entry, .. } => match entry.update_type {
+            OutputType::AgentMessage | OutputType::AgentThought => {
+                let text = if state.in_message { entry.content.as_str() } else { entry.content.trim_start() };
+                if text.is_empty() {
+                    return String::new();
+                }
+                state.in_message = true;
+                state.needs_newline = !text.ends_with('\n');
+                return text.to_string();
+            }
+            _ => format!("[{}] {}", entry.update_type.label(), entry.content.trim()),
+        },
+        SessionEvent::Info { tag, message, .. } => {
+            let bell = if tag == "notify" { "\x07" } else { "" };
+            format!("{}[{}] {}", bell, tag, message)
+        }
+    };
+    state.in_message = false;
+    let sep = if std::mem::take(&mut state.needs_newline) { "\n" } else { "" };
+    format!("{}{}\n", sep, line)
+}
+
 // ==================== 单元测试 ====================
 
 #[cfg(test)]
@@ -207,6 +562,7 @@ mod tests {
             pending_permissions: 0,
             agent_info_name: None,
             agent_info_version: None,
+            ..Default::default()
         }
     }
 
@@ -237,7 +593,7 @@ mod tests {
     #[test]
     fn response_status() {
         print_session_response(&SessionResponse::Status {
-            summary: make_summary("alice"),
+            summary: Box::new(make_summary("alice")),
         });
     }
 
@@ -246,7 +602,7 @@ mod tests {
         let mut s = make_summary("bob");
         s.agent_info_name = Some("Gemini".into());
         s.agent_info_version = Some("1.0".into());
-        print_session_response(&SessionResponse::Status { summary: s });
+        print_session_response(&SessionResponse::Status { summary: Box::new(s) });
     }
 
     #[test]
@@ -287,6 +643,16 @@ mod tests {
         print_agent_list(&[make_summary("alice"), bob]);
     }
 
+    #[test]
+    fn session_version_marks_old_sessions() {
+        let mut old = make_summary("old");
+        assert_eq!(session_version(&old), "an older agent-team (protocol 0)");
+        print_agent_list(std::slice::from_ref(&old));
+        old.version = Some("0.3.0".into());
+        old.protocol = 1;
+        assert_eq!(session_version(&old), "agent-team 0.3.0 (protocol 1)");
+    }
+
     // -- print_entries --
 
     #[test]
@@ -348,4 +714,164 @@ mod tests {
         ];
         print_entries("bot", &entries);
     }
+
+    // -- print_agent_reply / print_agent_error --
+
+    #[test]
+    fn agent_reply_with_status() {
+        let entries = vec![
+            make_entry(OutputType::AgentMessage, "answer"),
+            make_entry(OutputType::PromptResponse, "EndTurn"),
+        ];
+        print_agent_reply("bot", "idle", &entries);
+    }
+
+    #[test]
+    fn agent_reply_empty() {
+        print_agent_reply("bot", "idle", &[make_entry(OutputType::PromptResponse, "EndTurn")]);
+    }
+
+    #[test]
+    fn agent_error_block() {
+        print_agent_error("bot", "unreachable", "connection refused");
+    }
+
+    #[test]
+    fn render_changes_table_and_detail() {
+        use crate::protocol::messages::{ChangeKind, FileChange};
+        let turn = |n: u64, files: Vec<FileChange>| TurnChanges {
+            turn: n,
+            prompt: format!("task {}", n),
+            started_at: "bad-time".into(),
+            files,
+            undone: n == 1,
+        };
+        let file = |path: &str, kind, diff: Option<&str>| FileChange {
+            path: path.into(),
+            kind,
+            diff: diff.map(String::from),
+        };
+
+        assert_eq!(render_changes(&[]), "No turns recorded\n");
+
+        let list = render_changes(&[
+            turn(1, vec![]),
+            turn(2, vec![file("a.rs", ChangeKind::Modified, None)]),
+        ]);
+        assert_eq!(
+            list,
+            "TURN  TIME      FILES  PROMPT\n\
+             1     bad-time  0      task 1 (undone)\n\
+             2     bad-time  1      task 2\n",
+        );
+
+        let detail = render_changes(&[turn(
+            2,
+            vec![
+                file("a.rs", ChangeKind::Modified, Some("--- a/a.rs\n+++ b/a.rs\n")),
+                file("b.rs", ChangeKind::Added, None),
+            ],
+        )]);
+        assert_eq!(
+            detail,
+            "Turn 2 (bad-time) task 2\n  M a.rs\n  A b.rs\n\n--- a/a.rs\n+++ b/a.rs\n",
+        );
+    }
+
+    #[test]
+    fn render_plan_lists_entries() {
+        use crate::protocol::messages::{PlanPriority, PlanStatus};
+        assert_eq!(render_plan("bot", &[]), "No plan reported by bot\n");
+        let entry = |content: &str, priority, status| PlanEntry {
+            content: content.into(),
+            priority,
+            status,
+        };
+        let text = render_plan(
+            "bot",
+            &[
+                entry("read code", PlanPriority::High, PlanStatus::Completed),
+                entry("write fix", PlanPriority::Medium, PlanStatus::InProgress),
+                entry("add tests", PlanPriority::Low, PlanStatus::Pending),
+            ],
+        );
+        assert_eq!(
+            text,
+            "[bot] Plan (1/3 done)\n  [x] read code (high)\n  [~] write fix (medium)\n  \
+             [ ] add tests (low)\n",
+        );
+        assert_eq!(plan_cell(PlanProgress::of(&[])), "-");
+    }
+
+    #[test]
+    fn usage_lines_and_tokens_cell() {
+        use crate::protocol::messages::{Budget, Cost, TokenUsage};
+        assert!(usage_lines(None).is_empty());
+        assert_eq!(tokens_cell(None), "-");
+
+        let mut u = UsageSummary {
+            tokens: TokenUsage { input: 9000, output: 3345, total: 12345, estimated: true },
+            turns: 3,
+            cost: None,
+            budget: Budget::default(),
+        };
+        assert_eq!(usage_lines(Some(&u)), vec!["Tokens: ~12.3k tokens (in 9.0k / out 3.3k) over 3 turn(s)"]);
+        assert_eq!(tokens_cell(Some(&u)), "~12.3k");
+
+        u.tokens.estimated = false;
+        u.cost = Some(Cost { amount: 0.4211, currency: "USD".into() });
+        u.budget = Budget { max_tokens: Some(50_000), max_cost: Some(2.0) };
+        let lines = usage_lines(Some(&u));
+        assert_eq!(lines[1], "Cost: 0.42 USD");
+        assert_eq!(lines[2], "Budget: 50.0k tokens, 2.00 cost");
+        assert_eq!(tokens_cell(Some(&u)), "12.3k/50.0k");
+    }
+
+    #[test]
+    fn render_entries_layout() {
+        let entries = vec![
+            make_entry(OutputType::UserPrompt, "  ask  "),
+            make_entry(OutputType::AgentMessage, "part one"),
+            make_entry(OutputType::ToolCallStart, "read a.rs"),
+            make_entry(OutputType::AgentMessage, "part two"),
+            make_entry(OutputType::PromptResponse, "EndTurn"),
+        ];
+        let text = render_entries("bot", &entries);
+        assert_eq!(
+            text,
+            "<msg role=\"user\">\nask\n</msg>\n\n\
+             <msg role=\"agent\" name=\"bot\">\npart one\n\n[tool] read a.rs\n\npart two\n</msg>\n",
+        );
+    }
+
+    #[test]
+    fn render_event_follow() {
+        let mut st = FollowState::default();
+        let out = |entry| SessionEvent::Output { entry, session: None };
+        let mut text = String::new();
+        text += &render_event(&mut st, &out(make_entry(OutputType::AgentMessage, "  Hello")));
+        text += &render_event(&mut st, &out(make_entry(OutputType::AgentMessage, " world")));
+        text += &render_event(&mut st, &out(make_entry(OutputType::ToolCallStart, "read a.rs")));
+        text += &render_event(&mut st, &SessionEvent::Info {
+            tag: "notify".into(),
+            message: "Turn finished (EndTurn)".into(),
+            session: None,
+        });
+        assert_eq!(text, "Hello world\n[tool] read a.rs\n\x07[notify] Turn finished (EndTurn)\n");
+    }
+
+    #[test]
+    fn permissions_table_and_age() {
+        assert_eq!(render_permissions(&[]), "No pending permissions\n");
+        let edit = PermissionInfo { id: 7, session: None, tool: "Edit src/a.rs".into(), kind: Some("edit".into()), age_secs: 125 };
+        let run = PermissionInfo { id: 2, session: Some("review".into()), tool: "cargo test".into(), kind: None, age_secs: 9 };
+        assert_eq!(
+            render_permissions(&[("coder", &edit), ("bot", &run)]),
+            "#  AGENT       KIND  AGE  TOOL\n\
+             1  coder       edit  2m   Edit src/a.rs\n\
+             2  bot/review  -     9s   cargo test\n",
+        );
+        assert_eq!(fmt_age(7200), "2h");
+        assert_eq!(fmt_age(7500), "2h5m");
+    }
 }
@@ -0,0 +1,720 @@
+// ============================================================
+// export - 对话记录导出（Markdown / HTML / JSON）
+// ============================================================
+// 把输出缓冲整理成按 turn 分组的 Transcript：用户 prompt、合并后的 agent 消息、
+// 思考过程、带状态的工具调用、plan、权限请求及其审批结果；默认 session 另附每个 turn 的改动与 diff。
+// 三种格式共用同一结构，JSON 直接序列化，Markdown / HTML 中思考过程与 diff 可折叠。
+
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use anyhow::{bail, Result};
+use serde::Serialize;
+
+use crate::acp_client::team_client::permission_outcome;
+use crate::config::TeamConfig;
+use crate::protocol::messages::{FileChange, OutputEntry, OutputType, SessionRequest, SessionResponse, TurnChanges};
+
+use super::client;
+
+/// ACP ToolCallStatus 的 Debug 形式，位于工具更新文本末尾
+const TOOL_STATUSES: [&str; 4] = ["Pending", "InProgress", "Completed", "Failed"];
+/// 尚未审批的权限请求
+const PENDING: &str = "pending";
+
+// ==================== 选项 ====================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Markdown,
+    Html,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "md" | "markdown" => Ok(Self::Markdown),
+            "html" => Ok(Self::Html),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown format '{}' (md, html, json)", s)),
+        }
+    }
+}
+
+/// `--turns`：`3`、`2..5`、`4..`、`..2`，两端都包含
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TurnRange {
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+}
+
+impl TurnRange {
+    fn contains(&self, n: usize) -> bool {
+        self.start.is_none_or(|s| n >= s) && self.end.is_none_or(|e| n <= e)
+    }
+}
+
+impl FromStr for TurnRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let num = |t: &str| -> Result<Option<usize>, String> {
+            match t.trim() {
+                "" => Ok(None),
+                t => t.parse().map(Some).map_err(|_| format!("invalid turn number '{}'", t)),
+            }
+        };
+        let range = match s.split_once("..") {
+            Some((a, b)) => Self { start: num(a)?, end: num(b)? },
+            None => {
+                let n = num(s)?.ok_or("empty turn range")?;
+                Self { start: Some(n), end: Some(n) }
+            }
+        };
+        match range {
+            Self { start: Some(0), .. } | Self { end: Some(0), .. } => Err("turns are numbered from 1".into()),
+            Self { start: Some(a), end: Some(b) } if a > b => Err(format!("empty turn range {}..{}", a, b)),
+            r => Ok(r),
+        }
+    }
+}
+
+// ==================== 结构 ====================
+
+#[derive(Debug, Serialize)]
+pub struct Transcript {
+    pub agent: String,
+    pub agent_type: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    pub cwd: String,
+    pub exported_at: String,
+    pub turns: Vec<Turn>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Turn {
+    /// 输出缓冲中的顺序（1 = 保留的最早一个 turn）
+    pub number: usize,
+    pub started_at: String,
+    pub prompt: String,
+    pub items: Vec<Item>,
+    /// turn 内最后一次上报的 plan
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changes: Vec<FileChange>,
+    /// 改动已被 undo 还原
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub undone: bool,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Item {
+    Message { text: String },
+    Thought { text: String },
+    ToolCall {
+        title: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        status: Option<String>,
+    },
+    Permission { tool: String, decision: String },
+    /// 模式 / 配置变更、错误等
+    Note { label: String, text: String },
+}
+
+impl Turn {
+    fn push_text(&mut self, thought: bool, chunk: &str) {
+        match self.items.last_mut() {
+            Some(Item::Thought { text }) if thought => text.push_str(chunk),
+            Some(Item::Message { text }) if !thought => text.push_str(chunk),
+            _ if thought => self.items.push(Item::Thought { text: chunk.to_string() }),
+            _ => self.items.push(Item::Message { text: chunk.to_string() }),
+        }
+    }
+
+    /// 工具更新归到最近一次工具调用：末尾的状态词为状态，其余为新标题
+    fn update_tool(&mut self, update: &str) {
+        let (rest, status) = match update.rsplit_once(' ') {
+            Some((rest, last)) if TOOL_STATUSES.contains(&last) => (rest, Some(last)),
+            _ if TOOL_STATUSES.contains(&update) => ("", Some(update)),
+            _ => (update, None),
+        };
+        let rest = rest.trim();
+        let last = self.items.iter_mut().rev().find_map(|i| match i {
+            Item::ToolCall { title, status } => Some((title, status)),
+            _ => None,
+        });
+        match last {
+            Some((title, st)) => {
+                if !rest.is_empty() && rest != "(No details)" {
+                    *title = rest.to_string();
+                }
+                if let Some(s) = status {
+                    *st = Some(s.to_string());
+                }
+            }
+            None => self.items.push(Item::ToolCall {
+                title: rest.to_string(),
+                status: status.map(str::to_string),
+            }),
+        }
+    }
+
+    /// 审批结果回填到同一工具最近一条待审批请求
+    fn permission(&mut self, content: &str) {
+        let Some((tool, outcome)) = permission_outcome(content) else {
+            self.items.push(Item::Note { label: "permission".into(), text: content.to_string() });
+            return;
+        };
+        if let Some(outcome @ ("approved" | "denied")) = outcome {
+            let pending = self.items.iter_mut().rev().find_map(|i| match i {
+                Item::Permission { tool: t, decision } if t == tool && decision == PENDING => Some(decision),
+                _ => None,
+            });
+            if let Some(decision) = pending {
+                *decision = outcome.to_string();
+                return;
+            }
+        }
+        self.items.push(Item::Permission {
+            tool: tool.to_string(),
+            decision: outcome.unwrap_or(PENDING).to_string(),
+        });
+    }
+}
+
+/// 输出缓冲 → 按 turn 分组；changes 按开始时间归入对应 turn
+pub fn group_turns(entries: &[OutputEntry], changes: &[TurnChanges]) -> Vec<Turn> {
+    let mut turns: Vec<Turn> = vec![];
+    for e in entries {
+        if matches!(e.update_type, OutputType::UserPrompt) {
+            turns.push(Turn {
+                number: turns.len() + 1,
+                started_at: e.timestamp.clone(),
+                prompt: e.content.trim().to_string(),
+                ..Default::default()
+            });
+            continue;
+        }
+        if turns.is_empty() {
+            turns.push(Turn { number: 1, started_at: e.timestamp.clone(), ..Default::default() });
+        }
+        let turn = turns.last_mut().expect("turn pushed above");
+        match e.update_type {
+            OutputType::AgentMessage => turn.push_text(false, &e.content),
+            OutputType::AgentThought => turn.push_text(true, &e.content),
+            OutputType::ToolCallStart => turn.items.push(Item::ToolCall { title: e.content.clone(), status: None }),
+            OutputType::ToolCallUpdate | OutputType::ToolCallResult => turn.update_tool(&e.content),
+            OutputType::PermissionRequest => turn.permission(&e.content),
+            OutputType::PlanUpdate => {
+                let p = e.content.strip_prefix("Plan:\n").unwrap_or(&e.content);
+                turn.plan = Some(p.trim_end().to_string());
+            }
+            OutputType::PromptResponse => turn.stop_reason = Some(e.content.clone()),
+            OutputType::Usage => turn.usage = Some(e.content.clone()),
+            OutputType::UserPrompt => unreachable!("handled above"),
+            OutputType::ModeUpdate | OutputType::ConfigUpdate | OutputType::Error => turn.items.push(Item::Note {
+                label: e.update_type.label().to_string(),
+                text: e.content.clone(),
+            }),
+        }
+    }
+    for t in &mut turns {
+        for item in &mut t.items {
+            if let Item::Message { text } | Item::Thought { text } = item {
+                *text = text.trim().to_string();
+            }
+        }
+        t.items.retain(|i| !matches!(i, Item::Message { text } | Item::Thought { text } if text.is_empty()));
+    }
+
+    let starts: Vec<_> = turns.iter().map(|t| parse_time(&t.started_at)).collect();
+    for c in changes {
+        let Some(at) = parse_time(&c.started_at) else { continue };
+        // 开始时间不晚于改动快照的最后一个 turn
+        let idx = starts.iter().rposition(|s| s.is_some_and(|s| s <= at));
+        if let Some(t) = idx.map(|i| &mut turns[i]) {
+            t.changes.extend(c.files.iter().cloned());
+            t.undone |= c.undone;
+        }
+    }
+    turns
+}
+
+fn parse_time(rfc3339: &str) -> Option<chrono::DateTime<chrono::FixedOffset>> {
+    chrono::DateTime::parse_from_rfc3339(rfc3339).ok()
+}
+
+fn local_time(rfc3339: &str) -> String {
+    parse_time(rfc3339)
+        .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| rfc3339.to_string())
+}
+
+// ==================== Markdown ====================
+
+pub fn render_markdown(t: &Transcript) -> String {
+    let mut out = format!("# Transcript: {}\n\n", t.agent);
+    let _ = writeln!(out, "- Agent type: {}", t.agent_type);
+    if let Some(s) = &t.session {
+        let _ = writeln!(out, "- Session: {}", s);
+    }
+    let _ = writeln!(out, "- Directory: {}", code_span(&t.cwd));
+    let _ = writeln!(out, "- Exported: {}", local_time(&t.exported_at));
+    let _ = writeln!(out, "- Turns: {}", turns_label(&t.turns));
+
+    for turn in &t.turns {
+        let _ = write!(out, "\n---\n\n## Turn {}", turn.number);
+        if !turn.started_at.is_empty() {
+            let _ = write!(out, " · {}", local_time(&turn.started_at));
+        }
+        out.push_str("\n\n");
+        if !turn.prompt.is_empty() {
+            out.push_str("**Prompt**\n\n");
+            for line in turn.prompt.lines() {
+                let _ = writeln!(out, "> {}", line);
+            }
+            out.push('\n');
+        }
+        for item in &turn.items {
+            match item {
+                Item::Message { text } => {
+                    let _ = writeln!(out, "{}\n", text);
+                }
+                Item::Thought { text } => {
+                    let _ = writeln!(out, "<details>\n<summary>Thinking</summary>\n\n{}\n\n</details>\n", text);
+                }
+                Item::ToolCall { title, status } => {
+                    let _ = write!(out, "- **Tool** {}", code_span(title));
+                    if let Some(s) = status {
+                        let _ = write!(out, " — {}", s);
+                    }
+                    out.push_str("\n\n");
+                }
+                Item::Permission { tool, decision } => {
+                    let _ = writeln!(out, "- **Permission** {} — {}\n", code_span(tool), decision);
+                }
+                Item::Note { label, text } => {
+                    let _ = writeln!(out, "- **{}** {}\n", capitalize(label), text.trim());
+                }
+            }
+        }
+        if let Some(plan) = &turn.plan {
+            let _ = writeln!(out, "**Plan**\n\n{}", fence(plan, ""));
+        }
+        if !turn.changes.is_empty() {
+            let undone = if turn.undone { " (undone)" } else { "" };
+            let _ = writeln!(out, "**Files changed{}**\n", undone);
+            for f in &turn.changes {
+                let _ = writeln!(out, "- `{}` {}", f.kind.letter(), code_span(&f.path));
+            }
+            out.push('\n');
+            for f in &turn.changes {
+                if let Some(diff) = f.diff.as_deref().filter(|d| !d.trim().is_empty()) {
+                    let _ = writeln!(
+                        out,
+                        "<details>\n<summary>Diff: {}</summary>\n\n{}</details>\n",
+                        escape_html(&f.path),
+                        fence(diff, "diff"),
+                    );
+                }
+            }
+        }
+        let footer: Vec<String> = [
+            turn.stop_reason.as_ref().map(|s| format!("Stop reason: {}", s)),
+            turn.usage.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !footer.is_empty() {
+            let _ = writeln!(out, "_{}_", footer.join(" · "));
+        }
+    }
+    out
+}
+
+fn turns_label(turns: &[Turn]) -> String {
+    match (turns.first(), turns.last()) {
+        (Some(a), Some(b)) if a.number == b.number => a.number.to_string(),
+        (Some(a), Some(b)) => format!("{}–{}", a.number, b.number),
+        _ => "none".into(),
+    }
+}
+
+/// 反引号数量多于内容中最长的连续反引号
+fn fence(text: &str, info: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text).max(2) + 1);
+    let nl = if text.ends_with('\n') { "" } else { "\n" };
+    format!("{}{}\n{}{}{}\n", ticks, info, text, nl, ticks)
+}
+
+fn code_span(text: &str) -> String {
+    let ticks = "`".repeat(longest_backtick_run(text) + 1);
+    let pad = if text.starts_with('`') || text.ends_with('`') { " " } else { "" };
+    format!("{}{}{}{}{}", ticks, pad, text, pad, ticks)
+}
+
+fn longest_backtick_run(text: &str) -> usize {
+    text.split(|c| c != '`').map(str::len).max().unwrap_or(0)
+}
+
+fn capitalize(s: &str) -> String {
+    let mut c = s.chars();
+    c.next().map(|f| f.to_uppercase().chain(c).collect()).unwrap_or_default()
+}
+
+// ==================== HTML ====================
+
+const HTML_STYLE: &str = "\
+body{font-family:system-ui,sans-serif;max-width:960px;margin:2em auto;padding:0 1em;line-height:1.5;color:#222}
+.meta{color:#555}.meta dt{font-weight:600;float:left;clear:left;width:8em}.meta dd{margin-left:8em}
+.turn{border-top:1px solid #ddd;margin-top:2em}.turn h2 small{color:#888;font-weight:normal;font-size:.6em}
+.prompt{background:#eef4ff;border-left:4px solid #4a7bd0;padding:.5em 1em;white-space:pre-wrap}
+.message{white-space:pre-wrap;margin:1em 0}
+details{margin:.5em 0}summary{cursor:pointer;color:#555}
+.thought .text{white-space:pre-wrap;color:#666;font-style:italic;padding-left:1em}
+.tool,.permission,.note{font-size:.9em;margin:.3em 0}
+.status,.decision{display:inline-block;padding:0 .4em;border-radius:3px;background:#eee;font-size:.85em}
+.completed,.approved,.auto-approved{background:#dfd}.failed,.denied{background:#fdd}.pending,.inprogress{background:#ffd}
+pre{background:#f6f6f6;padding:.6em;overflow-x:auto}
+.diff .add{color:#080}.diff .del{color:#b00}.diff .hunk{color:#07a}
+.footer{color:#888;font-size:.85em}";
+
+pub fn render_html(t: &Transcript) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <title>Transcript: {0}</title>\n<style>\n{1}\n</style>\n</head>\n<body>\n\
+         <h1>Transcript: {0}</h1>\n<dl class=\"meta\">\n",
+        escape_html(&t.agent),
+        HTML_STYLE,
+    );
+    let mut meta = vec![("Agent type", t.agent_type.clone())];
+    if let Some(s) = &t.session {
+        meta.push(("Session", s.clone()));
+    }
+    meta.push(("Directory", t.cwd.clone()));
+    meta.push(("Exported", local_time(&t.exported_at)));
+    meta.push(("Turns", turns_label(&t.turns)));
+    for (k, v) in meta {
+        let _ = writeln!(out, "<dt>{}</dt><dd>{}</dd>", k, escape_html(&v));
+    }
+    out.push_str("</dl>\n");
+
+    for turn in &t.turns {
+        let _ = write!(out, "<section class=\"turn\" id=\"turn-{0}\">\n<h2>Turn {0}", turn.number);
+        if !turn.started_at.is_empty() {
+            let _ = write!(out, " <small>{}</small>", escape_html(&local_time(&turn.started_at)));
+        }
+        out.push_str("</h2>\n");
+        if !turn.prompt.is_empty() {
+            let _ = writeln!(out, "<div class=\"prompt\">{}</div>", escape_html(&turn.prompt));
+        }
+        for item in &turn.items {
+            match item {
+                Item::Message { text } => {
+                    let _ = writeln!(out, "<div class=\"message\">{}</div>", escape_html(text));
+                }
+                Item::Thought { text } => {
+                    let _ = writeln!(
+                        out,
+                        "<details class=\"thought\"><summary>Thinking</summary><div class=\"text\">{}</div></details>",
+                        escape_html(text),
+                    );
+                }
+                Item::ToolCall { title, status } => {
+                    let _ = write!(out, "<div class=\"tool\">Tool <code>{}</code>", escape_html(title));
+                    if let Some(s) = status {
+                        let _ = write!(out, " <span class=\"status {}\">{}</span>", s.to_ascii_lowercase(), escape_html(s));
+                    }
+                    out.push_str("</div>\n");
+                }
+                Item::Permission { tool, decision } => {
+                    let _ = writeln!(
+                        out,
+                        "<div class=\"permission\">Permission <code>{}</code> <span class=\"decision {}\">{}</span></div>",
+                        escape_html(tool),
+                        decision,
+                        escape_html(decision),
+                    );
+                }
+                Item::Note { label, text } => {
+                    let _ = writeln!(
+                        out,
+                        "<div class=\"note\"><strong>{}</strong> {}</div>",
+                        escape_html(&capitalize(label)),
+                        escape_html(text.trim()),
+                    );
+                }
+            }
+        }
+        if let Some(plan) = &turn.plan {
+            let _ = writeln!(out, "<details class=\"plan\" open><summary>Plan</summary><pre>{}</pre></details>", escape_html(plan));
+        }
+        if !turn.changes.is_empty() {
+            let undone = if turn.undone { " (undone)" } else { "" };
+            let _ = writeln!(out, "<h3>Files changed{}</h3>\n<ul>", undone);
+            for f in &turn.changes {
+                let _ = writeln!(out, "<li><code>{}</code> {}</li>", f.kind.letter(), escape_html(&f.path));
+            }
+            out.push_str("</ul>\n");
+            for f in &turn.changes {
+                if let Some(diff) = f.diff.as_deref().filter(|d| !d.trim().is_empty()) {
+                    let _ = writeln!(
+                        out,
+                        "<details><summary>Diff: {}</summary><pre class=\"diff\">{}</pre></details>",
+                        escape_html(&f.path),
+                        diff_html(diff),
+                    );
+                }
+            }
+        }
+        let footer: Vec<String> = [
+            turn.stop_reason.as_ref().map(|s| format!("Stop reason: {}", s)),
+            turn.usage.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if !footer.is_empty() {
+            let _ = writeln!(out, "<p class=\"footer\">{}</p>", escape_html(&footer.join(" · ")));
+        }
+        out.push_str("</section>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn diff_html(diff: &str) -> String {
+    diff.lines()
+        .map(|line| {
+            let class = if line.starts_with("+++") || line.starts_with("---") {
+                None
+            } else if line.starts_with('+') {
+                Some("add")
+            } else if line.starts_with('-') {
+                Some("del")
+            } else if line.starts_with("@@") {
+                Some("hunk")
+            } else {
+                None
+            };
+            match class {
+                Some(c) => format!("<span class=\"{}\">{}</span>\n", c, escape_html(line)),
+                None => format!("{}\n", escape_html(line)),
+            }
+        })
+        .collect()
+}
+
+fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// ==================== 收集 ====================
+
+/// 读取 summary、输出缓冲与改动记录（含 diff），整理并按范围筛选
+pub async fn collect(
+    config: &TeamConfig,
+    name: &str,
+    session: Option<&str>,
+    range: Option<TurnRange>,
+) -> Result<Transcript> {
+    let summary = match client::send(config, name, SessionRequest::GetStatus).await? {
+        SessionResponse::Status { summary } => summary,
+        SessionResponse::Error { message } => bail!(message),
+        _ => bail!("Unexpected response from '{}'", name),
+    };
+    let req = SessionRequest::GetOutput { last: 0, agent_only: false }.in_session(session);
+    let entries = match client::send(config, name, req).await? {
+        SessionResponse::Output { entries, .. } => entries,
+        SessionResponse::Error { message } => bail!(message),
+        _ => bail!("Unexpected response from '{}'", name),
+    };
+    // 改动只跟踪默认 session；追踪关闭时返回错误，视为无记录
+    let changes = match session {
+        Some(_) => vec![],
+        None => match client::send(config, name, SessionRequest::GetChanges { turn: None, diff: true }).await {
+            Ok(SessionResponse::Changes { turns, .. }) => turns,
+            _ => vec![],
+        },
+    };
+
+    let mut turns = group_turns(&entries, &changes);
+    if let Some(r) = range {
+        let total = turns.len();
+        turns.retain(|t| r.contains(t.number));
+        if turns.is_empty() {
+            bail!("No turns in that range ({} has {} turn(s))", name, total);
+        }
+    }
+    Ok(Transcript {
+        agent: name.to_string(),
+        agent_type: summary.agent_type.clone(),
+        session: session.map(str::to_string),
+        cwd: summary.cwd.clone(),
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        turns,
+    })
+}
+
+pub fn render(t: &Transcript, format: Format) -> Result<String> {
+    Ok(match format {
+        Format::Markdown => render_markdown(t),
+        Format::Html => render_html(t),
+        Format::Json => serde_json::to_string_pretty(t)? + "\n",
+    })
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::messages::ChangeKind;
+
+    fn entry(t: OutputType, ts: &str, c: &str) -> OutputEntry {
+        OutputEntry { timestamp: ts.into(), update_type: t, content: c.into() }
+    }
+
+    fn sample() -> Vec<OutputEntry> {
+        let t1 = "2026-01-01T10:00:00+00:00";
+        let t2 = "2026-01-01T11:00:00+00:00";
+        vec![
+            entry(OutputType::UserPrompt, t1, "fix the bug"),
+            entry(OutputType::AgentThought, t1, "Let me "),
+            entry(OutputType::AgentThought, t1, "look."),
+            entry(OutputType::ToolCallStart, t1, "Read src/a.rs"),
+            entry(OutputType::ToolCallUpdate, t1, "Completed"),
+            entry(OutputType::PermissionRequest, t1, "Permission requested: rm tmp (Waiting for approval)"),
+            entry(OutputType::PermissionRequest, t1, "Permission denied: rm tmp"),
+            entry(OutputType::AgentMessage, t1, "Fixed "),
+            entry(OutputType::AgentMessage, t1, "it."),
+            entry(OutputType::PlanUpdate, t1, "Plan:\n  [Completed] fix"),
+            entry(OutputType::Usage, t1, "Turn 1: ~20 tokens"),
+            entry(OutputType::PromptResponse, t1, "EndTurn"),
+            entry(OutputType::UserPrompt, t2, "now test it"),
+            entry(OutputType::AgentMessage, t2, "Tests <pass>"),
+            entry(OutputType::PromptResponse, t2, "EndTurn"),
+        ]
+    }
+
+    fn changes() -> Vec<TurnChanges> {
+        vec![TurnChanges {
+            turn: 7,
+            prompt: "fix the bug".into(),
+            started_at: "2026-01-01T10:00:01+00:00".into(),
+            files: vec![FileChange {
+                path: "src/a.rs".into(),
+                kind: ChangeKind::Modified,
+                diff: Some("@@ -1 +1 @@\n-old\n+new\n".into()),
+            }],
+            undone: false,
+        }]
+    }
+
+    fn transcript(range: Option<TurnRange>) -> Transcript {
+        let mut turns = group_turns(&sample(), &changes());
+        if let Some(r) = range {
+            turns.retain(|t| r.contains(t.number));
+        }
+        Transcript {
+            agent: "coder".into(),
+            agent_type: "gemini".into(),
+            session: None,
+            cwd: "/repo".into(),
+            exported_at: "2026-01-01T12:00:00+00:00".into(),
+            turns,
+        }
+    }
+
+    #[test]
+    fn groups_entries_into_turns() {
+        let turns = group_turns(&sample(), &changes());
+        assert_eq!(turns.len(), 2);
+        let t = &turns[0];
+        assert_eq!((t.number, t.prompt.as_str()), (1, "fix the bug"));
+        assert_eq!(
+            t.items,
+            vec![
+                Item::Thought { text: "Let me look.".into() },
+                Item::ToolCall { title: "Read src/a.rs".into(), status: Some("Completed".into()) },
+                Item::Permission { tool: "rm tmp".into(), decision: "denied".into() },
+                Item::Message { text: "Fixed it.".into() },
+            ],
+        );
+        assert_eq!(t.plan.as_deref(), Some("  [Completed] fix"));
+        assert_eq!(t.stop_reason.as_deref(), Some("EndTurn"));
+        assert_eq!(t.changes.len(), 1);
+        assert!(turns[1].changes.is_empty());
+    }
+
+    #[test]
+    fn parses_turn_ranges() {
+        let r = |s: &str| s.parse::<TurnRange>();
+        assert_eq!(r("3"), Ok(TurnRange { start: Some(3), end: Some(3) }));
+        assert_eq!(r("2..5"), Ok(TurnRange { start: Some(2), end: Some(5) }));
+        assert_eq!(r("4.."), Ok(TurnRange { start: Some(4), end: None }));
+        assert_eq!(r("..2"), Ok(TurnRange { start: None, end: Some(2) }));
+        for bad in ["", "0", "5..2", "a..b", "0..3"] {
+            assert!(r(bad).is_err(), "{}", bad);
+        }
+        assert!(r("2..").unwrap().contains(9));
+        assert!(!r("..2").unwrap().contains(3));
+    }
+
+    #[test]
+    fn markdown_has_collapsible_thoughts_and_diffs() {
+        let md = render_markdown(&transcript(None));
+        assert!(md.starts_with("# Transcript: coder\n"));
+        assert!(md.contains("- Turns: 1–2\n"));
+        assert!(md.contains("**Prompt**\n\n> fix the bug\n"));
+        assert!(md.contains("<details>\n<summary>Thinking</summary>\n\nLet me look.\n\n</details>"));
+        assert!(md.contains("- **Tool** `Read src/a.rs` — Completed"));
+        assert!(md.contains("- **Permission** `rm tmp` — denied"));
+        assert!(md.contains("- `M` `src/a.rs`"));
+        assert!(md.contains("<summary>Diff: src/a.rs</summary>\n\n```diff\n@@ -1 +1 @@\n-old\n+new\n```\n"));
+        assert!(md.contains("_Stop reason: EndTurn · Turn 1: ~20 tokens_"));
+
+        let only_second = render_markdown(&transcript(Some("2".parse().unwrap())));
+        assert!(!only_second.contains("fix the bug") && only_second.contains("## Turn 2"));
+        assert_eq!(code_span("a`b"), "``a`b``");
+        assert!(fence("```rust\n```", "").starts_with("````\n"));
+    }
+
+    #[test]
+    fn html_escapes_and_json_is_structured() {
+        let html = render_html(&transcript(None));
+        assert!(html.contains("<div class=\"message\">Tests &lt;pass&gt;</div>"));
+        assert!(html.contains("<span class=\"status completed\">Completed</span>"));
+        assert!(html.contains("<span class=\"del\">-old</span>"));
+        assert!(html.trim_end().ends_with("</html>"));
+
+        let json: serde_json::Value = serde_json::from_str(&render(&transcript(None), Format::Json).unwrap()).unwrap();
+        assert_eq!(json["turns"][0]["items"][2]["type"], "permission");
+        assert_eq!(json["turns"][0]["changes"][0]["kind"], "modified");
+        assert_eq!(json["turns"][1]["number"], 2);
+    }
+}
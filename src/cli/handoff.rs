@@ -0,0 +1,332 @@
+// ============================================================
+// handoff - 跨 agent 类型交接任务
+// ============================================================
+// 从源 agent 的输出缓冲与改动记录整理出结构化交接 prompt
+// （用户 prompt、每个 turn 的最终回复、最新 plan、改动文件），
+// 按模板渲染并控制在 token 预算内，再交给目标 agent。
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::TeamConfig;
+use crate::protocol::messages::{
+    ChangeKind, OutputEntry, OutputType, SessionRequest, SessionResponse, TurnChanges,
+};
+
+use super::client;
+
+/// 模板变量
+const VAR_FROM: &str = "{{from}}";
+const VAR_TYPE: &str = "{{type}}";
+const VAR_CONVERSATION: &str = "{{conversation}}";
+const VAR_PLAN: &str = "{{plan}}";
+const VAR_FILES: &str = "{{files}}";
+const VAR_TASK: &str = "{{task}}";
+
+pub const DEFAULT_TEMPLATE: &str = "\
+You are taking over a task from agent '{{from}}' ({{type}}). \
+Below is a handoff of its session; the files it changed are already on disk.
+
+## Conversation
+{{conversation}}
+
+## Current plan
+{{plan}}
+
+## Files changed
+{{files}}
+
+## Your task
+{{task}}
+";
+
+const DEFAULT_TASK: &str = "Continue the work from where it stopped.";
+
+/// 粗略估算：约 4 个字符一个 token
+const CHARS_PER_TOKEN: usize = 4;
+/// 文件列表最多列出的条目
+const MAX_FILES: usize = 200;
+
+// ==================== 交接内容 ====================
+
+#[derive(Debug, Default)]
+pub struct HandoffSource {
+    pub from: String,
+    pub agent_type: String,
+    pub turns: Vec<HandoffTurn>,
+    /// 最新一次 plan 更新
+    pub plan: Option<String>,
+    /// 按路径排序的改动文件（取最后一次改动的类型）
+    pub files: Vec<(String, ChangeKind)>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct HandoffTurn {
+    pub prompt: String,
+    /// turn 内最后一段 agent 回复
+    pub reply: String,
+}
+
+impl HandoffSource {
+    /// 从输出缓冲（GetOutput last=0）与改动记录（GetChanges）整理
+    pub fn from_output(
+        from: &str,
+        agent_type: &str,
+        entries: &[OutputEntry],
+        changes: &[TurnChanges],
+    ) -> Self {
+        let mut turns: Vec<HandoffTurn> = vec![];
+        let mut plan = None;
+        for e in entries {
+            match e.update_type {
+                OutputType::UserPrompt => turns.push(HandoffTurn {
+                    prompt: e.content.trim().to_string(),
+                    reply: String::new(),
+                }),
+                OutputType::AgentMessage => {
+                    if turns.is_empty() {
+                        turns.push(HandoffTurn::default());
+                    }
+                    if let Some(t) = turns.last_mut() {
+                        t.reply = e.content.trim().to_string();
+                    }
+                }
+                OutputType::PlanUpdate => {
+                    let p = e.content.strip_prefix("Plan:\n").unwrap_or(&e.content);
+                    plan = Some(p.trim_end().to_string());
+                }
+                _ => {}
+            }
+        }
+        turns.retain(|t| !t.prompt.is_empty() || !t.reply.is_empty());
+
+        let mut files = BTreeMap::new();
+        for t in changes.iter().filter(|t| !t.undone) {
+            for f in &t.files {
+                files.insert(f.path.clone(), f.kind);
+            }
+        }
+        Self {
+            from: from.to_string(),
+            agent_type: agent_type.to_string(),
+            turns,
+            plan,
+            files: files.into_iter().collect(),
+        }
+    }
+
+    /// 按模板渲染；超出 token 预算时先丢弃最早的 turn，仍超出则截断对话开头
+    pub fn render(&self, template: &str, task: Option<&str>, budget: usize) -> String {
+        let budget_chars = budget.saturating_mul(CHARS_PER_TOKEN);
+        let fill = |conversation: &str| {
+            template
+                .replace(VAR_FROM, &self.from)
+                .replace(VAR_TYPE, &self.agent_type)
+                .replace(VAR_PLAN, self.plan.as_deref().unwrap_or("(none)"))
+                .replace(VAR_FILES, &self.files_section())
+                .replace(VAR_TASK, task.unwrap_or(DEFAULT_TASK))
+                .replace(VAR_CONVERSATION, conversation)
+        };
+
+        let mut skip = 0;
+        loop {
+            let conversation = self.conversation(skip);
+            let out = fill(&conversation);
+            let len = out.chars().count();
+            if len <= budget_chars {
+                return out;
+            }
+            if skip + 1 < self.turns.len() {
+                skip += 1;
+                continue;
+            }
+            // 只剩一个 turn：保留对话末尾
+            let conv_len = conversation.chars().count();
+            let keep = conv_len.saturating_sub(len - budget_chars + 1);
+            return fill(&tail_chars(&conversation, keep));
+        }
+    }
+
+    /// 估算 token 数
+    pub fn estimate_tokens(text: &str) -> usize {
+        text.chars().count().div_ceil(CHARS_PER_TOKEN)
+    }
+
+    fn conversation(&self, skip: usize) -> String {
+        if self.turns.is_empty() {
+            return "(no conversation)".into();
+        }
+        let mut parts = vec![];
+        if skip > 0 {
+            parts.push(format!("({} earlier turn(s) omitted)", skip));
+        }
+        for (i, t) in self.turns.iter().enumerate().skip(skip) {
+            let mut s = format!("### Turn {}\nUser: {}", i + 1, t.prompt);
+            if !t.reply.is_empty() {
+                s.push_str(&format!("\n{}: {}", self.from, t.reply));
+            }
+            parts.push(s);
+        }
+        parts.join("\n\n")
+    }
+
+    fn files_section(&self) -> String {
+        if self.files.is_empty() {
+            return "(none recorded)".into();
+        }
+        let mut lines: Vec<String> = self
+            .files
+            .iter()
+            .take(MAX_FILES)
+            .map(|(path, kind)| format!("{} {}", kind.letter(), path))
+            .collect();
+        if self.files.len() > MAX_FILES {
+            lines.push(format!("... and {} more", self.files.len() - MAX_FILES));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 保留末尾 keep 个字符，截断处加省略号
+fn tail_chars(s: &str, keep: usize) -> String {
+    let total = s.chars().count();
+    if total <= keep {
+        return s.to_string();
+    }
+    let tail: String = s.chars().skip(total - keep).collect();
+    format!("…{}", tail)
+}
+
+// ==================== 收集 ====================
+
+/// 读取源 agent 的 summary、输出与改动记录
+pub async fn collect(config: &TeamConfig, from: &str) -> Result<HandoffSource> {
+    let summary = match client::send(config, from, SessionRequest::GetStatus).await? {
+        SessionResponse::Status { summary } => summary,
+        SessionResponse::Error { message } => bail!(message),
+        _ => bail!("Unexpected response from '{}'", from),
+    };
+    let entries = match client::send(
+        config,
+        from,
+        SessionRequest::GetOutput { last: 0, agent_only: false },
+    )
+    .await?
+    {
+        SessionResponse::Output { entries, .. } => entries,
+        _ => vec![],
+    };
+    // 改动追踪关闭时返回错误，视为无记录
+    let changes = match client::send(
+        config,
+        from,
+        SessionRequest::GetChanges { turn: None, diff: false },
+    )
+    .await
+    {
+        Ok(SessionResponse::Changes { turns, .. }) => turns,
+        _ => vec![],
+    };
+    Ok(HandoffSource::from_output(from, &summary.agent_type, &entries, &changes))
+}
+
+/// 模板：文件优先，否则默认模板
+pub fn load_template(path: Option<&Path>) -> Result<String> {
+    match path {
+        Some(p) => std::fs::read_to_string(p)
+            .with_context(|| format!("Cannot read template {}", p.display())),
+        None => Ok(DEFAULT_TEMPLATE.to_string()),
+    }
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::messages::FileChange;
+
+    fn entry(update_type: OutputType, content: &str) -> OutputEntry {
+        OutputEntry {
+            timestamp: "t".into(),
+            update_type,
+            content: content.into(),
+        }
+    }
+
+    fn change(turn: u64, path: &str, kind: ChangeKind, undone: bool) -> TurnChanges {
+        TurnChanges {
+            turn,
+            prompt: String::new(),
+            started_at: String::new(),
+            files: vec![FileChange { path: path.into(), kind, diff: None }],
+            undone,
+        }
+    }
+
+    fn sample() -> HandoffSource {
+        let entries = vec![
+            entry(OutputType::UserPrompt, "add a parser"),
+            entry(OutputType::AgentMessage, "looking"),
+            entry(OutputType::PlanUpdate, "Plan:\n  [Pending] write tests"),
+            entry(OutputType::ToolCallStart, "edit parser.rs"),
+            entry(OutputType::AgentMessage, "parser added"),
+            entry(OutputType::PromptResponse, "EndTurn"),
+            entry(OutputType::UserPrompt, "now the lexer"),
+            entry(OutputType::AgentMessage, "lexer done"),
+        ];
+        let changes = vec![
+            change(1, "src/parser.rs", ChangeKind::Added, false),
+            change(2, "src/lexer.rs", ChangeKind::Modified, false),
+            change(2, "src/parser.rs", ChangeKind::Modified, false),
+            change(3, "junk.txt", ChangeKind::Added, true),
+        ];
+        HandoffSource::from_output("gemini-1", "gemini", &entries, &changes)
+    }
+
+    #[test]
+    fn collects_turns_plan_and_files() {
+        let src = sample();
+        assert_eq!(src.turns.len(), 2);
+        assert_eq!(src.turns[0].prompt, "add a parser");
+        assert_eq!(src.turns[0].reply, "parser added");
+        assert_eq!(src.plan.as_deref(), Some("  [Pending] write tests"));
+        assert_eq!(
+            src.files,
+            vec![
+                ("src/lexer.rs".to_string(), ChangeKind::Modified),
+                ("src/parser.rs".to_string(), ChangeKind::Modified),
+            ]
+        );
+
+        let out = src.render(DEFAULT_TEMPLATE, Some("write the docs"), 10_000);
+        assert!(out.contains("agent 'gemini-1' (gemini)"));
+        assert!(out.contains("### Turn 2\nUser: now the lexer\ngemini-1: lexer done"));
+        assert!(out.contains("M src/lexer.rs"));
+        assert!(out.contains("## Your task\nwrite the docs"));
+    }
+
+    #[test]
+    fn custom_template_and_empty_source() {
+        let src = HandoffSource::from_output("a", "claude", &[], &[]);
+        let out = src.render("{{from}}|{{plan}}|{{files}}|{{conversation}}|{{task}}", None, 1000);
+        assert_eq!(out, format!("a|(none)|(none recorded)|(no conversation)|{}", DEFAULT_TASK));
+    }
+
+    #[test]
+    fn budget_drops_oldest_turns_then_truncates() {
+        let mut src = sample();
+        src.turns[0].reply = "x".repeat(4000);
+        let out = src.render(VAR_CONVERSATION, None, 100);
+        assert!(out.starts_with("(1 earlier turn(s) omitted)"));
+        assert!(out.contains("lexer done"));
+
+        src.turns[1].reply = "y".repeat(4000);
+        let out = src.render(VAR_CONVERSATION, None, 100);
+        assert!(HandoffSource::estimate_tokens(&out) <= 100);
+        assert!(out.starts_with('…'));
+        assert!(out.ends_with('y'));
+    }
+}
@@ -0,0 +1,785 @@
+// ============================================================
+// http - REST / SSE 网关
+// ============================================================
+// `agent-team serve --http <addr>` 把 HTTP 请求映射为 SessionRequest / DaemonRequest，
+// 供浏览器、脚本等非 Rust 客户端使用；实时事件经 Server-Sent Events 推送。
+// 手写最小 HTTP/1.1：每个连接一个请求（Connection: close），
+// 认证复用远程访问 token（Authorization: Bearer，或 EventSource 用的 ?token=）。
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+use crate::config::{validate_agent_name, AutoApprovePolicy, TeamConfig};
+use crate::protocol::messages::{FileAttachment, SessionEvent, SessionRequest, SessionResponse};
+use crate::protocol::remote::{load_or_create_token, token_eq};
+use crate::session::server::signal_shutdown;
+
+use super::{broadcast_all, client, launch, LaunchOptions};
+
+/// 请求行 + header 上限
+const MAX_HEAD: u64 = 16 * 1024;
+/// body 上限（prompt 可附带文件内容）
+const MAX_BODY: usize = 8 * 1024 * 1024;
+/// 读取请求的超时，避免空闲连接占用
+const READ_TIMEOUT_SECS: u64 = 10;
+/// /events 重新扫描新 agent 的间隔
+const RESCAN_SECS: u64 = 2;
+/// SSE 心跳间隔（同时用于发现已断开的客户端）
+const PING_SECS: u64 = 15;
+/// GET output 默认条数，与 `output` 命令一致
+const DEFAULT_OUTPUT_LAST: usize = 1;
+
+struct Gateway {
+    config: TeamConfig,
+    token: String,
+}
+
+/// 网关主循环：每个连接一个 local task，收到信号后退出
+pub async fn run_gateway(config: TeamConfig, addr: &str) -> Result<()> {
+    let token = load_or_create_token(&config.remote.token_file)?;
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Cannot listen on {}", addr))?;
+    println!("HTTP gateway listening on http://{}", listener.local_addr()?);
+    println!("Token: {}", config.remote.token_file.display());
+
+    let gw = Rc::new(Gateway { config, token });
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let (stream, _) = result.context("Accept failed")?;
+                let gw = Rc::clone(&gw);
+                tokio::task::spawn_local(async move {
+                    let _ = serve_connection(&gw, stream).await;
+                });
+            }
+            _ = signal_shutdown() => break,
+        }
+    }
+    Ok(())
+}
+
+async fn serve_connection(gw: &Gateway, mut stream: TcpStream) -> Result<()> {
+    let (read, mut write) = stream.split();
+    let mut reader = BufReader::new(read);
+
+    let req = match tokio::time::timeout(
+        Duration::from_secs(READ_TIMEOUT_SECS),
+        read_request(&mut reader),
+    )
+    .await
+    {
+        Ok(Ok(req)) => req,
+        Ok(Err(e)) => return write_error(&mut write, e).await,
+        Err(_) => return write_error(&mut write, HttpError::new(408, "Request timeout")).await,
+    };
+
+    if !authorized(&req, &gw.token) {
+        return write_error(&mut write, HttpError::new(401, "Missing or invalid token")).await;
+    }
+
+    match route(&req) {
+        Ok(Route::List) => {
+            let (status, body) = list_agents(&gw.config).await;
+            write_json(&mut write, status, &body).await
+        }
+        Ok(Route::Spawn(body)) => {
+            let (status, body) = spawn_agent(&gw.config, body).await;
+            write_json(&mut write, status, &body).await
+        }
+        Ok(Route::Agent { name, request }) => {
+            let (status, body) = forward(&gw.config, &name, request).await;
+            write_json(&mut write, status, &body).await
+        }
+        Ok(Route::Events(name)) => stream_events(&gw.config, name, &mut write).await,
+        Err(e) => write_error(&mut write, e).await,
+    }
+}
+
+// ==================== 请求解析 ====================
+
+#[derive(Debug)]
+struct HttpError {
+    status: u16,
+    message: String,
+}
+
+impl HttpError {
+    fn new(status: u16, message: impl Into<String>) -> Self {
+        Self { status, message: message.into() }
+    }
+}
+
+#[derive(Debug, Default)]
+struct HttpRequest {
+    method: String,
+    /// 已 percent-decode 的路径段
+    path: Vec<String>,
+    query: HashMap<String, String>,
+    /// header 名统一小写
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+async fn read_request<R: AsyncBufRead + Unpin>(reader: &mut R) -> Result<HttpRequest, HttpError> {
+    let mut head = (&mut *reader).take(MAX_HEAD);
+    let mut line = String::new();
+    let read_line = |n: std::io::Result<usize>| {
+        n.map_err(|_| HttpError::new(400, "Malformed request"))
+    };
+
+    if read_line(head.read_line(&mut line).await)? == 0 {
+        return Err(HttpError::new(400, "Empty request"));
+    }
+    let mut req = parse_request_line(line.trim_end())?;
+
+    loop {
+        line.clear();
+        if read_line(head.read_line(&mut line).await)? == 0 || !line.ends_with('\n') {
+            return Err(match head.limit() {
+                0 => HttpError::new(431, "Request header too large"),
+                _ => HttpError::new(400, "Incomplete request header"),
+            });
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((k, v)) = header.split_once(':') {
+            req.headers.insert(k.trim().to_ascii_lowercase(), v.trim().to_string());
+        }
+    }
+
+    let len = match req.headers.get("content-length") {
+        Some(v) => v.parse::<usize>().map_err(|_| HttpError::new(400, "Invalid Content-Length"))?,
+        None => 0,
+    };
+    if len > MAX_BODY {
+        return Err(HttpError::new(413, "Request body too large"));
+    }
+    req.body = vec![0; len];
+    reader
+        .read_exact(&mut req.body)
+        .await
+        .map_err(|_| HttpError::new(400, "Truncated request body"))?;
+    Ok(req)
+}
+
+/// `GET /agents/a%20b/output?last=3 HTTP/1.1`
+fn parse_request_line(line: &str) -> Result<HttpRequest, HttpError> {
+    let mut parts = line.split(' ');
+    let (Some(method), Some(target), Some(version)) = (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(HttpError::new(400, "Malformed request line"));
+    };
+    if !version.starts_with("HTTP/1.") {
+        return Err(HttpError::new(505, "Only HTTP/1.x is supported"));
+    }
+
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let path = path
+        .split('/')
+        .filter(|s| !s.is_empty())
+        .map(percent_decode)
+        .collect();
+    let query = query
+        .split('&')
+        .filter(|s| !s.is_empty())
+        .map(|kv| {
+            let (k, v) = kv.split_once('=').unwrap_or((kv, ""));
+            (percent_decode(&k.replace('+', " ")), percent_decode(&v.replace('+', " ")))
+        })
+        .collect();
+
+    Ok(HttpRequest {
+        method: method.to_string(),
+        path,
+        query,
+        ..Default::default()
+    })
+}
+
+/// %XX 解码；非法序列原样保留
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(b) => {
+                out.push(b);
+                i += 3;
+            }
+            None => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn authorized(req: &HttpRequest, token: &str) -> bool {
+    let presented = req
+        .headers
+        .get("authorization")
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| req.query.get("token").map(String::as_str));
+    presented.is_some_and(|t| token_eq(t.trim(), token))
+}
+
+// ==================== 路由 ====================
+
+#[derive(Debug)]
+enum Route {
+    List,
+    Spawn(SpawnBody),
+    Agent { name: String, request: SessionRequest },
+    /// None = 所有 agent
+    Events(Option<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct SpawnBody {
+    agent_type: String,
+    name: Option<String>,
+    cwd: Option<PathBuf>,
+    args: Option<String>,
+    auto_approve: Option<String>,
+    #[serde(default)]
+    no_mcp: bool,
+}
+
+#[derive(Deserialize)]
+struct PromptBody {
+    text: String,
+    #[serde(default)]
+    files: Vec<FileAttachment>,
+}
+
+#[derive(Deserialize)]
+struct ModeBody {
+    mode: String,
+}
+
+#[derive(Deserialize)]
+struct ConfigBody {
+    key: String,
+    value: String,
+}
+
+#[derive(Deserialize)]
+struct BudgetBody {
+    #[serde(default)]
+    max_tokens: Option<u64>,
+    #[serde(default)]
+    max_cost: Option<f64>,
+    #[serde(default)]
+    clear: bool,
+}
+
+#[derive(Deserialize)]
+struct NotifyBody {
+    enabled: bool,
+}
+
+#[derive(Deserialize)]
+struct ResolveBody {
+    approve: bool,
+}
+
+#[derive(Default, Deserialize)]
+struct UndoBody {
+    turn: Option<u64>,
+}
+
+fn route(req: &HttpRequest) -> Result<Route, HttpError> {
+    let path: Vec<&str> = req.path.iter().map(String::as_str).collect();
+    let method = req.method.as_str();
+    // 名字会拼成 socket 路径，`..` 之类不能逃出 socket 目录
+    if let ["agents", name, ..] = path.as_slice() {
+        validate_agent_name(name).map_err(|e| HttpError::new(400, e))?;
+    }
+    let agent = |name: &str, request| {
+        Ok(Route::Agent {
+            name: name.to_string(),
+            request,
+        })
+    };
+
+    match (method, path.as_slice()) {
+        ("GET", ["agents"]) => Ok(Route::List),
+        ("POST", ["agents"]) => Ok(Route::Spawn(json_body(req)?)),
+        ("GET", ["events"]) => Ok(Route::Events(None)),
+
+        ("GET", ["agents", name]) => agent(name, SessionRequest::GetStatus),
+        ("DELETE", ["agents", name]) => agent(name, SessionRequest::Shutdown),
+        ("GET", ["agents", name, "events"]) => Ok(Route::Events(Some(name.to_string()))),
+        ("GET", ["agents", name, "output"]) => agent(
+            name,
+            SessionRequest::GetOutput {
+                last: query_parse(req, "last")?.unwrap_or(DEFAULT_OUTPUT_LAST),
+                agent_only: query_parse(req, "agent_only")?.unwrap_or(false),
+            },
+        ),
+        ("GET", ["agents", name, "plan"]) => agent(name, SessionRequest::GetPlan),
+        ("GET", ["agents", name, "permissions"]) => agent(name, SessionRequest::GetPermissions),
+        ("POST", ["agents", name, "permissions", id]) => {
+            let permission_id = id
+                .parse()
+                .map_err(|_| HttpError::new(400, format!("Invalid permission id '{}'", id)))?;
+            let approve = json_body::<ResolveBody>(req)?.approve;
+            agent(name, SessionRequest::ResolvePermission { permission_id, approve })
+        }
+        ("GET", ["agents", name, "changes"]) => agent(
+            name,
+            SessionRequest::GetChanges {
+                turn: query_parse(req, "turn")?,
+                diff: query_parse(req, "diff")?.unwrap_or(false),
+            },
+        ),
+        ("POST", ["agents", name, action]) => {
+            let request = match *action {
+                "prompt" => {
+                    let body: PromptBody = json_body(req)?;
+                    SessionRequest::Prompt {
+                        text: body.text,
+                        files: body.files,
+                        chain: vec![],
+                    }
+                }
+                "approve" => SessionRequest::ApprovePermission,
+                "deny" => SessionRequest::DenyPermission,
+                "cancel" => SessionRequest::Cancel,
+                "restart" => SessionRequest::Restart,
+                "mode" => SessionRequest::SetMode {
+                    mode: json_body::<ModeBody>(req)?.mode,
+                },
+                "config" => {
+                    let body: ConfigBody = json_body(req)?;
+                    SessionRequest::SetConfig {
+                        key: body.key,
+                        value: body.value,
+                    }
+                }
+                "budget" => {
+                    let body: BudgetBody = json_body(req)?;
+                    SessionRequest::SetBudget {
+                        max_tokens: body.max_tokens,
+                        max_cost: body.max_cost,
+                        clear: body.clear,
+                    }
+                }
+                "notify" => {
+                    let body: NotifyBody = json_body(req)?;
+                    SessionRequest::SetNotify { enabled: body.enabled }
+                }
+                "undo" => {
+                    let body: UndoBody = if req.body.is_empty() {
+                        UndoBody::default()
+                    } else {
+                        json_body(req)?
+                    };
+                    SessionRequest::Undo { turn: body.turn }
+                }
+                _ => return Err(HttpError::new(404, format!("Unknown action '{}'", action))),
+            };
+            agent(name, request)
+        }
+
+        (_, ["agents"] | ["events"] | ["agents", _] | ["agents", _, _]) => {
+            Err(HttpError::new(405, format!("{} not allowed here", method)))
+        }
+        _ => Err(HttpError::new(404, "Not found")),
+    }
+}
+
+fn json_body<T: serde::de::DeserializeOwned>(req: &HttpRequest) -> Result<T, HttpError> {
+    serde_json::from_slice(&req.body)
+        .map_err(|e| HttpError::new(400, format!("Invalid JSON body: {}", e)))
+}
+
+fn query_parse<T: std::str::FromStr>(req: &HttpRequest, key: &str) -> Result<Option<T>, HttpError> {
+    req.query
+        .get(key)
+        .map(|v| {
+            v.parse()
+                .map_err(|_| HttpError::new(400, format!("Invalid value for '{}'", key)))
+        })
+        .transpose()
+}
+
+// ==================== 处理 ====================
+
+async fn list_agents(config: &TeamConfig) -> (u16, Value) {
+    let names = match client::session_names(config).await {
+        Ok(n) => n,
+        Err(e) => return (502, json!({ "error": format!("{:#}", e) })),
+    };
+    let results = broadcast_all(config, &names, || SessionRequest::GetStatus).await;
+    let agents: Vec<_> = results
+        .into_iter()
+        .filter_map(|(_, r)| match r {
+            Ok(SessionResponse::Status { summary }) => Some(*summary),
+            _ => None,
+        })
+        .collect();
+    (200, json!({ "agents": agents }))
+}
+
+/// session 不可达 → 404；session 拒绝 → 409；其余原样返回 SessionResponse
+async fn forward(config: &TeamConfig, name: &str, request: SessionRequest) -> (u16, Value) {
+    match client::send(config, name, request).await {
+        Ok(SessionResponse::Error { message }) => (409, json!({ "error": message })),
+        Ok(resp) => (200, serde_json::to_value(&resp).unwrap_or(Value::Null)),
+        Err(e) => (404, json!({ "error": format!("{:#}", e) })),
+    }
+}
+
+/// 与 `add -b` 相同：daemon 运行时交给 daemon，否则启动独立后台进程
+async fn spawn_agent(config: &TeamConfig, body: SpawnBody) -> (u16, Value) {
+    if !config.agent_types.contains_key(&body.agent_type) {
+        return (400, json!({ "error": format!("Unknown agent type '{}'", body.agent_type) }));
+    }
+    let auto_approve = match body.auto_approve.as_deref().map(str::parse::<AutoApprovePolicy>) {
+        Some(Err(e)) => return (400, json!({ "error": e })),
+        Some(Ok(p)) => Some(p),
+        None => None,
+    };
+    let names = match client::session_names(config).await {
+        Ok(n) => n,
+        Err(e) => return (502, json!({ "error": format!("{:#}", e) })),
+    };
+    let name = body
+        .name
+        .unwrap_or_else(|| crate::config::next_name(&body.agent_type, &names));
+    if let Err(e) = validate_agent_name(&name) {
+        return (400, json!({ "error": e }));
+    }
+    if names.contains(&name) {
+        return (409, json!({ "error": format!("Agent '{}' already exists", name) }));
+    }
+
+    // 远程模式下 cwd 省略时以远程 daemon 为准
+    let cwd = body.cwd.or_else(|| {
+        config.remote.host.is_none().then(|| config.default_cwd.clone())
+    });
+    let opts = LaunchOptions {
+        cwd: cwd.as_deref(),
+        args: body.args.as_deref(),
+        auto_approve: auto_approve.as_ref(),
+        no_mcp: body.no_mcp,
+        ..Default::default()
+    };
+    match launch(config, &body.agent_type, &name, &opts).await {
+        Ok(how) => (201, json!({ "name": name, "started": how })),
+        Err(e) => (500, json!({ "error": format!("{:#}", e) })),
+    }
+}
+
+// ==================== Server-Sent Events ====================
+
+/// 订阅一个或所有 agent 的事件；`event:` 为 output / info，`data:` 为 SessionResponse::Event
+async fn stream_events<W: AsyncWrite + Unpin>(
+    config: &TeamConfig,
+    only: Option<String>,
+    writer: &mut W,
+) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Forwarded>();
+    let subscribed = Rc::new(RefCell::new(HashSet::<String>::new()));
+    let mut tasks = Vec::new();
+
+    // 单个 agent：订阅失败直接返回 404，而不是一个空的事件流
+    if let Some(ref name) = only {
+        let mut sub = match subscribe(config, name).await {
+            Ok(c) => c,
+            Err(e) => return write_error(writer, HttpError::new(404, format!("{:#}", e))).await,
+        };
+        let tx = tx.clone();
+        let name = name.clone();
+        tasks.push(tokio::task::spawn_local(async move {
+            pump(&mut sub, &name, &tx).await;
+            tx.send(Forwarded::Closed(name)).ok();
+        }));
+    }
+
+    writer
+        .write_all(
+            b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\n\
+              Cache-Control: no-cache\r\nConnection: close\r\n\r\n",
+        )
+        .await?;
+    writer.flush().await?;
+
+    let mut rescan = tokio::time::interval(Duration::from_secs(RESCAN_SECS));
+    let mut ping = tokio::time::interval(Duration::from_secs(PING_SECS));
+    ping.tick().await;
+
+    let result = loop {
+        let chunk = tokio::select! {
+            Some(fwd) = rx.recv() => match fwd {
+                Forwarded::Event(name, event) => sse_chunk(name, event),
+                Forwarded::Closed(name) if only.is_some() => {
+                    let _ = writer.write_all(sse_closed(&name).as_bytes()).await;
+                    break Ok(());
+                }
+                Forwarded::Closed(name) => {
+                    subscribed.borrow_mut().remove(&name);
+                    sse_closed(&name)
+                }
+            },
+            _ = rescan.tick(), if only.is_none() => {
+                let names = client::session_names(config).await.unwrap_or_default();
+                for name in names {
+                    if !subscribed.borrow_mut().insert(name.clone()) {
+                        continue;
+                    }
+                    let (config, tx) = (config.clone(), tx.clone());
+                    tasks.push(tokio::task::spawn_local(async move {
+                        if let Ok(mut sub) = subscribe(&config, &name).await {
+                            pump(&mut sub, &name, &tx).await;
+                        }
+                        tx.send(Forwarded::Closed(name)).ok();
+                    }));
+                }
+                continue;
+            }
+            _ = ping.tick() => ": ping\n\n".to_string(),
+        };
+        if let Err(e) = writer.write_all(chunk.as_bytes()).await {
+            break Err(e.into());
+        }
+        if let Err(e) = writer.flush().await {
+            break Err(e.into());
+        }
+    };
+
+    for t in tasks {
+        t.abort();
+    }
+    result
+}
+
+enum Forwarded {
+    Event(String, SessionEvent),
+    /// 订阅结束（agent 退出或连接断开）
+    Closed(String),
+}
+
+async fn subscribe(config: &TeamConfig, name: &str) -> Result<client::SessionClient> {
+    let mut sub = client::SessionClient::connect(config, name).await?;
+    sub.subscribe().await?;
+    Ok(sub)
+}
+
+async fn pump(sub: &mut client::SessionClient, name: &str, tx: &mpsc::UnboundedSender<Forwarded>) {
+    while let Ok(Some((_, event))) = sub.next_event().await {
+        if tx.send(Forwarded::Event(name.to_string(), event)).is_err() {
+            break;
+        }
+    }
+}
+
+fn sse_chunk(agent_name: String, event: SessionEvent) -> String {
+    let kind = match event {
+        SessionEvent::Output { .. } => "output",
+        SessionEvent::Info { .. } => "info",
+    };
+    let data = serde_json::to_string(&SessionResponse::Event { agent_name, event })
+        .unwrap_or_default();
+    format!("event: {}\ndata: {}\n\n", kind, data)
+}
+
+fn sse_closed(agent_name: &str) -> String {
+    format!("event: closed\ndata: {}\n\n", json!({ "agent_name": agent_name }))
+}
+
+// ==================== 响应 ====================
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        502 => "Bad Gateway",
+        505 => "HTTP Version Not Supported",
+        _ => "Internal Server Error",
+    }
+}
+
+async fn write_json<W: AsyncWrite + Unpin>(writer: &mut W, status: u16, body: &Value) -> Result<()> {
+    let body = body.to_string();
+    let auth = if status == 401 { "WWW-Authenticate: Bearer\r\n" } else { "" };
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n",
+        status,
+        reason(status),
+        body.len(),
+        auth,
+    );
+    writer.write_all(head.as_bytes()).await?;
+    writer.write_all(body.as_bytes()).await?;
+    writer.flush().await?;
+    Ok(())
+}
+
+async fn write_error<W: AsyncWrite + Unpin>(writer: &mut W, e: HttpError) -> Result<()> {
+    write_json(writer, e.status, &json!({ "error": e.message })).await
+}
+
+// ==================== 测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn parse(raw: &str) -> Result<HttpRequest, HttpError> {
+        let mut reader = BufReader::new(raw.as_bytes());
+        read_request(&mut reader).await
+    }
+
+    fn request(method: &str, target: &str, body: &str) -> HttpRequest {
+        let mut req = parse_request_line(&format!("{} {} HTTP/1.1", method, target)).unwrap();
+        req.body = body.as_bytes().to_vec();
+        req
+    }
+
+    #[tokio::test]
+    async fn parses_request_with_body() {
+        let req = parse(
+            "POST /agents/a%20b/prompt?x=1+2 HTTP/1.1\r\nAuthorization: Bearer t\r\n\
+             Content-Length: 13\r\n\r\n{\"text\":\"hi\"}",
+        )
+        .await
+        .unwrap();
+        assert_eq!(req.method, "POST");
+        assert_eq!(req.path, ["agents", "a b", "prompt"]);
+        assert_eq!(req.query["x"], "1 2");
+        assert_eq!(req.headers["authorization"], "Bearer t");
+        assert_eq!(req.body, b"{\"text\":\"hi\"}");
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_requests() {
+        assert_eq!(parse("").await.unwrap_err().status, 400);
+        assert_eq!(parse("GET /\r\n\r\n").await.unwrap_err().status, 400);
+        assert_eq!(parse("GET / HTTP/2\r\n\r\n").await.unwrap_err().status, 505);
+        let huge = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_HEAD as usize));
+        assert_eq!(parse(&huge).await.unwrap_err().status, 431);
+        let big_body = format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY + 1);
+        assert_eq!(parse(&big_body).await.unwrap_err().status, 413);
+    }
+
+    #[test]
+    fn percent_decoding() {
+        assert_eq!(percent_decode("a%2Fb%20c"), "a/b c");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(percent_decode("%zz"), "%zz");
+    }
+
+    #[test]
+    fn token_from_header_or_query() {
+        let mut req = request("GET", "/agents", "");
+        assert!(!authorized(&req, "secret"));
+        req.headers.insert("authorization".into(), "Bearer secret".into());
+        assert!(authorized(&req, "secret"));
+        assert!(!authorized(&req, "other"));
+        assert!(authorized(&request("GET", "/events?token=secret", ""), "secret"));
+    }
+
+    #[test]
+    fn routes_map_to_session_requests() {
+        assert!(matches!(route(&request("GET", "/agents", "")), Ok(Route::List)));
+        assert!(matches!(route(&request("GET", "/events", "")), Ok(Route::Events(None))));
+        assert!(matches!(
+            route(&request("GET", "/agents/a/events", "")),
+            Ok(Route::Events(Some(n))) if n == "a"
+        ));
+        assert!(matches!(
+            route(&request("GET", "/agents/a/output?last=3&agent_only=true", "")),
+            Ok(Route::Agent { request: SessionRequest::GetOutput { last: 3, agent_only: true }, .. })
+        ));
+        assert!(matches!(
+            route(&request("POST", "/agents/a/prompt", r#"{"text":"hi"}"#)),
+            Ok(Route::Agent { request: SessionRequest::Prompt { text, .. }, .. }) if text == "hi"
+        ));
+        assert!(matches!(
+            route(&request("POST", "/agents/a/budget", r#"{"max_tokens":100}"#)),
+            Ok(Route::Agent { request: SessionRequest::SetBudget { max_tokens: Some(100), clear: false, .. }, .. })
+        ));
+        assert!(matches!(
+            route(&request("POST", "/agents/a/notify", r#"{"enabled":false}"#)),
+            Ok(Route::Agent { request: SessionRequest::SetNotify { enabled: false }, .. })
+        ));
+        assert!(matches!(
+            route(&request("POST", "/agents/a/permissions/7", r#"{"approve":true}"#)),
+            Ok(Route::Agent { request: SessionRequest::ResolvePermission { permission_id: 7, approve: true }, .. })
+        ));
+        assert!(matches!(
+            route(&request("GET", "/agents/a/plan", "")),
+            Ok(Route::Agent { request: SessionRequest::GetPlan, .. })
+        ));
+        assert!(matches!(
+            route(&request("DELETE", "/agents/a", "")),
+            Ok(Route::Agent { request: SessionRequest::Shutdown, .. })
+        ));
+        assert!(matches!(
+            route(&request("POST", "/agents/a/undo", "")),
+            Ok(Route::Agent { request: SessionRequest::Undo { turn: None }, .. })
+        ));
+        assert!(matches!(
+            route(&request("POST", "/agents", r#"{"agent_type":"gemini"}"#)),
+            Ok(Route::Spawn(SpawnBody { agent_type, name: None, .. })) if agent_type == "gemini"
+        ));
+    }
+
+    #[test]
+    fn route_errors() {
+        let status = |m, t, b| route(&request(m, t, b)).unwrap_err().status;
+        assert_eq!(status("GET", "/nope", ""), 404);
+        assert_eq!(status("POST", "/agents/a/fly", ""), 404);
+        assert_eq!(status("PUT", "/agents/a", ""), 405);
+        assert_eq!(status("POST", "/agents/a/permissions/x", r#"{"approve":true}"#), 400);
+        assert_eq!(status("POST", "/agents/a/prompt", "not json"), 400);
+        assert_eq!(status("GET", "/agents/a/output?last=x", ""), 400);
+        // 路径穿越：编码的 `/` 解码后仍在同一段
+        assert_eq!(status("GET", "/agents/..%2F..%2Fetc%2Fpasswd", ""), 400);
+        assert_eq!(status("DELETE", "/agents/..", ""), 400);
+        assert_eq!(status("GET", "/agents/.hidden/events", ""), 400);
+    }
+
+    #[test]
+    fn sse_framing() {
+        let chunk = sse_chunk(
+            "a".into(),
+            SessionEvent::Info { tag: "idle".into(), message: "done".into(), session: None },
+        );
+        assert!(chunk.starts_with("event: info\ndata: {"));
+        assert!(chunk.ends_with("}\n\n"));
+        let data: Value = serde_json::from_str(chunk.lines().nth(1).unwrap()[6..].trim()).unwrap();
+        assert_eq!(data["agent_name"], "a");
+        assert_eq!(data["event"]["kind"], "info");
+    }
+}
@@ -0,0 +1,136 @@
+// ============================================================
+// inbox - 全部 agent 的待审批权限
+// ============================================================
+// 并发向每个 agent 查询 GetPermissions，最早的请求排在前面；
+// 交互模式逐条 approve / deny / skip，`--approve-matching` / `--deny-matching` 非交互批量处理。
+// 审批按 id 发送 ResolvePermission，不受 session 内队列顺序影响。
+
+use std::io::Write;
+
+use anyhow::Result;
+
+use crate::config::defaults::glob_match;
+use crate::config::TeamConfig;
+use crate::protocol::messages::{PermissionInfo, SessionRequest, SessionResponse};
+
+use super::{client, display};
+
+/// 一个 agent 的一条待审批权限
+pub struct Pending {
+    pub agent: String,
+    pub info: PermissionInfo,
+}
+
+impl Pending {
+    /// glob 匹配工具描述或类别
+    pub fn matches(&self, pattern: &str) -> bool {
+        glob_match(pattern, &self.info.tool)
+            || self.info.kind.as_deref().is_some_and(|k| glob_match(pattern, k))
+    }
+}
+
+/// 查询全部 agent；不可达或不支持的 agent 在 stderr 提示后跳过
+pub async fn collect(config: &TeamConfig) -> Result<Vec<Pending>> {
+    let names = client::session_names(config).await?;
+    let futs = names.iter().map(|name| async move {
+        (name, client::send(config, name, SessionRequest::GetPermissions).await)
+    });
+    let mut pending = vec![];
+    for (name, result) in futures::future::join_all(futs).await {
+        match result {
+            Ok(SessionResponse::Permissions { pending: list, .. }) => {
+                pending.extend(list.into_iter().map(|info| Pending { agent: name.clone(), info }));
+            }
+            Ok(SessionResponse::Error { message }) => eprintln!("Skipping {}: {}", name, message),
+            Ok(_) => eprintln!("Skipping {}: unexpected response", name),
+            Err(e) => eprintln!("Skipping {}: {:#}", name, e),
+        }
+    }
+    pending.sort_by_key(|p| std::cmp::Reverse(p.info.age_secs));
+    Ok(pending)
+}
+
+pub fn render(pending: &[Pending]) -> String {
+    let rows: Vec<(&str, &PermissionInfo)> = pending.iter().map(|p| (p.agent.as_str(), &p.info)).collect();
+    display::render_permissions(&rows)
+}
+
+pub async fn resolve(config: &TeamConfig, p: &Pending, approve: bool) -> Result<SessionResponse> {
+    let req = SessionRequest::ResolvePermission { permission_id: p.info.id, approve };
+    client::send(config, &p.agent, req).await
+}
+
+/// 批量处理匹配的权限，返回处理条数
+pub async fn resolve_matching(
+    config: &TeamConfig,
+    pending: &[Pending],
+    pattern: &str,
+    approve: bool,
+) -> Result<usize> {
+    let mut count = 0;
+    for p in pending.iter().filter(|p| p.matches(pattern)) {
+        print!("{}: ", p.agent);
+        display::print_session_response(&resolve(config, p, approve).await?);
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// 逐条询问：a 批准、d 拒绝、s / 回车跳过、q 退出
+pub async fn interactive(config: &TeamConfig, pending: &[Pending]) -> Result<()> {
+    let stdin = std::io::stdin();
+    for (i, p) in pending.iter().enumerate() {
+        loop {
+            print!(
+                "[{}/{}] {} wants {} ({}) — [a]pprove / [d]eny / [s]kip / [q]uit: ",
+                i + 1,
+                pending.len(),
+                p.agent,
+                p.info.tool,
+                display::fmt_age(p.info.age_secs),
+            );
+            std::io::stdout().flush().ok();
+            let mut line = String::new();
+            if stdin.read_line(&mut line)? == 0 {
+                println!();
+                return Ok(());
+            }
+            let approve = match line.trim().to_ascii_lowercase().as_str() {
+                "a" | "approve" | "y" => true,
+                "d" | "deny" | "n" => false,
+                "s" | "skip" | "" => break,
+                "q" | "quit" => return Ok(()),
+                _ => continue,
+            };
+            display::print_session_response(&resolve(config, p, approve).await?);
+            break;
+        }
+    }
+    Ok(())
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_tool_or_kind() {
+        let p = Pending {
+            agent: "coder".into(),
+            info: PermissionInfo {
+                id: 1,
+                session: None,
+                tool: "Read src/main.rs".into(),
+                kind: Some("read".into()),
+                age_secs: 3,
+            },
+        };
+        assert!(p.matches("Read *"));
+        assert!(p.matches("read"));
+        assert!(p.matches("*main.rs"));
+        assert!(!p.matches("edit"));
+        assert!(!p.matches("Read"));
+    }
+}
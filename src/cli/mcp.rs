@@ -0,0 +1,444 @@
+// ============================================================
+// mcp - 面向 agent 的 stdio MCP server
+// ============================================================
+// session 在 NewSessionRequest.mcp_servers 中注册 `agent-team mcp --agent <name>`，
+// agent 借此调用 list_agents / ask_agent / read_agent_log 与其它 agent 协作。
+// 委派链随 Prompt.chain 传递，用于拒绝环路和超深委派。
+
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::config::TeamConfig;
+use crate::protocol::messages::{SessionRequest, SessionResponse};
+
+use super::{broadcast_all, client, display, pipe::final_message, run_prompt, AskOutcome};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const DEFAULT_PROTOCOL_VERSION: &str = "2024-11-05";
+/// ask_agent 默认超时
+const ASK_TIMEOUT_SECS: u64 = 600;
+
+// JSON-RPC 错误码
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+struct McpContext {
+    config: TeamConfig,
+    /// 本 server 所属 agent
+    agent: String,
+    /// 所属命名 session，None = 默认 session
+    session: Option<String>,
+    max_depth: usize,
+}
+
+/// stdio 主循环：逐行读取 JSON-RPC，请求并发处理，响应经 channel 串行写出
+pub async fn run_mcp(
+    config: TeamConfig,
+    agent: String,
+    session: Option<String>,
+    max_depth: usize,
+) -> Result<()> {
+    let ctx = Rc::new(McpContext { config, agent, session, max_depth });
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+
+    let writer = tokio::task::spawn_local(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(msg) = rx.recv().await {
+            let mut line = msg.to_string();
+            line.push('\n');
+            if stdout.write_all(line.as_bytes()).await.is_err() {
+                break;
+            }
+            let _ = stdout.flush().await;
+        }
+    });
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let msg: Value = match serde_json::from_str(&line) {
+            Ok(v) => v,
+            Err(e) => {
+                tx.send(rpc_error(Value::Null, PARSE_ERROR, &e.to_string())).ok();
+                continue;
+            }
+        };
+        // 无 id = notification（如 notifications/initialized），无需响应
+        let Some(id) = msg.get("id").cloned() else {
+            continue;
+        };
+        let method = msg.get("method").and_then(Value::as_str).unwrap_or("").to_string();
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+        let ctx = Rc::clone(&ctx);
+        let tx = tx.clone();
+        tokio::task::spawn_local(async move {
+            let resp = match handle_method(&ctx, &method, params).await {
+                Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+                Err((code, message)) => rpc_error(id, code, &message),
+            };
+            tx.send(resp).ok();
+        });
+    }
+    // stdin 关闭：等待进行中的请求写完（各任务持有 tx 副本）
+    drop(tx);
+    let _ = writer.await;
+    Ok(())
+}
+
+fn rpc_error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+// ==================== 方法分发 ====================
+
+async fn handle_method(
+    ctx: &McpContext,
+    method: &str,
+    params: Value,
+) -> Result<Value, (i64, String)> {
+    match method {
+        "initialize" => {
+            let version = params
+                .get("protocolVersion")
+                .and_then(Value::as_str)
+                .unwrap_or(DEFAULT_PROTOCOL_VERSION);
+            Ok(json!({
+                "protocolVersion": version,
+                "capabilities": { "tools": {} },
+                "serverInfo": { "name": "agent-team", "version": VERSION },
+            }))
+        }
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tool_defs() })),
+        "tools/call" => {
+            let name = params
+                .get("name")
+                .and_then(Value::as_str)
+                .ok_or((INVALID_PARAMS, "Missing tool name".to_string()))?;
+            let args = params.get("arguments").cloned().unwrap_or(json!({}));
+            // 工具失败以 isError 返回给模型，而不是 JSON-RPC 错误
+            let (text, is_error) = match call_tool(ctx, name, &args).await {
+                Ok(text) => (text, false),
+                Err(text) => (text, true),
+            };
+            Ok(json!({
+                "content": [{ "type": "text", "text": text }],
+                "isError": is_error,
+            }))
+        }
+        _ => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    }
+}
+
+fn tool_defs() -> Value {
+    json!([
+        {
+            "name": "list_agents",
+            "description": "List agents managed by agent-team with their type and status.",
+            "inputSchema": { "type": "object", "properties": {} },
+        },
+        {
+            "name": "ask_agent",
+            "description": "Send a prompt to another agent, wait for its turn to finish and return its final message. The target must be idle.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "agent": { "type": "string", "description": "Target agent name" },
+                    "prompt": { "type": "string", "description": "Prompt text" },
+                    "timeout_secs": { "type": "integer", "description": "Give up (and cancel) after this many seconds" },
+                },
+                "required": ["agent", "prompt"],
+            },
+        },
+        {
+            "name": "read_agent_log",
+            "description": "Read the recent conversation of an agent.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "agent": { "type": "string", "description": "Agent name" },
+                    "last": { "type": "integer", "description": "Number of messages (0 = all, default 1)" },
+                },
+                "required": ["agent"],
+            },
+        },
+    ])
+}
+
+// ==================== 工具实现 ====================
+
+async fn call_tool(ctx: &McpContext, name: &str, args: &Value) -> Result<String, String> {
+    match name {
+        "list_agents" => Ok(list_agents(ctx).await),
+        "ask_agent" => {
+            let target = str_arg(args, "agent")?;
+            let prompt = str_arg(args, "prompt")?;
+            let timeout = args
+                .get("timeout_secs")
+                .and_then(Value::as_u64)
+                .unwrap_or(ASK_TIMEOUT_SECS);
+            ask_agent(ctx, target, prompt, timeout).await
+        }
+        "read_agent_log" => {
+            let target = str_arg(args, "agent")?;
+            let last = args.get("last").and_then(Value::as_u64).unwrap_or(1) as usize;
+            let req = SessionRequest::GetOutput { last, agent_only: false };
+            match client::send(&ctx.config, target, req).await {
+                Ok(SessionResponse::Output { agent_name, entries }) => {
+                    Ok(display::render_entries(&agent_name, &entries))
+                }
+                Ok(SessionResponse::Error { message }) => Err(message),
+                Ok(_) => Err("Unexpected response".into()),
+                Err(e) => Err(format!("{:#}", e)),
+            }
+        }
+        _ => Err(format!("Unknown tool: {}", name)),
+    }
+}
+
+fn str_arg<'a>(args: &'a Value, key: &str) -> Result<&'a str, String> {
+    args.get(key)
+        .and_then(Value::as_str)
+        .ok_or_else(|| format!("Missing argument '{}'", key))
+}
+
+async fn list_agents(ctx: &McpContext) -> String {
+    let names = ctx.config.scan_sessions();
+    let results = broadcast_all(&ctx.config, &names, || SessionRequest::GetStatus).await;
+    let lines: Vec<String> = results
+        .into_iter()
+        .filter_map(|(n, r)| match r {
+            Ok(SessionResponse::Status { summary }) => {
+                let me = if n == ctx.agent { " (you)" } else { "" };
+                Some(format!(
+                    "{}{}: type={} status={} prompts={}",
+                    n, me, summary.agent_type, summary.status, summary.prompt_count,
+                ))
+            }
+            _ => None,
+        })
+        .collect();
+    if lines.is_empty() {
+        "No agents running".into()
+    } else {
+        lines.join("\n")
+    }
+}
+
+async fn ask_agent(
+    ctx: &McpContext,
+    target: &str,
+    prompt: &str,
+    timeout_secs: u64,
+) -> Result<String, String> {
+    // 本 agent 当前 turn 的上游链（取调用方所在 session）
+    let status = SessionRequest::GetStatus.in_session(ctx.session.as_deref());
+    let current = match client::send(&ctx.config, &ctx.agent, status).await {
+        Ok(SessionResponse::Status { summary }) => summary.delegation_chain,
+        _ => vec![],
+    };
+    let chain = delegation_chain(&current, &ctx.agent, target, ctx.max_depth)?;
+
+    // 目标忙碌时拒绝（直接 Prompt 会自动取消目标当前任务）
+    match client::send(&ctx.config, target, SessionRequest::GetStatus).await {
+        Ok(SessionResponse::Status { summary }) if summary.status == "idle" => {}
+        Ok(SessionResponse::Status { summary }) => {
+            return Err(format!("Agent '{}' is busy ({})", target, summary.status));
+        }
+        Ok(_) => return Err("Unexpected response".into()),
+        Err(e) => return Err(format!("{:#}", e)),
+    }
+
+    let req = SessionRequest::Prompt { text: prompt.to_string(), files: vec![], chain };
+    let outcome = tokio::time::timeout(
+        Duration::from_secs(timeout_secs),
+        run_prompt(&ctx.config, target, None, req),
+    )
+    .await;
+    match outcome {
+        Err(_) => {
+            let _ = client::send(&ctx.config, target, SessionRequest::Cancel).await;
+            Err(format!("Agent '{}' timed out after {}s (cancelled)", target, timeout_secs))
+        }
+        Ok(Err(e)) => Err(format!("{:#}", e)),
+        Ok(Ok(AskOutcome { status, resp })) => match (status.as_str(), resp) {
+            ("idle", SessionResponse::Output { entries, .. }) => {
+                let msg = final_message(&entries);
+                Ok(if msg.is_empty() { "(no text reply)".into() } else { msg })
+            }
+            ("waiting_permission", _) => Err(format!(
+                "Agent '{}' is waiting for a permission decision from the user",
+                target,
+            )),
+            (_, SessionResponse::Error { message }) => Err(message),
+            (status, _) => Err(format!("Agent '{}' ended with status {}", target, status)),
+        },
+    }
+}
+
+/// 校验委派：禁止环路（目标已在链上或为自身）与超深链，返回传给目标的新链
+fn delegation_chain(
+    current: &[String],
+    from: &str,
+    target: &str,
+    max_depth: usize,
+) -> Result<Vec<String>, String> {
+    let mut chain = current.to_vec();
+    chain.push(from.to_string());
+    if chain.iter().any(|n| n == target) {
+        return Err(format!("Delegation loop: {} → {}", chain.join(" → "), target));
+    }
+    if chain.len() > max_depth {
+        return Err(format!(
+            "Delegation depth limit ({}) reached: {} → {}",
+            max_depth,
+            chain.join(" → "),
+            target,
+        ));
+    }
+    Ok(chain)
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx() -> McpContext {
+        McpContext {
+            config: TeamConfig::default(),
+            agent: "coder".into(),
+            session: None,
+            max_depth: 2,
+        }
+    }
+
+    #[test]
+    fn chain_first_hop() {
+        let chain = delegation_chain(&[], "coder", "reviewer", 3).unwrap();
+        assert_eq!(chain, vec!["coder"]);
+    }
+
+    #[test]
+    fn chain_rejects_self_and_loops() {
+        assert!(delegation_chain(&[], "coder", "coder", 3).unwrap_err().contains("loop"));
+        let current = vec!["reviewer".to_string()];
+        let err = delegation_chain(&current, "coder", "reviewer", 3).unwrap_err();
+        assert!(err.contains("reviewer → coder → reviewer"));
+    }
+
+    #[test]
+    fn chain_depth_limit() {
+        let current = vec!["a".to_string(), "b".to_string()];
+        assert!(delegation_chain(&current, "c", "d", 3).is_ok());
+        let err = delegation_chain(&current, "c", "d", 2).unwrap_err();
+        assert!(err.contains("depth limit"));
+    }
+
+    #[tokio::test]
+    async fn initialize_echoes_version() {
+        let result = handle_method(&ctx(), "initialize", json!({ "protocolVersion": "2025-03-26" }))
+            .await
+            .unwrap();
+        assert_eq!(result["protocolVersion"], "2025-03-26");
+        assert_eq!(result["serverInfo"]["name"], "agent-team");
+    }
+
+    #[tokio::test]
+    async fn tools_list_names() {
+        let result = handle_method(&ctx(), "tools/list", Value::Null).await.unwrap();
+        let names: Vec<&str> = result["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|t| t["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, vec!["list_agents", "ask_agent", "read_agent_log"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_method_and_tool() {
+        let err = handle_method(&ctx(), "resources/list", Value::Null).await.unwrap_err();
+        assert_eq!(err.0, METHOD_NOT_FOUND);
+
+        let result = handle_method(&ctx(), "tools/call", json!({ "name": "nope" }))
+            .await
+            .unwrap();
+        assert_eq!(result["isError"], true);
+    }
+
+    #[tokio::test]
+    async fn ask_agent_missing_args() {
+        let result = handle_method(
+            &ctx(),
+            "tools/call",
+            json!({ "name": "ask_agent", "arguments": { "agent": "x" } }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(result["isError"], true);
+        assert!(result["content"][0]["text"].as_str().unwrap().contains("prompt"));
+    }
+
+    /// 假 session：默认 session 无委派链，命名 session "review" 的链为 planner → lead
+    async fn fake_session(listener: tokio::net::UnixListener) {
+        use crate::protocol::messages::{AgentSummary, PROTOCOL_VERSION};
+        use crate::protocol::transport::{JsonLineReader, JsonLineWriter};
+
+        loop {
+            let Ok((stream, _)) = listener.accept().await else { return };
+            let (read, write) = stream.into_split();
+            let (mut reader, mut writer) = (JsonLineReader::new(read), JsonLineWriter::new(write));
+            while let Ok(Some(req)) = reader.read::<SessionRequest>().await {
+                let resp = match req {
+                    SessionRequest::Hello { .. } => SessionResponse::Hello {
+                        protocol: PROTOCOL_VERSION,
+                        version: VERSION.into(),
+                        capabilities: SessionRequest::capabilities(),
+                        max_frame: 0,
+                    },
+                    SessionRequest::InSession { session, .. } if session == "review" => {
+                        let summary = AgentSummary {
+                            delegation_chain: vec!["planner".into(), "lead".into()],
+                            ..Default::default()
+                        };
+                        SessionResponse::Status { summary: Box::new(summary) }
+                    }
+                    _ => SessionResponse::Status { summary: Box::default() },
+                };
+                let _ = writer.write(&resp).await;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn ask_agent_reads_chain_of_calling_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let listener = tokio::net::UnixListener::bind(config.session_socket("coder")).unwrap();
+        let server = tokio::spawn(fake_session(listener));
+
+        // 命名 session 中的调用：planner → lead → coder 超过深度 2
+        let named = McpContext { config: config.clone(), session: Some("review".into()), ..ctx() };
+        let err = ask_agent(&named, "tester", "hi", 5).await.unwrap_err();
+        assert!(err.contains("planner → lead → coder"), "{}", err);
+
+        // 默认 session 链为空，通过深度检查后才因目标不存在失败
+        let default = McpContext { config, ..ctx() };
+        let err = ask_agent(&default, "tester", "hi", 5).await.unwrap_err();
+        assert!(!err.contains("depth limit"), "{}", err);
+        server.abort();
+    }
+}
@@ -1,15 +1,28 @@
+mod chat;
 pub mod client;
 mod commands;
 mod display;
+mod export;
+mod handoff;
+pub mod http;
+mod inbox;
+mod mcp;
+mod pipe;
+mod team;
+mod top;
 mod update;
 
 use anyhow::{Context, Result};
 use clap::Parser;
 
 use crate::config::TeamConfig;
-use crate::protocol::messages::{SessionRequest, SessionResponse};
+use crate::protocol::messages::{
+    DaemonRequest, SessionEvent, SessionRequest, SessionResponse, WorktreeInfo,
+};
+use crate::session::worktree;
 
 pub use commands::{Cli, Command};
+use commands::ListenArgs;
 
 pub fn parse() -> Cli {
     Cli::parse()
@@ -27,7 +40,8 @@ pub fn run(cli: Cli) -> Result<()> {
 }
 
 async fn run_async(cli: Cli) -> Result<()> {
-    let config = TeamConfig::default();
+    let mut config = TeamConfig::default();
+    config.remote.host = cli.host;
 
     match cli.command {
         Command::Add {
@@ -36,6 +50,10 @@ async fn run_async(cli: Cli) -> Result<()> {
             cwd,
             args,
             background,
+            auto_approve,
+            no_mcp,
+            worktree,
+            listen,
         } => {
             // 检查 agent 类型是否支持
             let type_config = config.agent_types.get(&agent_type)
@@ -50,6 +68,30 @@ async fn run_async(cli: Cli) -> Result<()> {
                     },
                 ))?;
 
+            // 远程：交给远程 daemon 启动，cwd 省略时以远程 daemon 为准
+            if let Some(ref host) = config.remote.host {
+                if !background {
+                    anyhow::bail!("With --host only `add -b` is supported (the agent runs in the remote daemon)");
+                }
+                if worktree.is_some() || listen.listen.is_some() {
+                    anyhow::bail!("--worktree and --listen cannot be used with --host {}", host);
+                }
+                let names = client::session_names(&config).await?;
+                let resolved_name =
+                    name.unwrap_or_else(|| crate::config::next_name(&agent_type, &names));
+                crate::config::validate_agent_name(&resolved_name).map_err(anyhow::Error::msg)?;
+                let opts = LaunchOptions {
+                    cwd: cwd.as_deref(),
+                    args: args.as_deref(),
+                    auto_approve: auto_approve.as_ref(),
+                    no_mcp,
+                    ..Default::default()
+                };
+                let how = launch(&config, &agent_type, &resolved_name, &opts).await?;
+                println!("Agent '{}' started ({})", resolved_name, how);
+                return Ok(());
+            }
+
             // 适配器提示：检测命令是否在 PATH
             if let Some((adapter, install)) = crate::config::adapter_hint(&agent_type) {
                 if !command_exists(&type_config.command) {
@@ -64,20 +106,48 @@ async fn run_async(cli: Cli) -> Result<()> {
 
             let resolved_name = name
                 .unwrap_or_else(|| config.gen_name(&agent_type));
+            crate::config::validate_agent_name(&resolved_name).map_err(anyhow::Error::msg)?;
+            let effective_cwd = cwd
+                .unwrap_or_else(|| config.default_cwd.clone());
+
+            // 先在前台创建 worktree，错误直接报告；后台进程按约定路径复用
+            let worktree = match worktree.as_deref() {
+                Some(branch) => Some(worktree::prepare(
+                    &config, &resolved_name, &effective_cwd, Some(branch),
+                )?),
+                None => None,
+            };
 
             if background {
-                launch_background(
-                    &config, &agent_type, &resolved_name,
-                    cwd.as_deref(), args.as_deref(),
-                )?;
+                let opts = LaunchOptions {
+                    cwd: Some(effective_cwd.as_path()),
+                    args: args.as_deref(),
+                    auto_approve: auto_approve.as_ref(),
+                    no_mcp,
+                    worktree: worktree.as_ref(),
+                    listen: Some(&listen),
+                };
+                let how = launch(&config, &agent_type, &resolved_name, &opts).await?;
+                println!("Agent '{}' started ({})", resolved_name, how);
                 return Ok(());
             }
 
+            let mut config = config;
+            if let Some(policy) = auto_approve {
+                config.auto_approve = policy;
+            }
+            if no_mcp {
+                config.mcp_max_depth = 0;
+            }
+            listen.apply(&mut config.remote);
+
             let extra_args = args
                 .map(|a| a.split_whitespace().map(String::from).collect())
                 .unwrap_or_default();
-            let effective_cwd = cwd
-                .unwrap_or_else(|| config.default_cwd.clone());
+            let agent_cwd = match &worktree {
+                Some(w) => std::path::PathBuf::from(&w.path),
+                None => effective_cwd,
+            };
 
             // 启动独立 session（阻塞，stdout 输出）
             crate::session::server::run(
@@ -85,14 +155,70 @@ async fn run_async(cli: Cli) -> Result<()> {
                 agent_type,
                 config,
                 extra_args,
-                effective_cwd,
+                agent_cwd,
+                worktree,
             )
             .await?;
         }
 
-        Command::Rm { name, all } => {
+        Command::Daemon { background, stop, listen } => {
+            if stop {
+                let resp = client::daemon_send(&config, DaemonRequest::Stop).await?;
+                display::print_session_response(&resp);
+                return Ok(());
+            }
+            if config.remote.host.is_some() {
+                anyhow::bail!("A daemon can only be started locally (drop --host / AGENT_TEAM_HOST)");
+            }
+            if client::daemon_running(&config).await {
+                anyhow::bail!("Daemon already running ({})", config.daemon_socket().display());
+            }
+            if background {
+                let log_path = config.socket_dir.join("daemon.log");
+                let mut cmd_args = vec!["daemon".to_string()];
+                cmd_args.extend(listen.to_args());
+                let pid = spawn_detached(
+                    &config,
+                    &cmd_args,
+                    &log_path,
+                    &config.daemon_socket(),
+                    "Daemon",
+                )?;
+                println!("Daemon started (pid: {}, log: {})", pid, log_path.display());
+                return Ok(());
+            }
+            let mut config = config;
+            listen.apply(&mut config.remote);
+            crate::session::daemon::run(config).await?;
+        }
+
+        Command::Token { rotate } => {
+            let path = &config.remote.token_file;
+            let token = if rotate {
+                crate::protocol::remote::rotate_token(path)?
+            } else {
+                crate::protocol::remote::load_or_create_token(path)?
+            };
+            println!("{}", token);
+        }
+
+        Command::Serve { http } => {
+            http::run_gateway(config, &http).await?;
+        }
+
+        Command::Up { manifest } => {
+            let manifest = crate::config::TeamManifest::load(&manifest)?;
+            team::run_up(&config, &manifest).await?;
+        }
+
+        Command::Down { manifest } => {
+            let manifest = crate::config::TeamManifest::load(&manifest)?;
+            team::run_down(&config, &manifest).await;
+        }
+
+        Command::Rm { name, all, session } => {
             if all {
-                let names = config.scan_sessions();
+                let names = client::session_names(&config).await?;
                 if names.is_empty() {
                     println!("No agents running");
                     return Ok(());
@@ -113,13 +239,44 @@ async fn run_async(cli: Cli) -> Result<()> {
                 let name = name.ok_or_else(|| {
                     anyhow::anyhow!("Agent name required. Use --all to shut down all agents")
                 })?;
-                let resp = client::send(&config, &name, SessionRequest::Shutdown).await?;
+                // --session 只关闭该命名 session
+                let req = match session {
+                    Some(session) => SessionRequest::CloseSession { session },
+                    None => SessionRequest::Shutdown,
+                };
+                let resp = client::send(&config, &name, req).await?;
                 display::print_session_response(&resp);
             }
         }
 
+        Command::New { name, session } => {
+            let resp = client::send(&config, &name, SessionRequest::NewSession { session }).await?;
+            display::print_session_response(&resp);
+        }
+
+        Command::Fork { name, new_name } => {
+            let (fork, how) = run_fork(&config, &name, new_name).await?;
+            println!("Agent '{}' forked from '{}' ({})", fork, name, how);
+        }
+
+        Command::Merge { name } => {
+            let info = finish_worktree(&config, &name).await?;
+            let summary = worktree::merge(&info, &format!("agent-team: changes from {}", name))?;
+            println!("{} into {}", summary, info.repo);
+        }
+
+        Command::Discard { name } => {
+            let info = finish_worktree(&config, &name).await?;
+            worktree::remove(&info)?;
+            println!("Discarded worktree {} (branch '{}')", info.path, info.branch);
+        }
+
+        Command::Top => {
+            top::run(&config).await?;
+        }
+
         Command::Ls => {
-            let names = config.scan_sessions();
+            let names = client::session_names(&config).await?;
             if names.is_empty() {
                 println!("No agents running");
                 return Ok(());
@@ -129,7 +286,7 @@ async fn run_async(cli: Cli) -> Result<()> {
             for (n, result) in results {
                 match result {
                     Ok(SessionResponse::Status { summary }) => {
-                        summaries.push(summary);
+                        summaries.push(*summary);
                     }
                     Ok(SessionResponse::Error { message }) => {
                         eprintln!("Error: {}: {}", n, message);
@@ -143,22 +300,33 @@ async fn run_async(cli: Cli) -> Result<()> {
             display::print_agent_list(&summaries);
         }
 
-        Command::Ask { name, text, file } => {
-            let text = match text {
-                Some(t) => t,
-                None => {
-                    use std::io::Read;
-                    let mut buf = String::new();
-                    std::io::stdin()
-                        .read_to_string(&mut buf)
-                        .context("Failed to read from stdin")?;
-                    let buf = buf.trim().to_string();
-                    if buf.is_empty() {
-                        anyhow::bail!("No prompt text provided");
-                    }
-                    buf
+        Command::Ask { name, text, file, all, session } => {
+            // --all 时唯一的位置参数是 prompt
+            let (targets, text) = if all {
+                if name.is_some() && text.is_some() {
+                    anyhow::bail!("--all takes the prompt as its only argument (quote it if it has spaces)");
                 }
+                (client::session_names(&config).await?, text.or(name))
+            } else {
+                let spec = name.ok_or_else(|| {
+                    anyhow::anyhow!("Agent name required. Use --all to ask all agents")
+                })?;
+                let sessions = if spec.contains(['*', '?']) {
+                    client::session_names(&config).await?
+                } else {
+                    vec![]
+                };
+                (crate::config::match_targets(&spec, &sessions), text)
             };
+            if targets.is_empty() {
+                println!("No matching agents");
+                return Ok(());
+            }
+            if session.is_some() && targets.len() > 1 {
+                anyhow::bail!("--session needs a single agent");
+            }
+
+            let text = read_prompt(text)?;
 
             let mut files = Vec::new();
             for path in file {
@@ -171,41 +339,197 @@ async fn run_async(cli: Cli) -> Result<()> {
                 });
             }
 
-            prompt_and_wait(&config, &name, text, files).await?;
+            if let [name] = targets.as_slice() {
+                prompt_and_wait(&config, name, session.as_deref(), text, files).await?;
+            } else {
+                broadcast_prompt(&config, &targets, text, files).await;
+            }
+        }
+
+        Command::Chat { name, session } => {
+            chat::run(&config, &name, session.as_deref()).await?;
+        }
+
+        Command::Handoff { from, to, task, template, budget, dry_run } => {
+            if from == to {
+                anyhow::bail!("Source and target are the same agent");
+            }
+            let template = handoff::load_template(template.as_deref())?;
+            let source = handoff::collect(&config, &from).await?;
+            let text = source.render(&template, task.as_deref(), budget);
+            if dry_run {
+                println!("{}", text);
+                return Ok(());
+            }
+            println!(
+                "Handing off {} → {} (~{} tokens)",
+                from,
+                to,
+                handoff::HandoffSource::estimate_tokens(&text),
+            );
+            prompt_and_wait(&config, &to, None, text, vec![]).await?;
+        }
+
+        Command::Pipe {
+            input,
+            step,
+            file,
+            timeout,
+            continue_on_error,
+            record,
+            replay,
+        } => {
+            let (mut pipeline, input) = if let Some(path) = replay {
+                let rec = pipe::PipeRecord::load(&path)?;
+                (rec.pipeline, rec.input)
+            } else {
+                let mut pipeline = match file {
+                    Some(path) => pipe::Pipeline::load(&path)?,
+                    None => pipe::Pipeline { steps: vec![], stop_on_error: true },
+                };
+                for spec in &step {
+                    pipeline.steps.push(pipe::PipelineStep::parse(spec)?);
+                }
+                if pipeline.steps.is_empty() {
+                    anyhow::bail!("No steps. Use -s <agent[:template]> or --file");
+                }
+                (pipeline, read_prompt(input)?)
+            };
+            if continue_on_error {
+                pipeline.stop_on_error = false;
+            }
+            if timeout.is_some() {
+                for s in &mut pipeline.steps {
+                    s.timeout_secs = s.timeout_secs.or(timeout);
+                }
+            }
+            let record_path = record.unwrap_or_else(|| pipe::default_record_path(&config));
+            pipe::run_pipeline(&config, pipeline, input, &record_path).await?;
         }
 
-        Command::Log { name, last, agent_only } => {
+        Command::Log { name, last, agent_only, session, follow } => {
+            // 先订阅，历史与实时输出之间不丢事件
+            let mut live = None;
+            if follow {
+                let mut sub = client::SessionClient::connect(&config, &name).await?;
+                sub.subscribe().await?;
+                live = Some(sub);
+            }
             let resp = client::send(
                 &config,
                 &name,
-                SessionRequest::GetOutput { last, agent_only },
+                SessionRequest::GetOutput { last, agent_only }.in_session(session.as_deref()),
             )
             .await?;
             display::print_session_response(&resp);
+            if let Some(sub) = live.as_mut() {
+                follow_log(sub, session.as_deref()).await?;
+            }
         }
 
-        Command::Cancel { name } => {
-            let resp =
-                client::send(&config, &name, SessionRequest::Cancel).await?;
+        Command::Export { name, format, turns, output, session } => {
+            let format: export::Format = format.parse().map_err(anyhow::Error::msg)?;
+            let transcript = export::collect(&config, &name, session.as_deref(), turns).await?;
+            let text = export::render(&transcript, format)?;
+            match output {
+                Some(path) => {
+                    std::fs::write(&path, text).with_context(|| format!("Failed to write {}", path.display()))?;
+                    eprintln!("Exported {} turn(s) of '{}' to {}", transcript.turns.len(), name, path.display());
+                }
+                None => print!("{}", text),
+            }
+        }
+
+        Command::Notify { name, state } => {
+            let resp = match state.as_deref() {
+                Some(s) => {
+                    let req = SessionRequest::SetNotify { enabled: s == "on" };
+                    client::send(&config, &name, req).await?
+                }
+                None => match client::send(&config, &name, SessionRequest::GetStatus).await? {
+                    SessionResponse::Status { summary } => {
+                        let state = if summary.notify_muted { "off" } else { "on" };
+                        println!("Notifications for {}: {}", summary.name, state);
+                        return Ok(());
+                    }
+                    other => other,
+                },
+            };
             display::print_session_response(&resp);
         }
 
-        Command::Allow { name } => {
-            let resp =
-                client::send(&config, &name, SessionRequest::ApprovePermission).await?;
+        Command::Plan { name, session } => {
+            let req = SessionRequest::GetPlan.in_session(session.as_deref());
+            let resp = client::send(&config, &name, req).await?;
+            display::print_session_response(&resp);
+        }
+
+        Command::Budget { name, tokens, cost, clear } => {
+            // 不带参数只查看用量
+            if tokens.is_none() && cost.is_none() && !clear {
+                match client::send(&config, &name, SessionRequest::GetStatus).await? {
+                    SessionResponse::Status { summary } => display::print_usage(&summary),
+                    other => display::print_session_response(&other),
+                }
+                return Ok(());
+            }
+            let req = SessionRequest::SetBudget { max_tokens: tokens, max_cost: cost, clear };
+            let resp = client::send(&config, &name, req).await?;
             display::print_session_response(&resp);
         }
 
-        Command::Deny { name } => {
+        Command::Changes { name, turn, diff } => {
             let resp = client::send(
                 &config,
                 &name,
-                SessionRequest::DenyPermission,
+                SessionRequest::GetChanges { turn, diff },
             )
             .await?;
             display::print_session_response(&resp);
         }
 
+        Command::Undo { name, turn } => {
+            let resp = client::send(&config, &name, SessionRequest::Undo { turn }).await?;
+            display::print_session_response(&resp);
+        }
+
+        Command::Cancel { name, session } => {
+            let req = SessionRequest::Cancel.in_session(session.as_deref());
+            let resp = client::send(&config, &name, req).await?;
+            display::print_session_response(&resp);
+        }
+
+        Command::Inbox { approve_matching, deny_matching, list } => {
+            let pending = inbox::collect(&config).await?;
+            print!("{}", inbox::render(&pending));
+            if pending.is_empty() {
+                return Ok(());
+            }
+            let batch = approve_matching.map(|p| (p, true)).or(deny_matching.map(|p| (p, false)));
+            if let Some((pattern, approve)) = batch {
+                println!();
+                let n = inbox::resolve_matching(&config, &pending, &pattern, approve).await?;
+                if n == 0 {
+                    println!("Nothing matches '{}'", pattern);
+                }
+            } else if !list && std::io::IsTerminal::is_terminal(&std::io::stdin()) {
+                println!();
+                inbox::interactive(&config, &pending).await?;
+            }
+        }
+
+        Command::Allow { name, session } => {
+            let req = SessionRequest::ApprovePermission.in_session(session.as_deref());
+            let resp = client::send(&config, &name, req).await?;
+            display::print_session_response(&resp);
+        }
+
+        Command::Deny { name, session } => {
+            let req = SessionRequest::DenyPermission.in_session(session.as_deref());
+            let resp = client::send(&config, &name, req).await?;
+            display::print_session_response(&resp);
+        }
+
         Command::Info { name } => {
             let resp =
                 client::send(&config, &name, SessionRequest::GetStatus).await?;
@@ -234,6 +558,12 @@ async fn run_async(cli: Cli) -> Result<()> {
             display::print_session_response(&resp);
         }
 
+        Command::Mcp { agent, session, max_depth } => {
+            // MCP server 与 agent 同机，始终操作本地 session
+            config.remote.host = None;
+            mcp::run_mcp(config, agent, session, max_depth).await?;
+        }
+
         Command::Update => unreachable!("handled before runtime"),
     }
     Ok(())
@@ -241,38 +571,122 @@ async fn run_async(cli: Cli) -> Result<()> {
 
 // ==================== prompt（轮询等待） ====================
 
+/// 单个 agent 的 ask 结果：最终状态 + 最后一条消息（或拒绝原因）
+struct AskOutcome {
+    status: String,
+    resp: SessionResponse,
+}
+
+/// `log -f`：打印实时事件直到 session 关闭（只看指定 session）
+async fn follow_log(sub: &mut client::SessionClient, session: Option<&str>) -> Result<()> {
+    use std::io::Write;
+    let mut state = display::FollowState::default();
+    while let Some((_, event)) = sub.next_event().await? {
+        let from = match &event {
+            SessionEvent::Output { session, .. } | SessionEvent::Info { session, .. } => session.as_deref(),
+        };
+        if from != session {
+            continue;
+        }
+        print!("{}", display::render_event(&mut state, &event));
+        std::io::stdout().flush().ok();
+    }
+    println!("\n[closed] Session ended");
+    Ok(())
+}
+
 async fn prompt_and_wait(
     config: &TeamConfig,
     name: &str,
+    session: Option<&str>,
     text: String,
     files: Vec<crate::protocol::messages::FileAttachment>,
 ) -> Result<()> {
+    let prompt = SessionRequest::Prompt { text, files, chain: vec![] };
+    let outcome = run_prompt(config, name, session, prompt).await?;
+    display::print_session_response(&outcome.resp);
+    Ok(())
+}
+
+async fn prompt_and_collect(
+    config: &TeamConfig,
+    name: &str,
+    text: String,
+    files: Vec<crate::protocol::messages::FileAttachment>,
+) -> Result<AskOutcome> {
+    run_prompt(config, name, None, SessionRequest::Prompt { text, files, chain: vec![] }).await
+}
+
+/// 发送 Prompt 请求并轮询至 turn 结束；session 指定命名 ACP session
+async fn run_prompt(
+    config: &TeamConfig,
+    name: &str,
+    session: Option<&str>,
+    prompt: SessionRequest,
+) -> Result<AskOutcome> {
     let mut conn = client::SessionClient::connect(config, name).await?;
 
-    let resp = conn.send(SessionRequest::Prompt { text, files }).await?;
+    let resp = conn.send(prompt.in_session(session)).await?;
     if !matches!(resp, SessionResponse::Ok { .. }) {
-        display::print_session_response(&resp);
-        return Ok(());
+        return Ok(AskOutcome { status: "error".into(), resp });
     }
 
     // 轮询 GetStatus 直到 idle / error / waiting_permission
     // 无超时限制 — AI 输出可能很长，由用户 Ctrl+C 中止
-    loop {
+    let status = loop {
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        let resp = send_or_reconnect(&mut conn, config, name, SessionRequest::GetStatus).await?;
-        if let SessionResponse::Status { ref summary } = resp {
+        let req = SessionRequest::GetStatus.in_session(session);
+        let resp = send_or_reconnect(&mut conn, config, name, req).await?;
+        if let SessionResponse::Status { summary } = resp {
             match summary.status.as_str() {
-                "idle" | "error" | "waiting_permission" => break,
+                "idle" | "error" | "waiting_permission" => break summary.status,
                 _ => {}
             }
         }
-    }
+    };
 
     // 取最后一条消息（agent 回复 / 权限请求）
-    let req = SessionRequest::GetOutput { last: 1, agent_only: false };
+    let req = SessionRequest::GetOutput { last: 1, agent_only: false }.in_session(session);
     let resp = send_or_reconnect(&mut conn, config, name, req).await?;
-    display::print_session_response(&resp);
-    Ok(())
+    Ok(AskOutcome { status, resp })
+}
+
+/// 同一 prompt 并发发给多个 agent，全部结束后按顺序逐个输出
+async fn broadcast_prompt(
+    config: &TeamConfig,
+    names: &[String],
+    text: String,
+    files: Vec<crate::protocol::messages::FileAttachment>,
+) {
+    let futs: Vec<_> = names
+        .iter()
+        .map(|n| {
+            let text = text.clone();
+            let files = files.clone();
+            async move { (n.as_str(), prompt_and_collect(config, n, text, files).await) }
+        })
+        .collect();
+    let results = futures::future::join_all(futs).await;
+
+    for (i, (n, result)) in results.into_iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        match result {
+            Ok(AskOutcome { status, resp: SessionResponse::Output { entries, .. } }) => {
+                display::print_agent_reply(n, &status, &entries);
+            }
+            Ok(AskOutcome { status, resp: SessionResponse::Error { message } }) => {
+                display::print_agent_error(n, &status, &message);
+            }
+            Ok(AskOutcome { status, .. }) => {
+                display::print_agent_error(n, &status, "Unexpected response");
+            }
+            Err(e) => {
+                display::print_agent_error(n, "unreachable", &format!("{:#}", e));
+            }
+        }
+    }
 }
 
 // ==================== 通信辅助 ====================
@@ -311,34 +725,112 @@ async fn broadcast_all<'a>(
 
 // ==================== 后台启动 ====================
 
-fn launch_background(
+/// `add` 中需要透传给后台进程的选项
+#[derive(Default)]
+struct LaunchOptions<'a> {
+    cwd: Option<&'a std::path::Path>,
+    args: Option<&'a str>,
+    auto_approve: Option<&'a crate::config::AutoApprovePolicy>,
+    no_mcp: bool,
+    /// worktree（CLI 已创建，后台进程 / daemon 复用）
+    worktree: Option<&'a WorktreeInfo>,
+    /// 远程监听参数（设置 --listen 时不交给 daemon）
+    listen: Option<&'a ListenArgs>,
+}
+
+/// 后台启动 agent：daemon 运行中则交给 daemon 托管，否则 re-exec 独立进程。
+/// 返回启动方式描述（用于输出）
+async fn launch(
     config: &TeamConfig,
     agent_type: &str,
     name: &str,
-    cwd: Option<&std::path::Path>,
-    args: Option<&str>,
-) -> Result<()> {
-    config.ensure_socket_dir()?;
-
-    let exe = std::env::current_exe()
-        .context("Cannot resolve executable path")?;
+    opts: &LaunchOptions<'_>,
+) -> Result<String> {
+    // --listen 需要独立进程自己监听
+    let own_listener = opts.listen.is_some_and(|l| l.listen.is_some());
+    if own_listener || !client::daemon_running(config).await {
+        let pid = launch_background(config, agent_type, name, opts)?;
+        return Ok(format!("pid: {}, log: {}", pid, config.session_log(name).display()));
+    }
+    let req = DaemonRequest::Spawn {
+        name: name.to_string(),
+        agent_type: agent_type.to_string(),
+        cwd: opts.cwd.map(|c| c.to_path_buf()),
+        args: opts
+            .args
+            .map(|a| a.split_whitespace().map(String::from).collect())
+            .unwrap_or_default(),
+        auto_approve: opts.auto_approve.map(|p| p.label().to_string()),
+        no_mcp: opts.no_mcp,
+        worktree: opts.worktree.cloned(),
+    };
+    match client::daemon_send(config, req).await? {
+        SessionResponse::Ok { .. } => Ok(match &config.remote.host {
+            Some(host) => format!("daemon at {}", host),
+            None => "daemon".into(),
+        }),
+        SessionResponse::Error { message } => anyhow::bail!(message),
+        _ => anyhow::bail!("Unexpected daemon response"),
+    }
+}
 
+/// re-exec 自身启动后台 session，等 socket 出现后返回 pid
+fn launch_background(
+    config: &TeamConfig,
+    agent_type: &str,
+    name: &str,
+    opts: &LaunchOptions,
+) -> Result<u32> {
     // 重建命令行（不带 --background）
     let mut cmd_args = vec!["add".to_string(), agent_type.to_string()];
     cmd_args.extend(["--name".into(), name.to_string()]);
-    if let Some(c) = cwd {
+    if let Some(c) = opts.cwd {
         cmd_args.extend(["--cwd".into(), c.display().to_string()]);
     }
-    if let Some(a) = args {
-        cmd_args.extend(["--args".into(), a.to_string()]);
+    if let Some(a) = opts.args {
+        // --args=... 形式：值以 "-" 开头时避免被 clap 当作 flag
+        cmd_args.push(format!("--args={}", a));
+    }
+    if let Some(p) = opts.auto_approve {
+        cmd_args.extend(["--auto-approve".into(), p.label().to_string()]);
+    }
+    if opts.no_mcp {
+        cmd_args.push("--no-mcp".into());
     }
+    if let Some(w) = opts.worktree {
+        cmd_args.push(format!("--worktree={}", w.branch));
+    }
+    if let Some(l) = opts.listen {
+        cmd_args.extend(l.to_args());
+    }
+
+    spawn_detached(
+        config,
+        &cmd_args,
+        &config.session_log(name),
+        &config.session_socket(name),
+        &format!("Agent '{}'", name),
+    )
+}
 
-    let log_path = config.session_log(name);
-    let log_file = std::fs::File::create(&log_path)
+/// 以 args re-exec 自身为脱离终端的后台进程，等 ready_path 出现（最多 10s）后返回 pid
+fn spawn_detached(
+    config: &TeamConfig,
+    cmd_args: &[String],
+    log_path: &std::path::Path,
+    ready_path: &std::path::Path,
+    what: &str,
+) -> Result<u32> {
+    config.ensure_socket_dir()?;
+
+    let exe = std::env::current_exe()
+        .context("Cannot resolve executable path")?;
+
+    let log_file = std::fs::File::create(log_path)
         .with_context(|| format!("Cannot create log: {}", log_path.display()))?;
 
     let mut cmd = std::process::Command::new(exe);
-    cmd.args(&cmd_args)
+    cmd.args(cmd_args)
         .stdin(std::process::Stdio::null())
         .stdout(log_file.try_clone()?)
         .stderr(log_file);
@@ -353,11 +845,9 @@ fn launch_background(
     let child = cmd.spawn()
         .context("Failed to spawn background process")?;
 
-    // 等 socket 出现（最多 10s）
-    let sock_path = config.session_socket(name);
     let mut ready = false;
     for _ in 0..100 {
-        if sock_path.exists() {
+        if ready_path.exists() {
             ready = true;
             break;
         }
@@ -365,21 +855,113 @@ fn launch_background(
     }
 
     if ready {
-        println!(
-            "Agent '{}' started (pid: {}, log: {})",
-            name, child.id(), log_path.display(),
-        );
-        Ok(())
+        Ok(child.id())
     } else {
         anyhow::bail!(
-            "Agent '{}' failed to start within 10s (check {})",
-            name, log_path.display(),
+            "{} failed to start within 10s (check {})",
+            what, log_path.display(),
         )
     }
 }
 
+// ==================== fork ====================
+
+/// 以源 agent 的类型和 cwd 启动新 agent，交给它源对话（Seed），再在源 agent 记录关联。
+/// 返回新 agent 名与 seed 结果
+async fn run_fork(
+    config: &TeamConfig,
+    name: &str,
+    new_name: Option<String>,
+) -> Result<(String, String)> {
+    let summary = match client::send(config, name, SessionRequest::GetStatus).await? {
+        SessionResponse::Status { summary } => summary,
+        SessionResponse::Error { message } => anyhow::bail!(message),
+        _ => anyhow::bail!("Unexpected response from '{}'", name),
+    };
+    let transcript = match client::send(
+        config,
+        name,
+        SessionRequest::GetOutput { last: 0, agent_only: false },
+    )
+    .await?
+    {
+        SessionResponse::Output { entries, .. } => entries,
+        _ => vec![],
+    };
+
+    let names = client::session_names(config).await?;
+    let fork = new_name.unwrap_or_else(|| crate::config::next_name(&summary.agent_type, &names));
+    crate::config::validate_agent_name(&fork).map_err(anyhow::Error::msg)?;
+    if names.contains(&fork) {
+        anyhow::bail!("Agent '{}' already exists", fork);
+    }
+    let cwd = std::path::PathBuf::from(&summary.cwd);
+    let opts = LaunchOptions { cwd: Some(&cwd), ..Default::default() };
+    launch(config, &summary.agent_type, &fork, &opts).await?;
+
+    let seed = SessionRequest::Seed {
+        from: name.to_string(),
+        session_id: summary.session_id,
+        transcript,
+    };
+    let message = match client::send(config, &fork, seed).await? {
+        SessionResponse::Ok { message } => message,
+        SessionResponse::Error { message } => {
+            anyhow::bail!("Agent '{}' started but could not be seeded: {}", fork, message)
+        }
+        _ => anyhow::bail!("Unexpected response from '{}'", fork),
+    };
+    client::send(config, name, SessionRequest::LinkFork { fork: fork.clone() }).await?;
+    Ok((fork, message))
+}
+
+// ==================== worktree ====================
+
+/// merge / discard 前置：取 worktree 信息，session 仍在运行则先关闭并等待退出
+async fn finish_worktree(config: &TeamConfig, name: &str) -> Result<WorktreeInfo> {
+    if config.remote.host.is_some() {
+        anyhow::bail!("merge / discard operate on local worktrees; run them on the agent's host");
+    }
+    let (info, running) = match client::send(config, name, SessionRequest::GetStatus).await {
+        Ok(SessionResponse::Status { summary }) => {
+            let info = summary.worktree.ok_or_else(|| {
+                anyhow::anyhow!("Agent '{}' is not running in a worktree", name)
+            })?;
+            (info, true)
+        }
+        _ => (worktree::locate(config, name)?, false),
+    };
+    if running {
+        client::send(config, name, SessionRequest::Shutdown).await?;
+        for _ in 0..100 {
+            if !config.session_exists(name) {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+    }
+    Ok(info)
+}
+
 // ==================== 工具函数 ====================
 
+/// prompt 文本：参数优先，省略时读 stdin
+fn read_prompt(text: Option<String>) -> Result<String> {
+    if let Some(t) = text {
+        return Ok(t);
+    }
+    use std::io::Read;
+    let mut buf = String::new();
+    std::io::stdin()
+        .read_to_string(&mut buf)
+        .context("Failed to read from stdin")?;
+    let buf = buf.trim().to_string();
+    if buf.is_empty() {
+        anyhow::bail!("No prompt text provided");
+    }
+    Ok(buf)
+}
+
 fn command_exists(cmd: &str) -> bool {
     #[cfg(unix)]
     {
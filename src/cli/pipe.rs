@@ -0,0 +1,389 @@
+// ============================================================
+// pipe - 多 agent 流水线
+// ============================================================
+// 每一步 ask 一个 agent，取其最终消息填入下一步的 prompt 模板。
+// 整条链路记录为 JSON，可用 --replay 重跑。
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::config::TeamConfig;
+use crate::protocol::messages::{OutputEntry, OutputType, SessionRequest, SessionResponse};
+
+use super::{client, display, prompt_and_collect, AskOutcome};
+
+/// 上一步输出（第一步为流水线输入）
+const VAR_INPUT: &str = "{{input}}";
+/// 流水线初始输入
+const VAR_PROMPT: &str = "{{prompt}}";
+
+// ==================== 流水线定义 ====================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub steps: Vec<PipelineStep>,
+    /// 某一步失败后停止（默认 true）
+    #[serde(default = "default_true")]
+    pub stop_on_error: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipelineStep {
+    pub agent: String,
+    /// prompt 模板，支持 `{{input}}` / `{{prompt}}`
+    #[serde(default = "default_template")]
+    pub prompt: String,
+    /// 单步超时（秒），None = 不限
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_template() -> String {
+    VAR_INPUT.to_string()
+}
+
+impl PipelineStep {
+    /// 解析 `agent` 或 `agent:template`
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (agent, prompt) = match spec.split_once(':') {
+            Some((a, t)) => (a.trim(), t.to_string()),
+            None => (spec.trim(), default_template()),
+        };
+        if agent.is_empty() {
+            bail!("Empty agent name in step '{}'", spec);
+        }
+        Ok(Self {
+            agent: agent.to_string(),
+            prompt,
+            timeout_secs: None,
+        })
+    }
+}
+
+impl Pipeline {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read {}", path.display()))?;
+        let pipeline: Self = serde_json::from_str(&text)
+            .with_context(|| format!("Invalid pipeline file {}", path.display()))?;
+        if pipeline.steps.is_empty() {
+            bail!("Pipeline has no steps");
+        }
+        Ok(pipeline)
+    }
+}
+
+// ==================== 执行记录 ====================
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PipeRecord {
+    pub started_at: String,
+    pub input: String,
+    pub pipeline: Pipeline,
+    pub steps: Vec<StepRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StepRecord {
+    pub agent: String,
+    /// 渲染后的实际 prompt
+    pub prompt: String,
+    /// 最终状态：idle / error / waiting_permission / timeout / unreachable
+    pub status: String,
+    /// agent 最终消息
+    pub output: String,
+    pub elapsed_ms: u64,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl StepRecord {
+    fn ok(&self) -> bool {
+        self.status == "idle" && self.error.is_none()
+    }
+}
+
+impl PipeRecord {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read {}", path.display()))?;
+        serde_json::from_str(&text)
+            .with_context(|| format!("Invalid pipeline record {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<()> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json)
+            .with_context(|| format!("Cannot write {}", path.display()))
+    }
+}
+
+/// 默认记录路径：{socket_dir}/pipes/{时间戳}.json
+pub fn default_record_path(config: &TeamConfig) -> PathBuf {
+    let ts = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    config.socket_dir.join("pipes").join(format!("{}.json", ts))
+}
+
+// ==================== 执行 ====================
+
+/// 逐步执行流水线，每步输出一个 <msg> 块。
+/// 记录在开始前和每步结束后落盘，中途失败或 Ctrl-C 时 --replay 仍可重跑
+pub async fn run_pipeline(
+    config: &TeamConfig,
+    pipeline: Pipeline,
+    input: String,
+    record_path: &Path,
+) -> Result<()> {
+    let mut record = PipeRecord {
+        started_at: chrono::Utc::now().to_rfc3339(),
+        input: input.clone(),
+        pipeline: pipeline.clone(),
+        steps: vec![],
+    };
+    record.save(record_path)?;
+
+    let mut prev = input.clone();
+    let mut failed = false;
+    for (i, step) in pipeline.steps.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let prompt = render_template(&step.prompt, &prev, &input);
+        let (step_record, entries) = run_step(config, step, prompt).await;
+
+        if step_record.error.is_some() || entries.is_empty() {
+            let msg = step_record.error.as_deref().unwrap_or("No output");
+            display::print_agent_error(&step.agent, &step_record.status, msg);
+        } else {
+            display::print_agent_reply(&step.agent, &step_record.status, &entries);
+        }
+
+        let ok = step_record.ok();
+        prev = step_record.output.clone();
+        record.steps.push(step_record);
+        record.save(record_path)?;
+        if !ok {
+            failed = true;
+            if pipeline.stop_on_error {
+                break;
+            }
+        }
+    }
+
+    println!();
+    println!("Pipeline record: {}", record_path.display());
+    if failed {
+        bail!("Pipeline finished with errors");
+    }
+    Ok(())
+}
+
+async fn run_step(
+    config: &TeamConfig,
+    step: &PipelineStep,
+    prompt: String,
+) -> (StepRecord, Vec<OutputEntry>) {
+    let started = Instant::now();
+    let fut = prompt_and_collect(config, &step.agent, prompt.clone(), vec![]);
+    let result = match step.timeout_secs {
+        Some(secs) => match tokio::time::timeout(Duration::from_secs(secs), fut).await {
+            Ok(r) => r.map_err(|e| ("unreachable", format!("{:#}", e))),
+            Err(_) => {
+                // 超时：取消 agent 当前任务，避免继续占用
+                let _ = client::send(config, &step.agent, SessionRequest::Cancel).await;
+                Err(("timeout", format!("Timed out after {}s", secs)))
+            }
+        },
+        None => fut.await.map_err(|e| ("unreachable", format!("{:#}", e))),
+    };
+
+    let mut record = StepRecord {
+        agent: step.agent.clone(),
+        prompt,
+        status: String::new(),
+        output: String::new(),
+        elapsed_ms: 0,
+        error: None,
+    };
+    let mut entries = vec![];
+    match result {
+        Ok(AskOutcome { status, resp: SessionResponse::Output { entries: e, .. } }) => {
+            record.output = final_message(&e);
+            record.status = status;
+            entries = e;
+        }
+        Ok(AskOutcome { status, resp: SessionResponse::Error { message } }) => {
+            record.status = status;
+            record.error = Some(message);
+        }
+        Ok(AskOutcome { status, .. }) => {
+            record.status = status;
+            record.error = Some("Unexpected response".into());
+        }
+        Err((status, message)) => {
+            record.status = status.to_string();
+            record.error = Some(message);
+        }
+    }
+    record.elapsed_ms = started.elapsed().as_millis() as u64;
+    (record, entries)
+}
+
+// ==================== 辅助 ====================
+
+/// 单遍替换：代入的文本不再被扫描，上一步输出里的 `{{prompt}}` 原样保留
+fn render_template(template: &str, input: &str, prompt: &str) -> String {
+    let mut out = String::with_capacity(template.len() + input.len());
+    let mut rest = template;
+    while let Some(i) = rest.find("{{") {
+        out.push_str(&rest[..i]);
+        rest = &rest[i..];
+        if let Some(r) = rest.strip_prefix(VAR_INPUT) {
+            out.push_str(input);
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix(VAR_PROMPT) {
+            out.push_str(prompt);
+            rest = r;
+        } else {
+            out.push_str("{{");
+            rest = &rest[2..];
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// 最后一段 AgentMessage（buffer 已合并连续 chunk）
+pub(super) fn final_message(entries: &[OutputEntry]) -> String {
+    entries
+        .iter()
+        .rev()
+        .find(|e| matches!(e.update_type, OutputType::AgentMessage))
+        .map(|e| e.content.trim().to_string())
+        .unwrap_or_default()
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(update_type: OutputType, content: &str) -> OutputEntry {
+        OutputEntry {
+            timestamp: "t".into(),
+            update_type,
+            content: content.into(),
+        }
+    }
+
+    #[test]
+    fn step_parse_with_template() {
+        let s = PipelineStep::parse("reviewer:Review this:\n{{input}}").unwrap();
+        assert_eq!(s.agent, "reviewer");
+        assert_eq!(s.prompt, "Review this:\n{{input}}");
+    }
+
+    #[test]
+    fn step_parse_agent_only() {
+        let s = PipelineStep::parse("coder").unwrap();
+        assert_eq!(s.agent, "coder");
+        assert_eq!(s.prompt, "{{input}}");
+    }
+
+    #[test]
+    fn step_parse_empty_agent() {
+        assert!(PipelineStep::parse(":hello").is_err());
+    }
+
+    #[test]
+    fn render_vars() {
+        let out = render_template("Task: {{prompt}}\nCode:\n{{input}}", "fn x()", "write x");
+        assert_eq!(out, "Task: write x\nCode:\nfn x()");
+        // 代入的输出不再替换，未知变量原样保留
+        let out = render_template("{{input}} / {{other}} {{", "uses {{prompt}}", "p");
+        assert_eq!(out, "uses {{prompt}} / {{other}} {{");
+    }
+
+    #[test]
+    fn final_message_picks_last_text() {
+        let entries = vec![
+            entry(OutputType::AgentMessage, "let me look"),
+            entry(OutputType::ToolCallStart, "read"),
+            entry(OutputType::AgentThought, "hmm"),
+            entry(OutputType::AgentMessage, "  final answer \n"),
+            entry(OutputType::PromptResponse, "EndTurn"),
+        ];
+        assert_eq!(final_message(&entries), "final answer");
+        assert_eq!(final_message(&[]), "");
+    }
+
+    #[test]
+    fn pipeline_json_defaults() {
+        let p: Pipeline = serde_json::from_str(
+            r#"{"steps":[{"agent":"a"},{"agent":"b","prompt":"R: {{input}}","timeout_secs":30}]}"#,
+        )
+        .unwrap();
+        assert!(p.stop_on_error);
+        assert_eq!(p.steps[0].prompt, "{{input}}");
+        assert_eq!(p.steps[1].timeout_secs, Some(30));
+    }
+
+    #[test]
+    fn record_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("rec.json");
+        let rec = PipeRecord {
+            started_at: "now".into(),
+            input: "x".into(),
+            pipeline: Pipeline {
+                steps: vec![PipelineStep::parse("a").unwrap()],
+                stop_on_error: false,
+            },
+            steps: vec![StepRecord {
+                agent: "a".into(),
+                prompt: "x".into(),
+                status: "idle".into(),
+                output: "y".into(),
+                elapsed_ms: 5,
+                error: None,
+            }],
+        };
+        rec.save(&path).unwrap();
+        let back = PipeRecord::load(&path).unwrap();
+        assert_eq!(back.steps[0].output, "y");
+        assert!(!back.pipeline.stop_on_error);
+        assert!(back.steps[0].ok());
+    }
+
+    #[tokio::test]
+    async fn record_saved_when_step_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        let path = dir.path().join("pipes").join("rec.json");
+        let pipeline = Pipeline {
+            steps: vec![PipelineStep::parse("ghost").unwrap(), PipelineStep::parse("b").unwrap()],
+            stop_on_error: true,
+        };
+        // agent 不存在：第一步失败即停，记录仍已落盘，可 --replay
+        assert!(run_pipeline(&config, pipeline, "hi".into(), &path).await.is_err());
+        let back = PipeRecord::load(&path).unwrap();
+        assert_eq!(back.input, "hi");
+        assert_eq!(back.pipeline.steps.len(), 2);
+        assert_eq!(back.steps.len(), 1);
+        assert!(!back.steps[0].ok());
+    }
+}
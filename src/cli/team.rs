@@ -0,0 +1,157 @@
+// ============================================================
+// up / down - 按团队清单批量启停
+// ============================================================
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::config::{AgentSpec, TeamConfig, TeamManifest};
+use crate::protocol::messages::{SessionRequest, SessionResponse};
+
+use super::{client, display, launch, prompt_and_collect, LaunchOptions};
+
+/// 等待 session 完成 ACP 初始化的上限
+const READY_TIMEOUT_SECS: u64 = 60;
+
+/// 启动清单中所有 agent：已运行的跳过，新启动的应用 mode / config / 预算 / system prompt
+pub async fn run_up(config: &TeamConfig, manifest: &TeamManifest) -> Result<()> {
+    for spec in &manifest.agents {
+        if !config.agent_types.contains_key(&spec.agent_type) {
+            anyhow::bail!("Agent '{}': unknown type '{}'", spec.name, spec.agent_type);
+        }
+    }
+
+    // 1. 逐个后台启动（独立进程等待 socket；daemon 模式等待初始化完成）
+    let mut report: Vec<(String, String)> = vec![];
+    let mut started: Vec<&AgentSpec> = vec![];
+    for spec in &manifest.agents {
+        if is_running(config, &spec.name).await {
+            report.push((spec.name.clone(), "skipped (already running)".into()));
+            continue;
+        }
+        let opts = LaunchOptions {
+            cwd: spec.cwd.as_deref(),
+            args: spec.args.as_deref(),
+            auto_approve: spec.auto_approve.as_ref(),
+            ..Default::default()
+        };
+        match launch(config, &spec.agent_type, &spec.name, &opts).await {
+            Ok(_) => started.push(spec),
+            Err(e) => report.push((spec.name.clone(), format!("failed: {:#}", e))),
+        }
+    }
+
+    // 2. 并发等待就绪并应用配置
+    let futs = started.iter().map(|spec| async move {
+        let result = match configure(config, spec).await {
+            Ok(()) => "ready".to_string(),
+            Err(e) => format!("failed: {:#}", e),
+        };
+        (spec.name.clone(), result)
+    });
+    report.extend(futures::future::join_all(futs).await);
+
+    // 按清单顺序输出
+    let rows: Vec<Vec<String>> = manifest
+        .agents
+        .iter()
+        .filter_map(|spec| {
+            report
+                .iter()
+                .find(|(n, _)| n == &spec.name)
+                .map(|(n, r)| vec![n.clone(), spec.agent_type.clone(), r.clone()])
+        })
+        .collect();
+    display::print_table(&["NAME", "TYPE", "RESULT"], &rows);
+
+    if report.iter().any(|(_, r)| r.starts_with("failed")) {
+        anyhow::bail!("Some agents failed to start");
+    }
+    Ok(())
+}
+
+/// 关闭清单中所有正在运行的 agent
+pub async fn run_down(config: &TeamConfig, manifest: &TeamManifest) {
+    let futs = manifest.agents.iter().map(|spec| async move {
+        let result = if !maybe_running(config, &spec.name) {
+            "not running".to_string()
+        } else {
+            match client::send(config, &spec.name, SessionRequest::Shutdown).await {
+                Ok(SessionResponse::Ok { .. }) => "stopped".to_string(),
+                Ok(SessionResponse::Error { message }) => format!("failed: {}", message),
+                Ok(_) => "failed: unexpected response".to_string(),
+                Err(_) => "not running".to_string(),
+            }
+        };
+        vec![spec.name.clone(), spec.agent_type.clone(), result]
+    });
+    let rows = futures::future::join_all(futs).await;
+    display::print_table(&["NAME", "TYPE", "RESULT"], &rows);
+}
+
+/// 本地无 socket / 标记即可判定未运行；远程模式只能实际询问
+fn maybe_running(config: &TeamConfig, name: &str) -> bool {
+    config.remote.host.is_some() || config.session_exists(name)
+}
+
+async fn is_running(config: &TeamConfig, name: &str) -> bool {
+    if !maybe_running(config, name) {
+        return false;
+    }
+    matches!(
+        client::send(config, name, SessionRequest::GetStatus).await,
+        Ok(SessionResponse::Status { .. })
+    )
+}
+
+/// 等待就绪 → SetMode → SetConfig → SetBudget → SetNotify → system prompt
+async fn configure(config: &TeamConfig, spec: &AgentSpec) -> Result<()> {
+    // session 在 ACP 初始化完成后才开始 accept，GetStatus 返回即就绪
+    let status = tokio::time::timeout(
+        Duration::from_secs(READY_TIMEOUT_SECS),
+        client::send(config, &spec.name, SessionRequest::GetStatus),
+    )
+    .await
+    .map_err(|_| anyhow::anyhow!("not ready after {}s", READY_TIMEOUT_SECS))??;
+    if let SessionResponse::Status { summary } = &status {
+        if summary.status != "idle" {
+            anyhow::bail!("status {}", summary.status);
+        }
+    }
+
+    let mut conn = client::SessionClient::connect(config, &spec.name).await?;
+    if let Some(mode) = &spec.mode {
+        expect_ok(conn.send(SessionRequest::SetMode { mode: mode.clone() }).await?, "mode")?;
+    }
+    for (key, value) in spec.config_pairs() {
+        let label = format!("config {}", key);
+        expect_ok(conn.send(SessionRequest::SetConfig { key, value }).await?, &label)?;
+    }
+    if spec.max_tokens.is_some() || spec.max_cost.is_some() {
+        let req = SessionRequest::SetBudget {
+            max_tokens: spec.max_tokens,
+            max_cost: spec.max_cost,
+            clear: false,
+        };
+        expect_ok(conn.send(req).await?, "budget")?;
+    }
+    if let Some(enabled) = spec.notify {
+        expect_ok(conn.send(SessionRequest::SetNotify { enabled }).await?, "notify")?;
+    }
+    if let Some(prompt) = &spec.system_prompt {
+        let outcome = prompt_and_collect(config, &spec.name, prompt.clone(), vec![]).await?;
+        if outcome.status != "idle" {
+            anyhow::bail!("system prompt ended with status {}", outcome.status);
+        }
+    }
+    Ok(())
+}
+
+fn expect_ok(resp: SessionResponse, what: &str) -> Result<()> {
+    match resp {
+        SessionResponse::Ok { .. } => Ok(()),
+        SessionResponse::Error { message } => anyhow::bail!("{}: {}", what, message),
+        _ => anyhow::bail!("{}: unexpected response", what),
+    }
+}
@@ -0,0 +1,673 @@
+// ============================================================
+// top - 全屏团队面板
+// ============================================================
+// 每个 agent 一条 Subscribe 连接：事件实时写入该 agent 的日志与消息预览，
+// 影响汇总的事件（状态 tag、权限请求）触发一次 GetStatus 刷新 plan / 用量 / 权限数，
+// 不做定时轮询；uptime 在本地按刷新时的值递增。新 agent 与 HTTP 网关的 /events 一样靠扫描 socket 目录发现。
+// 按键：↑↓ 选择，Enter 实时日志，p 发送 prompt，a / d 审批，c 取消，r 重启，q 退出。
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::IsTerminal;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Result};
+use crossterm::event::{Event as TermEvent, EventStream, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use futures::StreamExt;
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+use tokio::sync::mpsc;
+
+use crate::config::TeamConfig;
+use crate::protocol::messages::{AgentSummary, OutputEntry, OutputType, SessionEvent, SessionRequest, SessionResponse};
+
+use super::{client, display};
+
+/// 扫描新 agent 的间隔
+const RESCAN_SECS: u64 = 2;
+/// 每个 agent / session 保留的日志字节数
+const LOG_BYTES: usize = 256 * 1024;
+/// 消息预览保留的字节数（只显示最后一行）
+const PREVIEW_BYTES: usize = 4096;
+/// 订阅时补充的历史条数
+const HISTORY_LAST: usize = 3;
+
+const KEYS_HELP: &str = "↑↓ select  Enter log  p prompt  a/d allow/deny  c cancel  r restart  q quit";
+
+/// agent 名 + 命名 session（None = 默认 session）
+type Target = (String, Option<String>);
+
+/// 后台 task 发回界面的消息
+enum Msg {
+    Event(String, SessionEvent),
+    History(String, Vec<OutputEntry>),
+    Status(String, Result<Box<AgentSummary>, String>),
+    /// 订阅结束（agent 退出或连接断开）
+    Closed(String),
+    /// 操作结果，显示在底栏
+    Done(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Action {
+    Approve,
+    Deny,
+    Cancel,
+    Restart,
+    Prompt(String),
+}
+
+impl Action {
+    fn request(self, session: Option<&str>) -> SessionRequest {
+        match self {
+            Self::Approve => SessionRequest::ApprovePermission.in_session(session),
+            Self::Deny => SessionRequest::DenyPermission.in_session(session),
+            Self::Cancel => SessionRequest::Cancel.in_session(session),
+            // Restart 换掉整个进程，只能发给 agent
+            Self::Restart => SessionRequest::Restart,
+            Self::Prompt(text) => SessionRequest::Prompt { text, files: vec![], chain: vec![] }.in_session(session),
+        }
+    }
+}
+
+// ==================== 状态 ====================
+
+/// 一个 session 的实时日志与最近消息
+#[derive(Default)]
+struct Pane {
+    log: String,
+    follow: display::FollowState,
+    preview: String,
+    in_message: bool,
+}
+
+impl Pane {
+    fn push(&mut self, event: &SessionEvent) {
+        if let SessionEvent::Output { entry, .. } = event {
+            if matches!(entry.update_type, OutputType::AgentMessage) {
+                if !self.in_message {
+                    self.preview.clear();
+                }
+                self.in_message = true;
+                self.preview.push_str(&entry.content);
+                trim_front(&mut self.preview, PREVIEW_BYTES);
+            } else {
+                self.in_message = false;
+            }
+        } else {
+            self.in_message = false;
+        }
+        self.log.push_str(&display::render_event(&mut self.follow, event).replace('\x07', ""));
+        trim_front(&mut self.log, LOG_BYTES);
+    }
+
+    fn last_line(&self) -> &str {
+        self.preview.lines().rev().map(str::trim).find(|l| !l.is_empty()).unwrap_or("")
+    }
+}
+
+#[derive(Default)]
+struct Agent {
+    summary: Option<AgentSummary>,
+    /// 刷新时的 uptime 秒数与刷新时刻
+    uptime: Option<(u64, Instant)>,
+    panes: HashMap<Option<String>, Pane>,
+    /// 有影响汇总的事件，待 GetStatus
+    stale: bool,
+    refreshing: bool,
+}
+
+#[derive(Default)]
+enum Mode {
+    #[default]
+    Normal,
+    Prompt(String),
+    ConfirmRestart,
+}
+
+#[derive(Default)]
+struct App {
+    agents: BTreeMap<String, Agent>,
+    selected: usize,
+    /// 查看中的实时日志
+    log_view: Option<Target>,
+    mode: Mode,
+    footer: String,
+    quit: bool,
+}
+
+impl App {
+    fn add_agent(&mut self, name: &str) -> bool {
+        if self.agents.contains_key(name) {
+            return false;
+        }
+        self.agents.insert(name.to_string(), Agent { stale: true, ..Default::default() });
+        true
+    }
+
+    fn handle(&mut self, msg: Msg) {
+        match msg {
+            Msg::Event(name, event) => {
+                let Some(agent) = self.agents.get_mut(&name) else { return };
+                let session = match &event {
+                    SessionEvent::Output { session, .. } | SessionEvent::Info { session, .. } => session.clone(),
+                };
+                agent.stale |= match &event {
+                    SessionEvent::Output { entry, .. } => matches!(entry.update_type, OutputType::PermissionRequest),
+                    SessionEvent::Info { tag, .. } => !matches!(tag.as_str(), "request" | "notify"),
+                };
+                agent.panes.entry(session).or_default().push(&event);
+            }
+            Msg::History(name, entries) => {
+                let Some(agent) = self.agents.get_mut(&name) else { return };
+                let pane = agent.panes.entry(None).or_default();
+                let live = std::mem::take(pane);
+                for entry in entries {
+                    pane.push(&SessionEvent::Output { entry, session: None });
+                }
+                // 订阅后、历史返回前已收到的事件排在历史之后
+                pane.log.push_str(&live.log);
+                if !live.preview.is_empty() {
+                    pane.preview = live.preview;
+                }
+            }
+            Msg::Status(name, result) => {
+                let Some(agent) = self.agents.get_mut(&name) else { return };
+                agent.refreshing = false;
+                match result {
+                    Ok(summary) => {
+                        agent.uptime = parse_uptime(&summary.uptime).map(|s| (s, Instant::now()));
+                        agent.summary = Some(*summary);
+                    }
+                    Err(e) => self.footer = format!("{}: {}", name, e),
+                }
+            }
+            Msg::Closed(name) => {
+                self.agents.remove(&name);
+                if self.log_view.as_ref().is_some_and(|(n, _)| *n == name) {
+                    self.log_view = None;
+                    self.footer = format!("{} exited", name);
+                }
+            }
+            Msg::Done(text) => self.footer = text,
+        }
+    }
+
+    /// 需要 GetStatus 的 agent（每个 agent 同时只有一个刷新在进行）
+    fn take_stale(&mut self) -> Vec<String> {
+        self.agents
+            .iter_mut()
+            .filter(|(_, a)| a.stale && !a.refreshing)
+            .map(|(name, a)| {
+                a.stale = false;
+                a.refreshing = true;
+                name.clone()
+            })
+            .collect()
+    }
+
+    /// 表格行顺序：agent 后紧跟它的命名 session
+    fn targets(&self) -> Vec<Target> {
+        self.agents
+            .iter()
+            .flat_map(|(name, a)| {
+                let sessions = a.summary.iter().flat_map(|s| &s.sessions);
+                std::iter::once((name.clone(), None)).chain(sessions.map(|s| (name.clone(), Some(s.name.clone()))))
+            })
+            .collect()
+    }
+
+    fn current(&self) -> Option<Target> {
+        if let Some(t) = &self.log_view {
+            return Some(t.clone());
+        }
+        self.targets().get(self.selected).cloned()
+    }
+
+    /// 处理按键，返回要发给 agent 的操作
+    fn on_key(&mut self, key: KeyEvent) -> Option<(Target, Action)> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return None;
+        }
+        match std::mem::take(&mut self.mode) {
+            Mode::Prompt(mut text) => {
+                match key.code {
+                    KeyCode::Enter if !text.trim().is_empty() => {
+                        return self.current().map(|t| (t, Action::Prompt(text)));
+                    }
+                    KeyCode::Esc | KeyCode::Enter => {}
+                    KeyCode::Backspace => {
+                        text.pop();
+                        self.mode = Mode::Prompt(text);
+                    }
+                    KeyCode::Char(c) => {
+                        text.push(c);
+                        self.mode = Mode::Prompt(text);
+                    }
+                    _ => self.mode = Mode::Prompt(text),
+                }
+                return None;
+            }
+            Mode::ConfirmRestart => {
+                if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                    return self.current().map(|t| (t, Action::Restart));
+                }
+                return None;
+            }
+            Mode::Normal => {}
+        }
+        let action = match key.code {
+            KeyCode::Char('q') | KeyCode::Esc | KeyCode::Left | KeyCode::Char('h') if self.log_view.is_some() => {
+                self.log_view = None;
+                return None;
+            }
+            KeyCode::Char('q') | KeyCode::Esc => {
+                self.quit = true;
+                return None;
+            }
+            KeyCode::Up | KeyCode::Char('k') => {
+                self.selected = self.selected.saturating_sub(1);
+                return None;
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                self.selected = (self.selected + 1).min(self.targets().len().saturating_sub(1));
+                return None;
+            }
+            KeyCode::Enter | KeyCode::Right | KeyCode::Char('l') => {
+                self.log_view = self.current();
+                return None;
+            }
+            KeyCode::Char('p') => {
+                self.mode = Mode::Prompt(String::new());
+                return None;
+            }
+            KeyCode::Char('r') => {
+                self.mode = Mode::ConfirmRestart;
+                return None;
+            }
+            KeyCode::Char('a') => Action::Approve,
+            KeyCode::Char('d') => Action::Deny,
+            KeyCode::Char('c') => Action::Cancel,
+            _ => return None,
+        };
+        self.current().map(|t| (t, action))
+    }
+
+    // ==================== 绘制 ====================
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [title, body, footer] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let pending: usize = self.agents.values().filter_map(|a| a.summary.as_ref()).map(|s| s.pending_permissions).sum();
+        frame.render_widget(
+            Paragraph::new(format!("agent-team top — {} agent(s), {} pending permission(s)", self.agents.len(), pending))
+                .style(Style::new().add_modifier(Modifier::BOLD)),
+            title,
+        );
+
+        match self.log_view.clone() {
+            Some(target) => self.draw_log(frame, body, &target),
+            None => self.draw_table(frame, body),
+        }
+
+        let (who, _) = self.current().map(|t| (scoped(&t), ())).unwrap_or_default();
+        let line = match &self.mode {
+            Mode::Prompt(text) => format!("Prompt {}: {}▏", who, text),
+            Mode::ConfirmRestart => format!("Restart {}? [y/N]", who),
+            Mode::Normal if !self.footer.is_empty() => format!("{}   ({})", self.footer, KEYS_HELP),
+            Mode::Normal => KEYS_HELP.to_string(),
+        };
+        frame.render_widget(Paragraph::new(line).style(Style::new().fg(Color::DarkGray)), footer);
+    }
+
+    fn draw_table(&mut self, frame: &mut Frame, area: ratatui::layout::Rect) {
+        let targets = self.targets();
+        if targets.is_empty() {
+            frame.render_widget(Paragraph::new("No agents running"), area);
+            return;
+        }
+        self.selected = self.selected.min(targets.len() - 1);
+        let rows = targets.iter().map(|(name, session)| {
+            let agent = &self.agents[name];
+            let pane = agent.panes.get(session);
+            let preview = pane.map(Pane::last_line).unwrap_or("").to_string();
+            let s = agent.summary.as_ref();
+            let cells = match (s, session) {
+                (None, _) => vec![name.clone(), String::new(), "…".into(), "-".into(), "-".into(), "-".into(), "-".into()],
+                (Some(s), None) => vec![
+                    name.clone(),
+                    s.agent_type.clone(),
+                    s.status.clone(),
+                    display::plan_cell(s.plan),
+                    s.pending_permissions.to_string(),
+                    display::tokens_cell(s.usage.as_ref()),
+                    agent.uptime.map(|(secs, at)| display::fmt_age(secs + at.elapsed().as_secs())).unwrap_or_else(|| s.uptime.clone()),
+                ],
+                (Some(s), Some(sess)) => {
+                    let n = s.sessions.iter().find(|x| &x.name == sess);
+                    vec![
+                        format!("{}/{}", name, sess),
+                        String::new(),
+                        n.map(|x| x.status.clone()).unwrap_or_default(),
+                        display::plan_cell(n.and_then(|x| x.plan)),
+                        n.map(|x| x.pending_permissions.to_string()).unwrap_or_default(),
+                        display::tokens_cell(n.and_then(|x| x.usage.as_ref())),
+                        String::new(),
+                    ]
+                }
+            };
+            let status_style = status_style(&cells[2]);
+            let mut cells: Vec<Cell> = cells.into_iter().map(Cell::from).collect();
+            cells[2] = cells[2].clone().style(status_style);
+            cells.push(Cell::from(preview));
+            Row::new(cells)
+        });
+        let header = Row::new(["NAME", "TYPE", "STATUS", "PLAN", "PERM", "TOKENS", "UPTIME", "LAST MESSAGE"])
+            .style(Style::new().add_modifier(Modifier::BOLD));
+        let widths = [
+            Constraint::Length(18),
+            Constraint::Length(10),
+            Constraint::Length(18),
+            Constraint::Length(6),
+            Constraint::Length(5),
+            Constraint::Length(12),
+            Constraint::Length(7),
+            Constraint::Fill(1),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header)
+            .row_highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = TableState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(table, area, &mut state);
+    }
+
+    fn draw_log(&self, frame: &mut Frame, area: ratatui::layout::Rect, target: &Target) {
+        let block = Block::default()
+            .borders(Borders::TOP)
+            .title(format!(" {} — live log (Esc back) ", scoped(target)));
+        let height = block.inner(area).height as usize;
+        let text = self.agents.get(&target.0).and_then(|a| a.panes.get(&target.1)).map(|p| p.log.as_str()).unwrap_or("");
+        let lines: Vec<&str> = text.lines().collect();
+        let tail = &lines[lines.len().saturating_sub(height)..];
+        let body: Vec<Line> = tail.iter().map(|l| Line::raw(*l)).collect();
+        frame.render_widget(Paragraph::new(body).block(block), area);
+    }
+}
+
+fn scoped((name, session): &Target) -> String {
+    match session {
+        Some(s) => format!("{}/{}", name, s),
+        None => name.clone(),
+    }
+}
+
+fn status_style(status: &str) -> Style {
+    let color = match status {
+        "idle" => Color::Green,
+        "running" => Color::Yellow,
+        "waiting_permission" => Color::Magenta,
+        s if s.starts_with("error") => Color::Red,
+        _ => return Style::new(),
+    };
+    Style::new().fg(color)
+}
+
+/// AgentSummary.uptime（"12m 5s"）→ 秒
+fn parse_uptime(text: &str) -> Option<u64> {
+    let (m, s) = text.split_once("m ")?;
+    Some(m.trim().parse::<u64>().ok()? * 60 + s.trim_end_matches('s').trim().parse::<u64>().ok()?)
+}
+
+/// 超出上限时从头部按整行丢弃
+fn trim_front(text: &mut String, max: usize) {
+    if text.len() <= max {
+        return;
+    }
+    let mut cut = text.len() - max;
+    while !text.is_char_boundary(cut) {
+        cut += 1;
+    }
+    let cut = text[cut..].find('\n').map(|i| cut + i + 1).unwrap_or(cut);
+    text.drain(..cut);
+}
+
+// ==================== 后台任务 ====================
+
+/// 订阅一个 agent，事件与开头的历史转发给界面
+async fn watch(config: TeamConfig, name: String, tx: mpsc::UnboundedSender<Msg>) {
+    let mut sub = match client::SessionClient::connect(&config, &name).await {
+        Ok(c) => c,
+        Err(_) => {
+            tx.send(Msg::Closed(name)).ok();
+            return;
+        }
+    };
+    if sub.subscribe().await.is_ok() {
+        let req = SessionRequest::GetOutput { last: HISTORY_LAST, agent_only: false };
+        if let Ok(SessionResponse::Output { entries, .. }) = client::send(&config, &name, req).await {
+            tx.send(Msg::History(name.clone(), entries)).ok();
+        }
+        while let Ok(Some((_, event))) = sub.next_event().await {
+            if tx.send(Msg::Event(name.clone(), event)).is_err() {
+                return;
+            }
+        }
+    }
+    tx.send(Msg::Closed(name)).ok();
+}
+
+fn spawn_refresh(config: &TeamConfig, name: String, tx: &mpsc::UnboundedSender<Msg>) {
+    let (config, tx) = (config.clone(), tx.clone());
+    tokio::task::spawn_local(async move {
+        let result = match client::send(&config, &name, SessionRequest::GetStatus).await {
+            Ok(SessionResponse::Status { summary }) => Ok(summary),
+            Ok(SessionResponse::Error { message }) => Err(message),
+            Ok(_) => Err("unexpected response".into()),
+            Err(e) => Err(format!("{:#}", e)),
+        };
+        tx.send(Msg::Status(name, result)).ok();
+    });
+}
+
+fn spawn_action(config: &TeamConfig, (name, session): Target, action: Action, tx: &mpsc::UnboundedSender<Msg>) {
+    let (config, tx) = (config.clone(), tx.clone());
+    tokio::task::spawn_local(async move {
+        let who = scoped(&(name.clone(), session.clone()));
+        let text = match client::send(&config, &name, action.request(session.as_deref())).await {
+            Ok(SessionResponse::Ok { message }) => message,
+            Ok(SessionResponse::Error { message }) => format!("Error: {}", message),
+            Ok(_) => "Unexpected response".into(),
+            Err(e) => format!("Error: {:#}", e),
+        };
+        tx.send(Msg::Done(format!("{}: {}", who, text))).ok();
+    });
+}
+
+/// `top` 入口：进入备用屏幕，退出（含出错）时恢复终端
+pub async fn run(config: &TeamConfig) -> Result<()> {
+    if !std::io::stdout().is_terminal() {
+        bail!("top needs a terminal; use `agent-team ls` in scripts");
+    }
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, config).await;
+    ratatui::restore();
+    result
+}
+
+async fn event_loop(terminal: &mut ratatui::DefaultTerminal, config: &TeamConfig) -> Result<()> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut app = App::default();
+    let mut keys = EventStream::new();
+    let mut watchers = Vec::new();
+    let mut rescan = tokio::time::interval(Duration::from_secs(RESCAN_SECS));
+    // 每秒重绘一次，uptime 随之增长
+    let mut tick = tokio::time::interval(Duration::from_secs(1));
+
+    let result = loop {
+        for name in app.take_stale() {
+            spawn_refresh(config, name, &tx);
+        }
+        terminal.draw(|f| app.draw(f))?;
+        tokio::select! {
+            Some(msg) = rx.recv() => {
+                app.handle(msg);
+                while let Ok(msg) = rx.try_recv() {
+                    app.handle(msg);
+                }
+            }
+            key = keys.next() => match key {
+                Some(Ok(TermEvent::Key(k))) if k.kind == KeyEventKind::Press => {
+                    if let Some((target, action)) = app.on_key(k) {
+                        spawn_action(config, target, action, &tx);
+                    }
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => break Err(e.into()),
+                None => break Ok(()),
+            },
+            _ = rescan.tick() => {
+                for name in client::session_names(config).await.unwrap_or_default() {
+                    if app.add_agent(&name) {
+                        watchers.push(tokio::task::spawn_local(watch(config.clone(), name, tx.clone())));
+                    }
+                }
+            }
+            _ = tick.tick() => {}
+        }
+        if app.quit {
+            break Ok(());
+        }
+    };
+    for w in watchers {
+        w.abort();
+    }
+    result
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+
+    fn output(t: OutputType, content: &str) -> SessionEvent {
+        SessionEvent::Output {
+            entry: OutputEntry { timestamp: String::new(), update_type: t, content: content.into() },
+            session: None,
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn screen(app: &mut App) -> String {
+        let mut terminal = ratatui::Terminal::new(TestBackend::new(110, 6)).unwrap();
+        terminal.draw(|f| app.draw(f)).unwrap();
+        let buf = terminal.backend().buffer();
+        buf.content()
+            .chunks(buf.area.width as usize)
+            .map(|row| row.iter().map(|c| c.symbol()).collect::<String>().trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn events_update_preview_log_and_refresh() {
+        let mut app = App::default();
+        assert!(app.add_agent("coder"));
+        assert_eq!(app.take_stale(), vec!["coder".to_string()]);
+        app.handle(Msg::Status("coder".into(), Ok(Box::new(AgentSummary { name: "coder".into(), ..Default::default() }))));
+
+        let send = |app: &mut App, e| app.handle(Msg::Event("coder".into(), e));
+        send(&mut app, output(OutputType::AgentMessage, "Looking"));
+        send(&mut app, output(OutputType::AgentMessage, " at it\nall done"));
+        assert!(app.take_stale().is_empty(), "message chunks do not need a refresh");
+        send(&mut app, output(OutputType::ToolCallStart, "read a.rs"));
+        send(&mut app, SessionEvent::Info { tag: "idle".into(), message: "Ready".into(), session: None });
+
+        let pane = &app.agents["coder"].panes[&None];
+        assert_eq!(pane.last_line(), "all done");
+        assert_eq!(pane.log, "Looking at it\nall done\n[tool] read a.rs\n[idle] Ready\n");
+        assert_eq!(app.take_stale(), vec!["coder".to_string()]);
+        // 刷新进行中不重复发起
+        send(&mut app, SessionEvent::Info { tag: "running".into(), message: "x".into(), session: None });
+        assert!(app.take_stale().is_empty());
+
+        app.handle(Msg::Closed("coder".into()));
+        assert!(app.agents.is_empty());
+    }
+
+    #[test]
+    fn table_shows_summary_and_preview() {
+        let mut app = App::default();
+        app.add_agent("coder");
+        let summary = AgentSummary {
+            name: "coder".into(),
+            agent_type: "gemini".into(),
+            status: "running".into(),
+            uptime: "2m 5s".into(),
+            pending_permissions: 1,
+            ..Default::default()
+        };
+        app.handle(Msg::Status("coder".into(), Ok(Box::new(summary))));
+        app.handle(Msg::Event("coder".into(), output(OutputType::AgentMessage, "Refactoring auth")));
+        let text = screen(&mut app);
+        assert!(text.starts_with("agent-team top — 1 agent(s), 1 pending permission(s)"), "{}", text);
+        let row = text.lines().nth(2).unwrap();
+        for part in ["coder", "gemini", "running", "1", "2m", "Refactoring auth"] {
+            assert!(row.contains(part), "{:?} missing in {:?}", part, row);
+        }
+    }
+
+    #[test]
+    fn keys_select_targets_and_actions() {
+        let mut app = App::default();
+        app.add_agent("a");
+        app.add_agent("b");
+        assert_eq!(app.on_key(key(KeyCode::Char('a'))), Some((("a".into(), None), Action::Approve)));
+        app.on_key(key(KeyCode::Down));
+        assert_eq!(app.on_key(key(KeyCode::Char('c'))), Some((("b".into(), None), Action::Cancel)));
+
+        // prompt 输入行
+        app.on_key(key(KeyCode::Char('p')));
+        for c in "hix".chars() {
+            app.on_key(key(KeyCode::Char(c)));
+        }
+        app.on_key(key(KeyCode::Backspace));
+        assert!(screen(&mut app).contains("Prompt b: hi"));
+        assert_eq!(app.on_key(key(KeyCode::Enter)), Some((("b".into(), None), Action::Prompt("hi".into()))));
+
+        // restart 需确认
+        app.on_key(key(KeyCode::Char('r')));
+        assert_eq!(app.on_key(key(KeyCode::Char('n'))), None);
+        app.on_key(key(KeyCode::Char('r')));
+        assert_eq!(app.on_key(key(KeyCode::Char('y'))), Some((("b".into(), None), Action::Restart)));
+
+        // 日志视图中 q 返回表格，表格中 q 退出
+        app.on_key(key(KeyCode::Enter));
+        assert_eq!(app.log_view, Some(("b".into(), None)));
+        app.on_key(key(KeyCode::Char('q')));
+        assert!(app.log_view.is_none() && !app.quit);
+        app.on_key(key(KeyCode::Char('q')));
+        assert!(app.quit);
+    }
+
+    #[test]
+    fn uptime_and_trim() {
+        assert_eq!(parse_uptime("12m 5s"), Some(725));
+        assert_eq!(parse_uptime("soon"), None);
+        let mut text = "line one\nline two\nthree\n".to_string();
+        trim_front(&mut text, 12);
+        assert_eq!(text, "three\n");
+    }
+}
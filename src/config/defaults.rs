@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::PathBuf;
 
+use crate::protocol::transport::{Framing, DEFAULT_MAX_FRAME};
+
 // ==================== Agent 类型配置 ====================
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -13,21 +15,149 @@ pub struct AgentTypeConfig {
 // ==================== 权限策略 ====================
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum AutoApprovePolicy {
     Always,
     Never,
     ReadOnly,
 }
 
+impl AutoApprovePolicy {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::Always => "always",
+            Self::Never => "never",
+            Self::ReadOnly => "read_only",
+        }
+    }
+}
+
+impl std::str::FromStr for AutoApprovePolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "always" => Ok(Self::Always),
+            "never" => Ok(Self::Never),
+            "read_only" | "read-only" => Ok(Self::ReadOnly),
+            _ => Err(format!("invalid policy '{}' (always, never, read_only)", s)),
+        }
+    }
+}
+
 // ==================== 全局配置 ====================
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct TeamConfig {
     pub auto_approve: AutoApprovePolicy,
+    /// 输出缓冲区条目上限
     pub output_buffer_size: usize,
+    /// 输出缓冲区字节上限，0 = 不限
+    pub output_buffer_bytes: usize,
     pub agent_types: HashMap<String, AgentTypeConfig>,
     pub default_cwd: PathBuf,
     pub socket_dir: PathBuf,
+    /// agent 间委派链最大深度，0 = 不向 agent 提供 agent-team MCP server
+    pub mcp_max_depth: usize,
+    /// 每个 turn 前后快照 cwd，记录文件改动（`changes` 命令）；默认关闭，AGENT_TEAM_TRACK_CHANGES=1 开启。
+    /// git 仓库内快照会把 blob 写进仓库的 .git/objects
+    pub track_changes: bool,
+    /// 远程访问（TCP 监听 / 远程 CLI）
+    pub remote: RemoteConfig,
+    /// 连接分帧与单帧上限
+    pub transport: TransportConfig,
+    /// 事件钩子配置（AGENT_TEAM_HOOKS），不存在 = 无钩子
+    pub hooks_file: PathBuf,
+    /// 权限请求 / turn 结束通知
+    pub notify: NotifyConfig,
+}
+
+// ==================== 传输 ====================
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TransportConfig {
+    /// 单帧（一条请求 / 响应）字节上限，超出的帧被跳过并回复错误（AGENT_TEAM_MAX_FRAME）
+    pub max_frame: usize,
+    /// 客户端在对端支持时改用长度前缀帧（AGENT_TEAM_FRAMING=length）
+    pub length_prefix: bool,
+}
+
+impl Default for TransportConfig {
+    fn default() -> Self {
+        let framing = std::env::var("AGENT_TEAM_FRAMING").ok();
+        Self {
+            max_frame: std::env::var("AGENT_TEAM_MAX_FRAME")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .filter(|&n: &usize| n > 0)
+                .unwrap_or(DEFAULT_MAX_FRAME),
+            length_prefix: framing
+                .and_then(|f| f.parse::<Framing>().ok())
+                .is_some_and(|f| f == Framing::LengthPrefixed),
+        }
+    }
+}
+
+// ==================== 通知 ====================
+
+/// 同一 session 同类通知的默认最小间隔（秒）
+const DEFAULT_NOTIFY_INTERVAL: u64 = 10;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NotifyConfig {
+    /// 通知写入的命名管道（AGENT_TEAM_NOTIFY_FIFO），无读端时跳过
+    pub fifo: Option<PathBuf>,
+    /// 通知追加到的共享收件箱文件（AGENT_TEAM_NOTIFY_INBOX）
+    pub inbox: Option<PathBuf>,
+    /// 同一 session 同类通知的最小间隔，期间的通知合并计数（AGENT_TEAM_NOTIFY_INTERVAL，秒）
+    pub min_interval_secs: u64,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        Self {
+            fifo: std::env::var_os("AGENT_TEAM_NOTIFY_FIFO").map(PathBuf::from),
+            inbox: std::env::var_os("AGENT_TEAM_NOTIFY_INBOX").map(PathBuf::from),
+            min_interval_secs: std::env::var("AGENT_TEAM_NOTIFY_INTERVAL")
+                .ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(DEFAULT_NOTIFY_INTERVAL),
+        }
+    }
+}
+
+// ==================== 远程访问 ====================
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RemoteConfig {
+    /// session / daemon 额外监听的 TCP 地址（`--listen`），None = 仅本地 socket
+    pub listen: Option<String>,
+    /// TLS 证书链 + 私钥（PEM），同时提供时 TCP 监听启用 TLS
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// 无 TLS 时也允许监听非回环地址（`--allow-plaintext`），仅限可信网络
+    pub allow_plaintext: bool,
+    /// CLI 连接的远程端点（`--host` / AGENT_TEAM_HOST）：`host:port` 或 `tls://host:port`
+    pub host: Option<String>,
+    /// 校验服务端证书的 CA（PEM，AGENT_TEAM_CA），None = 内置 webpki 根证书
+    pub tls_ca: Option<PathBuf>,
+    /// 认证 token 文件（0600）
+    pub token_file: PathBuf,
+}
+
+impl Default for RemoteConfig {
+    fn default() -> Self {
+        let base = dirs::config_dir().unwrap_or_else(std::env::temp_dir);
+        Self {
+            listen: None,
+            tls_cert: None,
+            tls_key: None,
+            allow_plaintext: false,
+            host: None,
+            tls_ca: std::env::var_os("AGENT_TEAM_CA").map(PathBuf::from),
+            token_file: base.join("agent-team").join("token"),
+        }
+    }
 }
 
 /// Unix: uid, Windows: pid
@@ -95,9 +225,22 @@ impl Default for TeamConfig {
         Self {
             auto_approve: AutoApprovePolicy::Never,
             output_buffer_size: 10000,
+            output_buffer_bytes: 16 * 1024 * 1024,
             agent_types,
             default_cwd: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
             socket_dir: std::env::temp_dir().join(format!("agent-team-{}", id)),
+            mcp_max_depth: 3,
+            track_changes: std::env::var("AGENT_TEAM_TRACK_CHANGES")
+                .is_ok_and(|v| matches!(v.trim(), "1" | "true" | "on")),
+            remote: RemoteConfig::default(),
+            transport: TransportConfig::default(),
+            hooks_file: std::env::var_os("AGENT_TEAM_HOOKS").map(PathBuf::from).unwrap_or_else(|| {
+                dirs::config_dir()
+                    .unwrap_or_else(std::env::temp_dir)
+                    .join("agent-team")
+                    .join("hooks.toml")
+            }),
+            notify: NotifyConfig::default(),
         }
     }
 }
@@ -125,12 +268,32 @@ impl TeamConfig {
         self.socket_dir.join(format!("{}.log", name))
     }
 
+    /// 改动追踪存储：{socket_dir}/snapshots/<name>
+    pub fn snapshot_dir(&self, name: &str) -> PathBuf {
+        self.socket_dir.join("snapshots").join(name)
+    }
+
+    /// daemon 控制 socket（不以 .sock 结尾，scan_sessions 不会当成 agent）
+    pub fn daemon_socket(&self) -> PathBuf {
+        self.socket_dir.join("daemon.ctl")
+    }
+
+    /// daemon 托管 agent 的标记文件：{socket_dir}/{name}.daemon
+    pub fn daemon_marker(&self, name: &str) -> PathBuf {
+        self.socket_dir.join(format!("{}.daemon", name))
+    }
+
+    /// agent 是否存在（独立 session socket 或 daemon 标记）
+    pub fn session_exists(&self, name: &str) -> bool {
+        self.session_socket(name).exists() || self.daemon_marker(name).exists()
+    }
+
     /// 确保 socket 目录存在
     pub fn ensure_socket_dir(&self) -> std::io::Result<()> {
         std::fs::create_dir_all(&self.socket_dir)
     }
 
-    /// 扫描活跃 session，返回 agent 名字列表
+    /// 扫描活跃 session（独立进程 + daemon 托管），返回 agent 名字列表
     pub fn scan_sessions(&self) -> Vec<String> {
         let Ok(entries) = std::fs::read_dir(&self.socket_dir) else {
             return vec![];
@@ -139,29 +302,101 @@ impl TeamConfig {
             .filter_map(|e| e.ok())
             .filter_map(|e| {
                 let name = e.file_name().to_string_lossy().to_string();
-                name.strip_suffix(".sock").map(|s| s.to_string())
+                name.strip_suffix(".sock")
+                    .or_else(|| name.strip_suffix(".daemon"))
+                    .map(|s| s.to_string())
             })
             .collect();
         names.sort();
+        names.dedup();
         names
     }
 
+    /// 解析目标 agent：逗号分隔的名字，含 `*` / `?` 的部分按 glob 匹配活跃 session
+    /// 结果去重并保持出现顺序；字面名字原样保留（不存在时由连接阶段报错）
+    pub fn resolve_targets(&self, spec: &str) -> Vec<String> {
+        let sessions = if spec.contains(['*', '?']) {
+            self.scan_sessions()
+        } else {
+            vec![]
+        };
+        match_targets(spec, &sessions)
+    }
+
     /// 生成下一个 agent 名字：扫描已有 socket，{type}-{max+1}
     pub fn gen_name(&self, agent_type: &str) -> String {
-        let prefix = format!("{}-", agent_type);
-        let max_num = self
-            .scan_sessions()
-            .iter()
-            .filter_map(|name| {
-                name.strip_prefix(&prefix)
-                    .and_then(|suffix| suffix.parse::<u32>().ok())
-            })
-            .max()
-            .unwrap_or(0);
-        format!("{}-{}", agent_type, max_num + 1)
+        next_name(agent_type, &self.scan_sessions())
     }
 }
 
+/// agent 名字用作 socket / 日志 / 快照的文件名：只允许字母、数字和 `-_.`，不能以 `.` 开头
+pub fn validate_agent_name(name: &str) -> Result<(), String> {
+    let ok = !name.is_empty()
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if ok {
+        Ok(())
+    } else {
+        Err(format!("Invalid agent name '{}' (use letters, digits, '-', '_' and '.')", name))
+    }
+}
+
+/// resolve_targets 的匹配部分，sessions 由调用方提供（远程模式来自远程 List）
+pub fn match_targets(spec: &str, sessions: &[String]) -> Vec<String> {
+    let mut out: Vec<String> = vec![];
+    for part in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        if part.contains(['*', '?']) {
+            for name in sessions.iter().filter(|n| glob_match(part, n)) {
+                if !out.contains(name) {
+                    out.push(name.clone());
+                }
+            }
+        } else if !out.iter().any(|n| n == part) {
+            out.push(part.to_string());
+        }
+    }
+    out
+}
+
+/// 在已有名字中取 {type}-{max+1}
+pub fn next_name(agent_type: &str, existing: &[String]) -> String {
+    let prefix = format!("{}-", agent_type);
+    let max_num = existing
+        .iter()
+        .filter_map(|name| {
+            name.strip_prefix(&prefix)
+                .and_then(|suffix| suffix.parse::<u32>().ok())
+        })
+        .max()
+        .unwrap_or(0);
+    format!("{}-{}", agent_type, max_num + 1)
+}
+
+/// 简单 glob：`*` 匹配任意串，`?` 匹配单个字符
+pub(crate) fn glob_match(pattern: &str, text: &str) -> bool {
+    let p: Vec<char> = pattern.chars().collect();
+    let t: Vec<char> = text.chars().collect();
+    let (mut pi, mut ti) = (0, 0);
+    // 最近一个 * 的位置 + 其匹配起点，失配时回溯
+    let mut star: Option<(usize, usize)> = None;
+    while ti < t.len() {
+        if pi < p.len() && (p[pi] == '?' || p[pi] == t[ti]) {
+            pi += 1;
+            ti += 1;
+        } else if pi < p.len() && p[pi] == '*' {
+            star = Some((pi, ti));
+            pi += 1;
+        } else if let Some((sp, st)) = star {
+            pi = sp + 1;
+            ti = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    p[pi..].iter().all(|&c| c == '*')
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -176,8 +411,10 @@ mod tests {
     #[test]
     fn gen_name_no_existing() {
         let dir = tempfile::tempdir().unwrap();
-        let mut config = TeamConfig::default();
-        config.socket_dir = dir.path().to_path_buf();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
         assert_eq!(config.gen_name("gemini"), "gemini-1");
     }
 
@@ -186,8 +423,10 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         std::fs::File::create(dir.path().join("gemini-1.sock")).unwrap();
         std::fs::File::create(dir.path().join("gemini-2.sock")).unwrap();
-        let mut config = TeamConfig::default();
-        config.socket_dir = dir.path().to_path_buf();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
         assert_eq!(config.gen_name("gemini"), "gemini-3");
     }
 
@@ -196,8 +435,10 @@ mod tests {
         let dir = tempfile::tempdir().unwrap();
         std::fs::File::create(dir.path().join("gemini-1.sock")).unwrap();
         std::fs::File::create(dir.path().join("claude-1.sock")).unwrap();
-        let mut config = TeamConfig::default();
-        config.socket_dir = dir.path().to_path_buf();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
         assert_eq!(config.gen_name("gemini"), "gemini-2");
         assert_eq!(config.gen_name("claude"), "claude-2");
         assert_eq!(config.gen_name("copilot"), "copilot-1");
@@ -206,8 +447,10 @@ mod tests {
     #[test]
     fn scan_sessions_empty() {
         let dir = tempfile::tempdir().unwrap();
-        let mut config = TeamConfig::default();
-        config.socket_dir = dir.path().to_path_buf();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
         assert!(config.scan_sessions().is_empty());
     }
 
@@ -217,12 +460,64 @@ mod tests {
         std::fs::File::create(dir.path().join("alice.sock")).unwrap();
         std::fs::File::create(dir.path().join("bob.sock")).unwrap();
         std::fs::File::create(dir.path().join("not-a-socket.txt")).unwrap();
-        let mut config = TeamConfig::default();
-        config.socket_dir = dir.path().to_path_buf();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
         let sessions = config.scan_sessions();
         assert_eq!(sessions, vec!["alice", "bob"]);
     }
 
+    #[test]
+    fn scan_sessions_includes_daemon_markers() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::File::create(dir.path().join("alice.sock")).unwrap();
+        std::fs::File::create(dir.path().join("carol.daemon")).unwrap();
+        std::fs::File::create(dir.path().join("alice.daemon")).unwrap();
+        std::fs::File::create(dir.path().join("daemon.ctl")).unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        assert_eq!(config.scan_sessions(), vec!["alice", "carol"]);
+        assert!(config.session_exists("carol"));
+        assert!(!config.session_exists("dave"));
+    }
+
+    #[test]
+    fn glob_match_patterns() {
+        assert!(glob_match("gemini-*", "gemini-1"));
+        assert!(glob_match("*-1", "claude-1"));
+        assert!(glob_match("g?mini-?", "gemini-2"));
+        assert!(glob_match("*", "anything"));
+        assert!(!glob_match("gemini-*", "claude-1"));
+        assert!(!glob_match("g?", "gem"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+    }
+
+    #[test]
+    fn resolve_targets_names_and_globs() {
+        let dir = tempfile::tempdir().unwrap();
+        for n in ["gemini-1", "gemini-2", "claude-1"] {
+            std::fs::File::create(dir.path().join(format!("{}.sock", n))).unwrap();
+        }
+        let config = TeamConfig {
+            socket_dir: dir.path().to_path_buf(),
+            ..Default::default()
+        };
+        assert_eq!(config.resolve_targets("claude-1"), vec!["claude-1"]);
+        assert_eq!(config.resolve_targets("gemini-*"), vec!["gemini-1", "gemini-2"]);
+        // 去重 + 保持顺序
+        assert_eq!(
+            config.resolve_targets("claude-1, gemini-*,gemini-1"),
+            vec!["claude-1", "gemini-1", "gemini-2"],
+        );
+        // 字面名字不校验存在性
+        assert_eq!(config.resolve_targets("ghost"), vec!["ghost"]);
+        assert!(config.resolve_targets("codex-*").is_empty());
+    }
+
     #[test]
     fn session_log_path() {
         let config = TeamConfig::default();
@@ -233,8 +528,10 @@ mod tests {
     #[test]
     fn ensure_socket_dir_creates() {
         let dir = tempfile::tempdir().unwrap();
-        let mut config = TeamConfig::default();
-        config.socket_dir = dir.path().join("nested").join("sockets");
+        let config = TeamConfig {
+            socket_dir: dir.path().join("nested").join("sockets"),
+            ..Default::default()
+        };
         config.ensure_socket_dir().unwrap();
         assert!(config.socket_dir.exists());
     }
@@ -257,6 +554,26 @@ mod tests {
         assert_eq!(config.agent_types.len(), expected.len());
     }
 
+    #[test]
+    fn agent_name_validation() {
+        for ok in ["gemini-1", "my_agent", "v1.2"] {
+            assert!(validate_agent_name(ok).is_ok(), "{}", ok);
+        }
+        for bad in ["", "..", ".hidden", "../etc", "a/b", "a\\b", "a b", "a:b"] {
+            assert!(validate_agent_name(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn auto_approve_policy_parse() {
+        for p in [AutoApprovePolicy::Always, AutoApprovePolicy::Never, AutoApprovePolicy::ReadOnly] {
+            let back: AutoApprovePolicy = p.label().parse().unwrap();
+            assert_eq!(back.label(), p.label());
+        }
+        assert!(matches!("read-only".parse(), Ok(AutoApprovePolicy::ReadOnly)));
+        assert!("sometimes".parse::<AutoApprovePolicy>().is_err());
+    }
+
     #[test]
     fn adapter_hint_known() {
         assert!(adapter_hint("claude").is_some());
@@ -0,0 +1,149 @@
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use super::defaults::glob_match;
+
+// ==================== 事件钩子（hooks.toml） ====================
+//
+// [[hook]]
+// on = ["done", "waiting_permission"]   # 事件 tag，"*" = 全部
+// agents = ["coder", "test-*"]          # 可选，agent 名 glob
+// command = 'notify-send agent-team "$AGENT_TEAM_AGENT: $AGENT_TEAM_EVENT"'   # stdin 为 JSON
+// timeout_secs = 10
+//
+// [[hook]]
+// on = ["error", "exited"]
+// url = "http://127.0.0.1:9000/agent-events"               # POST JSON
+
+/// 默认超时
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HooksFile {
+    #[serde(default, rename = "hook")]
+    pub hooks: Vec<Hook>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+    pub on: Vec<String>,
+    #[serde(default)]
+    pub agents: Vec<String>,
+    /// `sh -c` 执行，payload 写入 stdin
+    pub command: Option<String>,
+    /// 本地 webhook，只支持 http://
+    pub url: Option<String>,
+    #[serde(default = "default_timeout")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout() -> u64 {
+    DEFAULT_TIMEOUT_SECS
+}
+
+impl HooksFile {
+    /// 读取钩子配置；文件不存在视为没有钩子
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = match std::fs::read_to_string(path) {
+            Ok(t) => t,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(e).with_context(|| format!("Cannot read {}", path.display())),
+        };
+        Self::parse(&text).with_context(|| format!("Invalid hooks file {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: Self = toml::from_str(text)?;
+        for (i, h) in file.hooks.iter().enumerate() {
+            let n = i + 1;
+            if h.on.is_empty() {
+                bail!("Hook #{}: `on` must list at least one event", n);
+            }
+            match (&h.command, &h.url) {
+                (Some(_), None) => {}
+                (None, Some(url)) => {
+                    if !url.starts_with("http://") {
+                        bail!("Hook #{}: only http:// URLs are supported, got '{}'", n, url);
+                    }
+                }
+                _ => bail!("Hook #{}: set exactly one of `command` or `url`", n),
+            }
+            if h.timeout_secs == 0 {
+                bail!("Hook #{}: timeout_secs must be positive", n);
+            }
+        }
+        Ok(file)
+    }
+}
+
+impl Hook {
+    pub fn matches(&self, event: &str, agent: &str) -> bool {
+        self.on.iter().any(|e| e == "*" || e == event)
+            && (self.agents.is_empty() || self.agents.iter().any(|p| glob_match(p, agent)))
+    }
+
+    /// 日志用的简短描述
+    pub fn describe(&self) -> String {
+        match (&self.command, &self.url) {
+            (Some(cmd), _) => format!("`{}`", cmd),
+            (_, Some(url)) => url.clone(),
+            _ => "(empty hook)".into(),
+        }
+    }
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_match() {
+        let file = HooksFile::parse(
+            r#"
+            [[hook]]
+            on = ["done", "waiting_permission"]
+            agents = ["test-*"]
+            command = "cat"
+
+            [[hook]]
+            on = ["*"]
+            url = "http://127.0.0.1:9000/x"
+            timeout_secs = 3
+            "#,
+        )
+        .unwrap();
+        let [a, b] = &file.hooks[..] else { panic!("expected two hooks") };
+        assert_eq!(a.timeout_secs, DEFAULT_TIMEOUT_SECS);
+        assert!(a.matches("done", "test-1"));
+        assert!(!a.matches("done", "coder"));
+        assert!(!a.matches("error", "test-1"));
+        assert!(b.matches("exited", "coder"));
+        assert_eq!(b.describe(), "http://127.0.0.1:9000/x");
+    }
+
+    #[test]
+    fn rejects_invalid_hooks() {
+        for text in [
+            "[[hook]]\non = []\ncommand = \"x\"",
+            "[[hook]]\non = [\"done\"]",
+            "[[hook]]\non = [\"done\"]\ncommand = \"x\"\nurl = \"http://a\"",
+            "[[hook]]\non = [\"done\"]\nurl = \"https://example.com\"",
+            "[[hook]]\non = [\"done\"]\ncommand = \"x\"\ntimeout_secs = 0",
+            "[[hook]]\non = [\"done\"]\ncommand = \"x\"\nbogus = 1",
+        ] {
+            assert!(HooksFile::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn missing_file_means_no_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(HooksFile::load(&dir.path().join("hooks.toml")).unwrap().hooks.is_empty());
+    }
+}
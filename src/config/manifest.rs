@@ -0,0 +1,188 @@
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use super::defaults::{validate_agent_name, AutoApprovePolicy};
+
+// ==================== 团队清单（team.toml） ====================
+//
+// [[agent]]
+// name = "coder"
+// type = "claude"
+// cwd = "./backend"            # 相对清单所在目录
+// args = "--verbose"
+// mode = "code"
+// auto_approve = "always"      # always | never | read_only
+// system_prompt = "You write the code."
+// max_tokens = 200000          # 预算上限（同 `budget --tokens / --cost`）
+// max_cost = 5.0
+// notify = false               # 关闭权限请求 / turn 结束通知
+// [agent.config]
+// model = "sonnet"
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TeamManifest {
+    #[serde(default, rename = "agent")]
+    pub agents: Vec<AgentSpec>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AgentSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub agent_type: String,
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// 额外参数（空白分隔，同 `add --args`）
+    #[serde(default)]
+    pub args: Option<String>,
+    #[serde(default)]
+    pub mode: Option<String>,
+    /// 启动后逐项 SetConfig
+    #[serde(default)]
+    pub config: BTreeMap<String, toml::Value>,
+    #[serde(default)]
+    pub auto_approve: Option<AutoApprovePolicy>,
+    /// 就绪后作为第一条 prompt 发送
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// 用量上限，就绪后通过 SetBudget 设置
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+    /// false = 就绪后关闭通知
+    #[serde(default)]
+    pub notify: Option<bool>,
+}
+
+impl TeamManifest {
+    /// 读取清单；相对 cwd 以清单所在目录为基准
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Cannot read {}", path.display()))?;
+        let mut manifest = Self::parse(&text)
+            .with_context(|| format!("Invalid manifest {}", path.display()))?;
+        let base = path.parent().unwrap_or(Path::new("."));
+        for a in &mut manifest.agents {
+            if let Some(cwd) = &a.cwd {
+                if cwd.is_relative() {
+                    a.cwd = Some(base.join(cwd));
+                }
+            }
+        }
+        Ok(manifest)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let manifest: Self = toml::from_str(text)?;
+        if manifest.agents.is_empty() {
+            bail!("No [[agent]] entries");
+        }
+        let mut seen = HashSet::new();
+        for a in &manifest.agents {
+            if a.name.trim().is_empty() {
+                bail!("Agent name must not be empty");
+            }
+            validate_agent_name(&a.name).map_err(anyhow::Error::msg)?;
+            if !seen.insert(a.name.as_str()) {
+                bail!("Duplicate agent name '{}'", a.name);
+            }
+        }
+        Ok(manifest)
+    }
+}
+
+impl AgentSpec {
+    /// SetConfig 键值对：字符串原样，其它标量转为 TOML 字面量
+    pub fn config_pairs(&self) -> Vec<(String, String)> {
+        self.config
+            .iter()
+            .map(|(k, v)| {
+                let v = match v {
+                    toml::Value::String(s) => s.clone(),
+                    other => other.to_string(),
+                };
+                (k.clone(), v)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = r#"
+[[agent]]
+name = "coder"
+type = "claude"
+cwd = "backend"
+mode = "code"
+auto_approve = "always"
+system_prompt = "You write the code."
+max_tokens = 200000
+[agent.config]
+model = "sonnet"
+thinking_budget_tokens = 2048
+
+[[agent]]
+name = "reviewer"
+type = "gemini"
+args = "--sandbox"
+notify = false
+"#;
+
+    #[test]
+    fn parse_full() {
+        let m = TeamManifest::parse(SAMPLE).unwrap();
+        assert_eq!(m.agents.len(), 2);
+        let coder = &m.agents[0];
+        assert_eq!(coder.agent_type, "claude");
+        assert_eq!(coder.mode.as_deref(), Some("code"));
+        assert!(matches!(coder.auto_approve, Some(AutoApprovePolicy::Always)));
+        assert_eq!(coder.max_tokens, Some(200000));
+        assert!(coder.max_cost.is_none());
+        assert!(coder.notify.is_none());
+        assert_eq!(m.agents[1].notify, Some(false));
+        assert_eq!(
+            coder.config_pairs(),
+            vec![
+                ("model".to_string(), "sonnet".to_string()),
+                ("thinking_budget_tokens".to_string(), "2048".to_string()),
+            ],
+        );
+        let reviewer = &m.agents[1];
+        assert_eq!(reviewer.args.as_deref(), Some("--sandbox"));
+        assert!(reviewer.config.is_empty());
+        assert!(reviewer.auto_approve.is_none());
+    }
+
+    #[test]
+    fn parse_rejects_duplicates() {
+        let text = "[[agent]]\nname = \"a\"\ntype = \"gemini\"\n[[agent]]\nname = \"a\"\ntype = \"claude\"\n";
+        let err = TeamManifest::parse(text).unwrap_err();
+        assert!(err.to_string().contains("Duplicate"));
+    }
+
+    #[test]
+    fn parse_rejects_empty_and_unknown_fields() {
+        assert!(TeamManifest::parse("").is_err());
+        let text = "[[agent]]\nname = \"a\"\ntype = \"gemini\"\ncolour = \"red\"\n";
+        assert!(TeamManifest::parse(text).is_err());
+    }
+
+    #[test]
+    fn load_resolves_relative_cwd() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("team.toml");
+        std::fs::write(&path, SAMPLE).unwrap();
+        let m = TeamManifest::load(&path).unwrap();
+        assert_eq!(m.agents[0].cwd.as_deref(), Some(dir.path().join("backend").as_path()));
+        assert!(m.agents[1].cwd.is_none());
+    }
+}
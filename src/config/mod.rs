@@ -1,3 +1,10 @@
 pub mod defaults;
+pub mod hooks;
+pub mod manifest;
 
-pub use defaults::{adapter_hint, AgentTypeConfig, AutoApprovePolicy, TeamConfig};
+pub use defaults::{
+    adapter_hint, match_targets, next_name, validate_agent_name, AgentTypeConfig, AutoApprovePolicy, NotifyConfig,
+    RemoteConfig, TeamConfig, TransportConfig,
+};
+pub use hooks::{Hook, HooksFile};
+pub use manifest::{AgentSpec, TeamManifest};
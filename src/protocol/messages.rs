@@ -1,16 +1,32 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
 // ==================== Session 协议 ====================
 // 每个 session 管一个 agent，请求无需 name 字段
 
+/// 协议版本：新增 / 修改请求类型或连接语义时递增；不发 Hello 的旧 session 视为 0
+/// （1：Hello；2：带 id 的多路复用请求；3：长度前缀帧 + 单帧上限；4：命名 ACP session；
+/// 5：fork；6：结构化 plan；7：用量统计与预算；8：通知开关；9：按 id 审批权限）
+pub const PROTOCOL_VERSION: u32 = 9;
+/// 本二进制的 agent-team 版本
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type")]
 pub enum SessionRequest {
+    /// 握手：客户端每个连接的第一条请求，交换协议版本与能力
+    Hello {
+        protocol: u32,
+        version: String,
+    },
     GetStatus,
     Prompt {
         text: String,
         files: Vec<FileAttachment>,
+        /// 委派链：发起本次 prompt 的上游 agent（由 MCP ask_agent 填写）
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        chain: Vec<String>,
     },
     GetOutput {
         last: usize,
@@ -24,6 +40,59 @@ pub enum SessionRequest {
     Shutdown,
     SetMode { mode: String },
     SetConfig { key: String, value: String },
+    /// 每个 turn 的文件改动；turn 为空 = 全部
+    GetChanges {
+        #[serde(default)]
+        turn: Option<u64>,
+        /// 附带 unified diff
+        #[serde(default)]
+        diff: bool,
+    },
+    /// 还原某个 turn 的文件改动；turn 为空 = 最近一个有改动的 turn
+    Undo {
+        #[serde(default)]
+        turn: Option<u64>,
+    },
+    /// 订阅实时事件：回复 Ok 后该连接持续推送 SessionResponse::Event，直到断开
+    Subscribe,
+    /// 在同一 agent 进程中新建一个命名 ACP session
+    NewSession { session: String },
+    /// 关闭命名 session（取消进行中的 turn，丢弃其输出）
+    CloseSession { session: String },
+    /// 把请求路由到命名 session；只接受 GetStatus / Prompt / GetOutput / Cancel /
+    /// ApprovePermission / DenyPermission / GetPlan
+    InSession {
+        session: String,
+        request: Box<SessionRequest>,
+    },
+    /// fork 出的新 agent：加载源 ACP session（agent 支持 session/load 时），
+    /// 否则把源对话记录作为一条 prompt 回放
+    Seed {
+        from: String,
+        #[serde(default)]
+        session_id: Option<String>,
+        transcript: Vec<OutputEntry>,
+    },
+    /// fork 的源 agent：记录派生出的 agent（info 中互相关联）
+    LinkFork { fork: String },
+    /// agent 最新上报的 plan
+    GetPlan,
+    /// 设置 token / 花费上限（未给出的项保持不变，clear 先清空）
+    SetBudget {
+        #[serde(default)]
+        max_tokens: Option<u64>,
+        #[serde(default)]
+        max_cost: Option<f64>,
+        #[serde(default)]
+        clear: bool,
+    },
+    /// 开关本 agent 的通知（权限请求 / turn 结束）
+    SetNotify { enabled: bool },
+    /// 全部 session 的待审批权限（`inbox`）
+    GetPermissions,
+    /// 按 id 审批 / 拒绝一个待审批权限，不论其在哪个 session
+    /// （字段不能叫 `id`，那是多路复用信封的请求 id）
+    ResolvePermission { permission_id: u64, approve: bool },
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -32,21 +101,146 @@ pub enum SessionResponse {
     Ok {
         message: String,
     },
+    /// Hello 的回复；capabilities 为 session 支持的请求类型
+    Hello {
+        protocol: u32,
+        version: String,
+        capabilities: Vec<String>,
+        /// session 接受的单帧字节上限，0 = 未声明
+        #[serde(default)]
+        max_frame: usize,
+    },
     Error {
         message: String,
     },
     Status {
-        summary: AgentSummary,
+        summary: Box<AgentSummary>,
     },
     Output {
         agent_name: String,
         entries: Vec<OutputEntry>,
     },
+    Changes {
+        agent_name: String,
+        turns: Vec<TurnChanges>,
+    },
+    /// daemon List：所有托管 agent
+    Agents {
+        agents: Vec<AgentSummary>,
+    },
+    /// Subscribe 后推送的实时事件
+    Event {
+        agent_name: String,
+        event: SessionEvent,
+    },
+    /// GetPlan：空 = agent 尚未上报 plan
+    Plan {
+        agent_name: String,
+        entries: Vec<PlanEntry>,
+    },
+    /// GetPermissions：按请求先后排列
+    Permissions {
+        agent_name: String,
+        pending: Vec<PermissionInfo>,
+    },
+}
+
+/// session 事件流（与 session stdout 输出一致）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SessionEvent {
+    /// agent 输出（消息 chunk、工具调用、权限请求等）
+    Output {
+        entry: OutputEntry,
+        /// 来自命名 session（None = 默认 session）
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+    },
+    /// 生命周期事件（idle / running / changes / exited ...）
+    Info {
+        tag: String,
+        message: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        session: Option<String>,
+    },
+}
+
+// ==================== Daemon 协议 ====================
+// daemon 在一个控制 socket 上托管多个 agent，session 请求按 name 寻址
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum DaemonRequest {
+    /// 转发给指定 agent 的 session 请求
+    Session {
+        name: String,
+        request: SessionRequest,
+    },
+    /// 在 daemon 内启动新 agent（等 ACP 初始化完成后才返回）
+    Spawn {
+        name: String,
+        agent_type: String,
+        #[serde(default)]
+        cwd: Option<PathBuf>,
+        #[serde(default)]
+        args: Vec<String>,
+        /// 覆盖权限策略（always / never / read_only）
+        #[serde(default)]
+        auto_approve: Option<String>,
+        #[serde(default)]
+        no_mcp: bool,
+        #[serde(default)]
+        worktree: Option<WorktreeInfo>,
+    },
+    List,
+    /// 关闭所有 agent 并退出 daemon
+    Stop,
 }
 
 impl SessionRequest {
+    /// 本版本支持的全部请求类型（Hello 回复的 capabilities，另附 FEATURES）
+    pub const KINDS: &'static [&'static str] = &[
+        "Hello",
+        "GetStatus",
+        "Prompt",
+        "GetOutput",
+        "Cancel",
+        "ApprovePermission",
+        "DenyPermission",
+        "Restart",
+        "Shutdown",
+        "SetMode",
+        "SetConfig",
+        "GetChanges",
+        "Undo",
+        "Subscribe",
+        "NewSession",
+        "CloseSession",
+        "InSession",
+        "Seed",
+        "LinkFork",
+        "GetPlan",
+        "SetBudget",
+        "SetNotify",
+        "GetPermissions",
+        "ResolvePermission",
+    ];
+
+    /// 连接层能力（小写，与请求类型区分）
+    pub const FEATURES: &'static [&'static str] = &["multiplex", "length_prefix"];
+
+    /// Hello 回复的 capabilities
+    pub fn capabilities() -> Vec<String> {
+        Self::KINDS
+            .iter()
+            .chain(Self::FEATURES)
+            .map(|k| k.to_string())
+            .collect()
+    }
+
     pub fn label(&self) -> &str {
         match self {
+            Self::Hello { .. } => "Hello",
             Self::GetStatus => "GetStatus",
             Self::Prompt { .. } => "Prompt",
             Self::GetOutput { .. } => "GetOutput",
@@ -57,10 +251,73 @@ impl SessionRequest {
             Self::Shutdown => "Shutdown",
             Self::SetMode { .. } => "SetMode",
             Self::SetConfig { .. } => "SetConfig",
+            Self::GetChanges { .. } => "GetChanges",
+            Self::Undo { .. } => "Undo",
+            Self::Subscribe => "Subscribe",
+            Self::NewSession { .. } => "NewSession",
+            Self::CloseSession { .. } => "CloseSession",
+            Self::InSession { .. } => "InSession",
+            Self::Seed { .. } => "Seed",
+            Self::LinkFork { .. } => "LinkFork",
+            Self::GetPlan => "GetPlan",
+            Self::SetBudget { .. } => "SetBudget",
+            Self::SetNotify { .. } => "SetNotify",
+            Self::GetPermissions => "GetPermissions",
+            Self::ResolvePermission { .. } => "ResolvePermission",
+        }
+    }
+
+    /// session 为 Some 时包装为 InSession，路由到该命名 session
+    pub fn in_session(self, session: Option<&str>) -> Self {
+        match session {
+            Some(s) => Self::InSession {
+                session: s.to_string(),
+                request: Box::new(self),
+            },
+            None => self,
         }
     }
 }
 
+/// 多路复用信封：`{"id": N, ...}`。带 id 的请求并发处理，响应带回同一 id；
+/// 连接上不带 id 的响应是推送通知（Subscribe 的事件）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope<T> {
+    pub id: u64,
+    #[serde(flatten)]
+    pub body: T,
+}
+
+/// 解析对端请求；未知的请求类型（对端是更新的 CLI）返回可读错误，连接保持可用
+pub fn decode_request<T: DeserializeOwned>(value: serde_json::Value) -> Result<T, String> {
+    // DaemonRequest::Session 嵌套的 request.type 比外层 "Session" 更有意义
+    let kind = value
+        .get("request")
+        .and_then(|r| r.get("type"))
+        .or_else(|| value.get("type"))
+        .and_then(|t| t.as_str())
+        .unwrap_or("?")
+        .to_string();
+    serde_json::from_value(value).map_err(|e| {
+        if e.to_string().starts_with("unknown variant") {
+            format!(
+                "Unsupported request '{}' (session runs agent-team {}, protocol {})",
+                kind, VERSION, PROTOCOL_VERSION,
+            )
+        } else {
+            format!("Invalid '{}' request: {}", kind, e)
+        }
+    })
+}
+
+// ==================== 远程认证 ====================
+// TCP 连接的第一行；通过后按 DaemonRequest 协议通信（单 session 监听端同样按 name 寻址）
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthRequest {
+    pub token: String,
+}
+
 // ==================== 辅助类型 ====================
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -69,7 +326,7 @@ pub struct FileAttachment {
     pub content: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AgentSummary {
     pub name: String,
     pub agent_type: String,
@@ -82,6 +339,111 @@ pub struct AgentSummary {
     pub agent_info_name: Option<String>,
     /// agent 自报版本
     pub agent_info_version: Option<String>,
+    /// 当前 turn 的委派链（空 = 用户直接发起）
+    #[serde(default)]
+    pub delegation_chain: Vec<String>,
+    /// `add --worktree` 创建的专属 worktree
+    #[serde(default)]
+    pub worktree: Option<WorktreeInfo>,
+    /// session 进程的 agent-team 版本（旧版本不上报）
+    #[serde(default)]
+    pub version: Option<String>,
+    /// session 进程的协议版本（旧版本不上报 = 0）
+    #[serde(default)]
+    pub protocol: u32,
+    /// 默认 session 之外的命名 ACP session
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub sessions: Vec<NamedSessionSummary>,
+    /// 默认 ACP session 的 id（fork 时交给新 agent 加载）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    /// 本 agent 由哪个 agent fork 而来
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forked_from: Option<String>,
+    /// 从本 agent fork 出的 agent
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forks: Vec<String>,
+    /// 默认 session 的 plan 进度（未上报 plan 时为 None）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<PlanProgress>,
+    /// 全部 session 的累计用量与预算（尚无 turn 且未设预算时为 None）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageSummary>,
+    /// 通知已关闭（`notify <name> off`）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub notify_muted: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NamedSessionSummary {
+    pub name: String,
+    pub status: String,
+    pub prompt_count: u64,
+    pub pending_permissions: usize,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub plan: Option<PlanProgress>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub usage: Option<UsageSummary>,
+}
+
+impl AgentSummary {
+    /// session 进程比当前 CLI 旧（需要 rm + add 重建才能用上新功能）
+    pub fn is_outdated(&self) -> bool {
+        self.protocol < PROTOCOL_VERSION
+    }
+}
+
+/// 一个 turn 内 agent 改动的文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnChanges {
+    /// session 内递增的 turn 编号（从 1 开始，跨 restart 连续）
+    pub turn: u64,
+    /// prompt 首行摘要
+    pub prompt: String,
+    pub started_at: String,
+    pub files: Vec<FileChange>,
+    /// 已被 undo 还原
+    #[serde(default)]
+    pub undone: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileChange {
+    /// 相对 agent cwd 的路径
+    pub path: String,
+    pub kind: ChangeKind,
+    /// unified diff（GetChanges.diff 时填充）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diff: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl ChangeKind {
+    /// git status 风格单字母
+    pub fn letter(&self) -> char {
+        match self {
+            Self::Added => 'A',
+            Self::Modified => 'M',
+            Self::Deleted => 'D',
+        }
+    }
+}
+
+/// agent 专属 git worktree
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorktreeInfo {
+    /// worktree 目录（即 agent cwd）
+    pub path: String,
+    pub branch: String,
+    /// 主仓库根目录（merge 目标）
+    pub repo: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +466,8 @@ pub enum OutputType {
     PermissionRequest,
     ModeUpdate,
     ConfigUpdate,
+    /// turn 结束时的用量记录
+    Usage,
     Error,
 }
 
@@ -121,11 +485,219 @@ impl OutputType {
             Self::PermissionRequest => "permission",
             Self::ModeUpdate => "mode",
             Self::ConfigUpdate => "config",
+            Self::Usage => "usage",
             Self::Error => "error",
         }
     }
 }
 
+// ==================== Plan ====================
+
+/// ACP Plan 的一项；agent 每次上报完整 plan，session 只保留最新一份
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub content: String,
+    pub priority: PlanPriority,
+    pub status: PlanStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanPriority {
+    High,
+    Medium,
+    Low,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStatus {
+    Pending,
+    InProgress,
+    Completed,
+}
+
+impl PlanPriority {
+    pub fn label(&self) -> &str {
+        match self {
+            Self::High => "high",
+            Self::Medium => "medium",
+            Self::Low => "low",
+        }
+    }
+}
+
+impl PlanStatus {
+    /// 列表前缀：[ ] 待办、[~] 进行中、[x] 完成
+    pub fn marker(&self) -> &str {
+        match self {
+            Self::Pending => "[ ]",
+            Self::InProgress => "[~]",
+            Self::Completed => "[x]",
+        }
+    }
+}
+
+/// plan 完成度，显示为 "3/7 done"
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PlanProgress {
+    pub done: usize,
+    pub total: usize,
+}
+
+impl PlanProgress {
+    /// 空 plan 返回 None
+    pub fn of(entries: &[PlanEntry]) -> Option<Self> {
+        if entries.is_empty() {
+            return None;
+        }
+        let done = entries.iter().filter(|e| e.status == PlanStatus::Completed).count();
+        Some(Self { done, total: entries.len() })
+    }
+}
+
+impl std::fmt::Display for PlanProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{} done", self.done, self.total)
+    }
+}
+
+// ==================== 权限 ====================
+
+/// 一个待审批的权限请求
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PermissionInfo {
+    /// agent 内唯一，ResolvePermission 按此定位
+    pub id: u64,
+    /// 所在命名 session（None = 默认 session）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session: Option<String>,
+    pub tool: String,
+    /// ACP 工具类别（read / edit / execute ...）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kind: Option<String>,
+    pub age_secs: u64,
+}
+
+// ==================== 用量 ====================
+
+/// token 用量；estimated = agent 未上报，按文本长度估算
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input: u64,
+    pub output: u64,
+    pub total: u64,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub estimated: bool,
+}
+
+impl TokenUsage {
+    /// 累加；任一部分为估算则结果标记为估算
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.input += other.input;
+        self.output += other.output;
+        self.total += other.total;
+        self.estimated |= other.estimated;
+    }
+}
+
+/// "~12.3k tokens (in 10.0k / out 2.3k)"，估算值带 ~ 前缀
+impl std::fmt::Display for TokenUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{} tokens (in {} / out {})",
+            if self.estimated { "~" } else { "" },
+            fmt_tokens(self.total),
+            fmt_tokens(self.input),
+            fmt_tokens(self.output),
+        )
+    }
+}
+
+/// token 数的紧凑写法：950、12.3k、1.2M
+pub fn fmt_tokens(n: u64) -> String {
+    match n {
+        0..=999 => n.to_string(),
+        1_000..=999_999 => format!("{:.1}k", n as f64 / 1e3),
+        _ => format!("{:.1}M", n as f64 / 1e6),
+    }
+}
+
+/// agent 上报的累计花费
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cost {
+    pub amount: f64,
+    pub currency: String,
+}
+
+impl std::fmt::Display for Cost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.2} {}", self.amount, self.currency)
+    }
+}
+
+/// 每个 agent 的用量上限；None = 不限
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Budget {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<u64>,
+    /// 与 agent 上报的花费同币种
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_cost: Option<f64>,
+}
+
+impl Budget {
+    pub fn is_empty(&self) -> bool {
+        self.max_tokens.is_none() && self.max_cost.is_none()
+    }
+
+    /// 达到任一上限时返回原因
+    pub fn exceeded(&self, tokens: u64, cost: Option<f64>) -> Option<String> {
+        if let Some(max) = self.max_tokens.filter(|&m| tokens >= m) {
+            return Some(format!(
+                "token budget reached ({} / {})",
+                fmt_tokens(tokens),
+                fmt_tokens(max)
+            ));
+        }
+        match (self.max_cost, cost) {
+            (Some(max), Some(c)) if c >= max => {
+                Some(format!("cost budget reached ({:.2} / {:.2})", c, max))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Budget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if let Some(t) = self.max_tokens {
+            parts.push(format!("{} tokens", fmt_tokens(t)));
+        }
+        if let Some(c) = self.max_cost {
+            parts.push(format!("{:.2} cost", c));
+        }
+        if parts.is_empty() {
+            f.write_str("none")
+        } else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+/// summary 中的用量：累计 token、turn 数、花费与预算
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UsageSummary {
+    pub tokens: TokenUsage,
+    pub turns: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+    #[serde(default, skip_serializing_if = "Budget::is_empty")]
+    pub budget: Budget,
+}
+
 impl std::fmt::Display for OutputType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(self.label())
@@ -147,6 +719,7 @@ mod tests {
         let req = SessionRequest::Prompt {
             text: "hello".into(),
             files: vec![],
+            chain: vec![],
         };
         let json = serde_json::to_string(&req).unwrap();
         let back: SessionRequest = serde_json::from_str(&json).unwrap();
@@ -159,7 +732,7 @@ mod tests {
     #[test]
     fn session_response_roundtrip() {
         let resp = SessionResponse::Status {
-            summary: AgentSummary {
+            summary: Box::new(AgentSummary {
                 name: "gemini-1".into(),
                 agent_type: "gemini".into(),
                 cwd: "/tmp".into(),
@@ -169,7 +742,8 @@ mod tests {
                 pending_permissions: 0,
                 agent_info_name: None,
                 agent_info_version: None,
-            },
+                ..Default::default()
+            }),
         };
         let json = serde_json::to_string(&resp).unwrap();
         let back: SessionResponse = serde_json::from_str(&json).unwrap();
@@ -181,6 +755,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn daemon_request_roundtrip() {
+        let req = DaemonRequest::Session {
+            name: "coder".into(),
+            request: SessionRequest::Undo { turn: Some(2) },
+        };
+        let json = serde_json::to_string(&req).unwrap();
+        assert!(json.contains(r#""type":"Session""#));
+        match serde_json::from_str::<DaemonRequest>(&json).unwrap() {
+            DaemonRequest::Session { name, request: SessionRequest::Undo { turn } } => {
+                assert_eq!(name, "coder");
+                assert_eq!(turn, Some(2));
+            }
+            _ => panic!("wrong variant"),
+        }
+
+        // 可选字段省略
+        let spawn: DaemonRequest =
+            serde_json::from_str(r#"{"type":"Spawn","name":"a","agent_type":"gemini"}"#).unwrap();
+        assert!(matches!(spawn, DaemonRequest::Spawn { no_mcp: false, cwd: None, .. }));
+    }
+
     #[test]
     fn output_entry_serde() {
         let entry = OutputEntry {
@@ -198,7 +794,7 @@ mod tests {
     fn session_request_labels() {
         let cases: Vec<(SessionRequest, &str)> = vec![
             (SessionRequest::GetStatus, "GetStatus"),
-            (SessionRequest::Prompt { text: "".into(), files: vec![] }, "Prompt"),
+            (SessionRequest::Prompt { text: "".into(), files: vec![], chain: vec![] }, "Prompt"),
             (SessionRequest::GetOutput { last: 0, agent_only: false }, "GetOutput"),
             (SessionRequest::Cancel, "Cancel"),
             (SessionRequest::ApprovePermission, "ApprovePermission"),
@@ -245,4 +841,122 @@ mod tests {
         assert_eq!(back.path, PathBuf::from("/tmp/test.rs"));
         assert_eq!(back.content, "fn main() {}");
     }
+
+    #[test]
+    fn decode_unknown_request_types() {
+        let req: SessionRequest = decode_request(serde_json::json!({ "type": "GetStatus" })).unwrap();
+        assert!(matches!(req, SessionRequest::GetStatus));
+
+        let err = decode_request::<SessionRequest>(serde_json::json!({ "type": "Teleport" }))
+            .unwrap_err();
+        assert!(err.starts_with("Unsupported request 'Teleport'"), "{}", err);
+
+        // 嵌套在 DaemonRequest::Session 中时报内层类型
+        let err = decode_request::<DaemonRequest>(serde_json::json!({
+            "type": "Session", "name": "a", "request": { "type": "Teleport" }
+        }))
+        .unwrap_err();
+        assert!(err.starts_with("Unsupported request 'Teleport'"), "{}", err);
+
+        let err = decode_request::<SessionRequest>(serde_json::json!({ "type": "SetMode" }))
+            .unwrap_err();
+        assert!(err.starts_with("Invalid 'SetMode' request"), "{}", err);
+    }
+
+    #[test]
+    fn kinds_cover_labels_and_old_summaries_are_outdated() {
+        let reqs = [
+            SessionRequest::Hello { protocol: PROTOCOL_VERSION, version: VERSION.into() },
+            SessionRequest::Subscribe,
+            SessionRequest::Undo { turn: None },
+            SessionRequest::GetChanges { turn: None, diff: false },
+            SessionRequest::Seed { from: "a".into(), session_id: None, transcript: vec![] },
+            SessionRequest::LinkFork { fork: "b".into() },
+            SessionRequest::GetPlan,
+            SessionRequest::SetBudget { max_tokens: Some(1), max_cost: None, clear: false },
+            SessionRequest::SetNotify { enabled: false },
+            SessionRequest::GetPermissions,
+            SessionRequest::ResolvePermission { permission_id: 1, approve: true },
+        ];
+        for r in &reqs {
+            assert!(SessionRequest::KINDS.contains(&r.label()), "{}", r.label());
+        }
+
+        // 旧 session 的 summary 没有 version / protocol 字段
+        let old: AgentSummary = serde_json::from_str(
+            r#"{"name":"a","agent_type":"mock","cwd":"/","status":"idle","uptime":"0m 0s",
+                "prompt_count":0,"pending_permissions":0,"agent_info_name":null,"agent_info_version":null}"#,
+        )
+        .unwrap();
+        assert!(old.is_outdated());
+        assert!(old.version.is_none());
+        let current = AgentSummary { protocol: PROTOCOL_VERSION, ..Default::default() };
+        assert!(!current.is_outdated());
+    }
+
+    #[test]
+    fn in_session_wraps_request() {
+        assert!(matches!(SessionRequest::Cancel.in_session(None), SessionRequest::Cancel));
+        let req = SessionRequest::GetOutput { last: 2, agent_only: false }.in_session(Some("review"));
+        let json = serde_json::to_string(&req).unwrap();
+        let back: SessionRequest = serde_json::from_str(&json).unwrap();
+        match back {
+            SessionRequest::InSession { session, request } => {
+                assert_eq!(session, "review");
+                assert!(matches!(*request, SessionRequest::GetOutput { last: 2, .. }));
+            }
+            other => panic!("expected InSession, got {:?}", other),
+        }
+        assert_eq!(req.label(), "InSession");
+
+        // 默认 session 的事件不带 session 字段，旧客户端照常解析
+        let info = SessionEvent::Info { tag: "idle".into(), message: "Ready".into(), session: None };
+        assert!(!serde_json::to_string(&info).unwrap().contains("session"));
+        let summary = serde_json::to_string(&AgentSummary::default()).unwrap();
+        assert!(!summary.contains("sessions"));
+    }
+
+    #[test]
+    fn usage_formatting_and_budget() {
+        assert_eq!(fmt_tokens(950), "950");
+        assert_eq!(fmt_tokens(12_345), "12.3k");
+        assert_eq!(fmt_tokens(1_250_000), "1.2M");
+
+        let mut u = TokenUsage { input: 1000, output: 200, total: 1200, estimated: false };
+        u.add(&TokenUsage { input: 10, output: 5, total: 15, estimated: true });
+        assert_eq!(u.to_string(), "~1.2k tokens (in 1.0k / out 205)");
+        assert_eq!(serde_json::to_string(&TokenUsage::default()).unwrap(), r#"{"input":0,"output":0,"total":0}"#);
+
+        let budget = Budget { max_tokens: Some(1000), max_cost: Some(0.5) };
+        assert_eq!(budget.to_string(), "1.0k tokens, 0.50 cost");
+        assert!(budget.exceeded(999, Some(0.1)).is_none());
+        assert_eq!(budget.exceeded(1000, None).unwrap(), "token budget reached (1.0k / 1.0k)");
+        assert!(budget.exceeded(10, Some(0.5)).unwrap().starts_with("cost budget"));
+        assert!(Budget::default().exceeded(u64::MAX, Some(1e9)).is_none());
+    }
+
+    #[test]
+    fn plan_progress_and_serde() {
+        let entry = |status| PlanEntry { content: "step".into(), priority: PlanPriority::High, status };
+        assert!(PlanProgress::of(&[]).is_none());
+        let entries = [
+            entry(PlanStatus::Completed),
+            entry(PlanStatus::InProgress),
+            entry(PlanStatus::Pending),
+        ];
+        let p = PlanProgress::of(&entries).unwrap();
+        assert_eq!(p.to_string(), "1/3 done");
+        let json = serde_json::to_string(&entries[1]).unwrap();
+        assert_eq!(json, r#"{"content":"step","priority":"high","status":"in_progress"}"#);
+    }
+
+    #[test]
+    fn envelope_flattens_body() {
+        let json = serde_json::to_string(&Envelope { id: 7, body: SessionRequest::Cancel }).unwrap();
+        assert_eq!(json, r#"{"id":7,"type":"Cancel"}"#);
+        let back: Envelope<SessionResponse> =
+            serde_json::from_str(r#"{"id":9,"type":"Ok","message":"done"}"#).unwrap();
+        assert_eq!(back.id, 9);
+        assert!(matches!(back.body, SessionResponse::Ok { message } if message == "done"));
+    }
 }
@@ -1,2 +1,3 @@
 pub mod messages;
+pub mod remote;
 pub mod transport;
@@ -0,0 +1,515 @@
+// ============================================================
+// remote - TCP（可选 TLS）远程访问 + token 认证
+// ============================================================
+// session / daemon 以 `--listen` 额外监听 TCP；连接的第一行是 AuthRequest，
+// token 与本机 token 文件（0600）一致才继续，之后按 DaemonRequest 协议通信。
+// CLI 通过 `--host` / AGENT_TEAM_HOST 连接，token 取 AGENT_TEAM_TOKEN 或本机 token 文件。
+// 明文 TCP 只用于 localhost / SSH 隧道：非回环地址须配 TLS，或显式 `--allow-plaintext`。
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncWrite, ReadHalf, WriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_rustls::rustls::{self, pki_types::ServerName};
+use tokio_rustls::{TlsAcceptor, TlsConnector};
+
+use crate::config::RemoteConfig;
+use crate::protocol::messages::{AuthRequest, SessionResponse};
+use crate::protocol::transport::{JsonLineReader, JsonLineWriter, DEFAULT_MAX_FRAME};
+
+/// 握手（TLS + 认证）超时
+const HANDSHAKE_TIMEOUT_SECS: u64 = 10;
+/// 认证前的单帧上限：未认证的对端只能让我们缓冲这么多
+const AUTH_MAX_FRAME: usize = 4096;
+
+// ==================== 流类型 ====================
+
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+/// TCP 或 TLS 流
+pub type BoxStream = Box<dyn AsyncStream>;
+pub type RemoteReader = JsonLineReader<ReadHalf<BoxStream>>;
+pub type RemoteWriter = JsonLineWriter<WriteHalf<BoxStream>>;
+
+fn split(stream: BoxStream) -> (RemoteReader, RemoteWriter) {
+    let (read, write) = tokio::io::split(stream);
+    (JsonLineReader::new(read), JsonLineWriter::new(write))
+}
+
+// ==================== token ====================
+
+/// 读取 token 文件，不存在时生成（32 字节随机数 hex，权限 0600）
+pub fn load_or_create_token(path: &Path) -> Result<String> {
+    if path.exists() {
+        return read_token_file(path);
+    }
+    write_token(path)
+}
+
+/// 重新生成 token（旧 token 立即失效，已运行的监听端需重启）
+pub fn rotate_token(path: &Path) -> Result<String> {
+    if path.exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Cannot remove {}", path.display()))?;
+    }
+    write_token(path)
+}
+
+/// CLI 使用的 token：AGENT_TEAM_TOKEN 优先，其次本机 token 文件
+pub fn client_token(remote: &RemoteConfig) -> Result<String> {
+    if let Ok(t) = std::env::var("AGENT_TEAM_TOKEN") {
+        if !t.trim().is_empty() {
+            return Ok(t.trim().to_string());
+        }
+    }
+    read_token_file(&remote.token_file).context(
+        "No token: set AGENT_TEAM_TOKEN (run `agent-team token` on the remote host)",
+    )
+}
+
+fn write_token(path: &Path) -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|e| anyhow::anyhow!("No randomness: {}", e))?;
+    let token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let mut opts = std::fs::OpenOptions::new();
+    opts.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        opts.mode(0o600);
+    }
+    use std::io::Write;
+    let mut file = opts
+        .open(path)
+        .with_context(|| format!("Cannot create token file {}", path.display()))?;
+    writeln!(file, "{}", token)?;
+    Ok(token)
+}
+
+fn read_token_file(path: &Path) -> Result<String> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(path)
+            .with_context(|| format!("Cannot read {}", path.display()))?
+            .permissions()
+            .mode();
+        if mode & 0o077 != 0 {
+            bail!(
+                "Token file {} is accessible by other users (mode {:o}); run chmod 600",
+                path.display(),
+                mode & 0o777,
+            );
+        }
+    }
+    let token = std::fs::read_to_string(path)
+        .with_context(|| format!("Cannot read {}", path.display()))?
+        .trim()
+        .to_string();
+    if token.is_empty() {
+        bail!("Token file {} is empty", path.display());
+    }
+    Ok(token)
+}
+
+/// 定长比较，避免按前缀逐字节猜测
+pub(crate) fn token_eq(a: &str, b: &str) -> bool {
+    a.len() == b.len() && a.bytes().zip(b.bytes()).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// ==================== 远程地址 ====================
+
+/// `host:port`、`tcp://host:port` 或 `tls://host:port`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RemoteHost {
+    pub addr: String,
+    pub tls: bool,
+}
+
+impl RemoteHost {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+        let (tls, addr) = if let Some(rest) = spec.strip_prefix("tls://") {
+            (true, rest)
+        } else {
+            (false, spec.strip_prefix("tcp://").unwrap_or(spec))
+        };
+        let addr = addr.trim_end_matches('/');
+        match addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(Self {
+                addr: addr.to_string(),
+                tls,
+            }),
+            _ => bail!("Invalid host '{}' (expected [tls://]host:port)", spec),
+        }
+    }
+
+    /// TLS 校验用的主机名（去掉端口和 IPv6 方括号）
+    fn server_name(&self) -> &str {
+        let host = self.addr.rsplit_once(':').map(|(h, _)| h).unwrap_or(&self.addr);
+        host.trim_start_matches('[').trim_end_matches(']')
+    }
+}
+
+// ==================== 客户端 ====================
+
+/// 连接远程端点并完成认证
+pub async fn connect(remote: &RemoteConfig) -> Result<(RemoteReader, RemoteWriter)> {
+    let spec = remote.host.as_deref().context("No remote host configured")?;
+    let host = RemoteHost::parse(spec)?;
+    let token = client_token(remote)?;
+
+    let tcp = TcpStream::connect(&host.addr)
+        .await
+        .with_context(|| format!("Cannot connect to {}", host.addr))?;
+    tcp.set_nodelay(true).ok();
+    let stream: BoxStream = if host.tls {
+        let name = ServerName::try_from(host.server_name().to_string())
+            .with_context(|| format!("Invalid TLS server name '{}'", host.server_name()))?;
+        let connector = tls_connector(remote.tls_ca.as_deref())?;
+        Box::new(
+            connector
+                .connect(name, tcp)
+                .await
+                .with_context(|| format!("TLS handshake with {} failed", host.addr))?,
+        )
+    } else {
+        Box::new(tcp)
+    };
+
+    let (mut reader, mut writer) = split(stream);
+    writer.write(&AuthRequest { token }).await?;
+    match reader.read::<SessionResponse>().await? {
+        Some(SessionResponse::Ok { .. }) => Ok((reader, writer)),
+        Some(SessionResponse::Error { message }) => bail!("{}: {}", host.addr, message),
+        _ => bail!("{}: authentication failed", host.addr),
+    }
+}
+
+fn tls_connector(ca: Option<&Path>) -> Result<TlsConnector> {
+    let mut roots = rustls::RootCertStore::empty();
+    match ca {
+        Some(path) => {
+            let certs = load_certs(path)?;
+            let (added, _) = roots.add_parsable_certificates(certs);
+            if added == 0 {
+                bail!("No usable certificate in {}", path.display());
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+    let config = rustls::ClientConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConnector::from(Arc::new(config)))
+}
+
+// ==================== 服务端 ====================
+
+/// `--listen` 对应的 TCP 监听端
+pub struct RemoteListener {
+    listener: TcpListener,
+    auth: Authenticator,
+}
+
+/// 单个连接的 TLS 握手 + token 校验（可 clone 到连接任务中）
+#[derive(Clone)]
+pub struct Authenticator {
+    acceptor: Option<TlsAcceptor>,
+    token: Arc<str>,
+}
+
+impl RemoteListener {
+    /// 未配置 listen 时返回 None；token 文件不存在则生成。
+    /// 无 TLS 绑定非回环地址时拒绝，除非 allow_plaintext（此时打印警告）
+    pub async fn bind(remote: &RemoteConfig) -> Result<Option<Self>> {
+        let Some(addr) = remote.listen.as_deref() else {
+            return Ok(None);
+        };
+        let acceptor = match (&remote.tls_cert, &remote.tls_key) {
+            (Some(cert), Some(key)) => Some(tls_acceptor(cert, key)?),
+            (None, None) => None,
+            _ => bail!("--tls-cert and --tls-key must be given together"),
+        };
+        let token = load_or_create_token(&remote.token_file)?;
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen on {}", addr))?;
+        let local = listener.local_addr()?;
+        if acceptor.is_none() && !local.ip().is_loopback() {
+            if !remote.allow_plaintext {
+                bail!(
+                    "Refusing plain TCP on non-loopback address {}: the token and all traffic \
+                     would be unencrypted. Use --tls-cert/--tls-key, listen on 127.0.0.1 behind \
+                     an SSH tunnel, or pass --allow-plaintext on a trusted network",
+                    local
+                );
+            }
+            eprintln!(
+                "WARNING: listening on {} over plain TCP. The token and all traffic are \
+                 unencrypted; use this only on a trusted network.",
+                local
+            );
+        }
+        Ok(Some(Self {
+            listener,
+            auth: Authenticator {
+                acceptor,
+                token: token.into(),
+            },
+        }))
+    }
+
+    /// `tcp://addr` / `tls://addr`（实际绑定地址）
+    pub fn describe(&self) -> String {
+        let scheme = if self.auth.acceptor.is_some() { "tls" } else { "tcp" };
+        match self.listener.local_addr() {
+            Ok(addr) => format!("{}://{}", scheme, addr),
+            Err(_) => format!("{}://?", scheme),
+        }
+    }
+
+    pub async fn accept(&self) -> std::io::Result<(TcpStream, SocketAddr)> {
+        self.listener.accept().await
+    }
+
+    pub fn authenticator(&self) -> Authenticator {
+        self.auth.clone()
+    }
+}
+
+impl Authenticator {
+    /// TLS 握手 + 读取 AuthRequest；token 不符时回复 Error 并返回错误
+    pub async fn handshake(&self, tcp: TcpStream) -> Result<(RemoteReader, RemoteWriter)> {
+        tokio::time::timeout(
+            Duration::from_secs(HANDSHAKE_TIMEOUT_SECS),
+            self.handshake_inner(tcp),
+        )
+        .await
+        .context("Handshake timed out")?
+    }
+
+    async fn handshake_inner(&self, tcp: TcpStream) -> Result<(RemoteReader, RemoteWriter)> {
+        tcp.set_nodelay(true).ok();
+        let stream: BoxStream = match &self.acceptor {
+            Some(acceptor) => Box::new(acceptor.accept(tcp).await.context("TLS handshake failed")?),
+            None => Box::new(tcp),
+        };
+        let (mut reader, mut writer) = split(stream);
+        reader.set_max_frame(AUTH_MAX_FRAME);
+        let auth = reader.read::<AuthRequest>().await?;
+        if !auth.is_some_and(|a| token_eq(&a.token, &self.token)) {
+            let _ = writer
+                .write(&SessionResponse::Error {
+                    message: "Invalid token".into(),
+                })
+                .await;
+            bail!("Invalid token");
+        }
+        reader.set_max_frame(DEFAULT_MAX_FRAME);
+        writer
+            .write(&SessionResponse::Ok {
+                message: "Authenticated".into(),
+            })
+            .await?;
+        Ok((reader, writer))
+    }
+}
+
+fn tls_acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor> {
+    let certs = load_certs(cert)?;
+    if certs.is_empty() {
+        bail!("No certificate in {}", cert.display());
+    }
+    let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(
+        std::fs::File::open(key).with_context(|| format!("Cannot open {}", key.display()))?,
+    ))?
+    .with_context(|| format!("No private key in {}", key.display()))?;
+    let config = rustls::ServerConfig::builder_with_provider(provider())
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .context("Invalid TLS certificate / key")?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<rustls::pki_types::CertificateDer<'static>>> {
+    let file = std::fs::File::open(path).with_context(|| format!("Cannot open {}", path.display()))?;
+    rustls_pemfile::certs(&mut std::io::BufReader::new(file))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .with_context(|| format!("Invalid PEM in {}", path.display()))
+}
+
+fn provider() -> Arc<rustls::crypto::CryptoProvider> {
+    Arc::new(rustls::crypto::ring::default_provider())
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote_config(dir: &Path) -> RemoteConfig {
+        RemoteConfig {
+            listen: Some("127.0.0.1:0".into()),
+            token_file: dir.join("token"),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn host_parsing() {
+        assert_eq!(
+            RemoteHost::parse("build:7070").unwrap(),
+            RemoteHost { addr: "build:7070".into(), tls: false },
+        );
+        let h = RemoteHost::parse("tls://[::1]:7070").unwrap();
+        assert!(h.tls);
+        assert_eq!(h.server_name(), "::1");
+        assert!(!RemoteHost::parse("tcp://build:7070/").unwrap().tls);
+        assert!(RemoteHost::parse("build").is_err());
+        assert!(RemoteHost::parse("build:http").is_err());
+        assert!(RemoteHost::parse(":7070").is_err());
+    }
+
+    #[test]
+    fn token_file_created_private_and_reused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nested").join("token");
+        let token = load_or_create_token(&path).unwrap();
+        assert_eq!(token.len(), 64);
+        assert_eq!(load_or_create_token(&path).unwrap(), token);
+        assert_ne!(rotate_token(&path).unwrap(), token);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+            assert!(load_or_create_token(&path).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn plaintext_refused_on_non_loopback() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut remote = RemoteConfig {
+            listen: Some("0.0.0.0:0".into()),
+            ..remote_config(dir.path())
+        };
+        let err = RemoteListener::bind(&remote).await.err().unwrap();
+        assert!(format!("{:#}", err).contains("Refusing plain TCP"));
+
+        remote.allow_plaintext = true;
+        let listener = RemoteListener::bind(&remote).await.unwrap().unwrap();
+        assert!(listener.describe().starts_with("tcp://0.0.0.0:"));
+    }
+
+    #[test]
+    fn token_compare() {
+        assert!(token_eq("abc", "abc"));
+        assert!(!token_eq("abc", "abd"));
+        assert!(!token_eq("abc", "ab"));
+    }
+
+    /// 起监听端，接受一个连接并回显认证结果
+    async fn serve_once(listener: RemoteListener) -> Result<()> {
+        let (tcp, _) = listener.accept().await?;
+        let (mut reader, mut writer) = listener.authenticator().handshake(tcp).await?;
+        let req: crate::protocol::messages::DaemonRequest = reader.read().await?.unwrap();
+        assert!(matches!(req, crate::protocol::messages::DaemonRequest::List));
+        writer.write(&SessionResponse::Agents { agents: vec![] }).await
+    }
+
+    #[tokio::test]
+    async fn tcp_auth_accepts_and_rejects() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut remote = remote_config(dir.path());
+        let listener = RemoteListener::bind(&remote).await.unwrap().unwrap();
+        let addr = listener.describe();
+        assert!(addr.starts_with("tcp://127.0.0.1:"));
+        let server = tokio::spawn(serve_once(listener));
+
+        remote.listen = None;
+        remote.host = Some(addr);
+        let (mut reader, mut writer) = connect(&remote).await.unwrap();
+        writer.write(&crate::protocol::messages::DaemonRequest::List).await.unwrap();
+        let resp: SessionResponse = reader.read().await.unwrap().unwrap();
+        assert!(matches!(resp, SessionResponse::Agents { .. }));
+        server.await.unwrap().unwrap();
+
+        // 错误 token
+        let listener = RemoteListener::bind(&remote_config(dir.path())).await.unwrap().unwrap();
+        remote.host = Some(listener.describe());
+        let server = tokio::spawn(serve_once(listener));
+        let other = dir.path().join("other");
+        load_or_create_token(&other).unwrap();
+        remote.token_file = other;
+        let err = connect(&remote).await.err().unwrap();
+        assert!(format!("{:#}", err).contains("Invalid token"));
+        assert!(server.await.unwrap().is_err());
+
+        // 认证前的大帧不缓冲，直接拒绝
+        let listener = RemoteListener::bind(&remote_config(dir.path())).await.unwrap().unwrap();
+        let addr = listener.describe().trim_start_matches("tcp://").to_string();
+        let server = tokio::spawn(serve_once(listener));
+        let mut tcp = TcpStream::connect(addr).await.unwrap();
+        let big = format!("{{\"token\":\"{}\"}}\n", "x".repeat(AUTH_MAX_FRAME));
+        tokio::io::AsyncWriteExt::write_all(&mut tcp, big.as_bytes()).await.unwrap();
+        let err = server.await.unwrap().err().unwrap();
+        assert!(format!("{:#}", err).contains("exceeds"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn tls_auth_with_custom_ca() {
+        let dir = tempfile::tempdir().unwrap();
+        let cert = rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
+        let cert_path = dir.path().join("cert.pem");
+        let key_path = dir.path().join("key.pem");
+        std::fs::write(&cert_path, cert.cert.pem()).unwrap();
+        std::fs::write(&key_path, cert.key_pair.serialize_pem()).unwrap();
+
+        let mut remote = remote_config(dir.path());
+        remote.tls_cert = Some(cert_path.clone());
+        remote.tls_key = Some(key_path);
+        let listener = RemoteListener::bind(&remote).await.unwrap().unwrap();
+        let port = listener.describe().rsplit_once(':').unwrap().1.to_string();
+        assert!(listener.describe().starts_with("tls://"));
+        let server = tokio::spawn(serve_once(listener));
+
+        let client = RemoteConfig {
+            host: Some(format!("tls://localhost:{}", port)),
+            tls_ca: Some(cert_path),
+            token_file: remote.token_file.clone(),
+            ..Default::default()
+        };
+        let (mut reader, mut writer) = connect(&client).await.unwrap();
+        writer.write(&crate::protocol::messages::DaemonRequest::List).await.unwrap();
+        let resp: SessionResponse = reader.read().await.unwrap().unwrap();
+        assert!(matches!(resp, SessionResponse::Agents { .. }));
+        server.await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn tls_requires_cert_and_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut remote = remote_config(dir.path());
+        remote.tls_cert = Some(dir.path().join("cert.pem"));
+        assert!(RemoteListener::bind(&remote).await.is_err());
+        remote.tls_cert = None;
+        remote.listen = None;
+        assert!(RemoteListener::bind(&remote).await.unwrap().is_none());
+    }
+}
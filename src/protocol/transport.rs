@@ -1,57 +1,245 @@
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
-use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
 
-// ==================== JSON Lines 读取端 ====================
+// ==================== 分帧 ====================
+// 默认每帧一行 JSON（`\n` 结尾）。长度前缀帧为 0x00 + u32 大端长度 + JSON，
+// 适合大载荷（附件、对话记录）：读端按首字节逐帧识别（JSON 文本不会以 0x00 开头），
+// 因此两种帧可在同一连接上混用，写端的方式由调用方决定。
+
+/// 默认单帧上限
+pub const DEFAULT_MAX_FRAME: usize = 64 * 1024 * 1024;
+
+/// 长度前缀帧的首字节
+const FRAME_MARKER: u8 = 0x00;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    #[default]
+    Lines,
+    LengthPrefixed,
+}
+
+impl std::str::FromStr for Framing {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(Self::Lines),
+            "length" => Ok(Self::LengthPrefixed),
+            _ => Err(format!("invalid framing '{}' (lines, length)", s)),
+        }
+    }
+}
+
+/// 可恢复的帧错误：该帧已被完整跳过，连接仍可继续读取
+#[derive(Debug)]
+pub enum FrameError {
+    TooLarge { size: usize, max: usize },
+    Malformed(String),
+}
+
+impl FrameError {
+    /// 从 read 的错误中取出帧错误；None = I/O 错误，连接不可再用
+    pub fn of(err: &anyhow::Error) -> Option<&FrameError> {
+        err.downcast_ref()
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooLarge { size, max } => {
+                write!(f, "Frame of {} bytes exceeds the {}-byte limit; skipped", size, max)
+            }
+            Self::Malformed(e) => write!(f, "Malformed message skipped: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+// ==================== 读取端 ====================
 
 pub struct JsonLineReader<R> {
     reader: BufReader<R>,
+    max_frame: usize,
+    /// 最近一帧的分帧方式
+    framing: Framing,
 }
 
 impl<R: AsyncRead + Unpin> JsonLineReader<R> {
     pub fn new(read_half: R) -> Self {
         Self {
             reader: BufReader::new(read_half),
+            max_frame: DEFAULT_MAX_FRAME,
+            framing: Framing::Lines,
         }
     }
 
-    /// 读取下一条 JSON 消息，EOF 返回 None
+    pub fn set_max_frame(&mut self, max_frame: usize) {
+        self.max_frame = max_frame;
+    }
+
+    /// 最近读到的一帧是哪种分帧（服务端据此回复同种帧）
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    /// 读取下一条 JSON 消息，EOF 返回 None；跳过空行。
+    /// 超长或无法解析的帧返回 FrameError（见 `FrameError::of`），之后可继续读取
     pub async fn read<T: for<'de> Deserialize<'de>>(&mut self) -> Result<Option<T>> {
-        let mut line = String::new();
-        let n = self
+        loop {
+            let Some(frame) = self.read_frame().await? else {
+                return Ok(None);
+            };
+            if frame.iter().all(u8::is_ascii_whitespace) {
+                continue;
+            }
+            return match serde_json::from_slice(&frame) {
+                Ok(msg) => Ok(Some(msg)),
+                Err(e) => Err(FrameError::Malformed(e.to_string()).into()),
+            };
+        }
+    }
+
+    async fn read_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        let first = self
             .reader
-            .read_line(&mut line)
+            .fill_buf()
+            .await
+            .context("Failed to read from socket")?
+            .first()
+            .copied();
+        match first {
+            None => Ok(None),
+            Some(FRAME_MARKER) => {
+                self.framing = Framing::LengthPrefixed;
+                self.read_prefixed().await.map(Some)
+            }
+            Some(_) => {
+                self.framing = Framing::Lines;
+                self.read_line().await.map(Some)
+            }
+        }
+    }
+
+    /// 读一行；超过上限的部分直接丢弃，不占内存
+    async fn read_line(&mut self) -> Result<Vec<u8>> {
+        let max = self.max_frame;
+        let mut line = Vec::new();
+        let mut skipped = 0;
+        loop {
+            let buf = self
+                .reader
+                .fill_buf()
+                .await
+                .context("Failed to read from socket")?;
+            if buf.is_empty() {
+                break; // EOF：末尾没有换行的最后一帧
+            }
+            let (n, done) = match buf.iter().position(|&b| b == b'\n') {
+                Some(i) => (i + 1, true),
+                None => (buf.len(), false),
+            };
+            let content = if done { n - 1 } else { n };
+            if skipped == 0 && line.len() + content <= max {
+                line.extend_from_slice(&buf[..content]);
+            } else {
+                skipped += std::mem::take(&mut line).len() + content;
+            }
+            self.reader.consume(n);
+            if done {
+                break;
+            }
+        }
+        if skipped > 0 {
+            return Err(FrameError::TooLarge { size: skipped, max }.into());
+        }
+        Ok(line)
+    }
+
+    async fn read_prefixed(&mut self) -> Result<Vec<u8>> {
+        let mut header = [0u8; 5];
+        self.reader
+            .read_exact(&mut header)
             .await
-            .context("Failed to read from socket")?;
-        if n == 0 {
-            return Ok(None);
+            .context("Failed to read frame header")?;
+        let len = u32::from_be_bytes([header[1], header[2], header[3], header[4]]) as usize;
+        if len > self.max_frame {
+            let mut rest = (&mut self.reader).take(len as u64);
+            tokio::io::copy(&mut rest, &mut tokio::io::sink())
+                .await
+                .context("Failed to read from socket")?;
+            return Err(FrameError::TooLarge { size: len, max: self.max_frame }.into());
         }
-        let msg = serde_json::from_str(line.trim())
-            .context("Failed to deserialize message")?;
-        Ok(Some(msg))
+        let mut frame = vec![0; len];
+        self.reader
+            .read_exact(&mut frame)
+            .await
+            .context("Failed to read frame body")?;
+        Ok(frame)
     }
 }
 
-// ==================== JSON Lines 写入端 ====================
+// ==================== 写入端 ====================
 
 pub struct JsonLineWriter<W> {
     writer: W,
+    framing: Framing,
+    /// 对端声明的单帧上限，超出时在本地报错而不发送；None = 未知
+    max_frame: Option<usize>,
 }
 
 impl<W: AsyncWrite + Unpin> JsonLineWriter<W> {
     pub fn new(write_half: W) -> Self {
         Self {
             writer: write_half,
+            framing: Framing::Lines,
+            max_frame: None,
         }
     }
 
-    /// 写入一条 JSON 消息（自动追加换行符）
+    pub fn framing(&self) -> Framing {
+        self.framing
+    }
+
+    pub fn set_framing(&mut self, framing: Framing) {
+        self.framing = framing;
+    }
+
+    pub fn set_max_frame(&mut self, max_frame: Option<usize>) {
+        self.max_frame = max_frame;
+    }
+
+    /// 写入一条 JSON 消息（按当前分帧追加换行符或加长度前缀）
     pub async fn write<T: Serialize>(&mut self, msg: &T) -> Result<()> {
-        let mut json = serde_json::to_string(msg)
+        let json = serde_json::to_vec(msg)
             .context("Failed to serialize message")?;
-        json.push('\n');
+        if let Some(max) = self.max_frame.filter(|&max| json.len() > max) {
+            anyhow::bail!(
+                "Message of {} bytes exceeds the peer's {}-byte frame limit",
+                json.len(),
+                max,
+            );
+        }
+        let frame = match self.framing {
+            Framing::Lines => {
+                let mut frame = json;
+                frame.push(b'\n');
+                frame
+            }
+            Framing::LengthPrefixed => {
+                let len = u32::try_from(json.len()).context("Message too large for a frame")?;
+                let mut frame = Vec::with_capacity(json.len() + 5);
+                frame.push(FRAME_MARKER);
+                frame.extend_from_slice(&len.to_be_bytes());
+                frame.extend_from_slice(&json);
+                frame
+            }
+        };
         self.writer
-            .write_all(json.as_bytes())
+            .write_all(&frame)
             .await
             .context("Failed to write to socket")?;
         self.writer
@@ -103,7 +291,7 @@ mod tests {
         // 发送响应
         writer
             .write(&SessionResponse::Status {
-                summary: AgentSummary {
+                summary: Box::new(AgentSummary {
                     name: "test-1".into(),
                     agent_type: "mock".into(),
                     cwd: "/tmp".into(),
@@ -113,7 +301,8 @@ mod tests {
                     pending_permissions: 0,
                     agent_info_name: None,
                     agent_info_version: None,
-                },
+                    ..Default::default()
+                }),
             })
             .await
             .unwrap();
@@ -146,6 +335,7 @@ mod tests {
                 .write(&SessionRequest::Prompt {
                     text: "hello".into(),
                     files: vec![],
+                    chain: vec![],
                 })
                 .await
                 .unwrap();
@@ -198,4 +388,59 @@ mod tests {
         let result: Option<SessionRequest> = reader.read().await.unwrap();
         assert!(result.is_none());
     }
+
+    #[tokio::test]
+    async fn oversized_and_malformed_lines_are_skipped() {
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        let mut reader = JsonLineReader::new(server);
+        reader.set_max_frame(64);
+        let big = format!("{{\"type\":\"Prompt\",\"text\":\"{}\"}}\n", "x".repeat(10_000));
+        client.write_all(big.as_bytes()).await.unwrap();
+        client.write_all(b"not json\n\n{\"type\":\"GetStatus\"}\n").await.unwrap();
+        drop(client);
+
+        let err = reader.read::<SessionRequest>().await.unwrap_err();
+        assert!(matches!(FrameError::of(&err), Some(FrameError::TooLarge { max: 64, .. })));
+        let err = reader.read::<SessionRequest>().await.unwrap_err();
+        assert!(matches!(FrameError::of(&err), Some(FrameError::Malformed(_))));
+        // 空行跳过，之后的请求照常读取
+        let req = reader.read::<SessionRequest>().await.unwrap().unwrap();
+        assert!(matches!(req, SessionRequest::GetStatus));
+        assert!(reader.read::<SessionRequest>().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn length_prefixed_frames_mix_with_lines() {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (cr, cw) = tokio::io::split(client);
+        let mut writer = JsonLineWriter::new(cw);
+        let mut reader = JsonLineReader::new(server);
+        reader.set_max_frame(1024);
+
+        // 含换行的大载荷按长度前缀原样传输
+        writer.set_framing(Framing::LengthPrefixed);
+        let text = "line 1\nline 2".to_string();
+        let prompt = SessionRequest::Prompt { text: text.clone(), files: vec![], chain: vec![] };
+        writer.write(&prompt).await.unwrap();
+        writer.write(&SessionRequest::Prompt { text: "y".repeat(2000), files: vec![], chain: vec![] })
+            .await
+            .unwrap();
+        writer.set_framing(Framing::Lines);
+        writer.write(&SessionRequest::GetStatus).await.unwrap();
+
+        match reader.read::<SessionRequest>().await.unwrap().unwrap() {
+            SessionRequest::Prompt { text: got, .. } => assert_eq!(got, text),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(reader.framing(), Framing::LengthPrefixed);
+        let err = reader.read::<SessionRequest>().await.unwrap_err();
+        assert!(matches!(FrameError::of(&err), Some(FrameError::TooLarge { max: 1024, .. })));
+        assert!(matches!(reader.read::<SessionRequest>().await.unwrap(), Some(SessionRequest::GetStatus)));
+        assert_eq!(reader.framing(), Framing::Lines);
+
+        // 对端声明的上限：本地拒绝发送
+        writer.set_max_frame(Some(16));
+        assert!(writer.write(&prompt).await.is_err());
+        drop(cr);
+    }
 }
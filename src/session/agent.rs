@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
@@ -10,9 +10,17 @@ use tokio::process::Child;
 use tokio::sync::Mutex;
 use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
-use crate::acp_client::team_client::{PendingPermission, TeamClient};
-use crate::config::{AgentTypeConfig, AutoApprovePolicy};
-use crate::protocol::messages::{AgentSummary, OutputEntry, OutputType};
+use crate::acp_client::team_client::{
+    OutputSender, PendingPermission, SessionRoutes, SessionState, SharedPlan, TeamClient,
+};
+use crate::config::{AgentTypeConfig, TeamConfig};
+use crate::session::changes::ChangeTracker;
+use crate::session::notify::NotifyMuted;
+use crate::session::usage::{self, SharedUsage, UsageLedger};
+use crate::protocol::messages::{
+    AgentSummary, Budget, NamedSessionSummary, OutputEntry, OutputType, PlanProgress,
+    SessionEvent, WorktreeInfo, PROTOCOL_VERSION, VERSION,
+};
 
 // ==================== Agent 状态机 ====================
 
@@ -41,24 +49,56 @@ impl std::fmt::Display for AgentStatus {
 
 // ==================== 输出环形缓冲区 ====================
 
+/// 同时受条目数和字节数约束；连续同类流式 chunk 原地合并
 pub struct OutputRingBuffer {
     entries: VecDeque<OutputEntry>,
     capacity: usize,
+    /// 字节上限，0 = 不限
+    max_bytes: usize,
+    bytes: usize,
 }
 
 impl OutputRingBuffer {
     pub fn new(capacity: usize) -> Self {
+        Self::with_limits(capacity, 0)
+    }
+
+    pub fn with_limits(capacity: usize, max_bytes: usize) -> Self {
         Self {
-            entries: VecDeque::with_capacity(capacity),
+            entries: VecDeque::with_capacity(capacity.min(1024)),
             capacity,
+            max_bytes,
+            bytes: 0,
         }
     }
 
     pub fn push(&mut self, entry: OutputEntry) {
-        if self.entries.len() >= self.capacity {
-            self.entries.pop_front();
+        // AgentMessage / AgentThought chunk 追加到上一条同类条目
+        if is_stream_chunk(&entry.update_type) {
+            if let Some(last) = self.entries.back_mut() {
+                if std::mem::discriminant(&last.update_type)
+                    == std::mem::discriminant(&entry.update_type)
+                {
+                    self.bytes += entry.content.len();
+                    last.content.push_str(&entry.content);
+                    self.evict();
+                    return;
+                }
+            }
         }
+        self.bytes += entry_bytes(&entry);
         self.entries.push_back(entry);
+        self.evict();
+    }
+
+    /// 超出条目数 / 字节数时淘汰最旧条目（至少保留最新一条）
+    fn evict(&mut self) {
+        while self.entries.len() > self.capacity
+            || (self.max_bytes > 0 && self.bytes > self.max_bytes && self.entries.len() > 1)
+        {
+            let Some(old) = self.entries.pop_front() else { break };
+            self.bytes -= entry_bytes(&old);
+        }
     }
 
     /// 最近 n 条消息，0 = 全部
@@ -93,6 +133,23 @@ impl OutputRingBuffer {
     pub fn is_empty(&self) -> bool {
         self.entries.is_empty()
     }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// 当前占用字节数（content + timestamp）
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+fn is_stream_chunk(t: &OutputType) -> bool {
+    matches!(t, OutputType::AgentMessage | OutputType::AgentThought)
+}
+
+fn entry_bytes(e: &OutputEntry) -> usize {
+    e.content.len() + e.timestamp.len()
 }
 
 // ==================== Agent 句柄 ====================
@@ -106,12 +163,46 @@ pub struct AgentHandle {
     pub started_at: Instant,
     pub output_buffer: Arc<Mutex<OutputRingBuffer>>,
     pub pending_permissions: Arc<Mutex<VecDeque<PendingPermission>>>,
+    /// 默认 session 最新上报的 plan
+    pub plan: SharedPlan,
+    /// 默认 session 的 token / 花费账本
+    pub usage: SharedUsage,
+    /// 全部 session 合计的用量上限（`budget`）
+    pub budget: Budget,
+    /// 通知开关，与 print_events 中的 Notifier 共享
+    pub notify_muted: NotifyMuted,
     pub prompt_count: u64,
     pub session_id: Option<acp::SessionId>,
     pub acp_conn: Option<Rc<acp::ClientSideConnection>>,
     pub child: Option<Child>,
     /// agent 自报名称+版本（来自 InitializeResponse）
     pub agent_info: Option<(String, String)>,
+    /// 当前 turn 的委派链（Prompt.chain）
+    pub delegation_chain: Vec<String>,
+    /// `add --worktree` 创建的 worktree（cwd 即其目录）
+    pub worktree: Option<WorktreeInfo>,
+    /// 每个 turn 的文件改动（track_changes 关闭时为 None）
+    pub changes: Option<Arc<std::sync::Mutex<ChangeTracker>>>,
+    /// 实时事件广播（Subscribe 连接各持一个 receiver）
+    pub events: Option<tokio::sync::broadcast::Sender<SessionEvent>>,
+    /// `new --session` 创建的命名 ACP session，与默认 session 共用 agent 进程
+    pub sessions: BTreeMap<String, NamedSession>,
+    /// ACP session id → 命名 session，与 TeamClient 共享
+    pub routes: SessionRoutes,
+    /// agent 支持 session/load（fork 时据此加载源 session）
+    pub load_session: bool,
+    /// fork 关系：来源 agent 与派生出的 agent
+    pub forked_from: Option<String>,
+    pub forks: Vec<String>,
+}
+
+/// 命名 ACP session：独立的输出缓冲、状态与权限队列（不参与改动追踪）
+pub struct NamedSession {
+    pub id: acp::SessionId,
+    pub state: SessionState,
+    pub prompt_count: u64,
+    /// 当前 turn 的委派链（Prompt.chain）
+    pub delegation_chain: Vec<String>,
 }
 
 impl AgentHandle {
@@ -123,6 +214,36 @@ impl AgentHandle {
         self.status.lock().unwrap().clone()
     }
 
+    /// 默认 session 的状态视图（与 handle 共享同一组 Arc）
+    pub fn default_state(&self) -> SessionState {
+        SessionState {
+            name: None,
+            status: Arc::clone(&self.status),
+            output_buffer: Arc::clone(&self.output_buffer),
+            pending_permissions: Arc::clone(&self.pending_permissions),
+            plan: Arc::clone(&self.plan),
+            usage: Arc::clone(&self.usage),
+        }
+    }
+
+    /// 默认 session 与全部命名 session 的合计用量
+    pub fn total_usage(&self) -> UsageLedger {
+        let ledgers: Vec<UsageLedger> = std::iter::once(&self.usage)
+            .chain(self.sessions.values().map(|s| &s.state.usage))
+            .map(|u| u.lock().unwrap().clone())
+            .collect();
+        usage::combine(&ledgers)
+    }
+
+    /// 已达到预算上限时返回原因（含进行中 turn 的估算）
+    pub fn budget_exceeded(&self) -> Option<String> {
+        if self.budget.is_empty() {
+            return None;
+        }
+        let total = self.total_usage();
+        self.budget.exceeded(total.running_tokens(), total.cost.map(|c| c.amount))
+    }
+
     pub fn to_summary(&self) -> AgentSummary {
         let uptime = self.started_at.elapsed();
         let mins = uptime.as_secs() / 60;
@@ -149,6 +270,33 @@ impl AgentHandle {
             pending_permissions: pending,
             agent_info_name: info_name,
             agent_info_version: info_ver,
+            delegation_chain: self.delegation_chain.clone(),
+            worktree: self.worktree.clone(),
+            version: Some(VERSION.to_string()),
+            protocol: PROTOCOL_VERSION,
+            sessions: self
+                .sessions
+                .iter()
+                .map(|(name, s)| NamedSessionSummary {
+                    name: name.clone(),
+                    status: s.state.get_status().to_string(),
+                    prompt_count: s.prompt_count,
+                    pending_permissions: s
+                        .state
+                        .pending_permissions
+                        .try_lock()
+                        .map(|q| q.len())
+                        .unwrap_or(0),
+                    plan: PlanProgress::of(&s.state.plan.lock().unwrap()),
+                    usage: s.state.usage.lock().unwrap().summary(Budget::default()),
+                })
+                .collect(),
+            session_id: self.session_id.as_ref().map(|id| id.to_string()),
+            forked_from: self.forked_from.clone(),
+            forks: self.forks.clone(),
+            plan: PlanProgress::of(&self.plan.lock().unwrap()),
+            usage: self.total_usage().summary(self.budget.clone()),
+            notify_muted: self.notify_muted.get(),
         }
     }
 }
@@ -161,9 +309,8 @@ pub async fn spawn_agent(
     type_config: AgentTypeConfig,
     cwd: PathBuf,
     extra_args: Vec<String>,
-    buf_size: usize,
-    auto_approve: AutoApprovePolicy,
-    output_tx: Option<tokio::sync::mpsc::UnboundedSender<OutputEntry>>,
+    config: &TeamConfig,
+    output_tx: Option<OutputSender>,
 ) -> Result<AgentHandle> {
     let mut cmd = tokio::process::Command::new(&type_config.command);
     cmd.args(&type_config.default_args)
@@ -206,14 +353,26 @@ pub async fn spawn_agent(
     });
 
     let status = Arc::new(std::sync::Mutex::new(AgentStatus::Starting));
-    let output_buffer = Arc::new(Mutex::new(OutputRingBuffer::new(buf_size)));
+    let output_buffer = Arc::new(Mutex::new(OutputRingBuffer::with_limits(
+        config.output_buffer_size,
+        config.output_buffer_bytes,
+    )));
     let pending_permissions = Arc::new(Mutex::new(VecDeque::new()));
+    let plan = SharedPlan::default();
+    let usage = SharedUsage::default();
+    let routes = SessionRoutes::default();
     let err_tx = output_tx.clone();
     let client = TeamClient::new(
-        Arc::clone(&status),
-        Arc::clone(&output_buffer),
-        Arc::clone(&pending_permissions),
-        auto_approve,
+        SessionState {
+            name: None,
+            status: Arc::clone(&status),
+            output_buffer: Arc::clone(&output_buffer),
+            pending_permissions: Arc::clone(&pending_permissions),
+            plan: Arc::clone(&plan),
+            usage: Arc::clone(&usage),
+        },
+        Arc::clone(&routes),
+        config.auto_approve.clone(),
         output_tx,
     );
 
@@ -228,11 +387,11 @@ pub async fn spawn_agent(
     tokio::task::spawn_local(async move {
         if let Err(e) = io_task.await {
             if let Some(tx) = &err_tx {
-                tx.send(OutputEntry {
+                tx.send((None, OutputEntry {
                     timestamp: chrono::Utc::now().to_rfc3339(),
                     update_type: OutputType::Error,
                     content: format!("ACP IO error: {}", e),
-                })
+                }))
                 .ok();
             }
         }
@@ -260,9 +419,10 @@ pub async fn spawn_agent(
     let agent_info = init_resp.agent_info.map(|info| {
         (info.name, info.version)
     });
+    let load_session = init_resp.agent_capabilities.load_session;
 
     let session_resp = conn
-        .new_session(acp::NewSessionRequest::new(&cwd))
+        .new_session(acp::NewSessionRequest::new(&cwd).mcp_servers(mcp_servers(&name, None, config)))
         .await
         .context("ACP new_session() failed")?;
 
@@ -277,14 +437,51 @@ pub async fn spawn_agent(
         started_at: Instant::now(),
         output_buffer,
         pending_permissions,
+        plan,
+        usage,
+        budget: Budget::default(),
+        notify_muted: NotifyMuted::default(),
         prompt_count: 0,
         session_id: Some(session_resp.session_id),
         acp_conn: Some(Rc::new(conn)),
         child: Some(child),
         agent_info,
+        delegation_chain: vec![],
+        worktree: None,
+        changes: None,
+        events: None,
+        sessions: BTreeMap::new(),
+        routes,
+        load_session,
+        forked_from: None,
+        forks: vec![],
     })
 }
 
+/// agent-team 自身作为 stdio MCP server（`agent-team mcp`），供 agent 间委派；
+/// 命名 session 带 `--session`，委派链按调用方所在 session 读取
+pub(crate) fn mcp_servers(name: &str, session: Option<&str>, config: &TeamConfig) -> Vec<acp::McpServer> {
+    if config.mcp_max_depth == 0 {
+        return vec![];
+    }
+    let Ok(exe) = std::env::current_exe() else {
+        return vec![];
+    };
+    let mut args = vec![
+        "mcp".to_string(),
+        "--agent".to_string(),
+        name.to_string(),
+        "--max-depth".to_string(),
+        config.mcp_max_depth.to_string(),
+    ];
+    if let Some(s) = session {
+        args.extend(["--session".to_string(), s.to_string()]);
+    }
+    vec![acp::McpServer::Stdio(
+        acp::McpServerStdio::new("agent-team", exe).args(args),
+    )]
+}
+
 // ==================== 单元测试 ====================
 
 #[cfg(test)]
@@ -297,7 +494,7 @@ mod tests {
         for i in 0..5 {
             buf.push(OutputEntry {
                 timestamp: format!("t{}", i),
-                update_type: OutputType::AgentMessage,
+                update_type: OutputType::ToolCallStart,
                 content: format!("msg-{}", i),
             });
         }
@@ -476,6 +673,87 @@ mod tests {
         assert_eq!(last[0].content, "q");
     }
 
+    /// 连续同类 chunk 原地合并，保留首个 chunk 的时间戳
+    #[test]
+    fn ring_buffer_merges_chunks() {
+        let mut buf = OutputRingBuffer::new(100);
+        for (i, (t, c)) in [
+            (OutputType::AgentThought, "think "),
+            (OutputType::AgentThought, "more"),
+            (OutputType::AgentMessage, "Hello, "),
+            (OutputType::AgentMessage, "world"),
+            (OutputType::ToolCallStart, "read"),
+            (OutputType::AgentMessage, "done"),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            buf.push(OutputEntry {
+                timestamp: format!("t{}", i),
+                update_type: t,
+                content: c.into(),
+            });
+        }
+        let all = buf.last_msgs(0);
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[0].content, "think more");
+        assert_eq!(all[1].content, "Hello, world");
+        assert_eq!(all[1].timestamp, "t2");
+        assert_eq!(all[3].content, "done");
+    }
+
+    /// 非流式类型不合并
+    #[test]
+    fn ring_buffer_no_merge_other_types() {
+        let mut buf = OutputRingBuffer::new(100);
+        for _ in 0..3 {
+            buf.push(OutputEntry {
+                timestamp: "t".into(),
+                update_type: OutputType::ToolCallUpdate,
+                content: "x".into(),
+            });
+        }
+        assert_eq!(buf.len(), 3);
+    }
+
+    #[test]
+    fn ring_buffer_byte_limit() {
+        // 每条 10 字节（content 8 + timestamp 2）
+        let mut buf = OutputRingBuffer::with_limits(100, 25);
+        for i in 0..4 {
+            buf.push(OutputEntry {
+                timestamp: format!("t{}", i),
+                update_type: OutputType::ToolCallStart,
+                content: format!("content{}", i),
+            });
+        }
+        assert_eq!(buf.len(), 2);
+        assert_eq!(buf.bytes(), 20);
+        let all = buf.last_msgs(0);
+        assert_eq!(all[0].content, "content2");
+    }
+
+    /// 合并导致超出字节上限时淘汰旧条目，但保留正在增长的条目
+    #[test]
+    fn ring_buffer_byte_limit_on_merge() {
+        let mut buf = OutputRingBuffer::with_limits(100, 30);
+        buf.push(OutputEntry {
+            timestamp: "t0".into(),
+            update_type: OutputType::UserPrompt,
+            content: "question".into(),
+        });
+        for _ in 0..10 {
+            buf.push(OutputEntry {
+                timestamp: "t1".into(),
+                update_type: OutputType::AgentMessage,
+                content: "abcdef".into(),
+            });
+        }
+        assert_eq!(buf.len(), 1);
+        assert_eq!(buf.bytes(), 62);
+        assert_eq!(buf.last_msgs(0)[0].content.len(), 60);
+    }
+
     #[test]
     fn ring_buffer_is_empty() {
         let buf = OutputRingBuffer::new(10);
@@ -503,11 +781,24 @@ mod tests {
             started_at: Instant::now(),
             output_buffer: Arc::new(Mutex::new(OutputRingBuffer::new(10))),
             pending_permissions: Arc::new(Mutex::new(VecDeque::new())),
+            plan: SharedPlan::default(),
+            usage: SharedUsage::default(),
+            budget: Budget::default(),
+            notify_muted: Default::default(),
             prompt_count: 5,
             session_id: None,
             acp_conn: None,
             child: None,
             agent_info: Some(("Gemini".into(), "2.0".into())),
+            delegation_chain: vec![],
+            worktree: None,
+            changes: None,
+            events: None,
+            sessions: BTreeMap::new(),
+            routes: Default::default(),
+            load_session: false,
+            forked_from: None,
+            forks: vec![],
         };
         let s = handle.to_summary();
         assert_eq!(s.name, "test");
@@ -528,11 +819,24 @@ mod tests {
             started_at: Instant::now(),
             output_buffer: Arc::new(Mutex::new(OutputRingBuffer::new(10))),
             pending_permissions: Arc::new(Mutex::new(VecDeque::new())),
+            plan: SharedPlan::default(),
+            usage: SharedUsage::default(),
+            budget: Budget::default(),
+            notify_muted: Default::default(),
             prompt_count: 0,
             session_id: None,
             acp_conn: None,
             child: None,
             agent_info: None,
+            delegation_chain: vec![],
+            worktree: None,
+            changes: None,
+            events: None,
+            sessions: BTreeMap::new(),
+            routes: Default::default(),
+            load_session: false,
+            forked_from: None,
+            forks: vec![],
         };
         let s = handle.to_summary();
         assert_eq!(s.agent_type, "claude");
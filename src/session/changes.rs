@@ -0,0 +1,764 @@
+// ============================================================
+// changes - 每个 turn 的文件改动追踪
+// ============================================================
+// do_prompt 前后各做一次 cwd 快照（相对路径 → 内容 id），两者之差即本 turn 的改动。
+// - git 仓库内：独立 index（GIT_INDEX_FILE）+ `git add -A` 生成 blob，遵循 .gitignore，
+//   不影响用户自己的暂存区
+// - 仓库外：递归扫描，按 mtime+size 缓存 sha256，内容存入 {snapshot_dir}/objects
+// undo 从存储中取回 turn 前的内容（含可执行位）；文件在 turn 之后又被改过、
+// 或前后任一状态是大文件 / 符号链接时拒绝。
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+
+use crate::protocol::messages::{ChangeKind, FileChange, TurnChanges};
+
+/// 保留的 turn 记录数
+const MAX_TURNS: usize = 100;
+/// 非 git 目录扫描文件数上限，超出则本次快照失败
+const MAX_SCAN_FILES: usize = 20_000;
+/// 非 git 目录中超过此大小的文件不存内容，只按 size + mtime 记录
+const MAX_FILE_BYTES: u64 = 4 * 1024 * 1024;
+/// 超出大小上限的文件在快照中的 id 前缀（`large:<size>:<mtime>`），没有可读取的内容
+const LARGE_PREFIX: &str = "large:";
+/// 符号链接在快照中的 id 前缀，只用于比较，不能还原
+const SYMLINK_PREFIX: &str = "symlink:";
+/// 可执行文件的 id 后缀（其余部分是内容 id）
+const EXEC_SUFFIX: &str = "+x";
+/// 非 git 扫描跳过的目录
+const SKIP_DIRS: &[&str] = &[".git", "node_modules", "target", ".venv", "__pycache__"];
+/// prompt 摘要长度
+const PROMPT_SUMMARY_CHARS: usize = 60;
+
+/// 相对路径（`/` 分隔）→ 内容 id（git blob / sha256，可执行文件带 `+x`；`large:…` / `symlink:…`）
+pub type Snapshot = BTreeMap<String, String>;
+
+enum Store {
+    Git {
+        index: PathBuf,
+    },
+    Files {
+        objects: PathBuf,
+        /// 绝对路径 → (mtime, size, sha256)，避免重复读取未变文件
+        cache: HashMap<PathBuf, (SystemTime, u64, String)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TurnRecord {
+    pub turn: u64,
+    pub prompt: String,
+    pub started_at: String,
+    pub changes: Vec<ChangeRecord>,
+    /// 已被 undo 还原
+    pub undone: bool,
+}
+
+/// 单个文件改动；None = 不存在（新增 / 删除）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangeRecord {
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+impl ChangeRecord {
+    pub fn kind(&self) -> ChangeKind {
+        match (&self.before, &self.after) {
+            (None, _) => ChangeKind::Added,
+            (_, None) => ChangeKind::Deleted,
+            _ => ChangeKind::Modified,
+        }
+    }
+}
+
+pub struct ChangeTracker {
+    root: PathBuf,
+    store_dir: PathBuf,
+    store: Store,
+    turns: VecDeque<TurnRecord>,
+    /// 进行中 turn 及其起始快照
+    pending: Option<(TurnRecord, Snapshot)>,
+    next_turn: u64,
+}
+
+impl ChangeTracker {
+    /// root 为 agent cwd；在 git 仓库内则使用 git 存储
+    pub fn new(root: PathBuf, store_dir: PathBuf) -> Self {
+        let in_repo = run_git(&root, None, &["rev-parse", "--is-inside-work-tree"])
+            .is_ok_and(|out| out.starts_with(b"true"));
+        let store = if in_repo {
+            Store::Git { index: store_dir.join("index") }
+        } else {
+            Store::Files { objects: store_dir.join("objects"), cache: HashMap::new() }
+        };
+        Self {
+            root,
+            store_dir,
+            store,
+            turns: VecDeque::new(),
+            pending: None,
+            next_turn: 1,
+        }
+    }
+
+    pub fn is_git(&self) -> bool {
+        matches!(self.store, Store::Git { .. })
+    }
+
+    /// turn 开始：记录起始快照
+    pub fn begin_turn(&mut self, prompt: &str) -> Result<u64> {
+        let snap = self.snapshot()?;
+        let turn = self.next_turn;
+        self.next_turn += 1;
+        let record = TurnRecord {
+            turn,
+            prompt: summarize_prompt(prompt),
+            started_at: chrono::Utc::now().to_rfc3339(),
+            changes: vec![],
+            undone: false,
+        };
+        self.pending = Some((record, snap));
+        Ok(turn)
+    }
+
+    /// turn 结束：与起始快照比较并保存记录，返回 (turn, 改动文件数)
+    pub fn end_turn(&mut self) -> Result<Option<(u64, usize)>> {
+        let Some((mut record, before)) = self.pending.take() else {
+            return Ok(None);
+        };
+        let after = self.snapshot()?;
+        record.changes = diff_snapshots(&before, &after);
+        let result = (record.turn, record.changes.len());
+        self.turns.push_back(record);
+        while self.turns.len() > MAX_TURNS {
+            self.turns.pop_front();
+        }
+        Ok(Some(result))
+    }
+
+    /// 指定 turn（或全部）的改动，可附带 unified diff
+    pub fn turn_changes(&self, turn: Option<u64>, with_diff: bool) -> Result<Vec<TurnChanges>> {
+        let records: Vec<&TurnRecord> = match turn {
+            Some(n) => {
+                let r = self
+                    .turns
+                    .iter()
+                    .find(|r| r.turn == n)
+                    .with_context(|| format!("No changes recorded for turn {}", n))?;
+                vec![r]
+            }
+            None => self.turns.iter().collect(),
+        };
+        records
+            .into_iter()
+            .map(|r| {
+                let files = r
+                    .changes
+                    .iter()
+                    .map(|c| {
+                        let diff = if with_diff { Some(self.unified_diff(c)?) } else { None };
+                        Ok(FileChange { path: c.path.clone(), kind: c.kind(), diff })
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(TurnChanges {
+                    turn: r.turn,
+                    prompt: r.prompt.clone(),
+                    started_at: r.started_at.clone(),
+                    files,
+                    undone: r.undone,
+                })
+            })
+            .collect()
+    }
+
+    /// 把指定 turn（默认最近一个有改动且未还原的 turn）涉及的文件恢复到 turn 前的状态。
+    /// 任一文件当前内容与该 turn 结束时不一致（用户或后续 turn 改过）则整体拒绝。
+    pub fn undo(&mut self, turn: Option<u64>) -> Result<TurnRecord> {
+        if self.pending.is_some() {
+            bail!("A turn is in progress");
+        }
+        let idx = match turn {
+            Some(n) => self
+                .turns
+                .iter()
+                .position(|r| r.turn == n)
+                .with_context(|| format!("No changes recorded for turn {}", n))?,
+            None => self
+                .turns
+                .iter()
+                .rposition(|r| !r.undone && !r.changes.is_empty())
+                .context("No turn with changes to undo")?,
+        };
+        let record = self.turns[idx].clone();
+        if record.undone {
+            bail!("Turn {} was already undone", record.turn);
+        }
+        if record.changes.is_empty() {
+            bail!("Turn {} changed no files", record.turn);
+        }
+
+        let unrestorable: Vec<&str> = record
+            .changes
+            .iter()
+            .filter(|c| [&c.before, &c.after].into_iter().flatten().any(|id| regular_object(id).is_none()))
+            .map(|c| c.path.as_str())
+            .collect();
+        if !unrestorable.is_empty() {
+            bail!(
+                "Cannot undo turn {}: too large or not a regular file: {}",
+                record.turn,
+                unrestorable.join(", "),
+            );
+        }
+
+        let current = self.snapshot()?;
+        let modified: Vec<&str> = record
+            .changes
+            .iter()
+            .filter(|c| current.get(&c.path) != c.after.as_ref())
+            .map(|c| c.path.as_str())
+            .collect();
+        if !modified.is_empty() {
+            bail!(
+                "Files modified since turn {}: {}",
+                record.turn,
+                modified.join(", "),
+            );
+        }
+
+        // 先读出全部旧内容，避免写到一半才发现对象缺失
+        let mut restore: Vec<(PathBuf, Option<Vec<u8>>, bool)> = vec![];
+        for c in &record.changes {
+            let data = match &c.before {
+                Some(id) => Some(self.read_object(id)?),
+                None => None,
+            };
+            let exec = c.before.as_deref().is_some_and(|id| id.ends_with(EXEC_SUFFIX));
+            restore.push((self.root.join(&c.path), data, exec));
+        }
+        for (path, data, exec) in restore {
+            match data {
+                Some(data) => {
+                    if let Some(dir) = path.parent() {
+                        std::fs::create_dir_all(dir)?;
+                    }
+                    std::fs::write(&path, data)
+                        .with_context(|| format!("Cannot restore {}", path.display()))?;
+                    set_executable(&path, exec)?;
+                }
+                None => match std::fs::remove_file(&path) {
+                    Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                        return Err(e).with_context(|| format!("Cannot remove {}", path.display()));
+                    }
+                    _ => {}
+                },
+            }
+        }
+        self.turns[idx].undone = true;
+        Ok(record)
+    }
+
+    // ==================== 快照 / 存储 ====================
+
+    pub fn snapshot(&mut self) -> Result<Snapshot> {
+        std::fs::create_dir_all(&self.store_dir)
+            .with_context(|| format!("Cannot create {}", self.store_dir.display()))?;
+        match &mut self.store {
+            Store::Git { index } => git_snapshot(&self.root, index),
+            Store::Files { objects, cache } => files_snapshot(&self.root, objects, cache),
+        }
+    }
+
+    /// 按内容 id 读取快照中的文件内容
+    pub fn read_object(&self, id: &str) -> Result<Vec<u8>> {
+        if id.starts_with(LARGE_PREFIX) {
+            bail!("File content not stored (larger than {} bytes)", MAX_FILE_BYTES);
+        }
+        let Some(id) = regular_object(id) else {
+            bail!("Symbolic link content not stored");
+        };
+        match &self.store {
+            Store::Git { .. } => run_git(&self.root, None, &["cat-file", "blob", id]),
+            Store::Files { objects, .. } => std::fs::read(objects.join(id))
+                .with_context(|| format!("Snapshot object {} missing", id)),
+        }
+    }
+
+    fn unified_diff(&self, change: &ChangeRecord) -> Result<String> {
+        let ids = [&change.before, &change.after];
+        if ids.iter().any(|id| id.as_deref().is_some_and(|id| id.starts_with(LARGE_PREFIX))) {
+            return Ok(format!("{}: too large to diff\n", change.path));
+        }
+        if ids.iter().any(|id| id.as_deref().is_some_and(|id| id.starts_with(SYMLINK_PREFIX))) {
+            return Ok(format!("{}: symbolic link changed\n", change.path));
+        }
+        let load = |id: &Option<String>| -> Result<Vec<u8>> {
+            match id {
+                Some(id) => self.read_object(id),
+                None => Ok(vec![]),
+            }
+        };
+        let old = load(&change.before)?;
+        let new = load(&change.after)?;
+        let old_label = match change.before {
+            Some(_) => format!("a/{}", change.path),
+            None => "/dev/null".into(),
+        };
+        let new_label = match change.after {
+            Some(_) => format!("b/{}", change.path),
+            None => "/dev/null".into(),
+        };
+        // 只有可执行位变化
+        if let (Some(a), Some(b)) = (&change.before, &change.after) {
+            if regular_object(a) == regular_object(b) {
+                let mode = |id: &str| if id.ends_with(EXEC_SUFFIX) { "100755" } else { "100644" };
+                return Ok(format!("old mode {}\nnew mode {}\n", mode(a), mode(b)));
+            }
+        }
+        let (Ok(old), Ok(new)) = (std::str::from_utf8(&old), std::str::from_utf8(&new)) else {
+            return Ok(format!("Binary files {} and {} differ\n", old_label, new_label));
+        };
+        Ok(similar::TextDiff::from_lines(old, new)
+            .unified_diff()
+            .context_radius(3)
+            .header(&old_label, &new_label)
+            .to_string())
+    }
+}
+
+/// 两次快照之差，按路径排序
+pub fn diff_snapshots(before: &Snapshot, after: &Snapshot) -> Vec<ChangeRecord> {
+    let mut out = vec![];
+    for (path, id) in before {
+        match after.get(path) {
+            Some(new_id) if new_id == id => {}
+            other => out.push(ChangeRecord {
+                path: path.clone(),
+                before: Some(id.clone()),
+                after: other.cloned(),
+            }),
+        }
+    }
+    for (path, id) in after {
+        if !before.contains_key(path) {
+            out.push(ChangeRecord { path: path.clone(), before: None, after: Some(id.clone()) });
+        }
+    }
+    out.sort_by(|a, b| a.path.cmp(&b.path));
+    out
+}
+
+/// 普通文件的内容 id（去掉可执行后缀）；大文件 / 符号链接为 None
+fn regular_object(id: &str) -> Option<&str> {
+    if id.starts_with(LARGE_PREFIX) || id.starts_with(SYMLINK_PREFIX) {
+        return None;
+    }
+    Some(id.strip_suffix(EXEC_SUFFIX).unwrap_or(id))
+}
+
+#[cfg(unix)]
+fn set_executable(path: &Path, exec: bool) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut perms = std::fs::metadata(path)?.permissions();
+    let mode = perms.mode();
+    // 有读权限的角色才给执行位
+    let mode = if exec { mode | ((mode & 0o444) >> 2) } else { mode & !0o111 };
+    perms.set_mode(mode);
+    std::fs::set_permissions(path, perms).with_context(|| format!("Cannot chmod {}", path.display()))
+}
+
+#[cfg(not(unix))]
+fn set_executable(_path: &Path, _exec: bool) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(meta: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    meta.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_meta: &std::fs::Metadata) -> bool {
+    false
+}
+
+fn summarize_prompt(prompt: &str) -> String {
+    let line = prompt.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+    if line.chars().count() > PROMPT_SUMMARY_CHARS {
+        let cut: String = line.chars().take(PROMPT_SUMMARY_CHARS).collect();
+        format!("{}…", cut)
+    } else {
+        line.to_string()
+    }
+}
+
+// ==================== git 存储 ====================
+
+fn git_snapshot(root: &Path, index: &Path) -> Result<Snapshot> {
+    // 首次以仓库 index 为种子，git 可复用 stat 缓存而不必重新 hash 全部文件
+    if !index.exists() {
+        if let Ok(out) =
+            run_git(root, None, &["rev-parse", "--path-format=absolute", "--git-path", "index"])
+        {
+            let real = PathBuf::from(String::from_utf8_lossy(&out).trim());
+            let _ = std::fs::copy(real, index);
+        }
+    }
+    run_git(root, Some(index), &["add", "-A", "--", "."])?;
+    let out = run_git(root, Some(index), &["ls-files", "-s", "-z", "--", "."])?;
+
+    let mut snap = Snapshot::new();
+    for rec in out.split(|b| *b == 0).filter(|r| !r.is_empty()) {
+        // "<mode> <sha> <stage>\t<path>"
+        let rec = String::from_utf8_lossy(rec);
+        let Some((meta, path)) = rec.split_once('\t') else { continue };
+        let mut parts = meta.split(' ');
+        let (Some(mode), Some(sha)) = (parts.next(), parts.next()) else { continue };
+        let id = match mode {
+            // 跳过子模块
+            "160000" => continue,
+            "120000" => format!("{}{}", SYMLINK_PREFIX, sha),
+            "100755" => format!("{}{}", sha, EXEC_SUFFIX),
+            _ => sha.to_string(),
+        };
+        snap.insert(path.to_string(), id);
+    }
+    Ok(snap)
+}
+
+fn run_git(dir: &Path, index: Option<&Path>, args: &[&str]) -> Result<Vec<u8>> {
+    let mut cmd = Command::new("git");
+    cmd.arg("-C").arg(dir).args(args);
+    if let Some(index) = index {
+        cmd.env("GIT_INDEX_FILE", index);
+    }
+    let out = cmd.output().context("Failed to run git")?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim(),
+        );
+    }
+    Ok(out.stdout)
+}
+
+// ==================== 文件存储 ====================
+
+fn files_snapshot(
+    root: &Path,
+    objects: &Path,
+    cache: &mut HashMap<PathBuf, (SystemTime, u64, String)>,
+) -> Result<Snapshot> {
+    std::fs::create_dir_all(objects)?;
+    let mut snap = Snapshot::new();
+    let mut stack = vec![root.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for entry in entries.flatten() {
+            let Ok(ft) = entry.file_type() else { continue };
+            let path = entry.path();
+            if ft.is_dir() {
+                let name = entry.file_name();
+                if !SKIP_DIRS.iter().any(|d| name == *d) {
+                    stack.push(path);
+                }
+                continue;
+            }
+            if ft.is_symlink() {
+                let Ok(target) = std::fs::read_link(&path) else { continue };
+                let id = format!("{}{}", SYMLINK_PREFIX, target.to_string_lossy());
+                snap.insert(rel_path(root, &path), id);
+                continue;
+            }
+            if !ft.is_file() {
+                continue;
+            }
+            let Ok(meta) = entry.metadata() else { continue };
+            if snap.len() >= MAX_SCAN_FILES {
+                bail!("More than {} files under {}", MAX_SCAN_FILES, root.display());
+            }
+            let mtime = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            let id = match cache.get(&path) {
+                // 大文件不读内容；size / mtime 变化即视为修改
+                _ if meta.len() > MAX_FILE_BYTES => {
+                    let nanos = mtime.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default().as_nanos();
+                    format!("{}{}:{}", LARGE_PREFIX, meta.len(), nanos)
+                }
+                Some((m, size, id)) if *m == mtime && *size == meta.len() => id.clone(),
+                _ => {
+                    let Ok(data) = std::fs::read(&path) else { continue };
+                    let id = store_object(objects, &data)?;
+                    cache.insert(path.clone(), (mtime, meta.len(), id.clone()));
+                    id
+                }
+            };
+            let id = if is_executable(&meta) && !id.starts_with(LARGE_PREFIX) {
+                format!("{}{}", id, EXEC_SUFFIX)
+            } else {
+                id
+            };
+            snap.insert(rel_path(root, &path), id);
+        }
+    }
+    Ok(snap)
+}
+
+/// 内容寻址写入 objects/<sha256>
+fn store_object(objects: &Path, data: &[u8]) -> Result<String> {
+    let id = format!("{:x}", Sha256::digest(data));
+    let path = objects.join(&id);
+    if !path.exists() {
+        std::fs::write(&path, data)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+    }
+    Ok(id)
+}
+
+fn rel_path(root: &Path, path: &Path) -> String {
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    rel.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snap(pairs: &[(&str, &str)]) -> Snapshot {
+        pairs.iter().map(|(p, id)| (p.to_string(), id.to_string())).collect()
+    }
+
+    #[test]
+    fn diff_detects_add_modify_delete() {
+        let before = snap(&[("a", "1"), ("b", "2"), ("c", "3")]);
+        let after = snap(&[("a", "1"), ("b", "9"), ("d", "4")]);
+        let changes = diff_snapshots(&before, &after);
+        let kinds: Vec<(&str, ChangeKind)> =
+            changes.iter().map(|c| (c.path.as_str(), c.kind())).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("b", ChangeKind::Modified),
+                ("c", ChangeKind::Deleted),
+                ("d", ChangeKind::Added),
+            ],
+        );
+    }
+
+    #[test]
+    fn summarize_takes_first_line() {
+        assert_eq!(summarize_prompt("\n  fix the bug  \nmore"), "fix the bug");
+        let long = "x".repeat(100);
+        assert_eq!(summarize_prompt(&long).chars().count(), PROMPT_SUMMARY_CHARS + 1);
+    }
+
+    #[test]
+    fn files_backend_tracks_turn() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("work");
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("node_modules")).unwrap();
+        std::fs::write(root.join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(root.join("old.txt"), "bye\n").unwrap();
+
+        let mut t = ChangeTracker::new(root.clone(), dir.path().join("store"));
+        assert!(!t.is_git());
+        assert_eq!(t.begin_turn("edit files").unwrap(), 1);
+        std::fs::write(root.join("src/main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        std::fs::write(root.join("new.txt"), "hi\n").unwrap();
+        std::fs::remove_file(root.join("old.txt")).unwrap();
+        std::fs::write(root.join("node_modules/x.js"), "ignored").unwrap();
+        assert_eq!(t.end_turn().unwrap(), Some((1, 3)));
+
+        let turns = t.turn_changes(Some(1), true).unwrap();
+        let files = &turns[0].files;
+        assert_eq!(files.len(), 3);
+        assert_eq!(files[0].path, "new.txt");
+        assert_eq!(files[0].kind, ChangeKind::Added);
+        assert_eq!(files[2].path, "src/main.rs");
+        let diff = files[2].diff.as_deref().unwrap();
+        assert!(diff.contains("--- a/src/main.rs"));
+        assert!(diff.contains("+    run();"));
+        assert!(t.turn_changes(Some(7), false).is_err());
+    }
+
+    #[test]
+    fn files_backend_records_oversized_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("work");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("data.bin"), "small\n").unwrap();
+
+        let mut t = ChangeTracker::new(root.clone(), dir.path().join("store"));
+        t.begin_turn("grow data").unwrap();
+        std::fs::write(root.join("data.bin"), vec![b'x'; MAX_FILE_BYTES as usize + 1]).unwrap();
+        assert_eq!(t.end_turn().unwrap(), Some((1, 1)));
+
+        // 超过上限的文件记为修改而不是删除，diff 只给出说明
+        let turns = t.turn_changes(Some(1), true).unwrap();
+        let change = &turns[0].files[0];
+        assert_eq!((change.path.as_str(), change.kind), ("data.bin", ChangeKind::Modified));
+        assert_eq!(change.diff.as_deref(), Some("data.bin: too large to diff\n"));
+
+        // 大小不变、未被改动的大文件不算改动
+        t.begin_turn("no edits").unwrap();
+        assert_eq!(t.end_turn().unwrap(), Some((2, 0)));
+    }
+
+    #[test]
+    fn undo_restores_files_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("work");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("keep.txt"), "v1\n").unwrap();
+        std::fs::write(root.join("gone.txt"), "bye\n").unwrap();
+
+        let mut t = ChangeTracker::new(root.clone(), dir.path().join("store"));
+        t.begin_turn("turn 1").unwrap();
+        std::fs::write(root.join("keep.txt"), "v2, longer\n").unwrap();
+        std::fs::remove_file(root.join("gone.txt")).unwrap();
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/new.txt"), "new\n").unwrap();
+        t.end_turn().unwrap();
+        t.begin_turn("turn 2, no edits").unwrap();
+        t.end_turn().unwrap();
+
+        let undone = t.undo(None).unwrap();
+        assert_eq!(undone.turn, 1);
+        assert_eq!(std::fs::read_to_string(root.join("keep.txt")).unwrap(), "v1\n");
+        assert_eq!(std::fs::read_to_string(root.join("gone.txt")).unwrap(), "bye\n");
+        assert!(!root.join("sub/new.txt").exists());
+        assert!(t.turn_changes(Some(1), false).unwrap()[0].undone);
+        assert!(t.undo(Some(1)).unwrap_err().to_string().contains("already"));
+        assert!(t.undo(Some(2)).unwrap_err().to_string().contains("no files"));
+        assert!(t.undo(None).is_err());
+    }
+
+    #[test]
+    fn undo_refuses_when_modified_since() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("repo");
+        std::fs::create_dir_all(&root).unwrap();
+        run_git(&root, None, &["init", "-q"]).unwrap();
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+
+        let mut t = ChangeTracker::new(root.clone(), dir.path().join("store"));
+        t.begin_turn("edit a").unwrap();
+        std::fs::write(root.join("a.txt"), "two\n").unwrap();
+        t.end_turn().unwrap();
+
+        // 用户随后又改了 a.txt
+        std::fs::write(root.join("a.txt"), "three\n").unwrap();
+        let err = t.undo(Some(1)).unwrap_err().to_string();
+        assert!(err.contains("modified since turn 1: a.txt"));
+        assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "three\n");
+
+        // 恢复到 turn 结束时的内容后可以 undo
+        std::fs::write(root.join("a.txt"), "two\n").unwrap();
+        t.undo(Some(1)).unwrap();
+        assert_eq!(std::fs::read_to_string(root.join("a.txt")).unwrap(), "one\n");
+    }
+
+    #[test]
+    fn undo_refuses_oversized_and_non_regular_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("work");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("data.bin"), "small\n").unwrap();
+        std::fs::write(root.join("link"), "plain\n").unwrap();
+
+        let mut t = ChangeTracker::new(root.clone(), dir.path().join("store"));
+        t.begin_turn("grow data").unwrap();
+        let big = vec![b'x'; MAX_FILE_BYTES as usize + 1];
+        std::fs::write(root.join("data.bin"), &big).unwrap();
+        t.end_turn().unwrap();
+        let err = t.undo(Some(1)).unwrap_err().to_string();
+        assert!(err.contains("not a regular file: data.bin"), "{}", err);
+        assert_eq!(std::fs::read(root.join("data.bin")).unwrap().len(), big.len());
+
+        #[cfg(unix)]
+        {
+            // 普通文件被换成符号链接：不能写穿链接
+            std::fs::write(root.join("target.txt"), "elsewhere\n").unwrap();
+            t.begin_turn("link it").unwrap();
+            std::fs::remove_file(root.join("link")).unwrap();
+            std::os::unix::fs::symlink("target.txt", root.join("link")).unwrap();
+            assert_eq!(t.end_turn().unwrap(), Some((2, 1)));
+            let turns = t.turn_changes(Some(2), true).unwrap();
+            assert_eq!(turns[0].files[0].diff.as_deref(), Some("link: symbolic link changed\n"));
+            let err = t.undo(Some(2)).unwrap_err().to_string();
+            assert!(err.contains("not a regular file: link"), "{}", err);
+            assert_eq!(std::fs::read_to_string(root.join("target.txt")).unwrap(), "elsewhere\n");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn undo_keeps_executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = |p: &Path| std::fs::metadata(p).unwrap().permissions().mode() & 0o777;
+        for git in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let root = dir.path().join("work");
+            std::fs::create_dir_all(&root).unwrap();
+            if git {
+                run_git(&root, None, &["init", "-q"]).unwrap();
+            }
+            for name in ["run.sh", "gone.sh"] {
+                std::fs::write(root.join(name), "#!/bin/sh\n").unwrap();
+                std::fs::set_permissions(root.join(name), std::fs::Permissions::from_mode(0o755)).unwrap();
+            }
+
+            let mut t = ChangeTracker::new(root.clone(), dir.path().join("store"));
+            assert_eq!(t.is_git(), git);
+            t.begin_turn("edit scripts").unwrap();
+            std::fs::write(root.join("run.sh"), "#!/bin/sh\necho hi\n").unwrap();
+            std::fs::set_permissions(root.join("run.sh"), std::fs::Permissions::from_mode(0o644)).unwrap();
+            std::fs::remove_file(root.join("gone.sh")).unwrap();
+            assert_eq!(t.end_turn().unwrap(), Some((1, 2)));
+
+            t.undo(Some(1)).unwrap();
+            assert_eq!(std::fs::read_to_string(root.join("run.sh")).unwrap(), "#!/bin/sh\n");
+            assert_eq!(mode(&root.join("run.sh")), 0o755, "git={}", git);
+            assert_eq!(mode(&root.join("gone.sh")) & 0o111, 0o111, "git={}", git);
+        }
+    }
+
+    #[test]
+    fn git_backend_respects_gitignore() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path().join("repo");
+        std::fs::create_dir_all(&root).unwrap();
+        run_git(&root, None, &["init", "-q"]).unwrap();
+        std::fs::write(root.join(".gitignore"), "build/\n").unwrap();
+        std::fs::write(root.join("a.txt"), "one\n").unwrap();
+
+        let mut t = ChangeTracker::new(root.clone(), dir.path().join("store"));
+        assert!(t.is_git());
+        t.begin_turn("change a").unwrap();
+        std::fs::write(root.join("a.txt"), "two\n").unwrap();
+        std::fs::create_dir_all(root.join("build")).unwrap();
+        std::fs::write(root.join("build/out"), "bin").unwrap();
+        assert_eq!(t.end_turn().unwrap(), Some((1, 1)));
+
+        let turns = t.turn_changes(None, true).unwrap();
+        let change = &turns[0].files[0];
+        assert_eq!(change.path, "a.txt");
+        assert!(change.diff.as_deref().unwrap().contains("-one\n+two"));
+        // 用户自己的 index 不受影响
+        let status = run_git(&root, None, &["diff", "--cached", "--name-only"]).unwrap();
+        assert!(status.is_empty());
+    }
+}
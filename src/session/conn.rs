@@ -0,0 +1,314 @@
+// ============================================================
+// conn - 连接层：lockstep 与多路复用请求分发
+// ============================================================
+// 不带 id 的请求按顺序逐个处理并回复（旧客户端的 lockstep 模式）；
+// 带 id 的请求（`{"id": N, "type": ...}`）各自在 local task 中处理，
+// 响应带回同一 id、可乱序完成。Subscribe 的事件以不带 id 的通知推送：
+// lockstep 模式下连接转为事件流，多路复用模式下与其它请求共用连接。
+// 回复使用对端最近一帧的分帧方式（行 / 长度前缀）；坏帧被跳过并回复 Error。
+// session（本地 / 远程）与 daemon 共用，各自提供 dispatch。
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use anyhow::Result;
+use futures::future::LocalBoxFuture;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, Mutex, Notify};
+use tokio::task::JoinHandle;
+
+use crate::protocol::messages::{decode_request, Envelope, SessionEvent, SessionResponse};
+use crate::protocol::transport::{FrameError, Framing, JsonLineReader, JsonLineWriter};
+
+/// dispatch 的结果：响应 + 回复之后的动作
+pub(crate) type Reply = (SessionResponse, Followup);
+
+pub(crate) enum Followup {
+    None,
+    /// 回复后执行，连接继续（daemon 移除已关闭的 agent）
+    After(LocalBoxFuture<'static, ()>),
+    /// 回复后执行并结束连接（Shutdown / Stop）
+    Close(LocalBoxFuture<'static, ()>),
+    /// 回复后推送该 agent 的事件
+    Subscribe {
+        agent_name: String,
+        rx: broadcast::Receiver<SessionEvent>,
+    },
+}
+
+type SharedWriter<W> = Rc<Mutex<JsonLineWriter<W>>>;
+
+/// 连接主循环：读到 EOF、对端断开或 Close 后返回；I/O 错误返回 Err，
+/// 超长 / 无法解析的帧回复 Error 后继续
+pub(crate) async fn serve<R, W, T, D>(
+    mut reader: JsonLineReader<R>,
+    writer: JsonLineWriter<W>,
+    dispatch: D,
+) -> Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + 'static,
+    T: DeserializeOwned,
+    D: Fn(T) -> LocalBoxFuture<'static, Reply>,
+{
+    let writer: SharedWriter<W> = Rc::new(Mutex::new(writer));
+    let close = Rc::new(Notify::new());
+    // 多路复用模式下的事件推送 task，连接结束时中止
+    let streams: Rc<RefCell<Vec<JoinHandle<()>>>> = Rc::default();
+
+    let mut framing = Framing::Lines;
+
+    let result = loop {
+        // 连接即将关闭，丢弃读到一半的行无妨
+        let read = tokio::select! {
+            read = reader.read::<Value>() => read,
+            _ = close.notified() => break Ok(()),
+        };
+        // 按对端最近使用的分帧回复
+        if reader.framing() != framing {
+            framing = reader.framing();
+            writer.lock().await.set_framing(framing);
+        }
+        let mut value = match read {
+            Ok(Some(v)) => v,
+            Ok(None) => break Ok(()),
+            // 坏帧已被跳过：回复错误（无法得知 id）后继续
+            Err(e) => match FrameError::of(&e) {
+                Some(err) => {
+                    let resp = SessionResponse::Error { message: err.to_string() };
+                    if write_reply(&writer, None, &resp).await.is_err() {
+                        break Ok(());
+                    }
+                    continue;
+                }
+                None => break Err(e),
+            },
+        };
+        let id = take_id(&mut value);
+        let req = match decode_request::<T>(value) {
+            Ok(r) => r,
+            Err(message) => {
+                if write_reply(&writer, id, &SessionResponse::Error { message }).await.is_err() {
+                    break Ok(());
+                }
+                continue;
+            }
+        };
+        let reply = dispatch(req);
+
+        let Some(id) = id else {
+            // lockstep：处理完（含后续动作）再读下一条
+            let (resp, followup) = reply.await;
+            if write_reply(&writer, None, &resp).await.is_err() {
+                break Ok(());
+            }
+            match followup {
+                Followup::None => {}
+                Followup::After(f) => f.await,
+                Followup::Close(f) => {
+                    f.await;
+                    break Ok(());
+                }
+                Followup::Subscribe { agent_name, rx } => {
+                    push_events(&writer, agent_name, rx).await;
+                    break Ok(());
+                }
+            }
+            continue;
+        };
+
+        let (writer, close, streams) = (Rc::clone(&writer), Rc::clone(&close), Rc::clone(&streams));
+        tokio::task::spawn_local(async move {
+            let (resp, followup) = reply.await;
+            if write_reply(&writer, Some(id), &resp).await.is_err() {
+                return;
+            }
+            match followup {
+                Followup::None => {}
+                Followup::After(f) => f.await,
+                Followup::Close(f) => {
+                    f.await;
+                    close.notify_one();
+                }
+                Followup::Subscribe { agent_name, rx } => {
+                    let task = tokio::task::spawn_local(async move {
+                        push_events(&writer, agent_name, rx).await;
+                    });
+                    streams.borrow_mut().push(task);
+                }
+            }
+        });
+    };
+
+    // 进行中的请求继续完成；事件推送随连接结束
+    for task in streams.borrow_mut().drain(..) {
+        task.abort();
+    }
+    result
+}
+
+/// 取出并移除信封 id（请求类型里没有名为 id 的字段）
+fn take_id(value: &mut Value) -> Option<u64> {
+    let id = value.get("id")?.as_u64()?;
+    value.as_object_mut()?.remove("id");
+    Some(id)
+}
+
+async fn write_reply<W: AsyncWrite + Unpin>(
+    writer: &SharedWriter<W>,
+    id: Option<u64>,
+    resp: &SessionResponse,
+) -> Result<()> {
+    let mut w = writer.lock().await;
+    match id {
+        Some(id) => w.write(&Envelope { id, body: resp }).await,
+        None => w.write(resp).await,
+    }
+}
+
+/// 持续推送事件通知，直到对端断开或 session 关闭（broadcast sender 被丢弃）
+async fn push_events<W: AsyncWrite + Unpin>(
+    writer: &SharedWriter<W>,
+    agent_name: String,
+    mut rx: broadcast::Receiver<SessionEvent>,
+) {
+    loop {
+        let event = match rx.recv().await {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(n)) => SessionEvent::Info {
+                tag: "lagged".into(),
+                message: format!("{} event(s) dropped", n),
+                session: None,
+            },
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+        let resp = SessionResponse::Event {
+            agent_name: agent_name.clone(),
+            event,
+        };
+        if writer.lock().await.write(&resp).await.is_err() {
+            break;
+        }
+    }
+}
+
+// ==================== 测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::messages::SessionRequest;
+    use futures::FutureExt;
+    use std::time::Duration;
+    use tokio::io::{AsyncWriteExt, DuplexStream, ReadHalf, WriteHalf};
+
+    type Client = (JsonLineReader<ReadHalf<DuplexStream>>, WriteHalf<DuplexStream>);
+
+    /// 测试 dispatch：Cancel 慢、GetStatus 快；Subscribe 推送 events 的事件；Shutdown 关闭连接
+    fn start(events: broadcast::Sender<SessionEvent>) -> (Client, JoinHandle<Result<()>>) {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (sr, sw) = tokio::io::split(server);
+        let task = tokio::task::spawn_local(serve(
+            JsonLineReader::new(sr),
+            JsonLineWriter::new(sw),
+            move |req: SessionRequest| {
+                let events = events.clone();
+                async move {
+                    let ok = |m: &str| SessionResponse::Ok { message: m.into() };
+                    match req {
+                        SessionRequest::Cancel => {
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            (ok("cancel"), Followup::None)
+                        }
+                        SessionRequest::Subscribe => (
+                            ok("subscribed"),
+                            Followup::Subscribe { agent_name: "a".into(), rx: events.subscribe() },
+                        ),
+                        SessionRequest::Shutdown => {
+                            (ok("bye"), Followup::Close(async {}.boxed_local()))
+                        }
+                        _ => (ok("status"), Followup::None),
+                    }
+                }
+                .boxed_local()
+            },
+        ));
+        let (cr, cw) = tokio::io::split(client);
+        ((JsonLineReader::new(cr), cw), task)
+    }
+
+    async fn send_line(w: &mut WriteHalf<DuplexStream>, line: &str) {
+        w.write_all(format!("{}\n", line).as_bytes()).await.unwrap();
+    }
+
+    async fn next(r: &mut JsonLineReader<ReadHalf<DuplexStream>>) -> Value {
+        r.read::<Value>().await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn lockstep_without_ids() {
+        let local = tokio::task::LocalSet::new();
+        local.run_until(async {
+            let ((mut r, mut w), task) = start(broadcast::channel(8).0);
+            send_line(&mut w, r#"{"type":"Cancel"}"#).await;
+            send_line(&mut w, r#"{"type":"GetStatus"}"#).await;
+            // 按发送顺序回复，且不带 id
+            let first = next(&mut r).await;
+            assert_eq!(first["message"], "cancel");
+            assert!(first.get("id").is_none());
+            assert_eq!(next(&mut r).await["message"], "status");
+
+            send_line(&mut w, r#"{"type":"Shutdown"}"#).await;
+            assert_eq!(next(&mut r).await["message"], "bye");
+            assert!(task.await.unwrap().is_ok());
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn ids_complete_out_of_order() {
+        let local = tokio::task::LocalSet::new();
+        local.run_until(async {
+            let ((mut r, mut w), _task) = start(broadcast::channel(8).0);
+            send_line(&mut w, r#"{"id":1,"type":"Cancel"}"#).await;
+            send_line(&mut w, r#"{"id":2,"type":"GetStatus"}"#).await;
+            let first = next(&mut r).await;
+            assert_eq!((first["id"].as_u64(), first["message"].as_str()), (Some(2), Some("status")));
+            let second = next(&mut r).await;
+            assert_eq!((second["id"].as_u64(), second["message"].as_str()), (Some(1), Some("cancel")));
+
+            // 未知请求的错误也带回 id
+            send_line(&mut w, r#"{"id":3,"type":"Teleport"}"#).await;
+            let err = next(&mut r).await;
+            assert_eq!(err["id"], 3);
+            assert_eq!(err["type"], "Error");
+        }).await;
+    }
+
+    #[tokio::test]
+    async fn notifications_share_the_connection() {
+        let local = tokio::task::LocalSet::new();
+        local.run_until(async {
+            let events = broadcast::channel(8).0;
+            let ((mut r, mut w), task) = start(events.clone());
+            send_line(&mut w, r#"{"id":1,"type":"Subscribe"}"#).await;
+            assert_eq!(next(&mut r).await["id"], 1);
+            tokio::time::sleep(Duration::from_millis(20)).await;
+
+            events.send(SessionEvent::Info { tag: "idle".into(), message: "x".into(), session: None }).unwrap();
+            let note = next(&mut r).await;
+            assert_eq!(note["type"], "Event");
+            assert!(note.get("id").is_none());
+
+            // 订阅期间仍可发其它请求；Close 结束连接并中止推送
+            send_line(&mut w, r#"{"id":2,"type":"GetStatus"}"#).await;
+            assert_eq!(next(&mut r).await["id"], 2);
+            send_line(&mut w, r#"{"id":3,"type":"Shutdown"}"#).await;
+            assert_eq!(next(&mut r).await["id"], 3);
+            assert!(task.await.unwrap().is_ok());
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(events.receiver_count(), 0);
+        }).await;
+    }
+}
@@ -0,0 +1,343 @@
+// ============================================================
+// daemon - 单进程托管多个 agent
+// ============================================================
+// `agent-team daemon` 在一个 LocalSet 中托管多个 AgentHandle，
+// 通过控制 socket {socket_dir}/daemon.ctl 接收按 name 寻址的请求。
+// 每个托管 agent 写 {name}.daemon 标记，CLI 据此把请求转发到 daemon；
+// 未运行 daemon 时仍使用每个 agent 一个进程的模式。
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use futures::future::{FutureExt, LocalBoxFuture};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+
+use crate::config::{validate_agent_name, AutoApprovePolicy, TeamConfig};
+use crate::protocol::messages::{DaemonRequest, SessionRequest, SessionResponse};
+use crate::protocol::remote::RemoteListener;
+use crate::protocol::transport::{JsonLineReader, JsonLineWriter};
+use crate::session::agent::AgentHandle;
+use crate::session::server::{
+    accept_remote, bind_listener, cleanup_socket, event_broadcast, flush_events, handle_request,
+    log_request, print_events, signal_shutdown, start_session, stop_session, subscribe, Event,
+};
+use crate::session::conn::{self, Followup, Reply};
+use crate::session::hooks;
+use crate::session::notify::Notifier;
+
+/// 一个托管 agent；config 按 agent 独立保存，Restart 时沿用 auto_approve / no_mcp
+struct Hosted {
+    handle: Rc<RefCell<AgentHandle>>,
+    config: Rc<TeamConfig>,
+    event_tx: mpsc::UnboundedSender<Event>,
+}
+
+type Agents = Rc<RefCell<HashMap<String, Hosted>>>;
+
+// ==================== daemon 入口 ====================
+
+pub async fn run(config: TeamConfig) -> Result<()> {
+    let ctl_path = config.daemon_socket();
+    config.ensure_socket_dir()?;
+    // 是否已有 daemon 在运行由 CLI 先行检查，这里残留的文件视为过期
+    cleanup_socket(&ctl_path);
+    let listener = bind_listener(&ctl_path).await?;
+    let remote = RemoteListener::bind(&config.remote).await?;
+
+    let (event_tx, event_rx) = mpsc::unbounded_channel::<Event>();
+    tokio::task::spawn_local(print_events(event_rx, Some("daemon".into()), None, None, None));
+    event_tx
+        .send(Event::Info {
+            tag: "started",
+            message: format!("Listening on {}", ctl_path.display()),
+        })
+        .ok();
+    if let Some(ref r) = remote {
+        event_tx
+            .send(Event::Info {
+                tag: "remote",
+                message: format!("Listening on {}", r.describe()),
+            })
+            .ok();
+    }
+
+    let config = Rc::new(config);
+    let agents: Agents = Rc::new(RefCell::new(HashMap::new()));
+    let (shutdown_tx, mut shutdown_rx) = mpsc::unbounded_channel::<()>();
+
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let (stream, _) = result.context("Accept failed")?;
+                let (read, write) = stream.into_split();
+                let a = Rc::clone(&agents);
+                let c = Rc::clone(&config);
+                let stx = shutdown_tx.clone();
+                tokio::task::spawn_local(async move {
+                    let (reader, writer) = (JsonLineReader::new(read), JsonLineWriter::new(write));
+                    handle_connection(reader, writer, a, c, stx).await;
+                });
+            }
+            Some((tcp, peer, auth)) = accept_remote(remote.as_ref()) => {
+                let a = Rc::clone(&agents);
+                let c = Rc::clone(&config);
+                let stx = shutdown_tx.clone();
+                let etx = event_tx.clone();
+                tokio::task::spawn_local(async move {
+                    match auth.handshake(tcp).await {
+                        Ok((reader, writer)) => handle_connection(reader, writer, a, c, stx).await,
+                        Err(e) => {
+                            etx.send(Event::Info {
+                                tag: "remote",
+                                message: format!("Rejected {}: {:#}", peer, e),
+                            }).ok();
+                        }
+                    }
+                });
+            }
+            _ = shutdown_rx.recv() => {
+                event_tx.send(Event::Info {
+                    tag: "shutdown",
+                    message: "Remote request".into(),
+                }).ok();
+                break;
+            }
+            _ = signal_shutdown() => {
+                event_tx.send(Event::Info {
+                    tag: "shutdown",
+                    message: "Signal received".into(),
+                }).ok();
+                break;
+            }
+        }
+    }
+
+    let names: Vec<String> = agents.borrow().keys().cloned().collect();
+    for name in names {
+        remove_agent(&agents, &config, &name).await;
+    }
+    cleanup_socket(&ctl_path);
+    event_tx
+        .send(Event::Info {
+            tag: "stopped",
+            message: "Socket cleaned".into(),
+        })
+        .ok();
+    Ok(())
+}
+
+// ==================== 连接处理 ====================
+
+async fn handle_connection<R, W>(
+    mut reader: JsonLineReader<R>,
+    writer: JsonLineWriter<W>,
+    agents: Agents,
+    config: Rc<TeamConfig>,
+    shutdown_tx: mpsc::UnboundedSender<()>,
+) where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + 'static,
+{
+    reader.set_max_frame(config.transport.max_frame);
+    let on_request = move |req: DaemonRequest| {
+        dispatch(Rc::clone(&agents), Rc::clone(&config), shutdown_tx.clone(), req)
+    };
+    let _ = conn::serve(reader, writer, on_request).await;
+}
+
+fn dispatch(
+    agents: Agents,
+    config: Rc<TeamConfig>,
+    shutdown_tx: mpsc::UnboundedSender<()>,
+    req: DaemonRequest,
+) -> LocalBoxFuture<'static, Reply> {
+    async move {
+        match req {
+            DaemonRequest::Session {
+                name,
+                request: SessionRequest::Subscribe,
+            } => {
+                let handle = agents.borrow().get(&name).map(|a| Rc::clone(&a.handle));
+                match handle {
+                    Some(h) => subscribe(&h),
+                    None => (no_agent(&name), Followup::None),
+                }
+            }
+            DaemonRequest::Session { name, request } => {
+                // 先回复再关闭，与独立 session 的 Shutdown 行为一致
+                match forward(&agents, &name, request).await {
+                    (resp, Some(stopped)) => (
+                        resp,
+                        Followup::After(
+                            async move { remove_agent(&agents, &config, &stopped).await }
+                                .boxed_local(),
+                        ),
+                    ),
+                    (resp, None) => (resp, Followup::None),
+                }
+            }
+            DaemonRequest::Spawn { .. } => (spawn(&agents, &config, req).await, Followup::None),
+            DaemonRequest::List => {
+                let mut summaries: Vec<_> = agents
+                    .borrow()
+                    .values()
+                    .map(|a| a.handle.borrow().to_summary())
+                    .collect();
+                summaries.sort_by(|a, b| a.name.cmp(&b.name));
+                (SessionResponse::Agents { agents: summaries }, Followup::None)
+            }
+            DaemonRequest::Stop => (
+                SessionResponse::Ok {
+                    message: "Daemon shutting down".into(),
+                },
+                Followup::Close(async move { shutdown_tx.send(()).ok(); }.boxed_local()),
+            ),
+        }
+    }
+    .boxed_local()
+}
+
+fn no_agent(name: &str) -> SessionResponse {
+    SessionResponse::Error {
+        message: format!("No agent named '{}' in daemon", name),
+    }
+}
+
+/// 转发 session 请求；Shutdown 时返回需要移除的 agent 名
+async fn forward(
+    agents: &Agents,
+    name: &str,
+    request: SessionRequest,
+) -> (SessionResponse, Option<String>) {
+    let (handle, config, event_tx) = match agents.borrow().get(name) {
+        Some(a) => (Rc::clone(&a.handle), Rc::clone(&a.config), a.event_tx.clone()),
+        None => return (no_agent(name), None),
+    };
+
+    let is_shutdown = matches!(request, SessionRequest::Shutdown);
+    log_request(&event_tx, &request);
+
+    let resp = handle_request(&handle, &config, request, &event_tx).await;
+    (resp, is_shutdown.then(|| name.to_string()))
+}
+
+/// 在 daemon 内启动 agent，ACP 初始化完成后写标记文件
+async fn spawn(agents: &Agents, config: &TeamConfig, req: DaemonRequest) -> SessionResponse {
+    let DaemonRequest::Spawn {
+        name,
+        agent_type,
+        cwd,
+        args,
+        auto_approve,
+        no_mcp,
+        worktree,
+    } = req
+    else {
+        unreachable!("spawn called with non-Spawn request");
+    };
+
+    if let Err(message) = validate_agent_name(&name) {
+        return SessionResponse::Error { message };
+    }
+    if agents.borrow().contains_key(&name) || config.session_exists(&name) {
+        return SessionResponse::Error {
+            message: format!("Agent '{}' already exists", name),
+        };
+    }
+
+    let mut agent_config = config.clone();
+    if let Some(policy) = auto_approve {
+        match policy.parse::<AutoApprovePolicy>() {
+            Ok(p) => agent_config.auto_approve = p,
+            Err(e) => return SessionResponse::Error { message: e },
+        }
+    }
+    if no_mcp {
+        agent_config.mcp_max_depth = 0;
+    }
+    let agent_cwd = match &worktree {
+        Some(w) => PathBuf::from(&w.path),
+        None => cwd.unwrap_or_else(|| config.default_cwd.clone()),
+    };
+
+    let (event_tx, event_rx) = mpsc::unbounded_channel::<Event>();
+    let events = event_broadcast();
+    let hooks = hooks::load_runner(&agent_config, &name, &event_tx);
+    let notifier = Notifier::new(&name, &agent_config.notify, event_tx.downgrade());
+    let notify_muted = notifier.muted();
+    tokio::task::spawn_local(print_events(
+        event_rx,
+        Some(name.clone()),
+        Some(events.downgrade()),
+        hooks,
+        Some(notifier),
+    ));
+
+    let handle = match start_session(
+        name.clone(),
+        agent_type,
+        &agent_config,
+        args,
+        agent_cwd,
+        worktree,
+        &event_tx,
+    )
+    .await
+    {
+        Ok(h) => h,
+        Err(e) => {
+            return SessionResponse::Error {
+                message: format!("Failed to start '{}': {:#}", name, e),
+            }
+        }
+    };
+    handle.borrow_mut().events = Some(events);
+    handle.borrow_mut().notify_muted = notify_muted;
+
+    // 初始化期间同名 agent 已被另一个请求抢先启动
+    if agents.borrow().contains_key(&name) {
+        stop_session(&handle, &agent_config, &event_tx).await;
+        return SessionResponse::Error {
+            message: format!("Agent '{}' already exists", name),
+        };
+    }
+
+    if let Err(e) = std::fs::write(config.daemon_marker(&name), std::process::id().to_string()) {
+        stop_session(&handle, &agent_config, &event_tx).await;
+        return SessionResponse::Error {
+            message: format!("Cannot write daemon marker: {}", e),
+        };
+    }
+    agents.borrow_mut().insert(
+        name.clone(),
+        Hosted {
+            handle,
+            config: Rc::new(agent_config),
+            event_tx,
+        },
+    );
+    SessionResponse::Ok {
+        message: format!("Agent '{}' started in daemon", name),
+    }
+}
+
+/// 关闭 agent 并移除标记文件
+async fn remove_agent(agents: &Agents, config: &TeamConfig, name: &str) {
+    let Some(hosted) = agents.borrow_mut().remove(name) else {
+        return;
+    };
+    let _ = std::fs::remove_file(config.daemon_marker(name));
+    stop_session(&hosted.handle, &hosted.config, &hosted.event_tx).await;
+    hosted
+        .event_tx
+        .send(Event::Info {
+            tag: "stopped",
+            message: "Agent removed from daemon".into(),
+        })
+        .ok();
+    flush_events(&hosted.event_tx).await;
+}
@@ -0,0 +1,404 @@
+// ============================================================
+// hooks - 生命周期 / 输出事件触发的用户钩子
+// ============================================================
+// print_events 把每个事件交给 HookRunner：跟踪各 session 的状态与最近一条 agent 消息，
+// 匹配到的钩子在后台执行（shell 命令读 stdin 的 JSON，或 POST 到本地 http:// URL），
+// 超时即终止；失败以 `hook` 事件报告，不影响 session。
+
+use std::collections::HashMap;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+use tokio::task::JoinSet;
+
+use crate::acp_client::team_client::awaiting_approval;
+use crate::config::{Hook, HooksFile, TeamConfig};
+use crate::protocol::messages::{OutputEntry, OutputType};
+use crate::session::server::Event;
+
+/// payload 中 last_message 的字符上限（保留末尾）
+const LAST_MESSAGE_MAX: usize = 4000;
+/// 钩子失败事件不再触发钩子，避免循环
+const HOOK_TAG: &str = "hook";
+
+/// 单个 session 的跟踪状态
+#[derive(Debug, Default)]
+struct Tracked {
+    status: Option<&'static str>,
+    last_message: String,
+    in_message: bool,
+}
+
+pub(crate) struct HookRunner {
+    agent: String,
+    hooks: Vec<Hook>,
+    sessions: HashMap<Option<String>, Tracked>,
+    running: JoinSet<()>,
+    /// 失败报告回事件流（弱引用，不阻止事件通道关闭）
+    report: mpsc::WeakUnboundedSender<Event>,
+}
+
+/// 读取钩子配置；没有钩子返回 None，配置错误以 `hook` 事件报告后忽略
+pub(crate) fn load_runner(
+    config: &TeamConfig,
+    agent: &str,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> Option<HookRunner> {
+    match HooksFile::load(&config.hooks_file) {
+        Ok(file) if file.hooks.is_empty() => None,
+        Ok(file) => Some(HookRunner::new(agent.to_string(), file.hooks, event_tx.downgrade())),
+        Err(e) => {
+            event_tx.send(Event::Info { tag: HOOK_TAG, message: format!("{:#}", e) }).ok();
+            None
+        }
+    }
+}
+
+impl HookRunner {
+    pub(crate) fn new(agent: String, hooks: Vec<Hook>, report: mpsc::WeakUnboundedSender<Event>) -> Self {
+        Self { agent, hooks, sessions: HashMap::new(), running: JoinSet::new(), report }
+    }
+
+    /// 观察一个事件（命名 session 的事件已拆出 session 名）
+    pub(crate) fn observe(&mut self, session: Option<&str>, event: &Event) {
+        let key = session.map(str::to_string);
+        let tracked = self.sessions.entry(key).or_default();
+        let fired = match event {
+            Event::Output(entry) => observe_output(tracked, entry),
+            Event::Info { tag, message } if *tag != HOOK_TAG => {
+                if let Some(status) = status_after(tag) {
+                    tracked.status = Some(status);
+                }
+                Some((tag.to_string(), message.clone()))
+            }
+            _ => None,
+        };
+        if let Some((name, message)) = fired {
+            self.fire(session, &name, &message);
+        }
+    }
+
+    /// 等待进行中的钩子（进程退出前调用，受各钩子超时约束）
+    pub(crate) async fn wait(&mut self) {
+        while self.running.join_next().await.is_some() {}
+    }
+
+    fn fire(&mut self, session: Option<&str>, event: &str, message: &str) {
+        while self.running.try_join_next().is_some() {}
+        let matched: Vec<Hook> = self
+            .hooks
+            .iter()
+            .filter(|h| h.matches(event, &self.agent))
+            .cloned()
+            .collect();
+        if matched.is_empty() {
+            return;
+        }
+        let payload = self.payload(session, event, message).to_string();
+        for hook in matched {
+            let payload = payload.clone();
+            let (event, agent) = (event.to_string(), self.agent.clone());
+            let report = self.report.clone();
+            self.running.spawn_local(async move {
+                if let Err(e) = run_hook(&hook, &payload, &event, &agent).await {
+                    if let Some(tx) = report.upgrade() {
+                        tx.send(Event::Info {
+                            tag: HOOK_TAG,
+                            message: format!("{} on {} failed: {:#}", hook.describe(), event, e),
+                        })
+                        .ok();
+                    }
+                }
+            });
+        }
+    }
+
+    fn payload(&self, session: Option<&str>, event: &str, message: &str) -> serde_json::Value {
+        let tracked = self.sessions.get(&session.map(str::to_string));
+        serde_json::json!({
+            "event": event,
+            "agent": self.agent,
+            "session": session,
+            "status": tracked.and_then(|t| t.status),
+            "message": message,
+            "last_message": tracked.map(|t| t.last_message.as_str()).filter(|m| !m.is_empty()),
+            "timestamp": chrono::Local::now().to_rfc3339(),
+        })
+    }
+}
+
+/// 输出条目：累积 agent 消息；等待审批的权限请求合成 `waiting_permission` 事件
+fn observe_output(tracked: &mut Tracked, entry: &OutputEntry) -> Option<(String, String)> {
+    match entry.update_type {
+        OutputType::AgentMessage => {
+            if !tracked.in_message {
+                tracked.last_message.clear();
+                tracked.in_message = true;
+            }
+            tracked.last_message.push_str(&entry.content);
+            keep_tail(&mut tracked.last_message, LAST_MESSAGE_MAX);
+            return None;
+        }
+        // 思考与消息交错输出，不打断当前消息
+        OutputType::AgentThought => return None,
+        _ => tracked.in_message = false,
+    }
+    match entry.update_type {
+        OutputType::UserPrompt => {
+            tracked.last_message.clear();
+            None
+        }
+        // auto-approve 的请求不等待
+        OutputType::PermissionRequest if awaiting_approval(&entry.content).is_some() => {
+            tracked.status = Some("waiting_permission");
+            Some(("waiting_permission".into(), entry.content.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// 生命周期 tag 对应的状态（与 AgentStatus 标签一致）
+fn status_after(tag: &str) -> Option<&'static str> {
+    Some(match tag {
+        "running" | "approved" | "denied" => "running",
+        "initialized" | "restarted" | "done" | "idle" => "idle",
+        "error" => "error",
+        "exited" | "stopped" => "stopped",
+        _ => return None,
+    })
+}
+
+fn keep_tail(s: &mut String, max_chars: usize) {
+    let count = s.chars().count();
+    if count > max_chars {
+        let cut = s.char_indices().nth(count - max_chars).map(|(i, _)| i).unwrap_or(0);
+        s.drain(..cut);
+    }
+}
+
+// ==================== 执行 ====================
+
+async fn run_hook(hook: &Hook, payload: &str, event: &str, agent: &str) -> Result<()> {
+    let timeout = Duration::from_secs(hook.timeout_secs);
+    let run = async {
+        match (&hook.command, &hook.url) {
+            (Some(cmd), _) => run_command(cmd, payload, event, agent).await,
+            (_, Some(url)) => post_json(url, payload).await,
+            _ => Ok(()),
+        }
+    };
+    // 超时丢弃 future，kill_on_drop 终止子进程
+    tokio::time::timeout(timeout, run)
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}s", hook.timeout_secs))?
+}
+
+async fn run_command(cmd: &str, payload: &str, event: &str, agent: &str) -> Result<()> {
+    #[cfg(unix)]
+    let mut command = {
+        let mut c = tokio::process::Command::new("sh");
+        c.arg("-c").arg(cmd);
+        c
+    };
+    #[cfg(not(unix))]
+    let mut command = {
+        let mut c = tokio::process::Command::new("cmd");
+        c.arg("/C").arg(cmd);
+        c
+    };
+    command
+        .env("AGENT_TEAM_EVENT", event)
+        .env("AGENT_TEAM_AGENT", agent)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    let mut child = command.spawn().context("spawn failed")?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // 钩子可能不读 stdin，忽略 broken pipe
+        let _ = stdin.write_all(payload.as_bytes()).await;
+    }
+    let out = child.wait_with_output().await?;
+    if !out.status.success() {
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        match stderr.lines().next() {
+            Some(line) => bail!("{}: {}", out.status, line),
+            None => bail!("{}", out.status),
+        }
+    }
+    Ok(())
+}
+
+/// 最小 HTTP/1.1 POST（仅 http://，面向本地接收端）
+async fn post_json(url: &str, payload: &str) -> Result<()> {
+    let rest = url.strip_prefix("http://").context("only http:// URLs are supported")?;
+    let (authority, path) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "/"),
+    };
+    let addr = if authority.rsplit_once(':').is_some_and(|(_, p)| p.parse::<u16>().is_ok()) {
+        authority.to_string()
+    } else {
+        format!("{}:80", authority)
+    };
+    let mut stream = tokio::net::TcpStream::connect(&addr)
+        .await
+        .with_context(|| format!("connect {}", addr))?;
+    let request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        authority,
+        payload.len(),
+        payload,
+    );
+    stream.write_all(request.as_bytes()).await?;
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line).await?;
+    let code = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|c| c.parse::<u16>().ok())
+        .context("invalid HTTP response")?;
+    if !(200..300).contains(&code) {
+        bail!("HTTP {}", code);
+    }
+    Ok(())
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(t: OutputType, content: &str) -> Event {
+        Event::Output(OutputEntry {
+            timestamp: String::new(),
+            update_type: t,
+            content: content.into(),
+        })
+    }
+
+    fn info(tag: &'static str, message: &str) -> Event {
+        Event::Info { tag, message: message.into() }
+    }
+
+    fn hook(on: &str, command: &str, timeout_secs: u64) -> Hook {
+        HooksFile::parse(&format!(
+            "[[hook]]\non = [\"{}\"]\ncommand = '''{}'''\ntimeout_secs = {}",
+            on, command, timeout_secs
+        ))
+        .unwrap()
+        .hooks
+        .remove(0)
+    }
+
+    fn runner(hooks: Vec<Hook>) -> (HookRunner, mpsc::UnboundedSender<Event>, mpsc::UnboundedReceiver<Event>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (HookRunner::new("coder".into(), hooks, tx.downgrade()), tx, rx)
+    }
+
+    #[test]
+    fn tracks_status_and_last_message() {
+        let (mut r, _tx, _rx) = runner(vec![]);
+        r.observe(None, &entry(OutputType::UserPrompt, "hi"));
+        r.observe(None, &info("running", "Processing"));
+        r.observe(None, &entry(OutputType::AgentMessage, "first"));
+        r.observe(None, &entry(OutputType::ToolCallStart, "read"));
+        r.observe(None, &entry(OutputType::AgentMessage, "All "));
+        r.observe(None, &entry(OutputType::AgentThought, "hmm"));
+        r.observe(None, &entry(OutputType::AgentMessage, "done."));
+        r.observe(Some("review"), &info("error", "boom"));
+        r.observe(None, &info("done", "EndTurn"));
+
+        let p = r.payload(None, "done", "EndTurn");
+        assert_eq!(p["agent"], "coder");
+        assert_eq!(p["status"], "idle");
+        assert_eq!(p["last_message"], "All done.");
+        assert!(p["session"].is_null());
+        let p = r.payload(Some("review"), "error", "boom");
+        assert_eq!(p["status"], "error");
+        assert!(p["last_message"].is_null());
+
+        let mut s = "é".repeat(10);
+        keep_tail(&mut s, 3);
+        assert_eq!(s, "ééé");
+    }
+
+    #[tokio::test]
+    async fn runs_matching_command_with_payload() {
+        let dir = tempfile::tempdir().unwrap();
+        let out = dir.path().join("out.json");
+        let cmd = format!("(cat; echo; echo \"$AGENT_TEAM_EVENT\") > '{}'", out.display());
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (mut r, _tx, _rx) = runner(vec![hook("waiting_permission", &cmd, 5)]);
+                r.observe(None, &entry(OutputType::PermissionRequest, "Permission auto-approved: ls"));
+                r.observe(None, &info("done", "EndTurn"));
+                r.observe(None, &entry(OutputType::PermissionRequest, "Permission requested: rm (Waiting for approval)"));
+                r.wait().await;
+            })
+            .await;
+        let text = std::fs::read_to_string(&out).unwrap();
+        let (json, event) = text.split_once('\n').unwrap();
+        let p: serde_json::Value = serde_json::from_str(json).unwrap();
+        assert_eq!(p["event"], "waiting_permission");
+        assert_eq!(p["status"], "waiting_permission");
+        assert_eq!(event.trim(), "waiting_permission");
+    }
+
+    #[tokio::test]
+    async fn reports_failures_and_timeouts() {
+        let local = tokio::task::LocalSet::new();
+        local
+            .run_until(async {
+                let (mut r, _tx, mut rx) = runner(vec![
+                    hook("done", "echo nope >&2; exit 3", 5),
+                    hook("exited", "sleep 5", 1),
+                ]);
+                r.observe(None, &info("done", "EndTurn"));
+                r.observe(None, &info("exited", "Code: 0"));
+                r.wait().await;
+                let mut messages = Vec::new();
+                while let Ok(Event::Info { tag, message }) = rx.try_recv() {
+                    assert_eq!(tag, HOOK_TAG);
+                    messages.push(message);
+                }
+                messages.sort();
+                assert_eq!(messages.len(), 2);
+                assert!(messages[0].contains("exit status: 3: nope"), "{}", messages[0]);
+                assert!(messages[1].contains("timed out after 1s"), "{}", messages[1]);
+                // 失败报告本身不触发钩子
+                r.observe(None, &info(HOOK_TAG, "x"));
+                assert!(r.running.is_empty());
+            })
+            .await;
+    }
+
+    #[tokio::test]
+    async fn posts_to_local_webhook() {
+        use tokio::io::AsyncReadExt;
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/events", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut s, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0u8; 4096];
+            let mut req = String::new();
+            while !req.contains("\"event\"") || !req.ends_with('}') {
+                let n = s.read(&mut buf).await.unwrap();
+                req.push_str(&String::from_utf8_lossy(&buf[..n]));
+            }
+            s.write_all(b"HTTP/1.1 204 No Content\r\n\r\n").await.unwrap();
+            req
+        });
+        post_json(&url, r#"{"event":"done"}"#).await.unwrap();
+        let req = server.await.unwrap();
+        assert!(req.starts_with("POST /events HTTP/1.1\r\n"));
+        assert!(req.contains("Content-Length: 16\r\n"));
+        assert!(post_json("http://127.0.0.1:1/x", "{}").await.is_err());
+    }
+}
@@ -1,5 +1,12 @@
 pub mod agent;
+pub mod changes;
+pub mod conn;
+pub mod daemon;
+pub mod hooks;
+pub mod notify;
 pub mod server;
+pub mod usage;
+pub mod worktree;
 
 #[cfg(test)]
 mod server_tests;
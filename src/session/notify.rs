@@ -0,0 +1,264 @@
+// ============================================================
+// notify - 权限请求 / turn 结束通知
+// ============================================================
+// 与钩子一样挂在 print_events 上：通知以 `notify` 事件推送给订阅者（`log -f`、前台终端响铃），
+// 并可写入命名管道、追加到共享收件箱文件。同一 session 同类通知按最小间隔限流，
+// 期间的通知合并计数；`notify <name> off` 关闭本 agent 的通知。
+
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use tokio::sync::mpsc;
+
+use crate::acp_client::team_client::awaiting_approval;
+use crate::config::NotifyConfig;
+use crate::protocol::messages::OutputType;
+use crate::session::server::Event;
+
+/// 通知开关，与 AgentHandle 共享（true = 已关闭）
+pub type NotifyMuted = Rc<Cell<bool>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Kind {
+    Permission,
+    Done,
+    Error,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Self::Permission => "permission",
+            Self::Done => "done",
+            Self::Error => "error",
+        }
+    }
+}
+
+pub(crate) struct Notifier {
+    agent: String,
+    config: NotifyConfig,
+    muted: NotifyMuted,
+    /// (session, 类别) → 上次通知时间、之后被合并的次数
+    limits: HashMap<(Option<String>, Kind), (Instant, u32)>,
+    /// `notify` 事件发回事件流（弱引用，不阻止事件通道关闭）
+    report: mpsc::WeakUnboundedSender<Event>,
+}
+
+impl Notifier {
+    pub(crate) fn new(agent: &str, config: &NotifyConfig, report: mpsc::WeakUnboundedSender<Event>) -> Self {
+        Self {
+            agent: agent.to_string(),
+            config: config.clone(),
+            muted: NotifyMuted::default(),
+            limits: HashMap::new(),
+            report,
+        }
+    }
+
+    pub(crate) fn muted(&self) -> NotifyMuted {
+        Rc::clone(&self.muted)
+    }
+
+    pub(crate) fn observe(&mut self, session: Option<&str>, event: &Event) {
+        if self.muted.get() {
+            return;
+        }
+        let Some((kind, text)) = classify(event) else {
+            return;
+        };
+        if let Some(text) = self.admit(session, kind, text, Instant::now()) {
+            self.deliver(session, kind, &text);
+        }
+    }
+
+    /// 限流：间隔内的同类通知只计数，下一条放行的通知带上合并数
+    fn admit(&mut self, session: Option<&str>, kind: Kind, text: String, now: Instant) -> Option<String> {
+        let interval = Duration::from_secs(self.config.min_interval_secs);
+        let key = (session.map(str::to_string), kind);
+        let skipped = match self.limits.get_mut(&key) {
+            Some((last, skipped)) if now.duration_since(*last) < interval => {
+                *skipped += 1;
+                return None;
+            }
+            Some((_, skipped)) => *skipped,
+            None => 0,
+        };
+        self.limits.insert(key, (now, 0));
+        Some(match skipped {
+            0 => text,
+            n => format!("{} (+{} more)", text, n),
+        })
+    }
+
+    fn deliver(&self, session: Option<&str>, kind: Kind, text: &str) {
+        if let Some(tx) = self.report.upgrade() {
+            let info = Event::Info { tag: "notify", message: text.to_string() };
+            let event = match session {
+                Some(s) => Event::Scoped(s.to_string(), Box::new(info)),
+                None => info,
+            };
+            tx.send(event).ok();
+        }
+        let who = match session {
+            Some(s) => format!("{}({})", self.agent, s),
+            None => self.agent.clone(),
+        };
+        let line = format!(
+            "{} {} [{}] {}\n",
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
+            who,
+            kind.label(),
+            text,
+        );
+        if let Some(fifo) = &self.config.fifo {
+            if let Err(e) = write_fifo(fifo, &line) {
+                eprintln!("notify: cannot write {}: {}", fifo.display(), e);
+            }
+        }
+        if let Some(inbox) = &self.config.inbox {
+            if let Err(e) = append(inbox, &line) {
+                eprintln!("notify: cannot append to {}: {}", inbox.display(), e);
+            }
+        }
+    }
+}
+
+/// 需要通知的事件：等待审批的权限请求、turn 结束、错误
+fn classify(event: &Event) -> Option<(Kind, String)> {
+    match event {
+        Event::Output(entry) if matches!(entry.update_type, OutputType::PermissionRequest) => {
+            awaiting_approval(&entry.content).map(|tool| (Kind::Permission, format!("Waiting for permission: {}", tool)))
+        }
+        Event::Info { tag: "done", message } => Some((Kind::Done, format!("Turn finished ({})", message))),
+        Event::Info { tag: "error", message } => Some((Kind::Error, message.clone())),
+        _ => None,
+    }
+}
+
+fn append(path: &Path, line: &str) -> std::io::Result<()> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)?
+        .write_all(line.as_bytes())
+}
+
+/// 非阻塞写入命名管道；没有读端或管道已满时丢弃
+#[cfg(unix)]
+fn write_fifo(path: &Path, line: &str) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(path);
+    let result = file.and_then(|mut f| f.write_all(line.as_bytes()));
+    match result {
+        Err(e) if e.raw_os_error() == Some(libc::ENXIO) || e.kind() == std::io::ErrorKind::WouldBlock => Ok(()),
+        other => other,
+    }
+}
+
+#[cfg(not(unix))]
+fn write_fifo(path: &Path, line: &str) -> std::io::Result<()> {
+    append(path, line)
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::messages::OutputEntry;
+
+    fn permission(content: &str) -> Event {
+        Event::Output(OutputEntry {
+            timestamp: String::new(),
+            update_type: OutputType::PermissionRequest,
+            content: content.into(),
+        })
+    }
+
+    fn notifier(config: NotifyConfig) -> (Notifier, mpsc::UnboundedSender<Event>, mpsc::UnboundedReceiver<Event>) {
+        let (tx, rx) = mpsc::unbounded_channel();
+        (Notifier::new("coder", &config, tx.downgrade()), tx, rx)
+    }
+
+    fn config(inbox: Option<&Path>, fifo: Option<&Path>) -> NotifyConfig {
+        NotifyConfig {
+            fifo: fifo.map(Path::to_path_buf),
+            inbox: inbox.map(Path::to_path_buf),
+            min_interval_secs: 60,
+        }
+    }
+
+    #[test]
+    fn notifies_permissions_and_turn_ends() {
+        let dir = tempfile::tempdir().unwrap();
+        let inbox = dir.path().join("inbox");
+        let (mut n, _tx, mut rx) = notifier(config(Some(&inbox), None));
+        n.observe(None, &permission("Permission auto-approved: ls"));
+        n.observe(None, &Event::Info { tag: "idle", message: "Ready".into() });
+        n.observe(None, &permission("Permission requested: rm -rf build (Waiting for approval)"));
+        n.observe(Some("review"), &Event::Info { tag: "done", message: "EndTurn".into() });
+
+        let Ok(Event::Info { tag: "notify", message }) = rx.try_recv() else { panic!("expected notify") };
+        assert_eq!(message, "Waiting for permission: rm -rf build");
+        let Ok(Event::Scoped(s, inner)) = rx.try_recv() else { panic!("expected scoped notify") };
+        assert_eq!(s, "review");
+        assert!(matches!(*inner, Event::Info { tag: "notify", ref message } if message == "Turn finished (EndTurn)"));
+        assert!(rx.try_recv().is_err());
+
+        let text = std::fs::read_to_string(&inbox).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].ends_with(" coder [permission] Waiting for permission: rm -rf build"), "{}", lines[0]);
+        assert!(lines[1].ends_with(" coder(review) [done] Turn finished (EndTurn)"), "{}", lines[1]);
+
+        // 关闭后不再通知
+        n.muted().set(true);
+        n.observe(None, &Event::Info { tag: "error", message: "boom".into() });
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn rate_limits_per_session_and_kind() {
+        let (mut n, _tx, _rx) = notifier(config(None, None));
+        let t0 = Instant::now();
+        let later = |s| t0 + Duration::from_secs(s);
+        assert_eq!(n.admit(None, Kind::Done, "a".into(), t0).as_deref(), Some("a"));
+        assert_eq!(n.admit(None, Kind::Done, "b".into(), later(10)), None);
+        assert_eq!(n.admit(None, Kind::Done, "c".into(), later(20)), None);
+        // 其它类别 / session 各自计时
+        assert!(n.admit(None, Kind::Permission, "p".into(), later(20)).is_some());
+        assert!(n.admit(Some("review"), Kind::Done, "r".into(), later(20)).is_some());
+        assert_eq!(n.admit(None, Kind::Done, "d".into(), later(61)).as_deref(), Some("d (+2 more)"));
+        assert_eq!(n.admit(None, Kind::Done, "e".into(), later(122)).as_deref(), Some("e"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fifo_without_reader_is_skipped() {
+        use std::io::Read;
+        use std::os::unix::fs::OpenOptionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let fifo = dir.path().join("notify.fifo");
+        let c = std::ffi::CString::new(fifo.to_str().unwrap()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(c.as_ptr(), 0o600) }, 0);
+
+        write_fifo(&fifo, "dropped\n").unwrap();
+        let mut reader = std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(&fifo)
+            .unwrap();
+        write_fifo(&fifo, "hello\n").unwrap();
+        let mut buf = String::new();
+        reader.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "hello\n");
+    }
+}
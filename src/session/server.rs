@@ -6,24 +6,40 @@ use std::time::Duration;
 
 use agent_client_protocol::{self as acp, Agent};
 use anyhow::{Context, Result};
+use futures::future::{FutureExt, LocalBoxFuture};
 #[cfg(unix)]
 use tokio::net::UnixListener;
 #[cfg(not(unix))]
 use tokio::net::TcpListener;
-use tokio::sync::mpsc;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::{broadcast, mpsc, oneshot};
 
-use crate::acp_client::team_client::PermissionDecision;
+use crate::acp_client::team_client::{permission_decided, token_usage, PendingPermission, PermissionDecision, SessionState};
 use crate::config::TeamConfig;
-use crate::session::agent::{spawn_agent, AgentHandle, AgentStatus};
-use crate::protocol::messages::{OutputEntry, OutputType, SessionRequest, SessionResponse};
+use crate::session::agent::{mcp_servers, spawn_agent, AgentHandle, AgentStatus, NamedSession, OutputRingBuffer};
+use crate::session::changes::ChangeTracker;
+use crate::session::hooks::{self, HookRunner};
+use crate::session::notify::Notifier;
+use crate::session::conn::{self, Followup, Reply};
+use crate::protocol::messages::{
+    Budget, DaemonRequest, OutputEntry, OutputType, SessionEvent, SessionRequest,
+    SessionResponse, WorktreeInfo, PROTOCOL_VERSION, VERSION,
+};
+use crate::protocol::remote::{Authenticator, RemoteListener};
 use crate::protocol::transport::{JsonLineReader, JsonLineWriter};
 
 const SHUTDOWN_TIMEOUT_SECS: u64 = 3;
+/// 每个 Subscribe 连接可积压的事件数，超出后丢弃最旧的并提示 lagged
+const EVENT_CAPACITY: usize = 1024;
+/// turn 进行中检查预算的间隔
+const BUDGET_CHECK: Duration = Duration::from_millis(500);
+/// 改动追踪默认关闭，提示开启方式
+const DISABLED_TRACKING: &str = "Change tracking is disabled (start the agent with AGENT_TEAM_TRACK_CHANGES=1)";
 
 #[cfg(unix)]
-type SessionStream = tokio::net::UnixStream;
+pub(crate) type SessionListener = UnixListener;
 #[cfg(not(unix))]
-type SessionStream = tokio::net::TcpStream;
+pub(crate) type SessionListener = TcpListener;
 
 // ==================== stdout 事件 ====================
 
@@ -32,6 +48,10 @@ pub(crate) enum Event {
     Output(OutputEntry),
     /// 系统生命周期事件
     Info { tag: &'static str, message: String },
+    /// 命名 session 的事件
+    Scoped(String, Box<Event>),
+    /// 之前的事件处理完、进行中的钩子结束后回复（进程退出前调用）
+    Flush(oneshot::Sender<()>),
 }
 
 fn now() -> String {
@@ -46,37 +66,22 @@ pub async fn run(
     config: TeamConfig,
     extra_args: Vec<String>,
     cwd: PathBuf,
+    worktree: Option<WorktreeInfo>,
 ) -> Result<()> {
     let sock_path = config.session_socket(&name);
     config.ensure_socket_dir()?;
     cleanup_socket(&sock_path);
 
     // 先 bind listener，让 socket 文件尽早可见
-    #[cfg(unix)]
-    let listener = UnixListener::bind(&sock_path)
-        .with_context(|| format!("Failed to bind: {}", sock_path.display()))?;
+    let listener = bind_listener(&sock_path).await?;
 
-    #[cfg(not(unix))]
-    let listener = {
-        let l = TcpListener::bind("127.0.0.1:0")
-            .await
-            .context("Failed to bind TCP")?;
-        let port = l.local_addr()?.port();
-        std::fs::write(&sock_path, port.to_string())
-            .with_context(|| format!("Failed to write port file: {}", sock_path.display()))?;
-        l
-    };
-
-    // 事件通道
+    // 事件通道 + stdout 打印
     let (event_tx, event_rx) = mpsc::unbounded_channel::<Event>();
-    let (output_tx, output_rx) = mpsc::unbounded_channel::<OutputEntry>();
-
-    // 桥接：TeamClient output → event 流
-    let bridge_tx = event_tx.clone();
-    tokio::task::spawn_local(bridge_output(output_rx, bridge_tx));
-
-    // stdout 打印
-    tokio::task::spawn_local(print_events(event_rx));
+    let events = event_broadcast();
+    let hooks = hooks::load_runner(&config, &name, &event_tx);
+    let notifier = Notifier::new(&name, &config.notify, event_tx.downgrade());
+    let notify_muted = notifier.muted();
+    tokio::task::spawn_local(print_events(event_rx, None, Some(events.downgrade()), hooks, Some(notifier)));
 
     event_tx
         .send(Event::Info {
@@ -89,7 +94,100 @@ pub async fn run(
         })
         .ok();
 
-    // spawn agent
+    // 远程监听在 agent 启动前 bind，地址 / 证书错误直接报告
+    let remote = RemoteListener::bind(&config.remote).await?;
+    if let Some(ref r) = remote {
+        event_tx
+            .send(Event::Info {
+                tag: "remote",
+                message: format!("Listening on {}", r.describe()),
+            })
+            .ok();
+    }
+
+    let handle = start_session(name, agent_type, &config, extra_args, cwd, worktree, &event_tx).await?;
+    handle.borrow_mut().events = Some(events);
+    handle.borrow_mut().notify_muted = notify_muted;
+    let config = Rc::new(config);
+    let (shutdown_tx, mut shutdown_rx) = mpsc::unbounded_channel::<()>();
+
+    // 主循环
+    loop {
+        tokio::select! {
+            result = listener.accept() => {
+                let (stream, _) = result.context("Accept failed")?;
+                let (read, write) = stream.into_split();
+                let h = Rc::clone(&handle);
+                let c = Rc::clone(&config);
+                let etx = event_tx.clone();
+                let stx = shutdown_tx.clone();
+                tokio::task::spawn_local(async move {
+                    let (reader, writer) = (JsonLineReader::new(read), JsonLineWriter::new(write));
+                    handle_connection(reader, writer, h, c, etx, stx, false).await;
+                });
+            }
+            Some((tcp, peer, auth)) = accept_remote(remote.as_ref()) => {
+                let h = Rc::clone(&handle);
+                let c = Rc::clone(&config);
+                let etx = event_tx.clone();
+                let stx = shutdown_tx.clone();
+                tokio::task::spawn_local(async move {
+                    match auth.handshake(tcp).await {
+                        Ok((reader, writer)) => {
+                            handle_connection(reader, writer, h, c, etx, stx, true).await;
+                        }
+                        Err(e) => {
+                            etx.send(Event::Info {
+                                tag: "remote",
+                                message: format!("Rejected {}: {:#}", peer, e),
+                            }).ok();
+                        }
+                    }
+                });
+            }
+            _ = shutdown_rx.recv() => {
+                event_tx.send(Event::Info {
+                    tag: "shutdown",
+                    message: "Remote request".into(),
+                }).ok();
+                break;
+            }
+            _ = signal_shutdown() => {
+                event_tx.send(Event::Info {
+                    tag: "shutdown",
+                    message: "Signal received".into(),
+                }).ok();
+                break;
+            }
+        }
+    }
+
+    stop_session(&handle, &config, &event_tx).await;
+    cleanup_socket(&sock_path);
+    event_tx
+        .send(Event::Info {
+            tag: "stopped",
+            message: "Socket cleaned".into(),
+        })
+        .ok();
+    flush_events(&event_tx).await;
+    Ok(())
+}
+
+/// spawn agent 并完成 ACP 初始化（独立 session 与 daemon 共用）
+pub(crate) async fn start_session(
+    name: String,
+    agent_type: String,
+    config: &TeamConfig,
+    extra_args: Vec<String>,
+    cwd: PathBuf,
+    worktree: Option<WorktreeInfo>,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> Result<Rc<RefCell<AgentHandle>>> {
+    // 桥接：TeamClient output → event 流
+    let (output_tx, output_rx) = mpsc::unbounded_channel();
+    tokio::task::spawn_local(bridge_output(output_rx, event_tx.clone()));
+
     let tc = config
         .agent_types
         .get(&agent_type)
@@ -103,17 +201,24 @@ pub async fn run(
         })
         .ok();
 
-    let handle = spawn_agent(
-        name.clone(),
+    let tracker = config.track_changes.then(|| {
+        Arc::new(std::sync::Mutex::new(ChangeTracker::new(
+            cwd.clone(),
+            config.snapshot_dir(&name),
+        )))
+    });
+    let mut handle = spawn_agent(
+        name,
         agent_type,
         tc,
         cwd,
         extra_args,
-        config.output_buffer_size,
-        config.auto_approve.clone(),
+        config,
         Some(output_tx),
     )
     .await?;
+    handle.worktree = worktree;
+    handle.changes = tracker;
 
     event_tx
         .send(Event::Info {
@@ -127,123 +232,227 @@ pub async fn run(
             message: "Ready".into(),
         })
         .ok();
+    Ok(Rc::new(RefCell::new(handle)))
+}
 
-    let handle = Rc::new(RefCell::new(handle));
-    let config = Rc::new(config);
-    let (shutdown_tx, mut shutdown_rx) = mpsc::unbounded_channel::<()>();
-
-    // 主循环
-    loop {
-        tokio::select! {
-            result = listener.accept() => {
-                let (stream, _) = result.context("Accept failed")?;
-                let h = Rc::clone(&handle);
-                let c = Rc::clone(&config);
-                let etx = event_tx.clone();
-                let stx = shutdown_tx.clone();
-                tokio::task::spawn_local(async move {
-                    handle_connection(stream, h, c, etx, stx).await;
-                });
-            }
-            _ = shutdown_rx.recv() => {
-                event_tx.send(Event::Info {
-                    tag: "shutdown",
-                    message: "Remote request".into(),
-                }).ok();
-                break;
-            }
-            _ = signal_shutdown() => {
-                event_tx.send(Event::Info {
-                    tag: "shutdown",
-                    message: "Signal received".into(),
-                }).ok();
-                break;
-            }
-        }
-    }
+/// Restart：session 级状态跨重启保留
+pub(crate) fn carry_over(old: &mut AgentHandle, new: &mut AgentHandle) {
+    new.worktree = old.worktree.take();
+    new.changes = old.changes.take();
+    new.events = old.events.take();
+    new.forked_from = old.forked_from.take();
+    new.forks = std::mem::take(&mut old.forks);
+    new.budget = std::mem::take(&mut old.budget);
+    new.usage.lock().unwrap().absorb(&old.total_usage());
+    // print_events 里的 Notifier 持有同一个开关
+    new.notify_muted = Rc::clone(&old.notify_muted);
+}
 
-    // 优雅关闭（take 销毁连接）
-    let (conn, sid, mut child) = {
+/// 优雅关闭 agent（take 销毁连接）并清理快照存储
+pub(crate) async fn stop_session(
+    handle: &Rc<RefCell<AgentHandle>>,
+    config: &TeamConfig,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) {
+    let (conn, sids, mut child, name) = {
         let mut h = handle.borrow_mut();
         h.set_status(AgentStatus::Stopping);
-        (h.acp_conn.take(), h.session_id.take(), h.child.take())
+        // 丢弃广播 sender，Subscribe 连接随之结束
+        h.events = None;
+        let named = std::mem::take(&mut h.sessions).into_values().map(|s| s.id);
+        let sids: Vec<_> = h.session_id.take().into_iter().chain(named).collect();
+        (h.acp_conn.take(), sids, h.child.take(), h.name.clone())
     };
-    if let (Some(conn), Some(sid)) = (conn, sid) {
-        let _ = conn.cancel(acp::CancelNotification::new(sid)).await;
+    if let Some(conn) = conn {
+        for sid in sids {
+            let _ = conn.cancel(acp::CancelNotification::new(sid)).await;
+        }
     }
     if let Some(ref mut child) = child {
-        shutdown_child(child, &event_tx).await;
+        shutdown_child(child, event_tx).await;
     }
+    let _ = std::fs::remove_dir_all(config.snapshot_dir(&name));
+}
 
-    cleanup_socket(&sock_path);
-    event_tx
-        .send(Event::Info {
-            tag: "stopped",
-            message: "Socket cleaned".into(),
-        })
-        .ok();
-    Ok(())
+// ==================== 事件订阅 ====================
+
+pub(crate) fn event_broadcast() -> broadcast::Sender<SessionEvent> {
+    broadcast::channel(EVENT_CAPACITY).0
+}
+
+/// Subscribe：回复 Ok，之后由连接层推送事件，直到客户端断开或 session 关闭
+pub(crate) fn subscribe(handle: &Rc<RefCell<AgentHandle>>) -> Reply {
+    let h = handle.borrow();
+    match h.events.as_ref() {
+        Some(tx) => (
+            SessionResponse::Ok {
+                message: format!("Subscribed to {}", h.name),
+            },
+            Followup::Subscribe {
+                agent_name: h.name.clone(),
+                rx: tx.subscribe(),
+            },
+        ),
+        None => (
+            SessionResponse::Error {
+                message: "Session is not accepting subscribers".into(),
+            },
+            Followup::None,
+        ),
+    }
+}
+
+/// 等待下一个远程 TCP 连接；未开启远程监听时永不返回（供 select! 使用）
+pub(crate) async fn accept_remote(
+    remote: Option<&RemoteListener>,
+) -> Option<(tokio::net::TcpStream, std::net::SocketAddr, Authenticator)> {
+    match remote {
+        Some(r) => r.accept().await.ok().map(|(tcp, peer)| (tcp, peer, r.authenticator())),
+        None => std::future::pending().await,
+    }
+}
+
+/// 先 bind 再返回，socket 文件（Windows 为端口文件）立即可见
+pub(crate) async fn bind_listener(sock_path: &Path) -> Result<SessionListener> {
+    #[cfg(unix)]
+    let listener = UnixListener::bind(sock_path)
+        .with_context(|| format!("Failed to bind: {}", sock_path.display()))?;
+
+    #[cfg(not(unix))]
+    let listener = {
+        let l = TcpListener::bind("127.0.0.1:0")
+            .await
+            .context("Failed to bind TCP")?;
+        let port = l.local_addr()?.port();
+        std::fs::write(sock_path, port.to_string())
+            .with_context(|| format!("Failed to write port file: {}", sock_path.display()))?;
+        l
+    };
+
+    Ok(listener)
 }
 
 // ==================== 连接处理 ====================
 
-async fn handle_connection(
-    stream: SessionStream,
+/// 本地连接收 SessionRequest，远程连接收按 name 寻址的 DaemonRequest
+pub(crate) async fn handle_connection<R, W>(
+    mut reader: JsonLineReader<R>,
+    writer: JsonLineWriter<W>,
     handle: Rc<RefCell<AgentHandle>>,
     config: Rc<TeamConfig>,
     event_tx: mpsc::UnboundedSender<Event>,
     shutdown_tx: mpsc::UnboundedSender<()>,
-) {
-    let (read, write) = stream.into_split();
-    let mut reader = JsonLineReader::new(read);
-    let mut writer = JsonLineWriter::new(write);
-
-    loop {
-        let req = match reader.read::<SessionRequest>().await {
-            Ok(Some(r)) => r,
-            Ok(None) => break,
-            Err(e) => {
-                event_tx
-                    .send(Event::Info {
-                        tag: "error",
-                        message: format!("Read error: {}", e),
-                    })
-                    .ok();
-                break;
-            }
+    remote: bool,
+) where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin + 'static,
+{
+    reader.set_max_frame(config.transport.max_frame);
+    let own = Rc::clone(&handle);
+    let etx = event_tx.clone();
+    let on_request = move |req: SessionRequest| {
+        dispatch(
+            Rc::clone(&handle),
+            Rc::clone(&config),
+            etx.clone(),
+            shutdown_tx.clone(),
+            req,
+        )
+    };
+    let result = if remote {
+        let on_remote = move |req: DaemonRequest| match remote_request(&own, req) {
+            Ok(req) => on_request(req),
+            Err(resp) => futures::future::ready((resp, Followup::None)).boxed_local(),
         };
+        conn::serve(reader, writer, on_remote).await
+    } else {
+        conn::serve(reader, writer, on_request).await
+    };
+    if let Err(e) = result {
+        event_tx
+            .send(Event::Info {
+                tag: "error",
+                message: format!("Read error: {}", e),
+            })
+            .ok();
+    }
+}
 
-        let is_shutdown = matches!(req, SessionRequest::Shutdown);
-        // GetStatus 是轮询心跳；Prompt 由 UserPrompt 事件覆盖
-        if !matches!(req, SessionRequest::GetStatus | SessionRequest::GetOutput { .. } | SessionRequest::Prompt { .. }) {
-            event_tx
-                .send(Event::Info {
-                    tag: "request",
-                    message: req.label().to_string(),
-                })
-                .ok();
+/// 处理一条 session 请求；Shutdown 回复后通知主循环退出
+fn dispatch(
+    handle: Rc<RefCell<AgentHandle>>,
+    config: Rc<TeamConfig>,
+    event_tx: mpsc::UnboundedSender<Event>,
+    shutdown_tx: mpsc::UnboundedSender<()>,
+    req: SessionRequest,
+) -> LocalBoxFuture<'static, Reply> {
+    async move {
+        if matches!(req, SessionRequest::Subscribe) {
+            return subscribe(&handle);
         }
 
+        log_request(&event_tx, &req);
+        let is_shutdown = matches!(req, SessionRequest::Shutdown);
         let resp = handle_request(&handle, &config, req, &event_tx).await;
+        let followup = if is_shutdown {
+            Followup::Close(async move { shutdown_tx.send(()).ok(); }.boxed_local())
+        } else {
+            Followup::None
+        };
+        (resp, followup)
+    }
+    .boxed_local()
+}
 
-        if writer.write(&resp).await.is_err() {
-            event_tx
-                .send(Event::Info {
-                    tag: "disconnected",
-                    message: "Client disconnected".into(),
-                })
-                .ok();
-            break;
-        }
-
-        if is_shutdown {
-            shutdown_tx.send(()).ok();
-            break;
-        }
+/// 打印收到的请求；GetStatus 是轮询心跳，Hello 每个连接一次，Prompt 由 UserPrompt 事件覆盖，
+/// 读输出 / plan 只读无副作用
+pub(crate) fn log_request(event_tx: &mpsc::UnboundedSender<Event>, req: &SessionRequest) {
+    let (inner, session) = match req {
+        SessionRequest::InSession { session, request } => (request.as_ref(), Some(session)),
+        r => (r, None),
+    };
+    if matches!(
+        inner,
+        SessionRequest::Hello { .. }
+            | SessionRequest::GetStatus
+            | SessionRequest::GetOutput { .. }
+            | SessionRequest::GetPlan
+            | SessionRequest::GetPermissions
+            | SessionRequest::Prompt { .. }
+    ) {
+        return;
     }
+    let event = Event::Info {
+        tag: "request",
+        message: inner.label().to_string(),
+    };
+    event_tx
+        .send(match session {
+            Some(s) => Event::Scoped(s.clone(), Box::new(event)),
+            None => event,
+        })
+        .ok();
 }
 
-// ==================== 请求分发 ====================
+/// 远程连接按 DaemonRequest 寻址：只接受发给本 agent 的请求，List 就地回复
+fn remote_request(
+    handle: &Rc<RefCell<AgentHandle>>,
+    req: DaemonRequest,
+) -> Result<SessionRequest, SessionResponse> {
+    let own = handle.borrow().name.clone();
+    match req {
+        DaemonRequest::Session { name, request } if name == own => Ok(request),
+        DaemonRequest::List => Err(SessionResponse::Agents {
+            agents: vec![handle.borrow().to_summary()],
+        }),
+        DaemonRequest::Session { name, .. } => Err(SessionResponse::Error {
+            message: format!("No agent named '{}' here (this is '{}')", name, own),
+        }),
+        _ => Err(SessionResponse::Error {
+            message: "Not a daemon: only session requests are accepted".into(),
+        }),
+    }
+}
 
 pub(crate) async fn handle_request(
     handle: &Rc<RefCell<AgentHandle>>,
@@ -252,57 +461,111 @@ pub(crate) async fn handle_request(
     event_tx: &mpsc::UnboundedSender<Event>,
 ) -> SessionResponse {
     match req {
+        SessionRequest::Hello { protocol, version } => {
+            if protocol > PROTOCOL_VERSION {
+                event_tx
+                    .send(Event::Info {
+                        tag: "protocol",
+                        message: format!(
+                            "Client agent-team {} (protocol {}) is newer than this session ({}); re-create the agent to upgrade",
+                            version, protocol, PROTOCOL_VERSION,
+                        ),
+                    })
+                    .ok();
+            }
+            SessionResponse::Hello {
+                protocol: PROTOCOL_VERSION,
+                version: VERSION.to_string(),
+                capabilities: SessionRequest::capabilities(),
+                max_frame: config.transport.max_frame,
+            }
+        }
+
         SessionRequest::GetStatus => {
             let h = handle.borrow();
             SessionResponse::Status {
-                summary: h.to_summary(),
+                summary: Box::new(h.to_summary()),
             }
         }
 
-        SessionRequest::Prompt { text, files } => {
-            // 忙碌时自动取消当前任务
-            if let Err(resp) = cancel_if_busy(handle, event_tx).await {
-                return resp;
-            }
-            // 前置校验
-            let h = handle.borrow();
-            if h.get_status() == AgentStatus::Running {
-                return SessionResponse::Error { message: "Agent is already running".into() };
-            }
-            if h.acp_conn.is_none() || h.session_id.is_none() {
-                return no_session();
-            }
-            drop(h);
-            // 提交 prompt
-            submit_prompt(handle, event_tx, text, files).await
+        SessionRequest::Prompt { text, files, chain } => {
+            prompt(handle, Target::default_of(handle), text, files, chain, event_tx).await
         }
 
         SessionRequest::GetOutput { last, agent_only } => {
-            let name = handle.borrow().name.clone();
-            let buf = handle.borrow().output_buffer.clone();
-            let mut entries = buf.lock().await.last_msgs(last);
-            if agent_only {
-                entries.retain(|e| !matches!(e.update_type, OutputType::UserPrompt));
-            }
-            SessionResponse::Output { agent_name: name, entries }
+            get_output(handle, &Target::default_of(handle), last, agent_only).await
         }
 
-        SessionRequest::Cancel => {
-            let (conn, sid) = clone_conn(handle);
-            let Some((conn, sid)) = conn.zip(sid) else {
-                return no_session();
-            };
-            let _ = conn.cancel(acp::CancelNotification::new(sid)).await;
-            event_tx.send(Event::Info { tag: "cancelled", message: "Cancel sent".into() }).ok();
-            SessionResponse::Ok { message: "Cancel sent".into() }
-        }
+        SessionRequest::Cancel => cancel(handle, &Target::default_of(handle), event_tx).await,
 
         SessionRequest::ApprovePermission => {
-            handle_permission(handle, event_tx, true).await
+            handle_permission(&Target::default_of(handle), event_tx, true).await
         }
 
         SessionRequest::DenyPermission => {
-            handle_permission(handle, event_tx, false).await
+            handle_permission(&Target::default_of(handle), event_tx, false).await
+        }
+
+        SessionRequest::NewSession { session } => {
+            new_session(handle, config, session, event_tx).await
+        }
+
+        SessionRequest::CloseSession { session } => {
+            close_session(handle, &session, event_tx).await
+        }
+
+        SessionRequest::InSession { session, request } => {
+            handle_in_session(handle, &session, *request, event_tx).await
+        }
+
+        SessionRequest::Seed { from, session_id, transcript } => {
+            seed(handle, config, from, session_id, transcript, event_tx).await
+        }
+
+        SessionRequest::LinkFork { fork } => {
+            let mut h = handle.borrow_mut();
+            if !h.forks.contains(&fork) {
+                h.forks.push(fork.clone());
+            }
+            SessionResponse::Ok {
+                message: format!("Linked fork '{}'", fork),
+            }
+        }
+
+        SessionRequest::GetPlan => get_plan(handle, &Target::default_of(handle)),
+
+        SessionRequest::SetBudget { max_tokens, max_cost, clear } => {
+            if max_cost.is_some_and(|c| c.is_nan() || c < 0.0) {
+                return SessionResponse::Error { message: "Cost budget must be a non-negative number".into() };
+            }
+            let mut h = handle.borrow_mut();
+            if clear {
+                h.budget = Budget::default();
+            }
+            if max_tokens.is_some() {
+                h.budget.max_tokens = max_tokens;
+            }
+            if max_cost.is_some() {
+                h.budget.max_cost = max_cost;
+            }
+            let message = format!("Budget for {}: {}", h.name, h.budget);
+            event_tx.send(Event::Info { tag: "budget", message: message.clone() }).ok();
+            SessionResponse::Ok { message }
+        }
+
+        SessionRequest::GetPermissions => get_permissions(handle).await,
+
+        SessionRequest::ResolvePermission { permission_id, approve } => {
+            resolve_permission(handle, permission_id, approve, event_tx).await
+        }
+
+        SessionRequest::SetNotify { enabled } => {
+            let h = handle.borrow();
+            h.notify_muted.set(!enabled);
+            let message = format!("Notifications for {} {}", h.name, if enabled { "on" } else { "off" });
+            // 不用 notify tag：开关本身不是通知
+            event_tx.send(Event::Info { tag: "notifications", message: message.clone() }).ok();
+            SessionResponse::Ok { message }
         }
 
         SessionRequest::Restart => {
@@ -328,8 +591,7 @@ pub(crate) async fn handle_request(
             }
 
             // 2. 新 output 桥接
-            let (new_output_tx, new_output_rx) =
-                mpsc::unbounded_channel::<OutputEntry>();
+            let (new_output_tx, new_output_rx) = mpsc::unbounded_channel();
             let bridge_tx = event_tx.clone();
             tokio::task::spawn_local(bridge_output(new_output_rx, bridge_tx));
 
@@ -353,13 +615,15 @@ pub(crate) async fn handle_request(
                 tc,
                 cwd,
                 extra_args,
-                config.output_buffer_size,
-                config.auto_approve.clone(),
+                config,
                 Some(new_output_tx),
             )
             .await
             {
-                Ok(new_handle) => {
+                Ok(mut new_handle) => {
+                    // 命名 session 随旧进程结束
+                    let closed = handle.borrow().sessions.len();
+                    carry_over(&mut handle.borrow_mut(), &mut new_handle);
                     *handle.borrow_mut() = new_handle;
                     event_tx
                         .send(Event::Info {
@@ -367,9 +631,11 @@ pub(crate) async fn handle_request(
                             message: "Agent restarted, idle".into(),
                         })
                         .ok();
-                    SessionResponse::Ok {
-                        message: "Agent restarted".into(),
-                    }
+                    let message = match closed {
+                        0 => "Agent restarted".to_string(),
+                        n => format!("Agent restarted ({} named session(s) closed)", n),
+                    };
+                    SessionResponse::Ok { message }
                 }
                 Err(e) => {
                     // S2: Restart 失败 → 状态标记为 Error，而非停留在 Stopping
@@ -381,10 +647,69 @@ pub(crate) async fn handle_request(
             }
         }
 
+        SessionRequest::GetChanges { turn, diff } => {
+            let (name, tracker) = {
+                let h = handle.borrow();
+                (h.name.clone(), h.changes.clone())
+            };
+            let Some(tracker) = tracker else {
+                return SessionResponse::Error { message: DISABLED_TRACKING.into() };
+            };
+            let result = tokio::task::spawn_blocking(move || {
+                tracker.lock().unwrap().turn_changes(turn, diff)
+            })
+            .await;
+            match result {
+                Ok(Ok(turns)) => SessionResponse::Changes { agent_name: name, turns },
+                Ok(Err(e)) => SessionResponse::Error { message: format!("{:#}", e) },
+                Err(e) => SessionResponse::Error { message: format!("{}", e) },
+            }
+        }
+
+        SessionRequest::Undo { turn } => {
+            let tracker = {
+                let h = handle.borrow();
+                if matches!(h.get_status(), AgentStatus::Running | AgentStatus::WaitingPermission) {
+                    return SessionResponse::Error {
+                        message: "Agent is busy; cancel the current turn first".into(),
+                    };
+                }
+                h.changes.clone()
+            };
+            let Some(tracker) = tracker else {
+                return SessionResponse::Error { message: DISABLED_TRACKING.into() };
+            };
+            let result = tokio::task::spawn_blocking(move || tracker.lock().unwrap().undo(turn)).await;
+            match result {
+                Ok(Ok(record)) => {
+                    let mut message = format!(
+                        "Reverted turn {} ({} file(s))",
+                        record.turn,
+                        record.changes.len(),
+                    );
+                    for c in &record.changes {
+                        message.push_str(&format!("\n  {} {}", c.kind().letter(), c.path));
+                    }
+                    event_tx.send(Event::Info {
+                        tag: "undo",
+                        message: format!("Reverted turn {}", record.turn),
+                    }).ok();
+                    SessionResponse::Ok { message }
+                }
+                Ok(Err(e)) => SessionResponse::Error { message: format!("{:#}", e) },
+                Err(e) => SessionResponse::Error { message: format!("{}", e) },
+            }
+        }
+
         SessionRequest::Shutdown => SessionResponse::Ok {
             message: "Session shutting down".into(),
         },
 
+        // 由连接层处理（接管整个连接），到这里说明调用方式不对
+        SessionRequest::Subscribe => SessionResponse::Error {
+            message: "Subscribe must be sent over a session connection".into(),
+        },
+
         SessionRequest::SetMode { mode } => {
             let msg = format!("Mode: {}", mode);
             acp_call(handle, event_tx, "mode", &msg, |conn, sid| {
@@ -407,31 +732,369 @@ pub(crate) async fn handle_request(
     }
 }
 
+// ==================== 目标 session ====================
+
+/// 请求作用的 ACP session：默认 session 或 `new --session` 创建的命名 session
+#[derive(Clone)]
+struct Target {
+    /// 命名 session 的 ACP id；默认 session 每次从 handle 读取（Restart 后会变）
+    sid: Option<acp::SessionId>,
+    state: SessionState,
+}
+
+impl Target {
+    fn default_of(handle: &Rc<RefCell<AgentHandle>>) -> Self {
+        Self { sid: None, state: handle.borrow().default_state() }
+    }
+
+    fn named(handle: &Rc<RefCell<AgentHandle>>, session: &str) -> Result<Self, SessionResponse> {
+        match handle.borrow().sessions.get(session) {
+            Some(s) => Ok(Self { sid: Some(s.id.clone()), state: s.state.clone() }),
+            None => Err(no_named_session(session)),
+        }
+    }
+
+    fn session_id(&self, handle: &Rc<RefCell<AgentHandle>>) -> Option<acp::SessionId> {
+        match self.state.name {
+            Some(_) => self.sid.clone(),
+            None => handle.borrow().session_id.clone(),
+        }
+    }
+
+    /// 命名 session 的事件包装为 Scoped
+    fn event(&self, event: Event) -> Event {
+        match &self.state.name {
+            Some(s) => Event::Scoped(s.clone(), Box::new(event)),
+            None => event,
+        }
+    }
+}
+
+fn no_named_session(session: &str) -> SessionResponse {
+    SessionResponse::Error {
+        message: format!("No session named '{}'", session),
+    }
+}
+
+async fn handle_in_session(
+    handle: &Rc<RefCell<AgentHandle>>,
+    session: &str,
+    req: SessionRequest,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
+    let target = match Target::named(handle, session) {
+        Ok(t) => t,
+        Err(resp) => return resp,
+    };
+    match req {
+        // 命名 session 的状态填入 summary（ask --session 轮询用）
+        SessionRequest::GetStatus => {
+            let mut summary = handle.borrow().to_summary();
+            if let Some(s) = summary.sessions.iter().find(|s| s.name == session).cloned() {
+                summary.status = s.status;
+                summary.prompt_count = s.prompt_count;
+                summary.pending_permissions = s.pending_permissions;
+            }
+            summary.delegation_chain = handle
+                .borrow()
+                .sessions
+                .get(session)
+                .map(|s| s.delegation_chain.clone())
+                .unwrap_or_default();
+            SessionResponse::Status { summary: Box::new(summary) }
+        }
+        SessionRequest::Prompt { text, files, chain } => {
+            prompt(handle, target, text, files, chain, event_tx).await
+        }
+        SessionRequest::GetOutput { last, agent_only } => {
+            get_output(handle, &target, last, agent_only).await
+        }
+        SessionRequest::Cancel => cancel(handle, &target, event_tx).await,
+        SessionRequest::ApprovePermission => handle_permission(&target, event_tx, true).await,
+        SessionRequest::DenyPermission => handle_permission(&target, event_tx, false).await,
+        SessionRequest::GetPlan => get_plan(handle, &target),
+        other => SessionResponse::Error {
+            message: format!("'{}' cannot be sent to a named session", other.label()),
+        },
+    }
+}
+
+/// 在同一 agent 进程中新建命名 ACP session
+async fn new_session(
+    handle: &Rc<RefCell<AgentHandle>>,
+    config: &TeamConfig,
+    session: String,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
+    if session.is_empty() || session.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        return SessionResponse::Error {
+            message: format!("Invalid session name '{}'", session),
+        };
+    }
+    let exists = || SessionResponse::Error {
+        message: format!("Session '{}' already exists", session),
+    };
+    let (conn, cwd, name) = {
+        let h = handle.borrow();
+        if h.sessions.contains_key(&session) {
+            return exists();
+        }
+        (h.acp_conn.clone(), h.cwd.clone(), h.name.clone())
+    };
+    let Some(conn) = conn else {
+        return no_session();
+    };
+    let req = acp::NewSessionRequest::new(&cwd).mcp_servers(mcp_servers(&name, Some(&session), config));
+    let id = match conn.new_session(req).await {
+        Ok(resp) => resp.session_id,
+        Err(e) => {
+            return SessionResponse::Error {
+                message: format!("ACP new_session() failed: {}", e),
+            }
+        }
+    };
+
+    let mut h = handle.borrow_mut();
+    // 等待期间同名 session 已被另一个请求创建
+    if h.sessions.contains_key(&session) {
+        return exists();
+    }
+    let state = SessionState::new(
+        Some(session.clone()),
+        OutputRingBuffer::with_limits(config.output_buffer_size, config.output_buffer_bytes),
+    );
+    h.routes.lock().unwrap().insert(id.clone(), state.clone());
+    h.sessions.insert(session.clone(), NamedSession { id, state, prompt_count: 0, delegation_chain: vec![] });
+    let message = format!("Session '{}' opened", session);
+    event_tx.send(Event::Info { tag: "session", message: message.clone() }).ok();
+    SessionResponse::Ok { message }
+}
+
+/// 关闭命名 session：取消进行中的 turn，拒绝待处理的权限请求
+async fn close_session(
+    handle: &Rc<RefCell<AgentHandle>>,
+    session: &str,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
+    let (removed, conn) = {
+        let mut h = handle.borrow_mut();
+        let removed = h.sessions.remove(session);
+        if let Some(s) = &removed {
+            h.routes.lock().unwrap().remove(&s.id);
+        }
+        (removed, h.acp_conn.clone())
+    };
+    let Some(closed) = removed else {
+        return no_named_session(session);
+    };
+    if matches!(closed.state.get_status(), AgentStatus::Running | AgentStatus::WaitingPermission) {
+        if let Some(conn) = conn {
+            let _ = conn.cancel(acp::CancelNotification::new(closed.id)).await;
+        }
+    }
+    drain_permissions(&closed.state.pending_permissions).await;
+    closed.state.set_status(AgentStatus::Stopping);
+    // 用量并入默认 session，关闭后仍计入预算
+    let usage = closed.state.usage.lock().unwrap().clone();
+    handle.borrow().usage.lock().unwrap().absorb(&usage);
+    let message = format!("Session '{}' closed", session);
+    event_tx.send(Event::Info { tag: "session", message: message.clone() }).ok();
+    SessionResponse::Ok { message }
+}
+
+// ==================== fork ====================
+
+/// 回放 prompt 最多保留的对话字节数（超出时丢弃最早的部分）
+const FORK_REPLAY_BYTES: usize = 32 * 1024;
+
+/// fork 的新 agent：agent 支持 session/load 时加载源 session，否则回放对话记录
+async fn seed(
+    handle: &Rc<RefCell<AgentHandle>>,
+    config: &TeamConfig,
+    from: String,
+    session_id: Option<String>,
+    transcript: Vec<OutputEntry>,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
+    let (conn, cwd, name, load) = {
+        let h = handle.borrow();
+        if h.prompt_count > 0 || h.forked_from.is_some() {
+            return SessionResponse::Error {
+                message: format!("'{}' already has a conversation; fork into a new agent", h.name),
+            };
+        }
+        (h.acp_conn.clone(), h.cwd.clone(), h.name.clone(), h.load_session)
+    };
+    let Some(conn) = conn else {
+        return no_session();
+    };
+    handle.borrow_mut().forked_from = Some(from.clone());
+
+    if let (true, Some(sid)) = (load, session_id) {
+        let sid = acp::SessionId::new(sid);
+        let req = acp::LoadSessionRequest::new(sid.clone(), &cwd).mcp_servers(mcp_servers(&name, None, config));
+        match conn.load_session(req).await {
+            Ok(_) => {
+                handle.borrow_mut().session_id = Some(sid.clone());
+                let message = format!("Loaded session {} from '{}'", sid, from);
+                event_tx.send(Event::Info { tag: "fork", message: message.clone() }).ok();
+                return SessionResponse::Ok { message };
+            }
+            Err(e) => {
+                let message = format!("session/load failed ({}), replaying transcript", e);
+                event_tx.send(Event::Info { tag: "fork", message }).ok();
+            }
+        }
+    }
+
+    let Some(text) = fork_prompt(&from, &transcript) else {
+        let message = format!("Forked from '{}' (no conversation to replay)", from);
+        event_tx.send(Event::Info { tag: "fork", message: message.clone() }).ok();
+        return SessionResponse::Ok { message };
+    };
+    match submit_prompt(handle, Target::default_of(handle), event_tx, text, vec![], vec![]).await {
+        SessionResponse::Ok { .. } => SessionResponse::Ok {
+            message: format!("Replaying conversation from '{}'", from),
+        },
+        other => other,
+    }
+}
+
+/// 把源对话（用户 prompt、agent 回复、工具调用）整理成一条回放 prompt；无内容时返回 None
+pub(crate) fn fork_prompt(from: &str, transcript: &[OutputEntry]) -> Option<String> {
+    let lines: Vec<String> = transcript
+        .iter()
+        .filter_map(|e| {
+            let role = match e.update_type {
+                OutputType::UserPrompt => "user",
+                OutputType::AgentMessage => "assistant",
+                OutputType::ToolCallStart => "tool",
+                _ => return None,
+            };
+            Some(format!("[{}] {}", role, e.content.trim()))
+        })
+        .collect();
+    if lines.is_empty() {
+        return None;
+    }
+    // 从最新往回取，保证最近的上下文完整
+    let mut kept = vec![];
+    let mut bytes = 0;
+    for line in lines.iter().rev() {
+        if bytes + line.len() > FORK_REPLAY_BYTES && !kept.is_empty() {
+            break;
+        }
+        bytes += line.len();
+        kept.push(line.as_str());
+    }
+    kept.reverse();
+    let omitted = if kept.len() < lines.len() {
+        format!("({} earlier message(s) omitted)\n", lines.len() - kept.len())
+    } else {
+        String::new()
+    };
+    Some(format!(
+        "This conversation is forked from agent '{}'. Below is the conversation so far; \
+         continue from this point. Reply only with a short acknowledgement for now.\n\n{}{}",
+        from,
+        omitted,
+        kept.join("\n\n"),
+    ))
+}
+
 // ==================== prompt 辅助 ====================
 
+async fn prompt(
+    handle: &Rc<RefCell<AgentHandle>>,
+    target: Target,
+    text: String,
+    files: Vec<crate::protocol::messages::FileAttachment>,
+    chain: Vec<String>,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
+    // 忙碌时自动取消当前任务
+    if let Err(resp) = cancel_if_busy(handle, &target, event_tx).await {
+        return resp;
+    }
+    // 前置校验
+    if target.state.get_status() == AgentStatus::Running {
+        return SessionResponse::Error { message: "Agent is already running".into() };
+    }
+    if handle.borrow().acp_conn.is_none() || target.session_id(handle).is_none() {
+        return no_session();
+    }
+    // 预算：全部 session 合计达到上限后拒绝新 prompt
+    let exceeded = handle.borrow().budget_exceeded();
+    if let Some(reason) = exceeded {
+        let name = handle.borrow().name.clone();
+        return SessionResponse::Error {
+            message: format!("{}: {} (raise it with `budget {} --tokens N --cost X`)", name, reason, name),
+        };
+    }
+    // 提交 prompt
+    submit_prompt(handle, target, event_tx, text, files, chain).await
+}
+
+async fn get_output(
+    handle: &Rc<RefCell<AgentHandle>>,
+    target: &Target,
+    last: usize,
+    agent_only: bool,
+) -> SessionResponse {
+    let name = handle.borrow().name.clone();
+    let mut entries = target.state.output_buffer.lock().await.last_msgs(last);
+    if agent_only {
+        entries.retain(|e| !matches!(e.update_type, OutputType::UserPrompt));
+    }
+    SessionResponse::Output { agent_name: name, entries }
+}
+
+/// 最新上报的 plan；未上报时条目为空
+fn get_plan(handle: &Rc<RefCell<AgentHandle>>, target: &Target) -> SessionResponse {
+    SessionResponse::Plan {
+        agent_name: handle.borrow().name.clone(),
+        entries: target.state.plan.lock().unwrap().clone(),
+    }
+}
+
+async fn cancel(
+    handle: &Rc<RefCell<AgentHandle>>,
+    target: &Target,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
+    let conn = handle.borrow().acp_conn.clone();
+    let Some((conn, sid)) = conn.zip(target.session_id(handle)) else {
+        return no_session();
+    };
+    let _ = conn.cancel(acp::CancelNotification::new(sid)).await;
+    event_tx.send(target.event(Event::Info { tag: "cancelled", message: "Cancel sent".into() })).ok();
+    SessionResponse::Ok { message: "Cancel sent".into() }
+}
+
 /// 忙碌时取消当前任务，等待 settle（5s 超时）
 async fn cancel_if_busy(
     handle: &Rc<RefCell<AgentHandle>>,
+    target: &Target,
     event_tx: &mpsc::UnboundedSender<Event>,
 ) -> Result<(), SessionResponse> {
-    let cur_status = handle.borrow().get_status();
+    let cur_status = target.state.get_status();
     if !matches!(cur_status, AgentStatus::Running | AgentStatus::WaitingPermission) {
         return Ok(());
     }
 
-    let (conn, sid) = clone_conn(handle);
-    if let (Some(conn), Some(sid)) = (conn, sid) {
+    let conn = handle.borrow().acp_conn.clone();
+    if let (Some(conn), Some(sid)) = (conn, target.session_id(handle)) {
         let _ = conn.cancel(acp::CancelNotification::new(sid)).await;
     }
 
-    let queue = handle.borrow().pending_permissions.clone();
-    drain_permissions(&queue).await;
-    event_tx.send(Event::Info { tag: "cancelled", message: "Auto-cancelled for new prompt".into() }).ok();
+    let queue = &target.state.pending_permissions;
+    drain_permissions(queue).await;
+    event_tx.send(target.event(Event::Info { tag: "cancelled", message: "Auto-cancelled for new prompt".into() })).ok();
 
     for _ in 0..50 {
         tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-        drain_permissions(&queue).await;
-        let s = handle.borrow().get_status();
+        drain_permissions(queue).await;
+        let s = target.state.get_status();
         if matches!(s, AgentStatus::Idle | AgentStatus::Error(_)) {
             return Ok(());
         }
@@ -451,26 +1114,40 @@ async fn drain_permissions(
 /// 记录 prompt + spawn 后台 do_prompt
 async fn submit_prompt(
     handle: &Rc<RefCell<AgentHandle>>,
+    target: Target,
     event_tx: &mpsc::UnboundedSender<Event>,
     text: String,
     files: Vec<crate::protocol::messages::FileAttachment>,
+    chain: Vec<String>,
 ) -> SessionResponse {
+    if !chain.is_empty() {
+        event_tx.send(target.event(Event::Info { tag: "delegated", message: format!("From {}", chain.join(" → ")) })).ok();
+    }
+    // 委派链记录在收到 prompt 的 session 上，MCP ask_agent 按调用方 session 读取
+    match &target.state.name {
+        None => handle.borrow_mut().delegation_chain = chain,
+        Some(s) => {
+            if let Some(named) = handle.borrow_mut().sessions.get_mut(s) {
+                named.delegation_chain = chain;
+            }
+        }
+    }
     let user_entry = OutputEntry {
         timestamp: chrono::Utc::now().to_rfc3339(),
         update_type: OutputType::UserPrompt,
         content: text.clone(),
     };
-    let buf = handle.borrow().output_buffer.clone();
-    buf.lock().await.push(user_entry.clone());
-    event_tx.send(Event::Output(user_entry)).ok();
+    target.state.output_buffer.lock().await.push(user_entry.clone());
+    event_tx.send(target.event(Event::Output(user_entry))).ok();
 
+    let text_summary = text.clone();
     let mut blocks: Vec<acp::ContentBlock> = vec![text.into()];
     for f in &files {
         blocks.push(format!("--- {} ---\n{}", f.path.display(), f.content).into());
     }
     let h = Rc::clone(handle);
     let etx = event_tx.clone();
-    tokio::task::spawn_local(async move { do_prompt(&h, blocks, &etx).await; });
+    tokio::task::spawn_local(async move { do_prompt(&h, target, blocks, text_summary, &etx).await; });
     SessionResponse::Ok { message: "Prompt submitted".into() }
 }
 
@@ -502,63 +1179,210 @@ where
 
 async fn do_prompt(
     handle: &Rc<RefCell<AgentHandle>>,
+    target: Target,
     prompt_blocks: Vec<acp::ContentBlock>,
+    prompt_text: String,
     event_tx: &mpsc::UnboundedSender<Event>,
 ) {
-    let (conn, sid, buf) = {
+    let state = &target.state;
+    let (conn, sid, tracker) = {
         let mut h = handle.borrow_mut();
         // S3: 优雅检查，避免与 Restart 交错时 panic
         let Some(conn) = h.acp_conn.as_ref().map(Rc::clone) else {
-            h.set_status(AgentStatus::Error("No ACP connection".into()));
-            event_tx.send(Event::Info { tag: "error", message: "No ACP connection in do_prompt".into() }).ok();
+            state.set_status(AgentStatus::Error("No ACP connection".into()));
+            event_tx.send(target.event(Event::Info { tag: "error", message: "No ACP connection in do_prompt".into() })).ok();
             return;
         };
-        let Some(sid) = h.session_id.clone() else {
-            h.set_status(AgentStatus::Error("No session ID".into()));
-            event_tx.send(Event::Info { tag: "error", message: "No session ID in do_prompt".into() }).ok();
+        let sid = match &state.name {
+            Some(_) => target.sid.clone(),
+            None => h.session_id.clone(),
+        };
+        let Some(sid) = sid else {
+            state.set_status(AgentStatus::Error("No session ID".into()));
+            event_tx.send(target.event(Event::Info { tag: "error", message: "No session ID in do_prompt".into() })).ok();
             return;
         };
-        h.set_status(AgentStatus::Running);
-        h.prompt_count += 1;
-        (conn, sid, Arc::clone(&h.output_buffer))
+        state.set_status(AgentStatus::Running);
+        // 改动追踪按 turn 快照 cwd，只跟踪默认 session
+        let tracker = match &state.name {
+            Some(name) => {
+                if let Some(s) = h.sessions.get_mut(name) {
+                    s.prompt_count += 1;
+                }
+                None
+            }
+            None => {
+                h.prompt_count += 1;
+                h.changes.clone()
+            }
+        };
+        (conn, sid, tracker)
+    };
+    event_tx.send(target.event(Event::Info { tag: "running", message: "Processing".into() })).ok();
+
+    // turn 前快照（完成后才把 prompt 交给 agent）
+    if let Some(t) = &tracker {
+        run_tracker(t, event_tx, move |t| t.begin_turn(&prompt_text)).await;
+    }
+
+    let input_chars = prompt_blocks
+        .iter()
+        .map(|b| match b {
+            acp::ContentBlock::Text(t) => t.text.chars().count(),
+            _ => 0,
+        })
+        .sum();
+    state.usage.lock().unwrap().begin_turn(input_chars);
+
+    // turn 进行中超出预算即取消（agent 收到 cancel 后自行结束 turn）
+    let mut prompt = std::pin::pin!(conn.prompt(acp::PromptRequest::new(sid.clone(), prompt_blocks)));
+    let mut over_budget = false;
+    let result = loop {
+        tokio::select! {
+            r = &mut prompt => break r,
+            _ = tokio::time::sleep(BUDGET_CHECK), if !over_budget => {
+                let exceeded = handle.borrow().budget_exceeded();
+                if let Some(reason) = exceeded {
+                    over_budget = true;
+                    let _ = conn.cancel(acp::CancelNotification::new(sid.clone())).await;
+                    let message = format!("{}, turn cancelled", reason);
+                    event_tx.send(target.event(Event::Info { tag: "budget", message })).ok();
+                }
+            }
+        }
     };
-    event_tx.send(Event::Info { tag: "running", message: "Processing".into() }).ok();
 
-    let result = conn.prompt(acp::PromptRequest::new(sid, prompt_blocks)).await;
+    // 无论成功与否都记账：失败的 turn 同样消耗了 token
+    let reported = result.as_ref().ok().and_then(|r| r.usage.as_ref()).map(token_usage);
+    let turn = state.usage.lock().unwrap().end_turn(reported);
+    state.output_buffer.lock().await.push(OutputEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        update_type: OutputType::Usage,
+        content: turn.to_string(),
+    });
+    event_tx.send(target.event(Event::Info { tag: "usage", message: turn.to_string() })).ok();
+
+    // turn 后快照：在状态回到 idle 之前完成，ask 返回后即可查询
+    if let Some(t) = &tracker {
+        if let Some(Some((turn, n))) = run_tracker(t, event_tx, |t| t.end_turn()).await {
+            if n > 0 {
+                let msg = format!("Turn {}: {} file(s) changed", turn, n);
+                event_tx.send(Event::Info { tag: "changes", message: msg }).ok();
+            }
+        }
+    }
     match result {
         Ok(resp) => {
-            buf.lock().await.push(OutputEntry {
+            state.output_buffer.lock().await.push(OutputEntry {
                 timestamp: chrono::Utc::now().to_rfc3339(),
                 update_type: OutputType::PromptResponse,
                 content: format!("{:?}", resp.stop_reason),
             });
             let msg = format!("{:?}", resp.stop_reason);
-            event_tx.send(Event::Info { tag: "done", message: msg }).ok();
-            handle.borrow().set_status(AgentStatus::Idle);
+            event_tx.send(target.event(Event::Info { tag: "done", message: msg })).ok();
+            state.set_status(AgentStatus::Idle);
         }
         Err(e) => {
-            handle.borrow().set_status(AgentStatus::Error(format!("{}", e)));
-            event_tx.send(Event::Info { tag: "error", message: format!("Prompt failed: {}", e) }).ok();
+            state.set_status(AgentStatus::Error(format!("{}", e)));
+            event_tx.send(target.event(Event::Info { tag: "error", message: format!("Prompt failed: {}", e) })).ok();
             return;
         }
     }
-    event_tx.send(Event::Info { tag: "idle", message: "Ready".into() }).ok();
+    event_tx.send(target.event(Event::Info { tag: "idle", message: "Ready".into() })).ok();
+}
+
+/// 在阻塞线程上执行快照；失败只记录事件，不影响 prompt
+async fn run_tracker<T, F>(
+    tracker: &Arc<std::sync::Mutex<ChangeTracker>>,
+    event_tx: &mpsc::UnboundedSender<Event>,
+    f: F,
+) -> Option<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut ChangeTracker) -> Result<T> + Send + 'static,
+{
+    let t = Arc::clone(tracker);
+    let result = tokio::task::spawn_blocking(move || f(&mut t.lock().unwrap())).await;
+    match result {
+        Ok(Ok(v)) => Some(v),
+        Ok(Err(e)) => {
+            let msg = format!("Change tracking failed: {:#}", e);
+            event_tx.send(Event::Info { tag: "changes", message: msg }).ok();
+            None
+        }
+        Err(e) => {
+            let msg = format!("Change tracking failed: {}", e);
+            event_tx.send(Event::Info { tag: "changes", message: msg }).ok();
+            None
+        }
+    }
 }
 
 // ==================== 连接辅助 ====================
 
 async fn handle_permission(
-    handle: &Rc<RefCell<AgentHandle>>,
+    target: &Target,
     event_tx: &mpsc::UnboundedSender<Event>,
     approve: bool,
 ) -> SessionResponse {
-    let queue = handle.borrow().pending_permissions.clone();
-    let mut q = queue.lock().await;
-    let Some(perm) = q.pop_front() else {
+    let perm = target.state.pending_permissions.lock().await.pop_front();
+    let Some(perm) = perm else {
         return SessionResponse::Error {
             message: "No pending permissions".into(),
         };
     };
+    decide(target, perm, approve, event_tx).await
+}
+
+/// 全部 session 中的待审批权限，按请求先后排列
+async fn get_permissions(handle: &Rc<RefCell<AgentHandle>>) -> SessionResponse {
+    let mut pending = vec![];
+    for target in all_targets(handle) {
+        let q = target.state.pending_permissions.lock().await;
+        pending.extend(q.iter().map(|p| p.info(target.state.name.clone())));
+    }
+    pending.sort_by_key(|p| p.id);
+    SessionResponse::Permissions {
+        agent_name: handle.borrow().name.clone(),
+        pending,
+    }
+}
+
+/// 按 id 审批，不论权限请求在哪个 session
+async fn resolve_permission(
+    handle: &Rc<RefCell<AgentHandle>>,
+    id: u64,
+    approve: bool,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
+    for target in all_targets(handle) {
+        let perm = {
+            let mut q = target.state.pending_permissions.lock().await;
+            q.iter().position(|p| p.id == id).and_then(|i| q.remove(i))
+        };
+        if let Some(perm) = perm {
+            return decide(&target, perm, approve, event_tx).await;
+        }
+    }
+    SessionResponse::Error {
+        message: format!("No pending permission #{}", id),
+    }
+}
+
+fn all_targets(handle: &Rc<RefCell<AgentHandle>>) -> Vec<Target> {
+    let h = handle.borrow();
+    std::iter::once(Target { sid: None, state: h.default_state() })
+        .chain(h.sessions.values().map(|s| Target { sid: Some(s.id.clone()), state: s.state.clone() }))
+        .collect()
+}
+
+/// 审批结果同时写入输出缓冲，log / export 中可见
+async fn decide(
+    target: &Target,
+    perm: PendingPermission,
+    approve: bool,
+    event_tx: &mpsc::UnboundedSender<Event>,
+) -> SessionResponse {
     let info = perm.tool_info.clone();
     let (decision, tag) = if approve {
         (PermissionDecision::Approve, "approved")
@@ -566,7 +1390,12 @@ async fn handle_permission(
         (PermissionDecision::Deny, "denied")
     };
     let _ = perm.response_tx.send(decision);
-    event_tx.send(Event::Info { tag, message: info.clone() }).ok();
+    target.state.output_buffer.lock().await.push(OutputEntry {
+        timestamp: chrono::Utc::now().to_rfc3339(),
+        update_type: OutputType::PermissionRequest,
+        content: permission_decided(&info, approve),
+    });
+    event_tx.send(target.event(Event::Info { tag, message: info.clone() })).ok();
     SessionResponse::Ok {
         message: format!("{}: {}", if approve { "Approved" } else { "Denied" }, info),
     }
@@ -588,20 +1417,96 @@ pub(crate) fn no_session() -> SessionResponse {
 // ==================== stdout 打印 ====================
 
 async fn bridge_output(
-    mut rx: mpsc::UnboundedReceiver<OutputEntry>,
+    mut rx: mpsc::UnboundedReceiver<(Option<String>, OutputEntry)>,
     tx: mpsc::UnboundedSender<Event>,
 ) {
-    while let Some(entry) = rx.recv().await {
-        tx.send(Event::Output(entry)).ok();
+    while let Some((session, entry)) = rx.recv().await {
+        let event = match session {
+            Some(s) => Event::Scoped(s, Box::new(Event::Output(entry))),
+            None => Event::Output(entry),
+        };
+        tx.send(event).ok();
     }
 }
 
-async fn print_events(mut rx: mpsc::UnboundedReceiver<Event>) {
-    use std::io::Write;
+/// 等待 print_events 处理完已发送的事件及其触发的钩子
+pub(crate) async fn flush_events(event_tx: &mpsc::UnboundedSender<Event>) {
+    let (tx, rx) = oneshot::channel();
+    if event_tx.send(Event::Flush(tx)).is_ok() {
+        let _ = rx.await;
+    }
+}
+
+/// prefix：daemon 模式下多个 agent 共用 stdout，每行加 agent 名前缀；
+/// subscribers：同时转发给 Subscribe 连接（弱引用，session 关闭后自然失效）；
+/// hooks / notifier：用户配置的事件钩子与通知
+pub(crate) async fn print_events(
+    mut rx: mpsc::UnboundedReceiver<Event>,
+    prefix: Option<String>,
+    subscribers: Option<broadcast::WeakSender<SessionEvent>>,
+    mut hooks: Option<HookRunner>,
+    mut notifier: Option<Notifier>,
+) {
+    use std::io::{IsTerminal, Write};
+    let bell = std::io::stdout().is_terminal();
+    let pre = prefix.map(|p| format!("{} ", p)).unwrap_or_default();
     let mut needs_newline = false;
     let mut in_message = false;
 
     while let Some(event) = rx.recv().await {
+        let (session, event) = match event {
+            Event::Flush(done) => {
+                if let Some(h) = hooks.as_mut() {
+                    h.wait().await;
+                }
+                let _ = done.send(());
+                continue;
+            }
+            Event::Scoped(s, inner) => (Some(s), *inner),
+            e => (None, e),
+        };
+        if let Some(h) = hooks.as_mut() {
+            h.observe(session.as_deref(), &event);
+        }
+        if let Some(n) = notifier.as_mut() {
+            n.observe(session.as_deref(), &event);
+        }
+        // 前台 session 的终端也是一个关注者
+        if bell && matches!(event, Event::Info { tag: "notify", .. }) {
+            print!("\x07");
+        }
+        if let Some(tx) = subscribers.as_ref().and_then(|w| w.upgrade()) {
+            if tx.receiver_count() > 0 {
+                let _ = tx.send(match &event {
+                    Event::Output(entry) => SessionEvent::Output {
+                        entry: entry.clone(),
+                        session: session.clone(),
+                    },
+                    Event::Info { tag, message } => SessionEvent::Info {
+                        tag: tag.to_string(),
+                        message: message.clone(),
+                        session: session.clone(),
+                    },
+                    Event::Scoped(..) | Event::Flush(_) => continue,
+                });
+            }
+        }
+        // 命名 session：不打断默认 session 的流式输出，只打印非 chunk 条目
+        if let Some(session) = session {
+            let line = match &event {
+                Event::Output(entry) if is_chunk(&entry.update_type) => continue,
+                Event::Output(entry) => format!("[{}] {}", entry.update_type.label(), entry.content.trim()),
+                Event::Info { tag, message } => format!("[{}] {}", tag, message),
+                Event::Scoped(..) | Event::Flush(_) => continue,
+            };
+            in_message = false;
+            if needs_newline {
+                println!();
+                needs_newline = false;
+            }
+            println!("{} {}({}) {}", now(), pre, session, line);
+            continue;
+        }
         match event {
             Event::Output(entry) => match entry.update_type {
                 OutputType::UserPrompt => {
@@ -610,7 +1515,7 @@ async fn print_events(mut rx: mpsc::UnboundedReceiver<Event>) {
                         println!();
                         needs_newline = false;
                     }
-                    println!("{} [request] Prompt:\n{}", now(), entry.content.trim());
+                    println!("{} {}[request] Prompt:\n{}", now(), pre, entry.content.trim());
                 }
                 OutputType::AgentMessage | OutputType::AgentThought => {
                     // 新消息段的第一个 chunk，去掉前导空白
@@ -620,6 +1525,9 @@ async fn print_events(mut rx: mpsc::UnboundedReceiver<Event>) {
                         &entry.content
                     };
                     if !text.is_empty() {
+                        if !in_message && !pre.is_empty() {
+                            print!("{}| ", pre);
+                        }
                         print!("{}", text);
                         std::io::stdout().flush().ok();
                         needs_newline = !text.ends_with('\n');
@@ -633,8 +1541,9 @@ async fn print_events(mut rx: mpsc::UnboundedReceiver<Event>) {
                         needs_newline = false;
                     }
                     println!(
-                        "{} [{}] {}",
+                        "{} {}[{}] {}",
                         now(),
+                        pre,
                         entry.update_type.label(),
                         entry.content,
                     );
@@ -646,15 +1555,20 @@ async fn print_events(mut rx: mpsc::UnboundedReceiver<Event>) {
                     println!();
                     needs_newline = false;
                 }
-                println!("{} [{}] {}", now(), tag, message);
+                println!("{} {}[{}] {}", now(), pre, tag, message);
             }
+            Event::Scoped(..) | Event::Flush(_) => {}
         }
     }
 }
 
+fn is_chunk(t: &OutputType) -> bool {
+    matches!(t, OutputType::AgentMessage | OutputType::AgentThought)
+}
+
 // ==================== 关闭 & 工具 ====================
 
-async fn signal_shutdown() {
+pub(crate) async fn signal_shutdown() {
     #[cfg(unix)]
     {
         use tokio::signal::unix::{signal, SignalKind};
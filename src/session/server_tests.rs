@@ -7,11 +7,19 @@ use std::time::Instant;
 
 use tokio::sync::mpsc;
 
-use crate::acp_client::team_client::{PendingPermission, PermissionDecision};
+use agent_client_protocol as acp;
+
+use crate::acp_client::team_client::{PendingPermission, PermissionDecision, SessionState};
 use crate::config::TeamConfig;
-use crate::protocol::messages::{OutputEntry, OutputType, SessionRequest, SessionResponse};
-use crate::session::agent::{AgentHandle, AgentStatus, OutputRingBuffer};
-use crate::session::server::{cleanup_socket, handle_request, no_session, Event};
+use crate::protocol::messages::{
+    OutputEntry, OutputType, PlanEntry, PlanPriority, PlanStatus, SessionRequest, SessionResponse,
+    PROTOCOL_VERSION, VERSION,
+};
+use crate::session::agent::{AgentHandle, AgentStatus, NamedSession, OutputRingBuffer};
+use crate::session::notify::Notifier;
+use crate::session::server::{
+    carry_over, cleanup_socket, fork_prompt, handle_connection, handle_request, no_session, Event,
+};
 
 fn stub_handle(name: &str) -> Rc<RefCell<AgentHandle>> {
     Rc::new(RefCell::new(AgentHandle {
@@ -23,11 +31,24 @@ fn stub_handle(name: &str) -> Rc<RefCell<AgentHandle>> {
         started_at: Instant::now(),
         output_buffer: Arc::new(tokio::sync::Mutex::new(OutputRingBuffer::new(100))),
         pending_permissions: Arc::new(tokio::sync::Mutex::new(VecDeque::new())),
+        plan: Default::default(),
+        usage: Default::default(),
+        budget: Default::default(),
+        notify_muted: Default::default(),
         prompt_count: 0,
         session_id: None,
         acp_conn: None,
         child: None,
         agent_info: None,
+        delegation_chain: vec![],
+        worktree: None,
+        changes: None,
+        events: None,
+        sessions: Default::default(),
+        routes: Default::default(),
+        load_session: false,
+        forked_from: None,
+        forks: vec![],
     }))
 }
 
@@ -57,7 +78,7 @@ async fn prompt_no_connection() {
     let etx = test_event_tx();
     let resp = handle_request(
         &h, &config,
-        SessionRequest::Prompt { text: "hello".into(), files: vec![] },
+        SessionRequest::Prompt { text: "hello".into(), files: vec![], chain: vec![] },
         &etx,
     ).await;
     assert!(matches!(resp, SessionResponse::Error { .. }));
@@ -159,6 +180,122 @@ async fn get_output_with_entries() {
     }
 }
 
+#[tokio::test]
+async fn get_plan_and_progress() {
+    let h = stub_handle("test");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    match handle_request(&h, &config, SessionRequest::GetPlan, &etx).await {
+        SessionResponse::Plan { agent_name, entries } => {
+            assert_eq!(agent_name, "test");
+            assert!(entries.is_empty());
+        }
+        _ => panic!("expected Plan"),
+    }
+    assert!(h.borrow().to_summary().plan.is_none());
+
+    *h.borrow().plan.lock().unwrap() = vec![
+        PlanEntry { content: "a".into(), priority: PlanPriority::High, status: PlanStatus::Completed },
+        PlanEntry { content: "b".into(), priority: PlanPriority::Low, status: PlanStatus::Pending },
+    ];
+    match handle_request(&h, &config, SessionRequest::GetPlan, &etx).await {
+        SessionResponse::Plan { entries, .. } => assert_eq!(entries[1].content, "b"),
+        _ => panic!("expected Plan"),
+    }
+    assert_eq!(h.borrow().to_summary().plan.unwrap().to_string(), "1/2 done");
+
+    // 命名 session 的 plan 独立于默认 session
+    let state = SessionState::new(Some("review".into()), OutputRingBuffer::new(10));
+    h.borrow_mut().sessions.insert(
+        "review".into(),
+        NamedSession { id: acp::SessionId::new("s-2"), state, prompt_count: 0, delegation_chain: vec![] },
+    );
+    let resp = handle_request(&h, &config, SessionRequest::GetPlan.in_session(Some("review")), &etx).await;
+    assert!(matches!(resp, SessionResponse::Plan { entries, .. } if entries.is_empty()));
+    assert!(h.borrow().to_summary().sessions[0].plan.is_none());
+}
+
+#[tokio::test]
+async fn set_budget_and_usage_summary() {
+    let h = stub_handle("test");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    assert!(h.borrow().to_summary().usage.is_none());
+
+    let req = SessionRequest::SetBudget { max_tokens: Some(100), max_cost: None, clear: false };
+    match handle_request(&h, &config, req, &etx).await {
+        SessionResponse::Ok { message } => assert_eq!(message, "Budget for test: 100 tokens"),
+        _ => panic!("expected Ok"),
+    }
+    let req = SessionRequest::SetBudget { max_tokens: None, max_cost: Some(-1.0), clear: false };
+    assert!(matches!(handle_request(&h, &config, req, &etx).await, SessionResponse::Error { .. }));
+    assert!(h.borrow().budget_exceeded().is_none());
+
+    // 命名 session 的用量计入 agent 合计
+    let state = SessionState::new(Some("review".into()), OutputRingBuffer::new(10));
+    state.usage.lock().unwrap().begin_turn(240);
+    state.usage.lock().unwrap().end_turn(None);
+    h.borrow_mut().sessions.insert(
+        "review".into(),
+        NamedSession { id: acp::SessionId::new("s-2"), state, prompt_count: 1, delegation_chain: vec![] },
+    );
+    h.borrow().usage.lock().unwrap().begin_turn(160);
+    assert_eq!(h.borrow().budget_exceeded().unwrap(), "token budget reached (100 / 100)");
+    let summary = h.borrow().to_summary();
+    let usage = summary.usage.unwrap();
+    assert_eq!((usage.tokens.total, usage.turns), (60, 1));
+    assert_eq!(summary.sessions[0].usage.as_ref().unwrap().tokens.total, 60);
+
+    // 关闭命名 session 后用量并入默认 session
+    let req = SessionRequest::CloseSession { session: "review".into() };
+    assert!(matches!(handle_request(&h, &config, req, &etx).await, SessionResponse::Ok { .. }));
+    assert_eq!(h.borrow().usage.lock().unwrap().turns, 1);
+
+    let req = SessionRequest::SetBudget { max_tokens: None, max_cost: None, clear: true };
+    handle_request(&h, &config, req, &etx).await;
+    assert!(h.borrow().budget_exceeded().is_none());
+}
+
+#[tokio::test]
+async fn set_notify_toggles_shared_switch() {
+    let h = stub_handle("test");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    let muted = h.borrow().notify_muted.clone();
+
+    let req = SessionRequest::SetNotify { enabled: false };
+    match handle_request(&h, &config, req, &etx).await {
+        SessionResponse::Ok { message } => assert_eq!(message, "Notifications for test off"),
+        _ => panic!("expected Ok"),
+    }
+    assert!(muted.get());
+    assert!(h.borrow().to_summary().notify_muted);
+
+    handle_request(&h, &config, SessionRequest::SetNotify { enabled: true }, &etx).await;
+    assert!(!muted.get());
+}
+
+#[tokio::test]
+async fn notify_switch_survives_restart() {
+    let h = stub_handle("test");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    let notifier = Notifier::new("test", &config.notify, etx.downgrade());
+    h.borrow_mut().notify_muted = notifier.muted();
+
+    handle_request(&h, &config, SessionRequest::SetNotify { enabled: false }, &etx).await;
+    // Restart 换上新 handle
+    let mut new_handle = Rc::try_unwrap(stub_handle("test")).ok().unwrap().into_inner();
+    carry_over(&mut h.borrow_mut(), &mut new_handle);
+    *h.borrow_mut() = new_handle;
+    assert!(h.borrow().to_summary().notify_muted);
+    assert!(notifier.muted().get());
+
+    handle_request(&h, &config, SessionRequest::SetNotify { enabled: true }, &etx).await;
+    assert!(!notifier.muted().get());
+    assert!(!h.borrow().to_summary().notify_muted);
+}
+
 #[tokio::test]
 async fn get_output_agent_only() {
     let h = stub_handle("test");
@@ -226,10 +363,7 @@ async fn approve_with_pending() {
     let (tx, rx) = tokio::sync::oneshot::channel();
     {
         let queue = h.borrow().pending_permissions.clone();
-        queue.lock().await.push_back(PendingPermission {
-            tool_info: "edit /tmp/a.txt".into(),
-            response_tx: tx,
-        });
+        queue.lock().await.push_back(PendingPermission::new("edit /tmp/a.txt".into(), None, tx));
     }
     let config = TeamConfig::default();
     let etx = test_event_tx();
@@ -250,10 +384,7 @@ async fn deny_with_pending() {
     let (tx, rx) = tokio::sync::oneshot::channel();
     {
         let queue = h.borrow().pending_permissions.clone();
-        queue.lock().await.push_back(PendingPermission {
-            tool_info: "rm /tmp/danger".into(),
-            response_tx: tx,
-        });
+        queue.lock().await.push_back(PendingPermission::new("rm /tmp/danger".into(), None, tx));
     }
     let config = TeamConfig::default();
     let etx = test_event_tx();
@@ -266,6 +397,49 @@ async fn deny_with_pending() {
     }
     let decision = rx.await.unwrap();
     assert!(matches!(decision, PermissionDecision::Deny));
+
+    // 审批结果写入输出缓冲
+    let resp = handle_request(&h, &config, SessionRequest::GetOutput { last: 0, agent_only: false }, &etx).await;
+    let SessionResponse::Output { entries, .. } = resp else { panic!("expected Output") };
+    assert_eq!(entries.last().unwrap().content, "Permission denied: rm /tmp/danger");
+}
+
+#[tokio::test]
+async fn list_and_resolve_permissions_by_id() {
+    let h = stub_handle("test");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    let state = SessionState::new(Some("review".into()), OutputRingBuffer::new(10));
+    h.borrow_mut().sessions.insert(
+        "review".into(),
+        NamedSession { id: acp::SessionId::new("s-2"), state: state.clone(), prompt_count: 0, delegation_chain: vec![] },
+    );
+    let (tx_a, rx_a) = tokio::sync::oneshot::channel();
+    let (tx_b, rx_b) = tokio::sync::oneshot::channel();
+    let first = PendingPermission::new("edit /tmp/a.txt".into(), Some("edit".into()), tx_a);
+    let second = PendingPermission::new("rm /tmp/b".into(), None, tx_b);
+    let (id_a, id_b) = (first.id, second.id);
+    let queue = h.borrow().pending_permissions.clone();
+    queue.lock().await.push_back(first);
+    state.pending_permissions.lock().await.push_back(second);
+
+    // 默认和命名 session 的待审批权限一起列出
+    let resp = handle_request(&h, &config, SessionRequest::GetPermissions, &etx).await;
+    let SessionResponse::Permissions { agent_name, pending } = resp else { panic!("expected Permissions") };
+    assert_eq!(agent_name, "test");
+    assert_eq!(pending.len(), 2);
+    assert_eq!((pending[0].id, pending[0].session.as_deref(), pending[0].kind.as_deref()), (id_a, None, Some("edit")));
+    assert_eq!((pending[1].id, pending[1].session.as_deref()), (id_b, Some("review")));
+
+    // 按 id 审批，不必是队首
+    let resp = handle_request(&h, &config, SessionRequest::ResolvePermission { permission_id: id_b, approve: false }, &etx).await;
+    assert!(matches!(resp, SessionResponse::Ok { message } if message.contains("rm /tmp/b")));
+    assert!(matches!(rx_b.await.unwrap(), PermissionDecision::Deny));
+    let resp = handle_request(&h, &config, SessionRequest::ResolvePermission { permission_id: id_b, approve: true }, &etx).await;
+    assert!(matches!(resp, SessionResponse::Error { message } if message.contains("No pending permission")));
+    let resp = handle_request(&h, &config, SessionRequest::ResolvePermission { permission_id: id_a, approve: true }, &etx).await;
+    assert!(matches!(resp, SessionResponse::Ok { .. }));
+    assert!(matches!(rx_a.await.unwrap(), PermissionDecision::Approve));
 }
 
 #[test]
@@ -296,6 +470,103 @@ fn no_session_returns_error() {
     }
 }
 
+#[tokio::test]
+async fn named_session_routing() {
+    let h = stub_handle("test");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    let state = SessionState::new(Some("review".into()), OutputRingBuffer::new(10));
+    h.borrow_mut().sessions.insert(
+        "review".into(),
+        NamedSession { id: acp::SessionId::new("s-2"), state: state.clone(), prompt_count: 3, delegation_chain: vec![] },
+    );
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    state.pending_permissions.lock().await.push_back(PendingPermission::new("edit /tmp/b.txt".into(), None, tx));
+
+    // 默认 session 没有待处理权限；命名 session 的权限请求独立排队
+    let resp = handle_request(&h, &config, SessionRequest::ApprovePermission, &etx).await;
+    assert!(matches!(resp, SessionResponse::Error { .. }));
+    let req = SessionRequest::ApprovePermission.in_session(Some("review"));
+    let resp = handle_request(&h, &config, req, &etx).await;
+    assert!(matches!(resp, SessionResponse::Ok { message } if message.contains("b.txt")));
+    assert!(matches!(rx.await.unwrap(), PermissionDecision::Approve));
+
+    let resp = handle_request(&h, &config, SessionRequest::GetStatus.in_session(Some("review")), &etx).await;
+    match resp {
+        SessionResponse::Status { summary } => {
+            assert_eq!(summary.prompt_count, 3);
+            assert_eq!(summary.sessions.len(), 1);
+        }
+        other => panic!("expected Status, got {:?}", other),
+    }
+
+    let resp = handle_request(&h, &config, SessionRequest::Restart.in_session(Some("review")), &etx).await;
+    assert!(matches!(resp, SessionResponse::Error { message } if message.contains("cannot be sent")));
+    let resp = handle_request(&h, &config, SessionRequest::Cancel.in_session(Some("nope")), &etx).await;
+    assert!(matches!(resp, SessionResponse::Error { message } if message.contains("No session named")));
+
+    // 没有 ACP 连接时不能新建；重名直接拒绝
+    let resp = handle_request(&h, &config, SessionRequest::NewSession { session: "other".into() }, &etx).await;
+    assert!(matches!(resp, SessionResponse::Error { message } if message.contains("No active session")));
+    let resp = handle_request(&h, &config, SessionRequest::NewSession { session: "review".into() }, &etx).await;
+    assert!(matches!(resp, SessionResponse::Error { message } if message.contains("already exists")));
+
+    let resp = handle_request(&h, &config, SessionRequest::CloseSession { session: "review".into() }, &etx).await;
+    assert!(matches!(resp, SessionResponse::Ok { .. }));
+    assert!(h.borrow().sessions.is_empty());
+}
+
+#[test]
+fn fork_prompt_replays_recent_conversation() {
+    let entry = |t, c: &str| OutputEntry { timestamp: "t".into(), update_type: t, content: c.into() };
+    assert!(fork_prompt("src", &[entry(OutputType::PromptResponse, "EndTurn")]).is_none());
+
+    let text = fork_prompt("src", &[
+        entry(OutputType::UserPrompt, "fix the bug"),
+        entry(OutputType::AgentThought, "hmm"),
+        entry(OutputType::ToolCallStart, "read lib.rs"),
+        entry(OutputType::AgentMessage, "fixed\n"),
+    ])
+    .unwrap();
+    assert!(text.contains("forked from agent 'src'"));
+    assert!(text.contains("[user] fix the bug\n\n[tool] read lib.rs\n\n[assistant] fixed"));
+    assert!(!text.contains("hmm"));
+
+    // 超出上限时丢弃最早的消息
+    let big = "x".repeat(20 * 1024);
+    let text = fork_prompt("src", &[
+        entry(OutputType::UserPrompt, "first"),
+        entry(OutputType::AgentMessage, &big),
+        entry(OutputType::UserPrompt, &big),
+    ])
+    .unwrap();
+    assert!(text.contains("(2 earlier message(s) omitted)"));
+    assert!(!text.contains("first"));
+}
+
+#[tokio::test]
+async fn seed_and_link_fork() {
+    let h = stub_handle("fork");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    let seed = SessionRequest::Seed { from: "src".into(), session_id: None, transcript: vec![] };
+    assert!(matches!(
+        handle_request(&h, &config, seed.clone(), &etx).await,
+        SessionResponse::Error { message } if message.contains("No active session")
+    ));
+    h.borrow_mut().prompt_count = 1;
+    assert!(matches!(
+        handle_request(&h, &config, seed, &etx).await,
+        SessionResponse::Error { message } if message.contains("already has a conversation")
+    ));
+
+    for _ in 0..2 {
+        let resp = handle_request(&h, &config, SessionRequest::LinkFork { fork: "b".into() }, &etx).await;
+        assert!(matches!(resp, SessionResponse::Ok { .. }));
+    }
+    assert_eq!(h.borrow().to_summary().forks, vec!["b".to_string()]);
+}
+
 #[tokio::test]
 async fn restart_unknown_agent_type() {
     let local = tokio::task::LocalSet::new();
@@ -316,3 +587,98 @@ async fn restart_unknown_agent_type() {
         assert!(matches!(h.borrow().get_status(), AgentStatus::Error(_)));
     }).await;
 }
+
+#[tokio::test]
+async fn hello_reports_version_and_capabilities() {
+    let h = stub_handle("test");
+    let config = TeamConfig::default();
+    let etx = test_event_tx();
+    let hello = SessionRequest::Hello { protocol: PROTOCOL_VERSION, version: VERSION.into() };
+    match handle_request(&h, &config, hello, &etx).await {
+        SessionResponse::Hello { protocol, version, capabilities, max_frame } => {
+            assert_eq!(protocol, PROTOCOL_VERSION);
+            assert_eq!(version, VERSION);
+            assert!(capabilities.iter().any(|c| c == "Subscribe"));
+            assert!(capabilities.iter().any(|c| c == "length_prefix"));
+            assert_eq!(max_frame, config.transport.max_frame);
+        }
+        other => panic!("expected Hello, got {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn unknown_request_keeps_connection_open() {
+    use crate::protocol::transport::{JsonLineReader, JsonLineWriter};
+    use tokio::io::AsyncWriteExt;
+
+    let local = tokio::task::LocalSet::new();
+    local.run_until(async {
+        let (client, server) = tokio::io::duplex(4096);
+        let (sr, sw) = tokio::io::split(server);
+        let (shutdown_tx, _shutdown_rx) = mpsc::unbounded_channel();
+        tokio::task::spawn_local(handle_connection(
+            JsonLineReader::new(sr),
+            JsonLineWriter::new(sw),
+            stub_handle("test"),
+            Rc::new(TeamConfig::default()),
+            test_event_tx(),
+            shutdown_tx,
+            false,
+        ));
+
+        let (cr, mut cw) = tokio::io::split(client);
+        let mut reader = JsonLineReader::new(cr);
+        // 更新版本 CLI 发来的未知请求
+        cw.write_all(b"{\"type\":\"Teleport\",\"to\":\"mars\"}\n").await.unwrap();
+        match reader.read::<SessionResponse>().await.unwrap().unwrap() {
+            SessionResponse::Error { message } => {
+                assert!(message.contains("Unsupported request 'Teleport'"), "{}", message)
+            }
+            other => panic!("expected Error, got {:?}", other),
+        }
+        cw.write_all(b"{\"type\":\"GetStatus\"}\n").await.unwrap();
+        let resp = reader.read::<SessionResponse>().await.unwrap().unwrap();
+        assert!(matches!(resp, SessionResponse::Status { .. }));
+    }).await;
+}
+
+#[tokio::test]
+async fn oversized_frame_is_skipped_and_framing_mirrored() {
+    use crate::protocol::transport::{Framing, JsonLineReader, JsonLineWriter};
+    use tokio::io::AsyncWriteExt;
+
+    let local = tokio::task::LocalSet::new();
+    local.run_until(async {
+        let (client, server) = tokio::io::duplex(64 * 1024);
+        let (sr, sw) = tokio::io::split(server);
+        let (shutdown_tx, _shutdown_rx) = mpsc::unbounded_channel();
+        let mut config = TeamConfig::default();
+        config.transport.max_frame = 1024;
+        tokio::task::spawn_local(handle_connection(
+            JsonLineReader::new(sr),
+            JsonLineWriter::new(sw),
+            stub_handle("test"),
+            Rc::new(config),
+            test_event_tx(),
+            shutdown_tx,
+            false,
+        ));
+
+        let (cr, mut cw) = tokio::io::split(client);
+        let mut reader = JsonLineReader::new(cr);
+        let huge = format!("{{\"type\":\"Prompt\",\"text\":\"{}\",\"files\":[]}}\n", "x".repeat(4096));
+        cw.write_all(huge.as_bytes()).await.unwrap();
+        match reader.read::<SessionResponse>().await.unwrap().unwrap() {
+            SessionResponse::Error { message } => assert!(message.contains("1024-byte limit"), "{}", message),
+            other => panic!("expected Error, got {:?}", other),
+        }
+
+        // 长度前缀帧的请求得到长度前缀帧的回复
+        let mut writer = JsonLineWriter::new(cw);
+        writer.set_framing(Framing::LengthPrefixed);
+        writer.write(&SessionRequest::GetStatus).await.unwrap();
+        let resp = reader.read::<SessionResponse>().await.unwrap().unwrap();
+        assert!(matches!(resp, SessionResponse::Status { .. }));
+        assert_eq!(reader.framing(), Framing::LengthPrefixed);
+    }).await;
+}
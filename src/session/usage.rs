@@ -0,0 +1,191 @@
+// ============================================================
+// usage - 每个 session 的 token / 花费账本
+// ============================================================
+// agent 在 PromptResponse.usage 中上报本 turn 的 token 时直接采用，
+// 否则按 prompt 与回复文本长度估算；花费来自 usage_update 通知（session 累计值）。
+
+use std::sync::{Arc, Mutex};
+
+use crate::protocol::messages::{Budget, Cost, TokenUsage, UsageSummary};
+
+/// 粗略估算：约 4 个字符一个 token
+const CHARS_PER_TOKEN: u64 = 4;
+
+pub type SharedUsage = Arc<Mutex<UsageLedger>>;
+
+#[derive(Debug, Default, Clone)]
+pub struct UsageLedger {
+    /// 已结束 turn 的累计用量
+    pub tokens: TokenUsage,
+    pub turns: u64,
+    /// agent 上报的 session 累计花费
+    pub cost: Option<Cost>,
+    /// 进行中 turn 的文本量（估算用）
+    turn_input_chars: u64,
+    turn_output_chars: u64,
+    /// turn 开始时的累计花费，用于算出本 turn 的增量
+    turn_cost_start: Option<f64>,
+    /// 并入的花费（已关闭的 session / 重启前），agent 上报的累计值从 0 重新开始
+    cost_base: f64,
+}
+
+/// 单个 turn 的用量（写入输出记录）
+#[derive(Debug, Clone, PartialEq)]
+pub struct TurnUsage {
+    pub turn: u64,
+    pub tokens: TokenUsage,
+    pub cost: Option<Cost>,
+}
+
+impl std::fmt::Display for TurnUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Turn {}: {}", self.turn, self.tokens)?;
+        if let Some(c) = &self.cost {
+            write!(f, ", {}", c)?;
+        }
+        Ok(())
+    }
+}
+
+impl UsageLedger {
+    pub fn begin_turn(&mut self, input_chars: usize) {
+        self.turn_input_chars = input_chars as u64;
+        self.turn_output_chars = 0;
+        self.turn_cost_start = self.cost.as_ref().map(|c| c.amount);
+    }
+
+    /// agent 回复 / 思考的文本块
+    pub fn record_output(&mut self, chars: usize) {
+        self.turn_output_chars += chars as u64;
+    }
+
+    pub fn record_cost(&mut self, cost: Cost) {
+        self.cost = Some(Cost { amount: cost.amount + self.cost_base, currency: cost.currency });
+    }
+
+    /// 并入另一个账本的已结束 turn（命名 session 关闭、agent 重启时保留用量）
+    pub fn absorb(&mut self, other: &UsageLedger) {
+        self.tokens.add(&other.tokens);
+        self.turns += other.turns;
+        if let Some(c) = &other.cost {
+            self.cost_base += c.amount;
+            let cost = self.cost.get_or_insert_with(|| Cost { amount: 0.0, currency: c.currency.clone() });
+            cost.amount += c.amount;
+        }
+    }
+
+    /// 结束 turn：优先采用 agent 上报的用量，否则按文本估算
+    pub fn end_turn(&mut self, reported: Option<TokenUsage>) -> TurnUsage {
+        let tokens = reported.unwrap_or_else(|| self.turn_estimate());
+        self.tokens.add(&tokens);
+        self.turns += 1;
+        let cost = self.cost.as_ref().and_then(|c| {
+            let delta = c.amount - self.turn_cost_start.unwrap_or(0.0);
+            (delta > 0.0).then(|| Cost { amount: delta, currency: c.currency.clone() })
+        });
+        self.turn_input_chars = 0;
+        self.turn_output_chars = 0;
+        TurnUsage { turn: self.turns, tokens, cost }
+    }
+
+    /// 累计 token 加上进行中 turn 的估算（turn 内检查预算用）
+    pub fn running_tokens(&self) -> u64 {
+        self.tokens.total + self.turn_estimate().total
+    }
+
+    pub fn summary(&self, budget: Budget) -> Option<UsageSummary> {
+        if self.turns == 0 && budget.is_empty() {
+            return None;
+        }
+        Some(UsageSummary {
+            tokens: self.tokens,
+            turns: self.turns,
+            cost: self.cost.clone(),
+            budget,
+        })
+    }
+
+    fn turn_estimate(&self) -> TokenUsage {
+        let input = self.turn_input_chars.div_ceil(CHARS_PER_TOKEN);
+        let output = self.turn_output_chars.div_ceil(CHARS_PER_TOKEN);
+        TokenUsage { input, output, total: input + output, estimated: true }
+    }
+}
+
+/// 多个 session 的账本合计为 agent 级用量
+pub fn combine<'a>(ledgers: impl IntoIterator<Item = &'a UsageLedger>) -> UsageLedger {
+    let mut out = UsageLedger::default();
+    for l in ledgers {
+        out.tokens.add(&l.tokens);
+        out.turns += l.turns;
+        out.turn_input_chars += l.turn_input_chars;
+        out.turn_output_chars += l.turn_output_chars;
+        if let Some(c) = &l.cost {
+            let cost = out.cost.get_or_insert_with(|| Cost { amount: 0.0, currency: c.currency.clone() });
+            cost.amount += c.amount;
+        }
+    }
+    out
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_when_not_reported() {
+        let mut l = UsageLedger::default();
+        l.begin_turn(40);
+        l.record_output(7);
+        l.record_output(1);
+        assert_eq!(l.running_tokens(), 12);
+        let t = l.end_turn(None);
+        assert_eq!(t.tokens, TokenUsage { input: 10, output: 2, total: 12, estimated: true });
+        assert_eq!(t.to_string(), "Turn 1: ~12 tokens (in 10 / out 2)");
+        assert_eq!(l.running_tokens(), 12);
+    }
+
+    #[test]
+    fn reported_usage_and_cost_delta() {
+        let mut l = UsageLedger::default();
+        l.record_cost(Cost { amount: 0.25, currency: "USD".into() });
+        l.begin_turn(4000);
+        l.record_cost(Cost { amount: 0.75, currency: "USD".into() });
+        let reported = TokenUsage { input: 900, output: 100, total: 1000, estimated: false };
+        let t = l.end_turn(Some(reported));
+        assert_eq!(t.tokens, reported);
+        assert_eq!(t.to_string(), "Turn 1: 1.0k tokens (in 900 / out 100), 0.50 USD");
+
+        // 花费未变化的 turn 不记增量
+        l.begin_turn(4);
+        assert!(l.end_turn(None).cost.is_none());
+        assert_eq!(l.turns, 2);
+        assert!(l.tokens.estimated);
+    }
+
+    #[test]
+    fn summary_and_combine() {
+        let mut a = UsageLedger::default();
+        assert!(a.summary(Budget::default()).is_none());
+        let budget = Budget { max_tokens: Some(10), max_cost: None };
+        assert_eq!(a.summary(budget.clone()).unwrap().budget, budget);
+
+        a.begin_turn(8);
+        a.end_turn(None);
+        let mut b = UsageLedger::default();
+        b.record_cost(Cost { amount: 1.0, currency: "USD".into() });
+        b.begin_turn(4);
+        let total = combine([&a, &b]);
+        assert_eq!(total.turns, 1);
+        assert_eq!(total.running_tokens(), 3);
+        assert_eq!(total.cost.unwrap().amount, 1.0);
+
+        // 重启后 agent 的累计花费从 0 开始，并入的部分保留
+        let mut fresh = UsageLedger::default();
+        fresh.absorb(&b);
+        fresh.record_cost(Cost { amount: 0.5, currency: "USD".into() });
+        assert_eq!(fresh.cost.unwrap().amount, 1.5);
+    }
+}
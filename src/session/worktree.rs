@@ -0,0 +1,217 @@
+// ============================================================
+// worktree - 每个 agent 独立的 git worktree
+// ============================================================
+// `add --worktree [branch]` 在 {socket_dir}/worktrees/<name> 创建 worktree 作为 agent cwd，
+// 结束时 `merge` 合入主仓库当前分支，或 `discard` 直接丢弃。
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::TeamConfig;
+use crate::protocol::messages::WorktreeInfo;
+
+/// worktree 目录：{socket_dir}/worktrees/<name>
+pub fn worktree_dir(config: &TeamConfig, name: &str) -> PathBuf {
+    config.socket_dir.join("worktrees").join(name)
+}
+
+/// 未指定分支时使用 agent-team/<name>
+pub fn default_branch(name: &str) -> String {
+    format!("agent-team/{}", name)
+}
+
+/// 为 agent 创建 worktree；目录已存在且分支一致时直接复用（后台模式由 CLI 预先创建）
+pub fn prepare(
+    config: &TeamConfig,
+    name: &str,
+    cwd: &Path,
+    branch: Option<&str>,
+) -> Result<WorktreeInfo> {
+    let repo = git(cwd, &["rev-parse", "--show-toplevel"])
+        .with_context(|| format!("{} is not inside a git repository", cwd.display()))?;
+    let branch = match branch.map(str::trim) {
+        Some(b) if !b.is_empty() => b.to_string(),
+        _ => default_branch(name),
+    };
+    let path = worktree_dir(config, name);
+
+    if path.exists() {
+        let current = git(&path, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+        if current != branch {
+            bail!(
+                "Worktree {} already exists on branch '{}' (wanted '{}')",
+                path.display(),
+                current,
+                branch,
+            );
+        }
+    } else {
+        std::fs::create_dir_all(path.parent().unwrap_or(&path))?;
+        let path_str = path.display().to_string();
+        let branch_ref = format!("refs/heads/{}", branch);
+        if git(Path::new(&repo), &["rev-parse", "--verify", "--quiet", &branch_ref]).is_ok() {
+            git(Path::new(&repo), &["worktree", "add", &path_str, &branch])?;
+        } else {
+            git(Path::new(&repo), &["worktree", "add", "-b", &branch, &path_str])?;
+        }
+    }
+
+    Ok(WorktreeInfo {
+        path: path.display().to_string(),
+        branch,
+        repo,
+    })
+}
+
+/// 按约定路径找回 agent 的 worktree（session 已退出时使用）
+pub fn locate(config: &TeamConfig, name: &str) -> Result<WorktreeInfo> {
+    let path = worktree_dir(config, name);
+    if !path.exists() {
+        bail!("Agent '{}' has no worktree", name);
+    }
+    let branch = git(&path, &["rev-parse", "--abbrev-ref", "HEAD"])?;
+    let common = git(&path, &["rev-parse", "--path-format=absolute", "--git-common-dir"])?;
+    let repo = Path::new(&common)
+        .parent()
+        .context("Cannot determine main repository")?
+        .display()
+        .to_string();
+    Ok(WorktreeInfo {
+        path: path.display().to_string(),
+        branch,
+        repo,
+    })
+}
+
+/// 提交 worktree 中未提交的改动，合入主仓库当前分支，然后移除 worktree 和分支。
+/// 冲突时中止合并并保留 worktree。
+pub fn merge(info: &WorktreeInfo, message: &str) -> Result<String> {
+    let path = Path::new(&info.path);
+    let repo = Path::new(&info.repo);
+
+    if !git(path, &["status", "--porcelain"])?.is_empty() {
+        git(path, &["add", "-A"])?;
+        git(path, &["commit", "-q", "-m", message])?;
+    }
+
+    let range = format!("HEAD..{}", info.branch);
+    let ahead: usize = git(repo, &["rev-list", "--count", &range])?.parse().unwrap_or(0);
+    let summary = if ahead == 0 {
+        "Nothing to merge".to_string()
+    } else {
+        if let Err(e) = git(repo, &["merge", "--no-ff", "--no-edit", &info.branch]) {
+            let _ = git(repo, &["merge", "--abort"]);
+            bail!("Merge of '{}' failed, worktree kept at {}: {:#}", info.branch, info.path, e);
+        }
+        format!("Merged {} commit(s) from '{}'", ahead, info.branch)
+    };
+
+    remove(info)?;
+    Ok(summary)
+}
+
+/// 强制移除 worktree 并删除分支
+pub fn remove(info: &WorktreeInfo) -> Result<()> {
+    let repo = Path::new(&info.repo);
+    git(repo, &["worktree", "remove", "--force", &info.path])?;
+    git(repo, &["branch", "-D", &info.branch])?;
+    Ok(())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let out = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Failed to run git")?;
+    if !out.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&out.stderr).trim(),
+        );
+    }
+    Ok(String::from_utf8_lossy(&out.stdout).trim().to_string())
+}
+
+// ==================== 单元测试 ====================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 临时仓库（含一次提交）+ 以临时目录为 socket_dir 的配置
+    fn setup() -> (tempfile::TempDir, PathBuf, TeamConfig) {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        git(&repo, &["init", "-q"]).unwrap();
+        git(&repo, &["config", "user.email", "t@example.com"]).unwrap();
+        git(&repo, &["config", "user.name", "t"]).unwrap();
+        std::fs::write(repo.join("a.txt"), "one\n").unwrap();
+        git(&repo, &["add", "-A"]).unwrap();
+        git(&repo, &["commit", "-q", "-m", "init"]).unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().join("sock"),
+            ..Default::default()
+        };
+        (dir, repo, config)
+    }
+
+    #[test]
+    fn prepare_creates_and_reuses() {
+        let (_dir, repo, config) = setup();
+        let info = prepare(&config, "coder", &repo, None).unwrap();
+        assert_eq!(info.branch, "agent-team/coder");
+        assert!(Path::new(&info.path).join("a.txt").exists());
+
+        let again = prepare(&config, "coder", &repo, Some("")).unwrap();
+        assert_eq!(again, info);
+        assert!(prepare(&config, "coder", &repo, Some("other")).is_err());
+
+        let found = locate(&config, "coder").unwrap();
+        assert_eq!(found.branch, info.branch);
+        assert_eq!(
+            Path::new(&found.repo).canonicalize().unwrap(),
+            repo.canonicalize().unwrap(),
+        );
+    }
+
+    #[test]
+    fn prepare_outside_repo_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let config = TeamConfig {
+            socket_dir: dir.path().join("sock"),
+            ..Default::default()
+        };
+        assert!(prepare(&config, "x", dir.path(), None).is_err());
+    }
+
+    #[test]
+    fn merge_commits_pending_changes() {
+        let (_dir, repo, config) = setup();
+        let info = prepare(&config, "coder", &repo, Some("feature")).unwrap();
+        std::fs::write(Path::new(&info.path).join("b.txt"), "two\n").unwrap();
+
+        let summary = merge(&info, "agent-team: coder").unwrap();
+        assert!(summary.contains("Merged 1 commit"));
+        assert!(repo.join("b.txt").exists());
+        assert!(!Path::new(&info.path).exists());
+        assert!(git(&repo, &["rev-parse", "--verify", "--quiet", "refs/heads/feature"]).is_err());
+    }
+
+    #[test]
+    fn discard_drops_branch() {
+        let (_dir, repo, config) = setup();
+        let info = prepare(&config, "coder", &repo, None).unwrap();
+        std::fs::write(Path::new(&info.path).join("b.txt"), "two\n").unwrap();
+
+        remove(&info).unwrap();
+        assert!(!repo.join("b.txt").exists());
+        assert!(!Path::new(&info.path).exists());
+        assert!(locate(&config, "coder").is_err());
+    }
+}
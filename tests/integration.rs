@@ -5,7 +5,9 @@ use std::collections::HashMap;
 use std::time::Duration;
 
 use agent_team::config::{AgentTypeConfig, AutoApprovePolicy, TeamConfig};
-use agent_team::protocol::messages::{SessionRequest, SessionResponse};
+use agent_team::protocol::messages::{
+    DaemonRequest, OutputType, SessionRequest, SessionResponse, WorktreeInfo,
+};
 use agent_team::protocol::transport::{JsonLineReader, JsonLineWriter};
 use tokio::net::UnixStream;
 
@@ -24,9 +26,16 @@ fn test_config(socket_dir: std::path::PathBuf) -> TeamConfig {
     TeamConfig {
         auto_approve: AutoApprovePolicy::Never,
         output_buffer_size: 100,
+        output_buffer_bytes: 0,
         agent_types,
         default_cwd: std::env::temp_dir(),
+        hooks_file: socket_dir.join("hooks.toml"),
         socket_dir,
+        mcp_max_depth: 0,
+        track_changes: false,
+        remote: Default::default(),
+        transport: Default::default(),
+        notify: Default::default(),
     }
 }
 
@@ -53,6 +62,7 @@ async fn send_prompt_and_wait(
         SessionRequest::Prompt {
             text: text.into(),
             files: vec![],
+            chain: vec![],
         },
     )
     .await;
@@ -87,6 +97,7 @@ async fn session_status() {
             session_config,
             vec![],
             std::env::temp_dir(),
+            None,
         )
         .await
     });
@@ -132,6 +143,7 @@ async fn prompt_and_output() {
             session_config,
             vec![],
             std::env::temp_dir(),
+            None,
         )
         .await
     });
@@ -210,6 +222,7 @@ async fn cancel_no_active_task() {
             session_config,
             vec![],
             std::env::temp_dir(),
+            None,
         )
         .await
     });
@@ -247,6 +260,11 @@ async fn restart_agent() {
             session_config,
             vec![],
             std::env::temp_dir(),
+            Some(WorktreeInfo {
+                path: std::env::temp_dir().display().to_string(),
+                branch: "agent-team/worker".into(),
+                repo: "/repo".into(),
+            }),
         )
         .await
     });
@@ -276,6 +294,11 @@ async fn restart_agent() {
                     assert_eq!(summary.name, "worker");
                     assert_eq!(summary.status, "idle");
                     assert_eq!(summary.prompt_count, 0);
+                    // worktree 跨重启保留
+                    assert_eq!(
+                        summary.worktree.as_ref().map(|w| w.branch.as_str()),
+                        Some("agent-team/worker"),
+                    );
                 }
                 other => panic!("expected Status, got: {:?}", other),
             }
@@ -304,6 +327,7 @@ async fn graceful_shutdown() {
             session_config,
             vec![],
             std::env::temp_dir(),
+            None,
         )
         .await
     });
@@ -358,6 +382,7 @@ async fn output_last_round() {
             session_config,
             vec![],
             std::env::temp_dir(),
+            None,
         )
         .await
     });
@@ -422,3 +447,843 @@ async fn output_last_round() {
         })
         .await;
 }
+
+// ==================== 改动追踪 ====================
+
+#[tokio::test]
+async fn changes_recorded_per_turn() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config(dir.path().to_path_buf());
+    config.track_changes = true;
+    let work = dir.path().join("work");
+    std::fs::create_dir_all(&work).unwrap();
+    std::fs::write(work.join("a.txt"), "one\n").unwrap();
+    let sock_path = config.session_socket("tracker");
+
+    let local = tokio::task::LocalSet::new();
+    let session_config = config.clone();
+    let _handle = local.spawn_local(async move {
+        agent_team::session::server::run(
+            "tracker".into(),
+            "mock".into(),
+            session_config,
+            vec![],
+            work,
+            None,
+        )
+        .await
+    });
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            send_prompt_and_wait(&sock_path, "first task", 1).await;
+            send_prompt_and_wait(&sock_path, "second task", 2).await;
+
+            let resp = send_recv(
+                &sock_path,
+                SessionRequest::GetChanges { turn: None, diff: false },
+            )
+            .await;
+            match &resp {
+                SessionResponse::Changes { agent_name, turns } => {
+                    assert_eq!(agent_name, "tracker");
+                    let ids: Vec<u64> = turns.iter().map(|t| t.turn).collect();
+                    assert_eq!(ids, vec![1, 2]);
+                    assert_eq!(turns[1].prompt, "second task");
+                    // mock agent 不改文件
+                    assert!(turns.iter().all(|t| t.files.is_empty()));
+                }
+                other => panic!("expected Changes, got: {:?}", other),
+            }
+
+            let resp = send_recv(
+                &sock_path,
+                SessionRequest::GetChanges { turn: Some(9), diff: false },
+            )
+            .await;
+            assert!(matches!(resp, SessionResponse::Error { .. }));
+
+            // 没有改动可还原
+            let resp = send_recv(&sock_path, SessionRequest::Undo { turn: None }).await;
+            match &resp {
+                SessionResponse::Error { message } => assert!(message.contains("No turn")),
+                other => panic!("expected Error, got: {:?}", other),
+            }
+
+            let resp = send_recv(&sock_path, SessionRequest::Shutdown).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+        })
+        .await;
+}
+
+// ==================== 同一进程内的命名 session ====================
+
+#[tokio::test]
+async fn named_sessions_share_agent_process() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = test_config(dir.path().to_path_buf());
+    let sock_path = config.session_socket("multi");
+
+    let local = tokio::task::LocalSet::new();
+    let session_config = config.clone();
+    let _handle = local.spawn_local(async move {
+        agent_team::session::server::run(
+            "multi".into(),
+            "mock".into(),
+            session_config,
+            vec![],
+            std::env::temp_dir(),
+            None,
+        )
+        .await
+    });
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            let open = SessionRequest::NewSession { session: "review".into() };
+            let resp = send_recv(&sock_path, open.clone()).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }), "{:?}", resp);
+            let resp = send_recv(&sock_path, open).await;
+            assert!(matches!(resp, SessionResponse::Error { .. }));
+
+            let prompt = SessionRequest::Prompt {
+                text: "review this".into(),
+                files: vec![],
+                chain: vec!["planner".into()],
+            };
+            let resp = send_recv(&sock_path, prompt.in_session(Some("review"))).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }), "{:?}", resp);
+
+            let mut done = false;
+            for _ in 0..100 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let req = SessionRequest::GetStatus.in_session(Some("review"));
+                if let SessionResponse::Status { summary } = send_recv(&sock_path, req).await {
+                    if summary.status == "idle" && summary.prompt_count == 1 {
+                        // 委派链记在收到 prompt 的命名 session 上
+                        assert_eq!(summary.delegation_chain, ["planner"]);
+                        done = true;
+                        break;
+                    }
+                }
+            }
+            assert!(done, "named session never finished its turn");
+
+            // 输出按 session 分开缓冲
+            let output = SessionRequest::GetOutput { last: 0, agent_only: false };
+            match send_recv(&sock_path, output.clone().in_session(Some("review"))).await {
+                SessionResponse::Output { entries, .. } => {
+                    assert!(entries.iter().any(|e| e.content == "review this"));
+                }
+                other => panic!("expected Output, got: {:?}", other),
+            }
+            match send_recv(&sock_path, output).await {
+                SessionResponse::Output { entries, .. } => assert!(entries.is_empty()),
+                other => panic!("expected Output, got: {:?}", other),
+            }
+
+            // 默认 session 不受影响，summary 列出命名 session
+            match send_recv(&sock_path, SessionRequest::GetStatus).await {
+                SessionResponse::Status { summary } => {
+                    assert_eq!(summary.prompt_count, 0);
+                    assert!(summary.delegation_chain.is_empty());
+                    assert_eq!(summary.sessions.len(), 1);
+                    assert_eq!(summary.sessions[0].name, "review");
+                    assert_eq!(summary.sessions[0].prompt_count, 1);
+                }
+                other => panic!("expected Status, got: {:?}", other),
+            }
+
+            let close = SessionRequest::CloseSession { session: "review".into() };
+            let resp = send_recv(&sock_path, close).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+            let req = SessionRequest::Cancel.in_session(Some("review"));
+            assert!(matches!(send_recv(&sock_path, req).await, SessionResponse::Error { .. }));
+
+            let resp = send_recv(&sock_path, SessionRequest::Shutdown).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+        })
+        .await;
+}
+
+// ==================== fork ====================
+
+/// 后台跑一个 mock session，返回 socket 路径
+fn spawn_mock_session(
+    local: &tokio::task::LocalSet,
+    config: &TeamConfig,
+    name: &str,
+) -> std::path::PathBuf {
+    let session_config = config.clone();
+    let name = name.to_string();
+    let sock_path = config.session_socket(&name);
+    local.spawn_local(async move {
+        agent_team::session::server::run(
+            name,
+            "mock".into(),
+            session_config,
+            vec![],
+            std::env::temp_dir(),
+            None,
+        )
+        .await
+    });
+    sock_path
+}
+
+async fn status_of(sock_path: &std::path::Path) -> agent_team::protocol::messages::AgentSummary {
+    match send_recv(sock_path, SessionRequest::GetStatus).await {
+        SessionResponse::Status { summary } => *summary,
+        other => panic!("expected Status, got: {:?}", other),
+    }
+}
+
+#[tokio::test]
+async fn fork_loads_or_replays_source_conversation() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = test_config(dir.path().to_path_buf());
+
+    let local = tokio::task::LocalSet::new();
+    let src = spawn_mock_session(&local, &config, "src");
+    let loaded = spawn_mock_session(&local, &config, "loaded");
+    let replayed = spawn_mock_session(&local, &config, "replayed");
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            send_prompt_and_wait(&src, "design the parser", 1).await;
+            let source = status_of(&src).await;
+            let sid = source.session_id.clone();
+            assert!(sid.is_some());
+            let transcript = match send_recv(
+                &src,
+                SessionRequest::GetOutput { last: 0, agent_only: false },
+            )
+            .await
+            {
+                SessionResponse::Output { entries, .. } => entries,
+                other => panic!("expected Output, got: {:?}", other),
+            };
+
+            // mock agent 支持 session/load：直接接管源 session
+            let seed = SessionRequest::Seed {
+                from: "src".into(),
+                session_id: sid.clone(),
+                transcript: transcript.clone(),
+            };
+            match send_recv(&loaded, seed).await {
+                SessionResponse::Ok { message } => assert!(message.contains("Loaded session")),
+                other => panic!("expected Ok, got: {:?}", other),
+            }
+            let fork = status_of(&loaded).await;
+            assert_eq!(fork.session_id, sid);
+            assert_eq!(fork.forked_from.as_deref(), Some("src"));
+            assert_eq!(fork.prompt_count, 0);
+
+            // load 失败时回放对话记录
+            let seed = SessionRequest::Seed {
+                from: "src".into(),
+                session_id: Some("unknown".into()),
+                transcript,
+            };
+            match send_recv(&replayed, seed).await {
+                SessionResponse::Ok { message } => assert!(message.contains("Replaying")),
+                other => panic!("expected Ok, got: {:?}", other),
+            }
+            for _ in 0..100 {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                let s = status_of(&replayed).await;
+                if s.status == "idle" && s.prompt_count == 1 {
+                    break;
+                }
+            }
+            match send_recv(&replayed, SessionRequest::GetOutput { last: 0, agent_only: false }).await {
+                SessionResponse::Output { entries, .. } => {
+                    assert!(entries[0].content.contains("[user] design the parser"));
+                }
+                other => panic!("expected Output, got: {:?}", other),
+            }
+
+            let link = SessionRequest::LinkFork { fork: "loaded".into() };
+            assert!(matches!(send_recv(&src, link).await, SessionResponse::Ok { .. }));
+            assert_eq!(status_of(&src).await.forks, vec!["loaded".to_string()]);
+
+            for sock in [&src, &loaded, &replayed] {
+                send_recv(sock, SessionRequest::Shutdown).await;
+            }
+        })
+        .await;
+}
+
+// ==================== 用量与预算 ====================
+
+#[tokio::test]
+async fn usage_recorded_and_budget_refuses_prompts() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = test_config(dir.path().to_path_buf());
+    let local = tokio::task::LocalSet::new();
+    let sock = spawn_mock_session(&local, &config, "metered");
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            // mock agent 不上报用量：按文本估算，并写入输出记录
+            send_prompt_and_wait(&sock, "count these tokens please", 1).await;
+            let usage = status_of(&sock).await.usage.expect("usage after a turn");
+            assert_eq!(usage.turns, 1);
+            assert!(usage.tokens.estimated && usage.tokens.total > 0);
+            match send_recv(&sock, SessionRequest::GetOutput { last: 1, agent_only: true }).await {
+                SessionResponse::Output { entries, .. } => {
+                    let u = entries.iter().find(|e| matches!(e.update_type, OutputType::Usage)).unwrap();
+                    assert!(u.content.starts_with("Turn 1: ~"), "{}", u.content);
+                }
+                other => panic!("expected Output, got: {:?}", other),
+            }
+
+            let req = SessionRequest::SetBudget { max_tokens: Some(1), max_cost: None, clear: false };
+            assert!(matches!(send_recv(&sock, req).await, SessionResponse::Ok { .. }));
+            let prompt = SessionRequest::Prompt { text: "more".into(), files: vec![], chain: vec![] };
+            match send_recv(&sock, prompt).await {
+                SessionResponse::Error { message } => assert!(message.contains("token budget reached")),
+                other => panic!("expected Error, got: {:?}", other),
+            }
+
+            send_recv(&sock, SessionRequest::Shutdown).await;
+        })
+        .await;
+}
+
+// ==================== daemon 托管多个 agent ====================
+
+async fn daemon_send_recv(
+    config: &TeamConfig,
+    req: DaemonRequest,
+) -> SessionResponse {
+    let stream = UnixStream::connect(config.daemon_socket()).await.unwrap();
+    let (read, write) = stream.into_split();
+    let mut writer = JsonLineWriter::new(write);
+    let mut reader = JsonLineReader::new(read);
+    writer.write(&req).await.unwrap();
+    reader.read::<SessionResponse>().await.unwrap().unwrap()
+}
+
+fn spawn_request(name: &str) -> DaemonRequest {
+    DaemonRequest::Spawn {
+        name: name.into(),
+        agent_type: "mock".into(),
+        cwd: None,
+        args: vec![],
+        auto_approve: Some("always".into()),
+        no_mcp: true,
+        worktree: None,
+    }
+}
+
+#[tokio::test]
+async fn daemon_hosts_agents() {
+    let dir = tempfile::tempdir().unwrap();
+    let config = test_config(dir.path().to_path_buf());
+
+    let local = tokio::task::LocalSet::new();
+    let daemon_config = config.clone();
+    let daemon_handle = local.spawn_local(async move {
+        agent_team::session::daemon::run(daemon_config).await
+    });
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+
+            for name in ["a-1", "a-2"] {
+                let resp = daemon_send_recv(&config, spawn_request(name)).await;
+                assert!(matches!(resp, SessionResponse::Ok { .. }), "{:?}", resp);
+                assert!(config.daemon_marker(name).exists());
+            }
+            assert_eq!(config.scan_sessions(), vec!["a-1", "a-2"]);
+
+            // 同名拒绝
+            let resp = daemon_send_recv(&config, spawn_request("a-1")).await;
+            assert!(matches!(resp, SessionResponse::Error { .. }));
+
+            // 按 name 寻址的 session 请求
+            let resp = daemon_send_recv(
+                &config,
+                DaemonRequest::Session { name: "a-2".into(), request: SessionRequest::GetStatus },
+            )
+            .await;
+            match &resp {
+                SessionResponse::Status { summary } => {
+                    assert_eq!(summary.name, "a-2");
+                    assert_eq!(summary.status, "idle");
+                }
+                other => panic!("expected Status, got: {:?}", other),
+            }
+
+            let resp = daemon_send_recv(
+                &config,
+                DaemonRequest::Session { name: "nope".into(), request: SessionRequest::GetStatus },
+            )
+            .await;
+            assert!(matches!(resp, SessionResponse::Error { .. }));
+
+            // 单个 agent Shutdown 只移除它自己
+            let resp = daemon_send_recv(
+                &config,
+                DaemonRequest::Session { name: "a-1".into(), request: SessionRequest::Shutdown },
+            )
+            .await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+            assert!(!config.daemon_marker("a-1").exists());
+
+            let resp = daemon_send_recv(&config, DaemonRequest::List).await;
+            match &resp {
+                SessionResponse::Agents { agents } => {
+                    let names: Vec<&str> = agents.iter().map(|a| a.name.as_str()).collect();
+                    assert_eq!(names, vec!["a-2"]);
+                }
+                other => panic!("expected Agents, got: {:?}", other),
+            }
+
+            let resp = daemon_send_recv(&config, DaemonRequest::Stop).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(daemon_handle.is_finished());
+            assert!(!config.daemon_socket().exists());
+            assert!(config.scan_sessions().is_empty());
+        })
+        .await;
+}
+
+// ==================== 远程访问（TCP + token） ====================
+
+#[tokio::test]
+async fn remote_session_over_tcp() {
+    use agent_team::cli::client;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config(dir.path().to_path_buf());
+    // 先占用再释放，拿到一个空闲端口
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    config.remote.listen = Some(format!("127.0.0.1:{}", port));
+    config.remote.token_file = dir.path().join("token");
+
+    let local = tokio::task::LocalSet::new();
+    let session_config = config.clone();
+    let session_handle = local.spawn_local(async move {
+        agent_team::session::server::run(
+            "r-1".into(),
+            "mock".into(),
+            session_config,
+            vec![],
+            std::env::temp_dir(),
+            None,
+        )
+        .await
+    });
+
+    // CLI 侧：只知道地址和 token 文件
+    let mut cli_config = test_config(dir.path().join("elsewhere"));
+    cli_config.remote.host = Some(format!("127.0.0.1:{}", port));
+    cli_config.remote.token_file = dir.path().join("token");
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            let resp = client::send(&cli_config, "r-1", SessionRequest::GetStatus).await.unwrap();
+            match &resp {
+                SessionResponse::Status { summary } => assert_eq!(summary.name, "r-1"),
+                other => panic!("expected Status, got: {:?}", other),
+            }
+            assert_eq!(client::session_names(&cli_config).await.unwrap(), vec!["r-1"]);
+
+            // 名字不符 / 非 daemon 请求
+            let resp = client::send(&cli_config, "other", SessionRequest::GetStatus).await.unwrap();
+            assert!(matches!(resp, SessionResponse::Error { .. }));
+            let resp = client::daemon_send(&cli_config, DaemonRequest::Stop).await.unwrap();
+            assert!(matches!(resp, SessionResponse::Error { .. }));
+
+            // 错误 token 被拒绝
+            let mut bad = cli_config.clone();
+            bad.remote.token_file = dir.path().join("bad-token");
+            agent_team::protocol::remote::load_or_create_token(&bad.remote.token_file).unwrap();
+            assert!(client::send(&bad, "r-1", SessionRequest::GetStatus).await.is_err());
+
+            let resp = client::send(&cli_config, "r-1", SessionRequest::Shutdown).await.unwrap();
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(session_handle.is_finished());
+        })
+        .await;
+}
+
+// ==================== HTTP 网关（REST + SSE） ====================
+
+/// 发一个 HTTP/1.1 请求（Connection: close），返回状态码和 body
+async fn http_request(
+    port: u16,
+    method: &str,
+    path: &str,
+    token: &str,
+    body: &str,
+) -> (u16, String) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let mut stream = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+    let req = format!(
+        "{} {} HTTP/1.1\r\nHost: test\r\nAuthorization: Bearer {}\r\nContent-Length: {}\r\n\r\n{}",
+        method,
+        path,
+        token,
+        body.len(),
+        body,
+    );
+    stream.write_all(req.as_bytes()).await.unwrap();
+    let mut resp = String::new();
+    stream.read_to_string(&mut resp).await.unwrap();
+    let status = resp[9..12].parse().unwrap();
+    let body = resp.split_once("\r\n\r\n").map(|(_, b)| b.to_string()).unwrap_or_default();
+    (status, body)
+}
+
+#[tokio::test]
+async fn http_gateway_rest_and_sse() {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config(dir.path().to_path_buf());
+    config.remote.token_file = dir.path().join("token");
+    let token = agent_team::protocol::remote::load_or_create_token(&config.remote.token_file).unwrap();
+    let sock_path = config.session_socket("h-1");
+    let port = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+
+    let local = tokio::task::LocalSet::new();
+    let session_config = config.clone();
+    let session_handle = local.spawn_local(async move {
+        agent_team::session::server::run(
+            "h-1".into(),
+            "mock".into(),
+            session_config,
+            vec![],
+            std::env::temp_dir(),
+            None,
+        )
+        .await
+    });
+    let gateway_config = config.clone();
+    local.spawn_local(async move {
+        let addr = format!("127.0.0.1:{}", port);
+        agent_team::cli::http::run_gateway(gateway_config, &addr).await
+    });
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+
+            let (status, _) = http_request(port, "GET", "/agents", "wrong", "").await;
+            assert_eq!(status, 401);
+
+            let (status, body) = http_request(port, "GET", "/agents", &token, "").await;
+            assert_eq!(status, 200);
+            let list: serde_json::Value = serde_json::from_str(&body).unwrap();
+            assert_eq!(list["agents"][0]["name"], "h-1");
+
+            let (status, _) = http_request(port, "GET", "/agents/nope", &token, "").await;
+            assert_eq!(status, 404);
+
+            // SSE：query token（EventSource 无法设置 header）
+            let mut sse = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+            sse.write_all(
+                format!("GET /agents/h-1/events?token={} HTTP/1.1\r\n\r\n", token).as_bytes(),
+            )
+            .await
+            .unwrap();
+            let mut sse = BufReader::new(sse);
+            let mut line = String::new();
+            sse.read_line(&mut line).await.unwrap();
+            assert!(line.starts_with("HTTP/1.1 200"), "got: {}", line);
+            loop {
+                line.clear();
+                sse.read_line(&mut line).await.unwrap();
+                if line == "\r\n" {
+                    break;
+                }
+            }
+
+            let (status, _) = http_request(
+                port,
+                "POST",
+                "/agents/h-1/prompt",
+                &token,
+                r#"{"text":"hello over http"}"#,
+            )
+            .await;
+            assert_eq!(status, 200);
+
+            // 事件流收到 prompt 产生的输出
+            let saw_output = tokio::time::timeout(Duration::from_secs(5), async {
+                loop {
+                    line.clear();
+                    if sse.read_line(&mut line).await.unwrap() == 0 {
+                        return false;
+                    }
+                    if line.starts_with("data: ") && line.contains(r#""kind":"output""#) {
+                        return true;
+                    }
+                }
+            })
+            .await
+            .unwrap();
+            assert!(saw_output);
+
+            for _ in 0..100 {
+                let resp = send_recv(&sock_path, SessionRequest::GetStatus).await;
+                if matches!(resp, SessionResponse::Status { ref summary } if summary.status == "idle") {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+            let (status, body) =
+                http_request(port, "GET", "/agents/h-1/output?last=0", &token, "").await;
+            assert_eq!(status, 200);
+            assert!(body.contains(r#""type":"Output""#));
+
+            // session 拒绝的请求 → 409
+            let (status, _) = http_request(port, "POST", "/agents/h-1/approve", &token, "").await;
+            assert_eq!(status, 409);
+
+            // DELETE 关闭 session，事件流以 closed 事件结束
+            let (status, _) = http_request(port, "DELETE", "/agents/h-1", &token, "").await;
+            assert_eq!(status, 200);
+            let mut rest = String::new();
+            tokio::time::timeout(Duration::from_secs(5), async {
+                while sse.read_line(&mut rest).await.unwrap() > 0 {}
+            })
+            .await
+            .unwrap();
+            assert!(rest.contains("event: closed"));
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            assert!(session_handle.is_finished());
+        })
+        .await;
+}
+
+// ==================== 多路复用连接 ====================
+
+#[tokio::test]
+async fn multiplexed_requests_and_notifications() {
+    use agent_team::cli::client::SessionClient;
+    use agent_team::protocol::messages::SessionEvent;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = test_config(dir.path().to_path_buf());
+    let sock_path = config.session_socket("m-1");
+
+    let local = tokio::task::LocalSet::new();
+    let session_config = config.clone();
+    local.spawn_local(async move {
+        agent_team::session::server::run(
+            "m-1".into(),
+            "mock".into(),
+            session_config,
+            vec![],
+            std::env::temp_dir(),
+            None,
+        )
+        .await
+    });
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            let client = SessionClient::connect(&config, "m-1").await.unwrap();
+            assert!(client.peer().supports("multiplex"));
+            let mut mux = client.into_mux().unwrap();
+            let mut notes = mux.take_notifications().unwrap();
+            mux.subscribe().await.unwrap();
+
+            // 同一连接上并发：prompt、状态查询
+            let prompt = SessionRequest::Prompt {
+                text: "hello mux".into(),
+                files: vec![],
+                chain: vec![],
+            };
+            let (a, b) = futures::join!(
+                mux.request(prompt),
+                mux.request(SessionRequest::GetStatus),
+            );
+            assert!(matches!(a.unwrap(), SessionResponse::Ok { .. }));
+            assert!(matches!(b.unwrap(), SessionResponse::Status { .. }));
+
+            // prompt 产生的事件作为通知推送到同一连接
+            let saw_done = tokio::time::timeout(Duration::from_secs(5), async {
+                while let Some((agent, event)) = notes.recv().await {
+                    assert_eq!(agent, "m-1");
+                    if matches!(event, SessionEvent::Info { ref tag, .. } if tag == "done") {
+                        return true;
+                    }
+                }
+                false
+            })
+            .await
+            .unwrap();
+            assert!(saw_done);
+
+            // 旧客户端的 lockstep 请求不受影响
+            let resp = send_recv(&sock_path, SessionRequest::GetStatus).await;
+            assert!(matches!(resp, SessionResponse::Status { ref summary } if summary.prompt_count == 1));
+
+            let resp = mux.request(SessionRequest::Shutdown).await.unwrap();
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+            // session 关闭后通知流结束
+            let closed = tokio::time::timeout(Duration::from_secs(5), async {
+                while notes.recv().await.is_some() {}
+            })
+            .await;
+            assert!(closed.is_ok());
+        })
+        .await;
+}
+
+// ==================== 按 id 审批 ====================
+
+/// 等到 agent 有 n 条待审批权限，返回其 id
+async fn pending_permission_ids(sock_path: &std::path::Path, n: usize) -> Vec<u64> {
+    for _ in 0..100 {
+        if let SessionResponse::Permissions { pending, .. } =
+            send_recv(sock_path, SessionRequest::GetPermissions).await
+        {
+            if pending.len() == n {
+                return pending.iter().map(|p| p.id).collect();
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    panic!("timed out waiting for {} pending permission(s)", n);
+}
+
+#[tokio::test]
+async fn resolve_permission_over_socket() {
+    use agent_team::cli::client::SessionClient;
+
+    let dir = tempfile::tempdir().unwrap();
+    let config = test_config(dir.path().to_path_buf());
+    let local = tokio::task::LocalSet::new();
+    let sock_path = spawn_mock_session(&local, &config, "perm-1");
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+            let prompt = |text: &str| SessionRequest::Prompt { text: text.into(), files: vec![], chain: vec![] };
+
+            // 直连 socket（无信封）拒绝
+            let resp = send_recv(&sock_path, prompt("permission: rm /tmp/a")).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+            let ids = pending_permission_ids(&sock_path, 1).await;
+            let req = SessionRequest::ResolvePermission { permission_id: ids[0], approve: false };
+            let resp = send_recv(&sock_path, req).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }), "{:?}", resp);
+            assert!(pending_permission_ids(&sock_path, 0).await.is_empty());
+
+            // 多路复用连接（带信封 id）批准
+            let mux = SessionClient::connect(&config, "perm-1").await.unwrap().into_mux().unwrap();
+            assert!(matches!(mux.request(prompt("permission: touch b")).await.unwrap(), SessionResponse::Ok { .. }));
+            let ids = pending_permission_ids(&sock_path, 1).await;
+            let req = SessionRequest::ResolvePermission { permission_id: ids[0], approve: true };
+            let resp = mux.request(req).await.unwrap();
+            assert!(matches!(resp, SessionResponse::Ok { .. }), "{:?}", resp);
+
+            // 不存在的 id 报错
+            let req = SessionRequest::ResolvePermission { permission_id: u64::MAX, approve: true };
+            assert!(matches!(send_recv(&sock_path, req).await, SessionResponse::Error { .. }));
+
+            // 审批结果写入输出缓冲
+            let resp = send_recv(&sock_path, SessionRequest::GetOutput { last: 0, agent_only: false }).await;
+            let SessionResponse::Output { entries, .. } = resp else { panic!("expected Output") };
+            let decisions: Vec<_> = entries
+                .iter()
+                .filter(|e| matches!(e.update_type, OutputType::PermissionRequest))
+                .map(|e| e.content.as_str())
+                .filter(|c| !c.contains("Waiting"))
+                .collect();
+            assert_eq!(decisions, ["Permission denied: rm /tmp/a", "Permission approved: touch b"]);
+
+            let resp = send_recv(&sock_path, SessionRequest::Shutdown).await;
+            assert!(matches!(resp, SessionResponse::Ok { .. }));
+        })
+        .await;
+}
+
+// ==================== 分帧 ====================
+
+#[tokio::test]
+async fn length_prefixed_framing_and_frame_limit() {
+    use agent_team::cli::client::SessionClient;
+    use agent_team::protocol::messages::FileAttachment;
+
+    let dir = tempfile::tempdir().unwrap();
+    let mut config = test_config(dir.path().to_path_buf());
+    config.transport.max_frame = 64 * 1024;
+    config.transport.length_prefix = true;
+    let sock_path = config.session_socket("f-1");
+
+    let local = tokio::task::LocalSet::new();
+    let session_config = config.clone();
+    local.spawn_local(async move {
+        agent_team::session::server::run(
+            "f-1".into(),
+            "mock".into(),
+            session_config,
+            vec![],
+            std::env::temp_dir(),
+            None,
+        )
+        .await
+    });
+
+    local
+        .run_until(async {
+            tokio::time::sleep(Duration::from_millis(300)).await;
+
+            let mut client = SessionClient::connect(&config, "f-1").await.unwrap();
+            assert!(client.peer().supports("length_prefix"));
+            let prompt = |size: usize| SessionRequest::Prompt {
+                text: "read the attachment".into(),
+                files: vec![FileAttachment {
+                    path: "notes.txt".into(),
+                    content: "line\n".repeat(size / 5),
+                }],
+                chain: vec![],
+            };
+
+            // 带换行的附件走长度前缀帧
+            let resp = client.send(prompt(20 * 1024)).await.unwrap();
+            assert!(matches!(resp, SessionResponse::Ok { .. }), "{:?}", resp);
+
+            // 超过 session 声明的上限：本地拒绝，连接仍可用
+            let err = client.send(prompt(128 * 1024)).await.unwrap_err();
+            assert!(format!("{:#}", err).contains("frame limit"), "{:#}", err);
+            let resp = client.send(SessionRequest::GetStatus).await.unwrap();
+            assert!(matches!(resp, SessionResponse::Status { .. }));
+
+            // 行分帧的旧客户端同时可用
+            let resp = send_recv(&sock_path, SessionRequest::GetStatus).await;
+            assert!(matches!(resp, SessionResponse::Status { .. }));
+
+            client.send(SessionRequest::Shutdown).await.unwrap();
+        })
+        .await;
+}